@@ -1,64 +1,446 @@
 //! API types that can be received from the Waifu Vault API
+#[cfg(not(target_arch = "wasm32"))]
+use crate::checksum::{stream_body, Hashed, SharedHasher};
+use crate::{ApiCaller, Checksum, Error, HashAlgorithm};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Defines a newtype around a token so tokens of different kinds can't be mixed up
+macro_rules! token {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            /// Create a token from its string form
+            pub fn new(token: impl Into<String>) -> Self {
+                Self(token.into())
+            }
+
+            /// The token as a string slice
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            /// Consume the token and return the underlying string
+            pub fn into_string(self) -> String {
+                self.0
+            }
+
+            /// The token percent-encoded for use as a single segment of an endpoint path
+            pub(crate) fn path_segment(&self) -> Result<String, Error> {
+                path_segment(&self.0)
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(token: String) -> Self {
+                Self(token)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(token: &str) -> Self {
+                Self(token.to_string())
+            }
+        }
+
+        impl From<&String> for $name {
+            fn from(token: &String) -> Self {
+                Self(token.clone())
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(token: &$name) -> Self {
+                token.clone()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(token: $name) -> Self {
+                token.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+/// Characters escaped in a path segment, including `/` so a token can't split the path
+const PATH_SEGMENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Percent-encodes a token so it stays a single path segment
+///
+/// Empty tokens and `.` or `..` would change which endpoint the path points to however
+/// they are encoded, so they fail with [`Error::InvalidToken`].
+fn path_segment(token: &str) -> Result<String, Error> {
+    if matches!(token, "" | "." | "..") {
+        return Err(Error::InvalidToken {
+            token: token.to_string(),
+        });
+    }
+
+    Ok(percent_encoding::utf8_percent_encode(token, PATH_SEGMENT).to_string())
+}
+
+token! {
+    /// Token identifying a single file, used for file info, updates and deleting
+    FileToken
+}
+
+token! {
+    /// Token identifying a bucket, used to upload into and manage the bucket
+    BucketToken
+}
+
+token! {
+    /// Token identifying an album, used to manage the album and its files
+    AlbumToken
+}
 
-/// The main API responses that can be received
+/// Values that can be given where a list of file tokens is expected
+///
+/// Implemented for [`FileToken`] and plain strings, and references to either, so lists can
+/// be passed however they are already held without collecting them again.
 ///
-/// Serde will deserialize these into the appropriate type
-#[derive(Deserialize, Debug, Clone)]
-#[serde(untagged)]
-pub(crate) enum WaifuApiResponse {
-    /// Everything is good, contains the info about the uploaded file
-    WaifuFileResponse(WaifuFileEntry),
+/// The trait is sealed. Other token types mustn't be accepted as file tokens, and keeping the
+/// set of implementations closed lets new ones be added without breaking downstream code.
+pub trait AsFileToken: sealed::Sealed {
+    /// The token as a string
+    fn as_file_token(&self) -> &str;
+}
+
+mod sealed {
+    /// Supertrait of [`super::AsFileToken`], so it can't be implemented outside this crate
+    pub trait Sealed {}
+
+    impl Sealed for super::FileToken {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl<T: Sealed + ?Sized> Sealed for &T {}
+}
 
-    /// Everything is good, contains info about the album
-    WaifuAlbumResponse(WaifuAlbumEntry),
+impl AsFileToken for FileToken {
+    fn as_file_token(&self) -> &str {
+        self.as_str()
+    }
+}
 
-    /// Everything is good, contains info about the bucket
-    WaifuBucketResponse(WaifuBucketEntry),
+impl AsFileToken for str {
+    fn as_file_token(&self) -> &str {
+        self
+    }
+}
 
-    /// Everything is good, contains generic success / failure message
-    WaifuGenericResponse(WaifuGenericMessage),
+impl AsFileToken for String {
+    fn as_file_token(&self) -> &str {
+        self
+    }
+}
 
-    /// Something went wrong, shows the error type and reason
-    WaifuError(WaifuError),
+impl<T: AsFileToken + ?Sized> AsFileToken for &T {
+    fn as_file_token(&self) -> &str {
+        (**self).as_file_token()
+    }
+}
 
-    /// Special case for the delete endpoint, just a boolean flag
-    /// if it was successful or not
-    Delete(bool),
+/// Collects anything iterating over file tokens into owned tokens for a request body
+pub(crate) fn collect_file_tokens<I>(file_tokens: I) -> Vec<FileToken>
+where
+    I: IntoIterator,
+    I::Item: AsFileToken,
+{
+    file_tokens
+        .into_iter()
+        .map(|token| FileToken::new(token.as_file_token()))
+        .collect()
 }
 
 /// This is a standard response for the service containing info about the entry
-#[derive(Debug, Deserialize, Clone)]
+///
+/// Entries are equal when every field is, with the retention period compared as the service
+/// sent it. The same file looked up twice is usually unequal, since its retention counts
+/// down in between.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct WaifuFileEntry {
+    /// Numeric id of the file, used to pick files from an album download
+    pub id: Option<u64>,
+
     /// File token - used for file info and deleting
-    pub token: String,
+    pub token: FileToken,
 
     /// Location of the uploaded file
     pub url: String,
 
     /// Bucket identifier
-    pub bucket: Option<String>,
+    pub bucket: Option<BucketToken>,
 
     /// Album the file is associated with, if any
     pub album: Option<WaifuAlbumMetadata>,
 
-    /// Number of views the file has
+    /// Number of views the file has, 0 if the service didn't say
+    #[serde(default)]
     pub views: usize,
 
-    /// How long the file will exist for
-    #[serde(rename = "retentionPeriod")]
+    /// How long the file will exist for, null if the service didn't say
+    #[serde(rename = "retentionPeriod", default)]
     pub retention_period: serde_json::Value,
 
     /// Response options for the file
     pub options: Option<WaifuFileOptions>,
 }
 
+impl WaifuFileEntry {
+    /// Create a new entry for the file at `url`, such as for a stub of [`crate::WaifuVaultApi`]
+    ///
+    /// Everything else is left unknown until set: no id, bucket, album or options, no views
+    /// and a null retention period.
+    pub fn new(token: impl Into<FileToken>, url: impl AsRef<str>) -> Self {
+        Self {
+            id: None,
+            token: token.into(),
+            url: url.as_ref().to_string(),
+            bucket: None,
+            album: None,
+            views: 0,
+            retention_period: serde_json::Value::Null,
+            options: None,
+        }
+    }
+
+    /// Set the id field on the entry
+    pub fn id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Set the bucket field on the entry
+    pub fn bucket(mut self, bucket: impl Into<BucketToken>) -> Self {
+        self.bucket = Some(bucket.into());
+        self
+    }
+
+    /// Set the album field on the entry
+    pub fn album(mut self, album: WaifuAlbumMetadata) -> Self {
+        self.album = Some(album);
+        self
+    }
+
+    /// Set the views field on the entry
+    pub fn views(mut self, views: usize) -> Self {
+        self.views = views;
+        self
+    }
+
+    /// Set the retention period, as milliseconds or as the text the service formats it as
+    pub fn retention_period(mut self, retention_period: impl Into<serde_json::Value>) -> Self {
+        self.retention_period = retention_period.into();
+        self
+    }
+
+    /// Set the options field on the entry
+    pub fn options(mut self, options: WaifuFileOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// When the file will be removed from the vault, measured from now
+    ///
+    /// Returns `None` if the retention period is missing or can't be understood.
+    /// See [`WaifuFileEntry::expires_at_from`] to measure from when the response was received.
+    /// Not available on wasm32, which has no system clock; use
+    /// [`WaifuFileEntry::expires_at_from`] with a time from the host there.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at_from(SystemTime::now())
+    }
+
+    /// When the file will be removed from the vault, measured from `anchor`
+    ///
+    /// The retention period is either a number of milliseconds or, when the entry was
+    /// requested with [`WaifuGetRequest::formatted`], text such as `"2 hours 5 minutes"`.
+    pub fn expires_at_from(&self, anchor: SystemTime) -> Option<SystemTime> {
+        let remaining = match &self.retention_period {
+            serde_json::Value::Number(ms) => Duration::from_millis(ms.as_u64()?),
+            serde_json::Value::String(text) => match text.trim().parse::<u64>() {
+                Ok(ms) => Duration::from_millis(ms),
+                Err(_) => parse_formatted_duration(text)?,
+            },
+            _ => return None,
+        };
+
+        anchor.checked_add(remaining)
+    }
+
+    /// When the file will be removed from the vault, measured from now, in UTC
+    ///
+    /// See [`WaifuFileEntry::expires_at`]. Available with the `chrono` feature, but not on
+    /// wasm32.
+    #[cfg(all(feature = "chrono", not(target_arch = "wasm32")))]
+    pub fn expires_at_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.expires_at().map(chrono::DateTime::from)
+    }
+
+    /// If the file needs a password to download
+    pub fn is_protected(&self) -> bool {
+        self.options.as_ref().is_some_and(|o| o.protected)
+    }
+
+    /// Downloads the contents of this file
+    ///
+    /// Fails without making a request if the file is protected and no password is given.
+    /// Files uploaded with [`WaifuUploadRequest::one_time_download`] are deleted by the
+    /// service once downloaded, so [`WaifuFileEntry::refresh`] will fail afterwards.
+    pub async fn download(
+        &self,
+        caller: &ApiCaller,
+        password: Option<String>,
+    ) -> anyhow::Result<Vec<u8>> {
+        if self.is_protected() && password.is_none() {
+            anyhow::bail!("this file requires a password to download");
+        }
+
+        caller.download_file(&self.url, password).await
+    }
+
+    /// Deletes this file from the vault
+    pub async fn delete(&self, caller: &ApiCaller) -> anyhow::Result<bool> {
+        caller.delete_file(&self.token).await
+    }
+
+    /// Fetches the current information on this file, such as its view count
+    pub async fn refresh(&self, caller: &ApiCaller) -> anyhow::Result<WaifuFileEntry> {
+        caller.file_info(WaifuGetRequest::new(&self.token)).await
+    }
+}
+
+impl std::fmt::Display for WaifuFileEntry {
+    /// Formats the entry on one line, such as
+    /// `some-file https://waifuvault.moe/f/some-file.txt (protected), expires in 1h 30m`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.token, self.url)?;
+
+        let mut flags = Vec::new();
+        if self.is_protected() {
+            flags.push("protected");
+        }
+        if self.options.as_ref().is_some_and(|o| o.one_time_download) {
+            flags.push("one time download");
+        }
+        if !flags.is_empty() {
+            write!(f, " ({})", flags.join(", "))?;
+        }
+
+        let remaining = match &self.retention_period {
+            serde_json::Value::Number(ms) => ms.as_u64().map(|ms| short_duration(ms / 1000)),
+            serde_json::Value::String(text) => Some(match text.trim().parse::<u64>() {
+                Ok(ms) => short_duration(ms / 1000),
+                Err(_) => text.clone(),
+            }),
+            _ => None,
+        };
+        match remaining {
+            Some(remaining) => write!(f, ", expires in {remaining}"),
+            None => f.write_str(", retention unknown"),
+        }
+    }
+}
+
+/// Formats a number of seconds such as `1d 2h 3m`, leaving out the seconds past a minute
+fn short_duration(secs: u64) -> String {
+    if secs < 60 {
+        return format!("{secs}s");
+    }
+
+    let units = [
+        (secs / 86_400, "d"),
+        (secs / 3_600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+    ];
+    units
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{amount}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses a formatted retention period such as `"1 day, 2 hours and 3 minutes"`
+fn parse_formatted_duration(text: &str) -> Option<Duration> {
+    let text = text.to_lowercase().replace(',', " ");
+    let mut words = text.split_whitespace().filter(|w| *w != "and");
+    let mut total = Duration::ZERO;
+    let mut parsed_any = false;
+
+    while let Some(amount) = words.next() {
+        let amount: u64 = amount.parse().ok()?;
+        let secs = match words.next()?.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+
+        total = total.checked_add(Duration::from_secs(amount.checked_mul(secs)?))?;
+        parsed_any = true;
+    }
+
+    parsed_any.then_some(total)
+}
+
 /// Response options for the uploaded file
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+///
+/// `hide_filename` and `protected` are false if the service leaves them out. There is no
+/// such default for `one_time_download`, since taking a one time download for a normal file
+/// could get it consumed, such as by [`crate::ApiCaller::copy_file`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
 pub struct WaifuFileOptions {
     /// If the filename is hidden
-    #[serde(rename = "hideFilename")]
+    #[serde(rename = "hideFilename", default)]
     pub hide_filename: bool,
 
     /// If this file will be deleted when it is accessed
@@ -66,14 +448,132 @@ pub struct WaifuFileOptions {
     pub one_time_download: bool,
 
     /// If this file requires a password
+    #[serde(default)]
     pub protected: bool,
 }
 
+impl WaifuFileOptions {
+    /// Create a new set of options
+    pub fn new(hide_filename: bool, one_time_download: bool, protected: bool) -> Self {
+        Self {
+            hide_filename,
+            one_time_download,
+            protected,
+        }
+    }
+}
+
+/// A value that was changed, from what it was to what it is now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change<T> {
+    /// Value before the change
+    pub before: T,
+
+    /// Value after the change
+    pub after: T,
+}
+
+/// What an update changed about a file, from [`crate::ApiCaller::update_file_with_diff`]
+///
+/// A field is `None` when it didn't change, such as when the service ignored it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OptionsDiff {
+    /// If the file needs a password to download
+    pub protected: Option<Change<bool>>,
+
+    /// If the filename is hidden from the URL
+    pub hide_filename: Option<Change<bool>>,
+
+    /// When the file expires, or `None` if its retention period couldn't be understood
+    ///
+    /// The service counts retention in whole minutes, so expiries less than a minute apart
+    /// count as unchanged.
+    pub retention: Option<Change<Option<SystemTime>>>,
+}
+
+impl OptionsDiff {
+    /// Compares an entry received at `before_at` with the same entry received at `after_at`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn between(
+        before: &WaifuFileEntry,
+        before_at: SystemTime,
+        after: &WaifuFileEntry,
+        after_at: SystemTime,
+    ) -> Self {
+        fn change<T: PartialEq>(before: T, after: T) -> Option<Change<T>> {
+            (before != after).then_some(Change { before, after })
+        }
+        let flag = |entry: &WaifuFileEntry, get: fn(&WaifuFileOptions) -> bool| {
+            entry.options.as_ref().is_some_and(get)
+        };
+
+        let expiries = (
+            before.expires_at_from(before_at),
+            after.expires_at_from(after_at),
+        );
+        let retention = match expiries {
+            (Some(before), Some(after)) => {
+                let apart = after
+                    .duration_since(before)
+                    .unwrap_or_else(|e| e.duration());
+                (apart >= Duration::from_secs(60)).then_some(Change {
+                    before: Some(before),
+                    after: Some(after),
+                })
+            }
+            (before, after) => change(before, after),
+        };
+
+        Self {
+            protected: change(flag(before, |o| o.protected), flag(after, |o| o.protected)),
+            hide_filename: change(
+                flag(before, |o| o.hide_filename),
+                flag(after, |o| o.hide_filename),
+            ),
+            retention,
+        }
+    }
+
+    /// If nothing changed
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for OptionsDiff {
+    /// Lists the changes, such as `protected: false -> true, hide_filename: true -> false`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let expiry = |at: Option<SystemTime>| match at {
+            Some(at) => httpdate::fmt_http_date(at),
+            None => "unknown".to_string(),
+        };
+
+        let mut changes = Vec::new();
+        if let Some(Change { before, after }) = self.protected {
+            changes.push(format!("protected: {before} -> {after}"));
+        }
+        if let Some(Change { before, after }) = self.hide_filename {
+            changes.push(format!("hide_filename: {before} -> {after}"));
+        }
+        if let Some(Change { before, after }) = self.retention {
+            changes.push(format!("expires: {} -> {}", expiry(before), expiry(after)));
+        }
+
+        if changes.is_empty() {
+            f.write_str("no changes")
+        } else {
+            f.write_str(&changes.join(", "))
+        }
+    }
+}
+
 /// Successful response from the API when interacting with the Bucket API
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct WaifuBucketEntry {
     /// Bucket token identifier
-    pub token: String,
+    pub token: BucketToken,
 
     /// Files contained within the bucket
     pub files: Vec<WaifuFileEntry>,
@@ -82,15 +582,140 @@ pub struct WaifuBucketEntry {
     pub albums: Option<Vec<WaifuAlbumMetadata>>,
 }
 
+impl WaifuBucketEntry {
+    /// Create a new entry for an empty bucket without albums
+    pub fn new(token: impl Into<BucketToken>) -> Self {
+        Self {
+            token: token.into(),
+            files: Vec::new(),
+            albums: None,
+        }
+    }
+
+    /// Set the files field on the entry
+    pub fn files(mut self, files: Vec<WaifuFileEntry>) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Set the albums field on the entry
+    pub fn albums(mut self, albums: Vec<WaifuAlbumMetadata>) -> Self {
+        self.albums = Some(albums);
+        self
+    }
+
+    /// Totals over the files and albums in the bucket
+    pub fn stats(&self) -> BucketStats {
+        let has = |flag: fn(&WaifuFileOptions) -> bool| {
+            self.files
+                .iter()
+                .filter(|f| f.options.as_ref().is_some_and(flag))
+                .count()
+        };
+
+        BucketStats {
+            file_count: self.files.len(),
+            album_count: self.albums.as_ref().map_or(0, Vec::len),
+            total_views: self.files.iter().map(|f| f.views as u64).sum(),
+            protected_count: has(|o| o.protected),
+            one_time_count: has(|o| o.one_time_download),
+            total_bytes: None,
+        }
+    }
+
+    /// Saves the token of the bucket to `path`, to be loaded again with
+    /// [`crate::ApiCaller::load_bucket`]
+    ///
+    /// Only the token is saved, as a small JSON file replacing anything at `path`. On Unix
+    /// the file is only readable by its owner, since the token gives full access to the
+    /// bucket. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, Error};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let bucket = match caller.load_bucket("bucket.json").await {
+    ///         Ok(bucket) => bucket,
+    ///         Err(e) if matches!(
+    ///             e.downcast_ref(),
+    ///             Some(Error::NoSavedBucket { .. } | Error::CorruptSavedBucket { .. })
+    ///         ) => {
+    ///             let bucket = caller.create_bucket().await?;
+    ///             bucket.save_token("bucket.json").await?;
+    ///             bucket
+    ///         }
+    ///         Err(e) => return Err(e),
+    ///     };
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn save_token(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        crate::store::write_token(path.as_ref(), &self.token).await
+    }
+}
+
+impl std::fmt::Display for WaifuBucketEntry {
+    /// Formats the bucket as its token and file count, such as `bucket some-bucket, 3 files`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bucket {}, {}",
+            self.token,
+            count(self.files.len(), "file")
+        )
+    }
+}
+
+/// Formats a number of things, such as `1 file` or `3 files`
+fn count(n: usize, thing: &str) -> String {
+    match n {
+        1 => format!("1 {thing}"),
+        n => format!("{n} {thing}s"),
+    }
+}
+
+/// Totals describing how full and how busy a bucket is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BucketStats {
+    /// Number of files in the bucket
+    pub file_count: usize,
+
+    /// Number of albums in the bucket
+    pub album_count: usize,
+
+    /// Views across every file in the bucket
+    pub total_views: u64,
+
+    /// Number of files needing a password to download
+    pub protected_count: usize,
+
+    /// Number of files deleted once downloaded
+    pub one_time_count: usize,
+
+    /// Size of every file in the bucket combined
+    ///
+    /// Always `None` for now, as the service doesn't report file sizes in bucket listings
+    pub total_bytes: Option<u64>,
+}
+
 /// Successful response from the API when interacting with the Album API
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct WaifuAlbumEntry {
     /// Album token identifier
-    pub token: String,
+    pub token: AlbumToken,
 
     /// Bucket token identifier
     #[serde(rename = "bucketToken")]
-    pub bucket_token: String,
+    pub bucket_token: BucketToken,
 
     /// Public token identifier
     #[serde(rename = "publicToken")]
@@ -101,13 +726,88 @@ pub struct WaifuAlbumEntry {
 
     /// Files contained within the Album
     pub files: Vec<WaifuFileEntry>,
+
+    /// Date the album was created, in milliseconds since the epoch, if the service said
+    #[serde(
+        rename = "dateCreated",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub date_created: Option<u64>,
+}
+
+impl WaifuAlbumEntry {
+    /// Create a new entry for an empty album that isn't shared
+    pub fn new(
+        token: impl Into<AlbumToken>,
+        bucket_token: impl Into<BucketToken>,
+        name: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            token: token.into(),
+            bucket_token: bucket_token.into(),
+            public_token: None,
+            name: name.as_ref().to_string(),
+            files: Vec::new(),
+            date_created: None,
+        }
+    }
+
+    /// Set the public_token field on the entry, as if the album were shared
+    pub fn public_token(mut self, public_token: impl AsRef<str>) -> Self {
+        self.public_token = Some(public_token.as_ref().to_string());
+        self
+    }
+
+    /// Set the files field on the entry
+    pub fn files(mut self, files: Vec<WaifuFileEntry>) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// Set the date_created field on the entry, in milliseconds since the epoch
+    pub fn date_created(mut self, date_created: u64) -> Self {
+        self.date_created = Some(date_created);
+        self
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl WaifuAlbumEntry {
+    /// When the album was created, in UTC, read the same way as
+    /// [`WaifuAlbumMetadata::date_created_utc`]
+    ///
+    /// Returns `None` if the service didn't say or the date is out of range. Available with
+    /// the `chrono` feature.
+    pub fn date_created_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        epoch_to_utc(self.date_created?)
+    }
+}
+
+impl std::fmt::Display for WaifuAlbumEntry {
+    /// Formats the album on one line, such as `album "holiday" (some-album), 2 files, shared`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "album {:?} ({}), {}, {}",
+            self.name,
+            self.token,
+            count(self.files.len(), "file"),
+            if self.public_token.is_some() {
+                "shared"
+            } else {
+                "not shared"
+            }
+        )
+    }
 }
 
 /// Album metadata which shows which album a file is apart of
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct WaifuAlbumMetadata {
     /// Album token
-    pub token: String,
+    pub token: AlbumToken,
 
     /// Public token
     #[serde(rename = "publicToken")]
@@ -117,15 +817,68 @@ pub struct WaifuAlbumMetadata {
     pub name: String,
 
     /// Bucket name
-    pub bucket: String,
+    pub bucket: BucketToken,
 
     /// Date the album was created
     #[serde(rename = "dateCreated")]
     pub date_created: u64,
 }
 
+impl WaifuAlbumMetadata {
+    /// Create new metadata for an album that isn't shared
+    pub fn new(
+        token: impl Into<AlbumToken>,
+        bucket: impl Into<BucketToken>,
+        name: impl AsRef<str>,
+        date_created: u64,
+    ) -> Self {
+        Self {
+            token: token.into(),
+            public_token: None,
+            name: name.as_ref().to_string(),
+            bucket: bucket.into(),
+            date_created,
+        }
+    }
+
+    /// Set the public_token field on the metadata, as if the album were shared
+    pub fn public_token(mut self, public_token: impl AsRef<str>) -> Self {
+        self.public_token = Some(public_token.as_ref().to_string());
+        self
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl WaifuAlbumMetadata {
+    /// When the album was created, in UTC
+    ///
+    /// The service sends milliseconds since the epoch, but a value too small to be a date
+    /// after March 1973 in milliseconds is read as seconds instead. Returns `None` if the
+    /// date is out of range. Available with the `chrono` feature.
+    pub fn date_created_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        epoch_to_utc(self.date_created)
+    }
+}
+
+/// Reads a timestamp that may be in seconds or milliseconds since the epoch
+///
+/// Milliseconds from before March 1973 are taken for seconds, which covers every date up
+/// to the year 5138.
+#[cfg(feature = "chrono")]
+fn epoch_to_utc(timestamp: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+    const SECONDS_BELOW: u64 = 100_000_000_000;
+
+    let millis = if timestamp < SECONDS_BELOW {
+        timestamp.checked_mul(1000)?
+    } else {
+        timestamp
+    };
+    chrono::DateTime::from_timestamp_millis(i64::try_from(millis).ok()?)
+}
+
 /// Generic response returned by the API indicating success / failure of operation
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct WaifuGenericMessage {
     /// If the operation was a success or not
     pub success: bool,
@@ -134,107 +887,862 @@ pub struct WaifuGenericMessage {
     pub description: String,
 }
 
-/// A standard error, all errors from the service take this shape
-#[derive(Debug, Deserialize, Clone)]
-pub struct WaifuError {
-    /// The name of the error, this is normally the HTTP exception thrown
-    pub name: String,
-
-    /// The thing that went wrong
-    pub message: String,
-
-    /// The HTTP status
-    pub status: u16,
+impl WaifuGenericMessage {
+    /// Create a new message
+    pub fn new(success: bool, description: impl AsRef<str>) -> Self {
+        Self {
+            success,
+            description: description.as_ref().to_string(),
+        }
+    }
 }
 
-impl std::fmt::Display for WaifuError {
+impl std::fmt::Display for WaifuGenericMessage {
+    /// Formats the message as its outcome and description, such as `succeeded: Album deleted`
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "WaifuError {} ({})\nMessage: {}",
-            self.name, self.status, self.message
-        )
+        let outcome = if self.success { "succeeded" } else { "failed" };
+        write!(f, "{outcome}: {}", self.description)
     }
 }
 
-impl std::error::Error for WaifuError {}
+/// A single restriction the service places on uploads
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct WaifuRestriction {
+    /// Kind of restriction, e.g. `MAX_FILE_SIZE` or `BANNED_MIME_TYPE`
+    #[serde(rename = "type")]
+    pub restriction_type: String,
 
-/// Upload request to upload content to the Waifu Vault
-#[derive(Debug, Default, Clone)]
-pub struct WaifuUploadRequest {
-    /// Path to a file to upload
-    pub(crate) file: Option<String>,
+    /// Value of the restriction, its shape depends on the kind
+    pub value: serde_json::Value,
+}
 
-    /// URL of a resource to upload
-    pub(crate) url: Option<String>,
+impl WaifuRestriction {
+    /// Create a new restriction, such as `MAX_FILE_SIZE` with a number of bytes
+    pub fn new(restriction_type: impl AsRef<str>, value: impl Into<serde_json::Value>) -> Self {
+        Self {
+            restriction_type: restriction_type.as_ref().to_string(),
+            value: value.into(),
+        }
+    }
+}
 
-    /// Raw bytes to upload to the vault
-    pub(crate) bytes: Option<Vec<u8>>,
+/// Restrictions the service places on uploads
+///
+/// Fetch these once with [`crate::ApiCaller::get_restrictions`] and check any number of
+/// uploads against them with [`WaifuUploadRequest::validate_against`].
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(transparent)]
+#[non_exhaustive]
+pub struct WaifuRestrictions {
+    /// Every restriction reported by the service
+    pub restrictions: Vec<WaifuRestriction>,
+}
 
-    /// Token of the bucket to upload to
-    pub(crate) bucket: Option<String>,
+impl WaifuRestrictions {
+    /// Create a new set of restrictions
+    pub fn new(restrictions: Vec<WaifuRestriction>) -> Self {
+        Self { restrictions }
+    }
 
-    /// Filename to be used when uploading raw bytes
-    pub(crate) filename: Option<String>,
+    /// Largest file the service accepts in bytes, if there is a limit
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.find("MAX_FILE_SIZE")?.as_u64()
+    }
 
-    /// Set an expiry for the content
-    /// This is a string containing a number and a letter (m for mins, h for hours, d for days)
-    /// Leave blank to keep the file for as long as the retention policy allows
-    pub(crate) expires: Option<String>,
+    /// MIME types the service refuses to store
+    pub fn banned_mime_types(&self) -> Vec<String> {
+        self.find("BANNED_MIME_TYPE")
+            .and_then(|value| value.as_str())
+            .map(|types| {
+                types
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-    /// Hide the filename from the generated URL
-    pub(crate) hide_filename: bool,
+    fn find(&self, restriction_type: &str) -> Option<&serde_json::Value> {
+        self.restrictions
+            .iter()
+            .find(|r| r.restriction_type == restriction_type)
+            .map(|r| &r.value)
+    }
+}
 
-    /// Set a password for the file
-    /// This encrypts the file on the server which can only be accessed by
-    /// retrieving it with the x-password header set
-    pub(crate) password: Option<String>,
+/// Result of replacing the contents of a file
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Replaced {
+    /// Entry now holding the new contents
+    pub new_entry: WaifuFileEntry,
+
+    /// If the contents are now reachable through a different token and URL
+    ///
+    /// Any links to the old file are broken when this is true
+    pub token_changed: bool,
+}
 
-    /// Delete the file after first access
-    pub(crate) one_time_download: bool,
+/// Result of downloading every file in a bucket with [`crate::ApiCaller::download_bucket`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct BucketDownload {
+    /// Files that were saved, and where
+    pub saved: Vec<(FileToken, std::path::PathBuf)>,
+
+    /// Files that couldn't be saved, and why
+    ///
+    /// Password protected files are always here, since there is no password to use
+    pub failed: Vec<(FileToken, anyhow::Error)>,
 }
 
-impl WaifuUploadRequest {
-    /// Create a new upload request
-    pub fn new() -> Self {
+/// A file downloaded with [`crate::ApiCaller::download_file_with_metadata`], along with what
+/// the response said about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DownloadedFile {
+    /// Content of the file
+    pub bytes: Vec<u8>,
+
+    /// MIME type from the `Content-Type` header, if there was one
+    pub content_type: Option<String>,
+
+    /// Name from the `Content-Disposition` header, if there was one
+    ///
+    /// Any directories in the name are dropped, so it can be joined onto a directory safely
+    pub filename: Option<String>,
+
+    /// Size from the `Content-Length` header, if there was one
+    pub length: Option<u64>,
+}
+
+/// Result of downloading a file to disk with [`crate::ApiCaller::download_file_to`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FileDownload {
+    /// Size of the file once downloaded
+    pub size: u64,
+
+    /// How much of the file was already on disk and not downloaded again
+    pub resumed_from: u64,
+
+    /// Why the download had to start over rather than resume, if it did
+    ///
+    /// Set when the server doesn't support ranges, or the partial file doesn't fit the file
+    /// on the server, such as when it is larger. The file is still downloaded in full.
+    pub warning: Option<String>,
+}
+
+/// Result of uploading a file with [`crate::ApiCaller::upload_file_with_outcome`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct UploadOutcome {
+    /// Entry of the uploaded file in the vault
+    pub entry: WaifuFileEntry,
+
+    /// Digest of the content sent, if [`WaifuUploadRequest::compute_checksum`] was set
+    ///
+    /// Content uploaded from a URL is fetched by the service, so it has no checksum.
+    pub checksum: Option<Checksum>,
+}
+
+/// Options for moving a file with [`crate::ApiCaller::move_file`]
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::api::{MoveOptions, WaifuUploadRequest};
+///
+/// let options = MoveOptions {
+///     password: Some("old-password".to_string()),
+///     verify: true,
+///     template: WaifuUploadRequest::new().expires("7d"),
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MoveOptions {
+    /// Password the file is protected with, needed to download it
+    pub password: Option<String>,
+
+    /// Download the new copy and check it matches what was uploaded before deleting the
+    /// original
+    ///
+    /// Checking a one time download would consume it, so it can't be verified
+    pub verify: bool,
+
+    /// Options the new copy is uploaded with, such as its expiry or password
+    ///
+    /// Any bucket set on the template is replaced by the one moved to
+    pub template: WaifuUploadRequest<NoSource>,
+}
+
+/// Result of moving a file with [`crate::ApiCaller::move_file`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct FileMove {
+    /// Entry of the new copy in the bucket moved to
+    pub entry: WaifuFileEntry,
+
+    /// If the service confirmed the original was deleted
+    pub source_deleted: bool,
+}
+
+/// Options for keeping a file alive with [`crate::ApiCaller::extend_or_reupload`]
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use waifuvault::api::KeepAliveOptions;
+///
+/// let options = KeepAliveOptions {
+///     expiry: Duration::from_secs(30 * 24 * 60 * 60),
+///     password: Some("some-password".to_string()),
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeepAliveOptions {
+    /// How long from now the file should be kept for once extended or uploaded again
+    pub expiry: Duration,
+
+    /// Password the file is protected with, needed to upload a protected file again
+    pub password: Option<String>,
+}
+
+/// What [`crate::ApiCaller::extend_or_reupload`] did to keep a file alive
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum KeepAlive {
+    /// The file had enough time left and was left alone
+    Untouched(WaifuFileEntry),
+
+    /// The expiry of the file was pushed back, keeping its token
+    Extended(WaifuFileEntry),
+
+    /// The file was uploaded again under a new token, and the original deleted
+    Reuploaded {
+        /// Token of the original file, which no longer exists
+        previous: FileToken,
+
+        /// Entry of the new upload
+        entry: WaifuFileEntry,
+    },
+}
+
+impl KeepAlive {
+    /// Entry of the file as it is now
+    pub fn entry(&self) -> &WaifuFileEntry {
+        match self {
+            Self::Untouched(entry) | Self::Extended(entry) => entry,
+            Self::Reuploaded { entry, .. } => entry,
+        }
+    }
+
+    /// New token of the file, if it had to be uploaded again
+    pub fn new_token(&self) -> Option<&FileToken> {
+        match self {
+            Self::Reuploaded { entry, .. } => Some(&entry.token),
+            _ => None,
+        }
+    }
+}
+
+/// How waiting with [`crate::ApiCaller::wait_until_consumed`] ended
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Consumption {
+    /// The service no longer knows the file, since it was downloaded or deleted
+    Consumed,
+
+    /// Time ran out before the service answered any lookup
+    TimedOut,
+
+    /// Time ran out with the file still there, as it was last looked up
+    StillPresent(Box<WaifuFileEntry>),
+}
+
+/// Result of emptying a bucket with [`crate::ApiCaller::empty_bucket`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct EmptiedBucket {
+    /// How many files were deleted
+    pub deleted: usize,
+
+    /// Files that couldn't be deleted, and why
+    pub failed: Vec<(FileToken, anyhow::Error)>,
+}
+
+/// Result of uploading a directory with [`crate::ApiCaller::upload_directory`]
+///
+/// Paths are relative to the directory that was uploaded.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct DirectoryUpload {
+    /// Files that were uploaded, and their entries in the vault
+    pub uploaded: Vec<(std::path::PathBuf, WaifuFileEntry)>,
+
+    /// Files that weren't uploaded, and why
+    ///
+    /// Symbolic links are not followed and are always here
+    pub failed: Vec<(std::path::PathBuf, anyhow::Error)>,
+}
+
+/// How [`crate::ApiCaller::sync_directory_to_bucket`] brings a bucket in line with a directory
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::api::{SyncOptions, WaifuUploadRequest};
+///
+/// let options = SyncOptions {
+///     prune: true,
+///     template: WaifuUploadRequest::new().expires("7d"),
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Delete files from the bucket that no longer exist in the directory
+    pub prune: bool,
+
+    /// Options every upload is made with, such as its expiry or password
+    ///
+    /// Hiding filenames stops later syncs from recognising the files, so a template that
+    /// hides them is refused. The password, if any, is also used to compare files already
+    /// in the bucket.
+    pub template: WaifuUploadRequest<NoSource>,
+}
+
+/// Result of syncing a directory with [`crate::ApiCaller::sync_directory_to_bucket`]
+///
+/// Paths are relative to the directory that was synced.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SyncReport {
+    /// Files that were new or changed, and their entries in the vault
+    pub uploaded: Vec<(std::path::PathBuf, WaifuFileEntry)>,
+
+    /// Files already in the bucket unchanged
+    pub skipped: Vec<std::path::PathBuf>,
+
+    /// Files removed from the bucket, either replaced by a changed file or pruned
+    pub deleted: Vec<FileToken>,
+
+    /// Files that couldn't be synced, and why
+    pub failed: Vec<(std::path::PathBuf, anyhow::Error)>,
+}
+
+/// Snapshot of the files in a bucket, from [`crate::ApiCaller::export_bucket_manifest`]
+///
+/// Serializable, so it can be kept somewhere safe and checked later with
+/// [`crate::ApiCaller::verify_manifest`] to find out which files have gone.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct BucketManifest {
+    /// Token of the bucket
+    pub bucket: BucketToken,
+
+    /// When the manifest was made, in milliseconds since the epoch
+    pub exported_at: u64,
+
+    /// Every file in the bucket when the manifest was made
+    pub files: Vec<ManifestEntry>,
+}
+
+/// A file recorded in a [`BucketManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    /// Token of the file
+    pub token: FileToken,
+
+    /// Location of the file
+    pub url: String,
+
+    /// Name of the file, from its URL, or `None` if the filename is hidden
+    pub filename: Option<String>,
+
+    /// Options of the file, if the service gave them
+    pub options: Option<WaifuFileOptions>,
+
+    /// When the file expires, in milliseconds since the epoch, or `None` if its retention
+    /// period couldn't be understood
+    pub expires_at: Option<u64>,
+}
+
+impl ManifestEntry {
+    /// Records `entry` as it was at `now`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new(entry: WaifuFileEntry, now: SystemTime) -> Self {
+        let hidden = entry.options.as_ref().is_some_and(|o| o.hide_filename);
+        let expires_at = entry
+            .expires_at_from(now)
+            .and_then(|at| at.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .and_then(|since| u64::try_from(since.as_millis()).ok());
+
+        Self {
+            filename: (!hidden).then(|| url_filename(&entry.url)).flatten(),
+            expires_at,
+            token: entry.token,
+            url: entry.url,
+            options: entry.options,
+        }
+    }
+}
+
+/// Result of checking a [`BucketManifest`] with [`crate::ApiCaller::verify_manifest`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ManifestCheck {
+    /// Files the service still knows
+    pub present: Vec<FileToken>,
+
+    /// Files the service no longer knows, as recorded in the manifest
+    pub missing: Vec<ManifestEntry>,
+
+    /// Files that couldn't be checked, and why
+    pub failed: Vec<(FileToken, anyhow::Error)>,
+}
+
+impl ManifestCheck {
+    /// If every file in the manifest is still there
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Public link to a shared album
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct WaifuShareResult {
+    /// URL the album can be viewed at
+    pub url: url::Url,
+
+    /// Public token of the album, the last part of its URL
+    #[serde(rename = "publicToken")]
+    pub public_token: String,
+}
+
+impl TryFrom<WaifuGenericMessage> for WaifuShareResult {
+    type Error = anyhow::Error;
+
+    /// Reads the public URL from the description of a share response
+    fn try_from(message: WaifuGenericMessage) -> anyhow::Result<Self> {
+        if !message.success {
+            anyhow::bail!("album was not shared: {}", message.description);
+        }
+
+        let url = url::Url::parse(&message.description).with_context(|| {
+            format!(
+                "share response description is not a URL: {}",
+                message.description
+            )
+        })?;
+        let public_token = url
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+            .map(str::to_string)
+            .with_context(|| format!("no public token in share URL {url}"))?;
+
+        Ok(Self { url, public_token })
+    }
+}
+
+/// What happened when revoking public access to an album
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RevokeOutcome {
+    /// The album was public and no longer is
+    Revoked,
+
+    /// The album wasn't public, so nothing was revoked
+    WasNotShared,
+}
+
+/// A standard error, all errors from the service take this shape
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[non_exhaustive]
+pub struct WaifuError {
+    /// The name of the error, this is normally the HTTP exception thrown
+    pub name: String,
+
+    /// The thing that went wrong
+    pub message: String,
+
+    /// The HTTP status
+    pub status: u16,
+}
+
+impl std::fmt::Display for WaifuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "WaifuError {} ({})\nMessage: {}",
+            self.name, self.status, self.message
+        )
+    }
+}
+
+impl std::error::Error for WaifuError {}
+
+/// Where the content of an upload comes from
+#[derive(Debug, Clone)]
+pub(crate) enum UploadSource {
+    /// A file on disk, never set on wasm32 where there is no filesystem to read from
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    File(std::path::PathBuf),
+
+    /// A resource the service fetches itself
+    Url(String),
+
+    /// Raw bytes, uploaded under the given filename
+    Bytes {
+        data: bytes::Bytes,
+        filename: String,
+    },
+
+    /// A stream uploaded under the given filename, which can only be sent once
+    #[cfg(not(target_arch = "wasm32"))]
+    Stream {
+        body: StreamBody,
+        filename: String,
+        length: Option<u64>,
+    },
+}
+
+impl UploadSource {
+    /// If the content can be sent again when an upload is retried
+    pub(crate) fn is_replayable(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let UploadSource::Stream { .. } = self {
+            return false;
+        }
+
+        true
+    }
+
+    /// Size of the content to upload, if it is known without reading it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        match self {
+            UploadSource::File(path) => std::fs::metadata(path).ok().map(|m| m.len()),
+            UploadSource::Bytes { data, .. } => Some(data.len() as u64),
+            UploadSource::Stream { length, .. } => *length,
+            UploadSource::Url(_) => None,
+        }
+    }
+}
+
+/// Makes the body of a streamed upload, hashing the stream as it is sent if given a hasher
+#[cfg(not(target_arch = "wasm32"))]
+type MakeStreamBody = Box<dyn FnOnce(Option<SharedHasher>) -> reqwest::Body + Send>;
+
+/// Body of a streamed upload, shared between clones of a request and taken by the first
+/// to be sent
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub(crate) struct StreamBody(std::sync::Arc<std::sync::Mutex<Option<MakeStreamBody>>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StreamBody {
+    /// Takes the body to send it, failing if it was already sent
+    pub(crate) fn take(&self, hasher: Option<SharedHasher>) -> Result<reqwest::Body, Error> {
+        let make = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .ok_or(Error::NonReplayableBody)?;
+
+        Ok(make(hasher))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for StreamBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StreamBody")
+    }
+}
+
+/// Where the content of a [`WaifuUploadRequest`] comes from, from
+/// [`WaifuUploadRequest::source_kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum UploadSourceKind {
+    /// A file on disk, set with [`WaifuUploadRequest::file`]
+    File,
+
+    /// A URL the service fetches, set with [`WaifuUploadRequest::url`]
+    Url,
+
+    /// Bytes held in memory, set with [`WaifuUploadRequest::bytes`]
+    Bytes,
+
+    /// A stream, set with `WaifuUploadRequest::body_stream`
+    Stream,
+}
+
+/// State of a [`WaifuUploadRequest`] that has no content to upload yet
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoSource;
+
+/// State of a [`WaifuUploadRequest`] with a file, url or bytes to upload
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WithSource;
+
+/// Upload request to upload content to the Waifu Vault
+///
+/// A new request is a `WaifuUploadRequest<NoSource>`, and only becomes a
+/// `WaifuUploadRequest<WithSource>`, which is what uploads take, once
+/// [`file`](WaifuUploadRequest::file), [`url`](WaifuUploadRequest::url) or
+/// [`bytes`](WaifuUploadRequest::bytes) is set. Forgetting the content is then a compile
+/// error rather than a failed upload.
+///
+/// ```rust,compile_fail
+/// # async fn upload(caller: waifuvault::ApiCaller) -> anyhow::Result<()> {
+/// use waifuvault::api::WaifuUploadRequest;
+///
+/// caller.upload_file(WaifuUploadRequest::new().expires("1h")).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// When the content is only decided at runtime, [`WaifuUploadRequest::unchecked`] skips the
+/// check and a request without content fails with [`Error::NoUploadSource`] instead.
+///
+/// Its `Debug` output shows the password as `"<redacted>"`, so requests can be logged.
+#[derive(Clone)]
+pub struct WaifuUploadRequest<S = WithSource> {
+    /// Content to upload, only one source can be set at a time
+    pub(crate) source: Option<UploadSource>,
+
+    /// Token of the bucket to upload to
+    pub(crate) bucket: Option<BucketToken>,
+
+    /// MIME type sent with a file or raw bytes, instead of leaving the service to guess
+    pub(crate) content_type: Option<String>,
+
+    /// Set an expiry for the content
+    /// This is a string containing a number and a letter (m for mins, h for hours, d for days)
+    /// Leave blank to keep the file for as long as the retention policy allows
+    pub(crate) expires: Option<String>,
+
+    /// Hide the filename from the generated URL
+    /// Left unset, the service's default applies
+    pub(crate) hide_filename: Option<bool>,
+
+    /// Set a password for the file
+    /// This encrypts the file on the server which can only be accessed by
+    /// retrieving it with the x-password header set
+    pub(crate) password: Option<String>,
+
+    /// Delete the file after first access
+    /// Left unset, the service's default applies
+    pub(crate) one_time_download: Option<bool>,
+
+    /// Replace parts of a filename that aren't valid UTF-8 rather than failing the upload
+    pub(crate) lossy_filename: bool,
+
+    /// Longest the upload may take before it is abandoned
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) timeout: Option<Duration>,
+
+    /// Algorithm to hash the content with as it is sent
+    pub(crate) checksum: Option<HashAlgorithm>,
+
+    state: std::marker::PhantomData<S>,
+}
+
+/// Stands in for a password in `Debug` output
+fn redacted(password: &Option<String>) -> Option<&'static str> {
+    password.as_ref().map(|_| "<redacted>")
+}
+
+impl<S> std::fmt::Debug for WaifuUploadRequest<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("WaifuUploadRequest");
+        s.field("source", &self.source)
+            .field("bucket", &self.bucket)
+            .field("content_type", &self.content_type)
+            .field("expires", &self.expires)
+            .field("hide_filename", &self.hide_filename)
+            .field("password", &redacted(&self.password))
+            .field("one_time_download", &self.one_time_download)
+            .field("lossy_filename", &self.lossy_filename);
+        #[cfg(not(target_arch = "wasm32"))]
+        s.field("timeout", &self.timeout);
+        s.field("checksum", &self.checksum).finish()
+    }
+}
+
+impl Default for WaifuUploadRequest<NoSource> {
+    fn default() -> Self {
+        Self {
+            source: None,
+            bucket: None,
+            content_type: None,
+            expires: None,
+            hide_filename: None,
+            password: None,
+            one_time_download: None,
+            lossy_filename: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            timeout: None,
+            checksum: None,
+            state: std::marker::PhantomData,
+        }
+    }
+}
+
+impl WaifuUploadRequest<NoSource> {
+    /// Create a new upload request
+    pub fn new() -> Self {
         Self::default()
     }
 
-    /// Sets the file field on the request
-    pub fn file(mut self, file: impl AsRef<Path>) -> Self {
-        let file = file.as_ref().display().to_string();
-        self.file = Some(file);
-        self
+    /// Lets the request be uploaded without knowing at compile time that it has content
+    ///
+    /// Uploading it without setting a file, url or bytes fails with
+    /// [`Error::NoUploadSource`] before anything is sent.
+    pub fn unchecked(self) -> WaifuUploadRequest<WithSource> {
+        self.into_state()
     }
+}
 
-    /// Sets the url field on the request
-    pub fn url(mut self, url: impl AsRef<str>) -> Self {
-        self.url = Some(url.as_ref().to_string());
-        self
+impl<S> WaifuUploadRequest<S> {
+    /// Uploads a file from disk
+    ///
+    /// The file is read as it is sent rather than loaded into memory first, so large files
+    /// can be uploaded. Replaces any url or bytes set before, as a request has only one
+    /// source. Not available on `wasm32`, where there is no filesystem to read from.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn file(self, file: impl AsRef<std::path::Path>) -> WaifuUploadRequest<WithSource> {
+        self.source(UploadSource::File(file.as_ref().to_path_buf()))
     }
 
-    /// Sets the bytes field on the request
-    pub fn bytes(mut self, bytes: Vec<u8>, filename: impl AsRef<str>) -> Self {
-        self.bytes = Some(bytes);
-        self.filename = Some(filename.as_ref().to_string());
-        self
+    /// Uploads a resource the service fetches from a URL
+    ///
+    /// Replaces any file or bytes set before, as a request has only one source. Anything but
+    /// an absolute http or https URL fails the upload with [`Error::InvalidUrl`] before it
+    /// is sent.
+    pub fn url(self, url: impl AsRef<str>) -> WaifuUploadRequest<WithSource> {
+        self.source(UploadSource::Url(url.as_ref().to_string()))
+    }
+
+    /// Uploads raw bytes under the given filename
+    ///
+    /// Takes anything convertible to [`bytes::Bytes`], such as a `Vec<u8>`. A `Bytes` is
+    /// uploaded without being copied, even if the request is cloned or retried. Replaces
+    /// any file or url set before, as a request has only one source.
+    pub fn bytes(
+        self,
+        bytes: impl Into<bytes::Bytes>,
+        filename: impl AsRef<str>,
+    ) -> WaifuUploadRequest<WithSource> {
+        self.source(UploadSource::Bytes {
+            data: bytes.into(),
+            filename: filename.as_ref().to_string(),
+        })
+    }
+
+    /// Uploads a stream of bytes under the given filename without buffering it
+    ///
+    /// Give `content_length` when it is known, otherwise the upload is sent chunked.
+    ///
+    /// A stream can only be read once, so it can't be retried. Uploading it with a caller
+    /// whose [`crate::RetryPolicy`] sets `retry_uploads`, or uploading a clone of the
+    /// request a second time, fails with [`Error::NonReplayableBody`]. Replaces any file,
+    /// url or bytes set before, as a request has only one source. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let source = reqwest::get("https://example.com/large.bin").await?;
+    ///     let length = source.content_length();
+    ///
+    ///     let request =
+    ///         WaifuUploadRequest::new().body_stream(source.bytes_stream(), "large.bin", length);
+    ///     caller.upload_file(request).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn body_stream<St>(
+        self,
+        stream: St,
+        filename: impl AsRef<str>,
+        content_length: Option<u64>,
+    ) -> WaifuUploadRequest<WithSource>
+    where
+        St: futures_core::TryStream + Send + 'static,
+        St::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        bytes::Bytes: From<St::Ok>,
+    {
+        let make: MakeStreamBody = Box::new(move |hasher| match hasher {
+            Some(hasher) => stream_body(Hashed::new(stream, hasher)),
+            None => reqwest::Body::wrap_stream(stream),
+        });
+        self.source(UploadSource::Stream {
+            body: StreamBody(std::sync::Arc::new(std::sync::Mutex::new(Some(make)))),
+            filename: filename.as_ref().to_string(),
+            length: content_length,
+        })
     }
 
     /// Sets the bucket token on the request
-    pub fn bucket(mut self, token: impl AsRef<str>) -> Self {
-        self.bucket = Some(token.as_ref().to_string());
+    pub fn bucket(mut self, token: impl Into<BucketToken>) -> Self {
+        self.bucket = Some(token.into());
         self
     }
 
     /// Sets the expires field on the request
+    ///
+    /// This is a number followed by `m`, `h` or `d`, such as `30m` or `7d`. Anything else
+    /// fails the upload with [`Error::InvalidExpiry`] before it is sent.
     pub fn expires(mut self, expires: impl AsRef<str>) -> Self {
         self.expires = Some(expires.as_ref().to_string());
         self
     }
 
+    /// Sets the expires field on the request from a duration
+    ///
+    /// The service counts in whole minutes, so the duration is rounded up to the next
+    /// minute, with anything shorter becoming one minute. It is sent in the largest unit
+    /// that holds it exactly, e.g. 36 hours as `36h` and 30 days as `30d`.
+    pub fn expires_in(self, expires: Duration) -> Self {
+        self.expires(expiry_from_duration(expires))
+    }
+
+    /// Sets the expires field on the request from a human readable duration
+    ///
+    /// Accepts anything [`humantime`] understands, such as `90m` or `2 days`, rounded the
+    /// same way as [`WaifuUploadRequest::expires_in`]. Available with the `humantime` feature.
+    #[cfg(feature = "humantime")]
+    pub fn expires_human(self, expires: &str) -> anyhow::Result<Self> {
+        Ok(self.expires_in(parse_human_expiry(expires)?))
+    }
+
+    /// Sets the MIME type of the uploaded file or bytes, such as `image/png`
+    ///
+    /// Content uploaded from a URL is fetched by the service, so this has no effect on it.
+    /// A value that isn't a valid MIME type fails the upload before it is sent.
+    pub fn content_type(mut self, mime: impl AsRef<str>) -> Self {
+        self.content_type = Some(mime.as_ref().to_string());
+        self
+    }
+
     /// Sets the hide_filename field on the request
     pub fn hide_filename(mut self, hide: bool) -> Self {
-        self.hide_filename = hide;
+        self.hide_filename = Some(hide);
         self
     }
 
@@ -246,16 +1754,202 @@ impl WaifuUploadRequest {
 
     /// Sets the one_time_download field on the request
     pub fn one_time_download(mut self, otd: bool) -> Self {
-        self.one_time_download = otd;
+        self.one_time_download = Some(otd);
+        self
+    }
+
+    /// Uploads files whose names aren't valid UTF-8 under a sanitized name
+    ///
+    /// The invalid parts of the name are swapped for the Unicode replacement character.
+    /// Without this, uploading such a file fails before anything is sent.
+    pub fn lossy_filename(mut self, lossy: bool) -> Self {
+        self.lossy_filename = lossy;
+        self
+    }
+
+    /// Abandons the upload if it takes longer than `timeout`, from connecting until the
+    /// response has been read
+    ///
+    /// An upload that runs out of time fails with [`Error::TimedOut`]. Not available on
+    /// wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Hashes the content with `algorithm` as it is sent
+    ///
+    /// The digest is returned by [`crate::ApiCaller::upload_file_with_outcome`], computed
+    /// from the file, bytes or stream as it is read for sending rather than in a pass of its
+    /// own. Content uploaded from a URL is fetched by the service, so it isn't hashed.
+    pub fn compute_checksum(mut self, algorithm: HashAlgorithm) -> Self {
+        self.checksum = Some(algorithm);
         self
     }
+
+    /// Checks the content against the service's restrictions without uploading it
+    ///
+    /// Fails with [`crate::Error::TooLarge`] or [`crate::Error::BannedMimeType`] when the
+    /// upload would be refused. The MIME type is the one set with
+    /// [`WaifuUploadRequest::content_type`], or else guessed from the filename. Content
+    /// uploaded from a URL is only known to the service, so it is not checked, and the size
+    /// of a stream is only checked when its length was given.
+    pub fn validate_against(&self, restrictions: &WaifuRestrictions) -> anyhow::Result<()> {
+        let (size, name) = match &self.source {
+            Some(UploadSource::File(file)) => {
+                (Some(local_file_metadata(file)?.len()), file.as_path())
+            }
+            Some(UploadSource::Bytes { data, filename }) => (
+                Some(data.len() as u64),
+                std::path::Path::new(filename.as_str()),
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(UploadSource::Stream {
+                filename, length, ..
+            }) => (*length, std::path::Path::new(filename.as_str())),
+            Some(UploadSource::Url(_)) | None => return Ok(()),
+        };
+
+        if let (Some(size), Some(max)) = (size, restrictions.max_file_size()) {
+            if size > max {
+                return Err(Error::TooLarge { size, max }.into());
+            }
+        }
+
+        let banned = restrictions.banned_mime_types();
+        let mimes: Vec<&str> = match &self.content_type {
+            Some(mime) => vec![mime.as_str()],
+            None => mime_guess::from_path(name).iter_raw().collect(),
+        };
+        if let Some(mime) = mimes
+            .into_iter()
+            .find(|mime| banned.iter().any(|b| b.eq_ignore_ascii_case(mime)))
+        {
+            return Err(Error::BannedMimeType {
+                mime: mime.to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> WaifuUploadRequest<S> {
+    fn source(mut self, source: UploadSource) -> WaifuUploadRequest<WithSource> {
+        self.source = Some(source);
+        self.into_state()
+    }
+
+    fn into_state<T>(self) -> WaifuUploadRequest<T> {
+        WaifuUploadRequest {
+            source: self.source,
+            bucket: self.bucket,
+            content_type: self.content_type,
+            expires: self.expires,
+            hide_filename: self.hide_filename,
+            password: self.password,
+            one_time_download: self.one_time_download,
+            lossy_filename: self.lossy_filename,
+            #[cfg(not(target_arch = "wasm32"))]
+            timeout: self.timeout,
+            checksum: self.checksum,
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Token of the bucket the request uploads to, if any
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use waifuvault::api::{UploadSourceKind, WaifuUploadRequest};
+    ///
+    /// let request = WaifuUploadRequest::new()
+    ///     .bytes(b"hello".to_vec(), "hello.txt")
+    ///     .bucket("some-bucket")
+    ///     .expires("1h")
+    ///     .password("secret");
+    ///
+    /// assert_eq!(request.bucket_token().unwrap(), "some-bucket");
+    /// assert_eq!(request.expiry(), Some("1h"));
+    /// assert_eq!(request.source_kind(), Some(UploadSourceKind::Bytes));
+    /// assert_eq!(request.filename().as_deref(), Some("hello.txt"));
+    /// assert!(request.has_password());
+    /// ```
+    pub fn bucket_token(&self) -> Option<&BucketToken> {
+        self.bucket.as_ref()
+    }
+
+    /// Expiry the request sets, such as `"1h"`, or `None` to keep the file for as long as
+    /// the retention policy allows
+    pub fn expiry(&self) -> Option<&str> {
+        self.expires.as_deref()
+    }
+
+    /// Where the content comes from, or `None` if no content is set yet
+    pub fn source_kind(&self) -> Option<UploadSourceKind> {
+        Some(match self.source.as_ref()? {
+            UploadSource::File(_) => UploadSourceKind::File,
+            UploadSource::Url(_) => UploadSourceKind::Url,
+            UploadSource::Bytes { .. } => UploadSourceKind::Bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            UploadSource::Stream { .. } => UploadSourceKind::Stream,
+        })
+    }
+
+    /// Name the content is uploaded under
+    ///
+    /// `None` for uploads from a URL, where the service picks the name, and for files whose
+    /// name can't be uploaded, such as one that isn't valid UTF-8 without
+    /// [`WaifuUploadRequest::lossy_filename`].
+    pub fn filename(&self) -> Option<String> {
+        match self.source.as_ref()? {
+            UploadSource::File(path) => self.upload_filename(path).ok(),
+            UploadSource::Url(_) => None,
+            UploadSource::Bytes { filename, .. } => Some(filename.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            UploadSource::Stream { filename, .. } => Some(filename.clone()),
+        }
+    }
+
+    /// Size of the content, if it is known without reading it
+    ///
+    /// Files are measured on disk, and streams have a size only if one was given. Uploads
+    /// from a URL have none. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn content_length(&self) -> Option<u64> {
+        self.source.as_ref()?.content_length()
+    }
+
+    /// If the request sets a password on the upload
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// Name the file at `path` is uploaded under
+    pub(crate) fn upload_filename(&self, path: &std::path::Path) -> anyhow::Result<String> {
+        let name = path
+            .file_name()
+            .with_context(|| format!("{} has no filename to upload under", path.display()))?;
+
+        match name.to_str() {
+            Some(name) => Ok(name.to_string()),
+            None if self.lossy_filename => Ok(name.to_string_lossy().into_owned()),
+            None => anyhow::bail!(
+                "filename of {} is not valid UTF-8, use lossy_filename to upload it anyway",
+                path.display()
+            ),
+        }
+    }
 }
 
 /// Request to be sent when requesting file information from the API
 #[derive(Debug, Default, Clone)]
 pub struct WaifuGetRequest {
     /// Token used to access the content
-    pub(crate) token: String,
+    pub(crate) token: FileToken,
 
     /// Flag to display the expiry time in human-readable format
     pub(crate) formatted: bool,
@@ -263,9 +1957,86 @@ pub struct WaifuGetRequest {
 
 impl WaifuGetRequest {
     /// Create a new Get Request
-    pub fn new(token: impl AsRef<str>) -> Self {
+    pub fn new(token: impl Into<FileToken>) -> Self {
+        Self {
+            token: token.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a Get Request from a public file URL such as `https://waifuvault.moe/f/{id}/{file}`
+    ///
+    /// The `{id}` portion of the URL is used to look the file up. URLs of any other shape
+    /// fail with [`Error::InvalidFileUrl`].
+    pub fn from_url(url: &str) -> anyhow::Result<Self> {
+        let invalid = || Error::InvalidFileUrl {
+            url: url.to_string(),
+        };
+
+        let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid().into());
+        }
+
+        let segments: Vec<&str> = parsed
+            .path_segments()
+            .ok_or_else(invalid)?
+            .filter(|s| !s.is_empty())
+            .collect();
+        match segments.as_slice() {
+            ["f", id] | ["f", id, _] => Ok(Self::new(*id)),
+            _ => Err(invalid().into()),
+        }
+    }
+
+    /// Set the formatted field on the request
+    pub fn formatted(mut self, format: bool) -> Self {
+        self.formatted = format;
+        self
+    }
+
+    /// Token of the file the request looks up
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use waifuvault::api::WaifuGetRequest;
+    ///
+    /// let request = WaifuGetRequest::from_url("https://waifuvault.moe/f/1712345/file.png")?
+    ///     .formatted(true);
+    ///
+    /// assert_eq!(request.token(), "1712345");
+    /// assert!(request.is_formatted());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn token(&self) -> &FileToken {
+        &self.token
+    }
+
+    /// If the retention period is asked for in human-readable form
+    pub fn is_formatted(&self) -> bool {
+        self.formatted
+    }
+}
+
+/// Request to be sent when requesting the contents of a bucket from the API
+///
+/// Bucket tokens convert into a request directly, so [`crate::ApiCaller::get_bucket`] can
+/// be given a token when no options are needed.
+#[derive(Debug, Default, Clone)]
+pub struct WaifuBucketRequest {
+    /// Token of the bucket
+    pub(crate) token: BucketToken,
+
+    /// Flag to display the expiry time of files in human-readable format
+    pub(crate) formatted: bool,
+}
+
+impl WaifuBucketRequest {
+    /// Create a new Bucket Request
+    pub fn new(token: impl Into<BucketToken>) -> Self {
         Self {
-            token: token.as_ref().to_string(),
+            token: token.into(),
             ..Default::default()
         }
     }
@@ -277,13 +2048,45 @@ impl WaifuGetRequest {
     }
 }
 
+impl From<BucketToken> for WaifuBucketRequest {
+    fn from(token: BucketToken) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<&BucketToken> for WaifuBucketRequest {
+    fn from(token: &BucketToken) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<String> for WaifuBucketRequest {
+    fn from(token: String) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<&String> for WaifuBucketRequest {
+    fn from(token: &String) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<&str> for WaifuBucketRequest {
+    fn from(token: &str) -> Self {
+        Self::new(token)
+    }
+}
+
 /// Modification request to be sent when updating options on
 /// the target resource stored in the vault
-#[derive(Debug, Default, Clone, Serialize)]
+///
+/// Its `Debug` output shows both passwords as `"<redacted>"`, so requests can be logged.
+#[derive(Default, Clone, Serialize)]
 pub struct WaifuModificationRequest {
     /// Token used to access the content
     #[serde(skip)]
-    pub(crate) token: String,
+    pub(crate) token: FileToken,
 
     /// Sets a password for the content
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -306,11 +2109,23 @@ pub struct WaifuModificationRequest {
     pub(crate) hide_filename: Option<bool>,
 }
 
+impl std::fmt::Debug for WaifuModificationRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WaifuModificationRequest")
+            .field("token", &self.token)
+            .field("password", &redacted(&self.password))
+            .field("previous_password", &redacted(&self.previous_password))
+            .field("custom_expiry", &self.custom_expiry)
+            .field("hide_filename", &self.hide_filename)
+            .finish()
+    }
+}
+
 impl WaifuModificationRequest {
     /// Create a new Modification request
-    pub fn new(token: impl AsRef<str>) -> Self {
+    pub fn new(token: impl Into<FileToken>) -> Self {
         Self {
-            token: token.as_ref().to_string(),
+            token: token.into(),
             ..Default::default()
         }
     }
@@ -327,15 +2142,1045 @@ impl WaifuModificationRequest {
         self
     }
 
+    /// Removes the password from a protected file, so it can be downloaded without one
+    ///
+    /// Sends an empty password along with the current one, which the service takes as
+    /// removing protection. Replaces any password set before on the request.
+    pub fn remove_password(self, previous_password: impl AsRef<str>) -> Self {
+        self.password("").previous_password(previous_password)
+    }
+
     /// Set the custom_expiry field on the request
+    ///
+    /// Checked the same way as [`WaifuUploadRequest::expires`]
     pub fn custom_expiry(mut self, expiry: impl AsRef<str>) -> Self {
         self.custom_expiry = Some(expiry.as_ref().to_string());
         self
     }
 
+    /// Removes a custom expiry set before, so the file is kept for as long as the retention
+    /// policy allows again
+    ///
+    /// Sends an empty expiry, which the service takes as removing the custom one. Replaces
+    /// any expiry set before on the request.
+    pub fn clear_custom_expiry(mut self) -> Self {
+        self.custom_expiry = Some(String::new());
+        self
+    }
+
+    /// Set the custom_expiry field on the request from a duration
+    ///
+    /// Rounded the same way as [`WaifuUploadRequest::expires_in`]
+    pub fn custom_expiry_in(self, expiry: Duration) -> Self {
+        self.custom_expiry(expiry_from_duration(expiry))
+    }
+
+    /// Set the custom_expiry field on the request from a human readable duration
+    ///
+    /// Parsed the same way as [`WaifuUploadRequest::expires_human`]. Available with the
+    /// `humantime` feature.
+    #[cfg(feature = "humantime")]
+    pub fn custom_expiry_human(self, expiry: &str) -> anyhow::Result<Self> {
+        Ok(self.custom_expiry_in(parse_human_expiry(expiry)?))
+    }
+
     /// Set the hide_filename field on the request
     pub fn hide_filename(mut self, hide: bool) -> Self {
         self.hide_filename = Some(hide);
         self
     }
+
+    /// Token of the file the request changes
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use waifuvault::api::WaifuModificationRequest;
+    ///
+    /// let request = WaifuModificationRequest::new("some-file")
+    ///     .remove_password("old-password")
+    ///     .custom_expiry("1d");
+    ///
+    /// assert_eq!(request.token(), "some-file");
+    /// assert!(request.has_password() && request.removes_password());
+    /// assert!(request.has_previous_password());
+    /// assert_eq!(request.expiry(), Some("1d"));
+    /// assert_eq!(request.hides_filename(), None);
+    /// ```
+    pub fn token(&self) -> &FileToken {
+        &self.token
+    }
+
+    /// If the request sets a new password, or removes the current one
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    /// If the request removes the password, as set with
+    /// [`WaifuModificationRequest::remove_password`]
+    pub fn removes_password(&self) -> bool {
+        self.password.as_deref() == Some("")
+    }
+
+    /// If the request gives the current password of the file
+    pub fn has_previous_password(&self) -> bool {
+        self.previous_password.is_some()
+    }
+
+    /// Expiry the request sets, if any
+    ///
+    /// An empty expiry removes a custom one, as set with
+    /// [`WaifuModificationRequest::clear_custom_expiry`].
+    pub fn expiry(&self) -> Option<&str> {
+        self.custom_expiry.as_deref()
+    }
+
+    /// If the request hides or shows the filename, or `None` if it leaves it as it is
+    pub fn hides_filename(&self) -> Option<bool> {
+        self.hide_filename
+    }
+}
+
+/// Options for downloading a file with [`crate::ApiCaller::download_file_with`]
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use waifuvault::api::DownloadOptions;
+///
+/// let options = DownloadOptions::new()
+///     .password("some-password")
+///     .timeout(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct DownloadOptions {
+    /// Password of a protected file
+    pub(crate) password: Option<String>,
+
+    /// Longest the download may take before it is abandoned
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) timeout: Option<Duration>,
+
+    /// Where to resume a download to disk from
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) resume: Option<Resume>,
+
+    /// Largest content the download may hold in memory
+    pub(crate) max_bytes: Option<u64>,
+
+    /// Digest the content must have
+    pub(crate) checksum: Option<crate::Checksum>,
+
+    /// Ask for the content as stored, without compression
+    pub(crate) identity_encoding: bool,
+}
+
+/// How [`crate::ApiCaller::download_file_parallel`] splits up a download
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::api::DownloadTuning;
+///
+/// let tuning = DownloadTuning {
+///     chunk_size: 32 * 1024 * 1024,
+///     connections: 8,
+/// };
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadTuning {
+    /// Size of each range of the file requested, in bytes
+    pub chunk_size: u64,
+
+    /// Most ranges downloaded at once
+    pub connections: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for DownloadTuning {
+    fn default() -> Self {
+        Self {
+            chunk_size: 8 * 1024 * 1024,
+            connections: 4,
+        }
+    }
+}
+
+/// Where [`crate::ApiCaller::download_file_to`] resumes from
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Resume {
+    /// The end of the partial file already on disk
+    Existing,
+    /// A given number of bytes into the file
+    From(u64),
+}
+
+impl DownloadOptions {
+    /// Create download options with nothing set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the password used to download a protected file
+    ///
+    /// Passwords with characters outside Latin-1, such as emoji, can't be sent and fail the
+    /// download with [`Error::UnencodablePassword`].
+    pub fn password(mut self, password: impl AsRef<str>) -> Self {
+        self.password = Some(password.as_ref().to_string());
+        self
+    }
+
+    /// Abandons the download if it takes longer than `timeout`, from connecting until the
+    /// whole file has been read
+    ///
+    /// A download that runs out of time fails with [`Error::TimedOut`]. Not available on
+    /// wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Continues a partial file left by an earlier download to disk rather than starting over
+    ///
+    /// Only used by [`crate::ApiCaller::download_file_to`], which then asks for the rest of
+    /// the file after what is already on disk. Without this, an existing file is overwritten.
+    /// Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resume(mut self) -> Self {
+        self.resume = Some(Resume::Existing);
+        self
+    }
+
+    /// Continues a download to disk from `offset` bytes into the file
+    ///
+    /// Like [`DownloadOptions::resume`], but anything on disk past `offset` is discarded
+    /// first, such as a tail that may not have been written completely. Not available on
+    /// wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resume_from(mut self, offset: u64) -> Self {
+        self.resume = Some(Resume::From(offset));
+        self
+    }
+
+    /// Fails the download with [`Error::ResponseTooLarge`] if the file is larger than
+    /// `max_bytes`, rather than reading it all into memory
+    ///
+    /// Takes the place of [`crate::ApiCallerBuilder::max_download_bytes`] for this download.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Fails the download with [`Error::ChecksumMismatch`] unless the content has the
+    /// digest `checksum`
+    ///
+    /// The content is hashed as it arrives. A download to disk with
+    /// [`crate::ApiCaller::download_file_to`] hashes the part already on disk when resuming,
+    /// and leaves the file in place if it doesn't match.
+    pub fn checksum(mut self, checksum: crate::Checksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// Asks for the content as it is stored, with `Accept-Encoding: identity`
+    ///
+    /// Keeps files that are already compressed, such as `.gz` artifacts, from being
+    /// decompressed on the way so they can be compared byte for byte. A server that
+    /// compresses the response anyway is still decompressed when the `gzip`, `brotli` or
+    /// `deflate` features are on, unless the caller was built with
+    /// `ApiCallerBuilder::decompress(false)`.
+    pub fn identity_encoding(mut self) -> Self {
+        self.identity_encoding = true;
+        self
+    }
+}
+
+/// Formats a duration as an expiry the service accepts, such as `90m`, `36h` or `30d`
+fn expiry_from_duration(duration: Duration) -> String {
+    let minutes = duration.as_millis().div_ceil(60_000).max(1);
+    if minutes.is_multiple_of(60 * 24) {
+        format!("{}d", minutes / (60 * 24))
+    } else if minutes.is_multiple_of(60) {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+/// Opens the file at `path` to upload it, along with its length
+pub(crate) fn open_local_file(path: &std::path::Path) -> anyhow::Result<(std::fs::File, u64)> {
+    let length = local_file_metadata(path)?.len();
+    let file =
+        std::fs::File::open(path).with_context(|| format!("opening file {}", path.display()))?;
+
+    Ok((file, length))
+}
+
+/// Metadata of a file about to be uploaded, failing if it is missing or not a regular file
+fn local_file_metadata(path: &std::path::Path) -> anyhow::Result<std::fs::Metadata> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("file {} does not exist", path.display())
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading metadata of {}", path.display())),
+    };
+
+    if !metadata.is_file() {
+        anyhow::bail!("{} is not a regular file", path.display());
+    }
+
+    Ok(metadata)
+}
+
+/// Checks an expiry is a whole number of minutes, hours or days, such as `90m` or `7d`
+pub(crate) fn validate_expiry(expiry: &str) -> Result<(), Error> {
+    let valid = match expiry.strip_suffix(['m', 'h', 'd']) {
+        Some(number) => !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidExpiry {
+            expiry: expiry.to_string(),
+        })
+    }
+}
+
+/// Checks a URL to upload from is an absolute http or https URL
+pub(crate) fn validate_upload_url(url: &str) -> Result<(), Error> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        _ => Err(Error::InvalidUrl {
+            url: url.to_string(),
+        }),
+    }
+}
+
+/// Filename given by a `Content-Disposition` header value, as described by RFC 6266
+///
+/// An RFC 5987 `filename*` parameter in UTF-8 or ISO-8859-1 is preferred over `filename`.
+/// Only the last component of a name containing directories is kept.
+pub(crate) fn content_disposition_filename(value: &str) -> Option<String> {
+    let mut filename = None;
+    let mut extended = None;
+    let mut rest = value.split_once(';')?.1;
+
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let value;
+        (value, rest) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (i, '"') => break i + 1,
+                        (_, '\\') => value.push(chars.next()?.1),
+                        (_, c) => value.push(c),
+                    }
+                };
+                let rest = quoted[end..].trim_start();
+                (value, rest.strip_prefix(';').unwrap_or(rest))
+            }
+            None => {
+                let (value, rest) = after.split_once(';').unwrap_or((after, ""));
+                (value.trim().to_string(), rest)
+            }
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename" => filename = Some(value),
+            "filename*" => extended = decode_ext_value(&value).or(extended),
+            _ => {}
+        }
+    }
+
+    extended
+        .or(filename)
+        .and_then(|name| name.rsplit(['/', '\\']).next().map(str::to_string))
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+}
+
+/// Filename at the end of a file URL
+///
+/// Such as `picture.png` in `https://waifuvault.moe/f/1712345/picture.png`.
+pub(crate) fn url_filename(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    let last = parsed.path_segments()?.rfind(|s| !s.is_empty())?;
+    let name = percent_encoding::percent_decode_str(last)
+        .decode_utf8()
+        .ok()?
+        .into_owned();
+
+    Some(name).filter(|name| name != "." && name != "..")
+}
+
+/// Decodes an RFC 5987 extended value, such as `UTF-8''na%C3%AFve.txt`
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(parts.next()?).collect();
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "humantime")]
+fn parse_human_expiry(expiry: &str) -> anyhow::Result<Duration> {
+    humantime::parse_duration(expiry.trim()).with_context(|| {
+        format!("invalid expiry {expiry:?}, expected a duration such as 90m or 2 days")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(retention_period: serde_json::Value) -> WaifuFileEntry {
+        serde_json::from_value(serde_json::json!({
+            "token": "some-file",
+            "url": "http://127.0.0.1:8081/f/some-file.txt",
+            "views": 0,
+            "retentionPeriod": retention_period,
+        }))
+        .expect("valid entry")
+    }
+
+    /// Deserializes `json` as `T` and checks it serializes back to the same JSON
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(json: serde_json::Value) {
+        let value: T = serde_json::from_value(json.clone()).expect("deserializes");
+        assert_eq!(serde_json::to_value(&value).expect("serializes"), json);
+    }
+
+    fn options_json() -> serde_json::Value {
+        serde_json::json!({ "hideFilename": true, "oneTimeDownload": false, "protected": true })
+    }
+
+    fn metadata_json() -> serde_json::Value {
+        serde_json::json!({
+            "token": "album", "publicToken": "public", "name": "holiday",
+            "bucket": "bucket", "dateCreated": 1712345678000u64
+        })
+    }
+
+    fn file_json() -> serde_json::Value {
+        serde_json::json!({
+            "id": 7,
+            "token": "some-file",
+            "url": "http://127.0.0.1:8081/f/some-file.txt",
+            "bucket": "bucket",
+            "album": metadata_json(),
+            "views": 3,
+            "retentionPeriod": "1 hour",
+            "options": options_json()
+        })
+    }
+
+    #[test]
+    fn file_entry_round_trips() {
+        round_trip::<WaifuFileEntry>(file_json());
+        round_trip::<WaifuFileOptions>(options_json());
+    }
+
+    #[test]
+    fn cosmetic_fields_may_be_missing() {
+        let mut json = file_json();
+        let file = json.as_object_mut().unwrap();
+        file.remove("views");
+        file.remove("retentionPeriod");
+        file.insert(
+            "options".into(),
+            serde_json::json!({ "oneTimeDownload": true }),
+        );
+
+        let entry: WaifuFileEntry = serde_json::from_value(json).expect("deserializes");
+        assert_eq!(entry.views, 0);
+        assert_eq!(entry.retention_period, serde_json::Value::Null);
+        assert_eq!(entry.expires_at(), None);
+        let options = entry.options.unwrap();
+        assert!(options.one_time_download);
+        assert!(!options.hide_filename && !options.protected);
+
+        // Whether a file is deleted once downloaded isn't guessed at
+        let unknown = serde_json::json!({ "hideFilename": false, "protected": false });
+        assert!(serde_json::from_value::<WaifuFileOptions>(unknown).is_err());
+    }
+
+    #[test]
+    fn bucket_entry_round_trips() {
+        round_trip::<WaifuBucketEntry>(serde_json::json!({
+            "token": "bucket", "files": [file_json()], "albums": [metadata_json()]
+        }));
+    }
+
+    #[test]
+    fn album_entries_round_trip() {
+        round_trip::<WaifuAlbumEntry>(serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [file_json()]
+        }));
+        round_trip::<WaifuAlbumEntry>(serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": "public",
+            "name": "holiday", "files": [], "dateCreated": 1712345678000u64
+        }));
+        round_trip::<WaifuAlbumMetadata>(metadata_json());
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip::<WaifuGenericMessage>(
+            serde_json::json!({ "success": true, "description": "album deleted" }),
+        );
+        round_trip::<WaifuError>(
+            serde_json::json!({ "name": "BAD_REQUEST", "message": "Unknown token", "status": 400 }),
+        );
+        round_trip::<WaifuRestrictions>(serde_json::json!([
+            { "type": "MAX_FILE_SIZE", "value": 104857600 }
+        ]));
+    }
+
+    #[test]
+    fn expires_at_numeric_retention() {
+        let anchor = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let expected = anchor + Duration::from_millis(3_600_000);
+
+        let numeric = entry(serde_json::json!(3_600_000));
+        assert_eq!(numeric.expires_at_from(anchor), Some(expected));
+
+        let digits = entry(serde_json::json!("3600000"));
+        assert_eq!(digits.expires_at_from(anchor), Some(expected));
+
+        let from_now = numeric.expires_at().expect("numeric retention");
+        assert!(from_now > SystemTime::now() + Duration::from_secs(3590));
+    }
+
+    #[test]
+    fn expires_at_formatted_retention() {
+        let anchor = SystemTime::UNIX_EPOCH;
+        let formatted = entry(serde_json::json!("1 day, 2 hours and 3 minutes"));
+        assert_eq!(
+            formatted.expires_at_from(anchor),
+            Some(anchor + Duration::from_secs(24 * 3600 + 2 * 3600 + 3 * 60))
+        );
+
+        let single = entry(serde_json::json!("59 Seconds"));
+        assert_eq!(
+            single.expires_at_from(anchor),
+            Some(anchor + Duration::from_secs(59))
+        );
+
+        let unknown = entry(serde_json::json!("forever"));
+        assert_eq!(unknown.expires_at_from(anchor), None);
+    }
+
+    #[test]
+    fn expires_at_absent_retention() {
+        assert_eq!(entry(serde_json::Value::Null).expires_at(), None);
+        assert_eq!(entry(serde_json::json!(-5)).expires_at(), None);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn timestamps_convert_to_utc() {
+        let metadata: WaifuAlbumMetadata = serde_json::from_value(metadata_json()).unwrap();
+        assert_eq!(metadata.date_created, 1712345678000);
+        assert_eq!(
+            metadata.date_created_utc().unwrap().to_rfc3339(),
+            "2024-04-05T19:34:38+00:00"
+        );
+
+        let album: WaifuAlbumEntry = serde_json::from_value(serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [], "dateCreated": 1712345678000u64
+        }))
+        .unwrap();
+        assert_eq!(album.date_created_utc(), metadata.date_created_utc());
+
+        // The same moment in seconds reads the same
+        assert_eq!(epoch_to_utc(1712345678), metadata.date_created_utc());
+        assert_eq!(epoch_to_utc(0).unwrap().timestamp(), 0);
+        assert_eq!(epoch_to_utc(u64::MAX), None);
+
+        let expiry = entry(serde_json::json!(3_600_000))
+            .expires_at_utc()
+            .unwrap();
+        let hour = chrono::DateTime::<chrono::Utc>::from(SystemTime::now())
+            + chrono::Duration::minutes(59);
+        assert!(expiry > hour, "{expiry}");
+        assert_eq!(entry(serde_json::Value::Null).expires_at_utc(), None);
+    }
+
+    #[test]
+    fn get_request_from_file_url() {
+        let request =
+            WaifuGetRequest::from_url("https://waifuvault.moe/f/1712345/picture.png").unwrap();
+        assert_eq!(request.token, "1712345");
+
+        let request = WaifuGetRequest::from_url("http://127.0.0.1:8081/f/1712345").unwrap();
+        assert_eq!(request.token, "1712345");
+
+        for url in [
+            "not a url",
+            "ftp://waifuvault.moe/f/1712345/picture.png",
+            "https://waifuvault.moe/album/public-token",
+            "https://waifuvault.moe/f/",
+            "https://waifuvault.moe/f/1712345/picture.png/extra",
+        ] {
+            let err = WaifuGetRequest::from_url(url).expect_err(url);
+            assert_eq!(
+                err.downcast_ref::<Error>(),
+                Some(&Error::InvalidFileUrl {
+                    url: url.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn share_result_from_message() {
+        let message = |success: bool, description: &str| WaifuGenericMessage {
+            success,
+            description: description.to_string(),
+        };
+
+        let shared =
+            WaifuShareResult::try_from(message(true, "https://waifuvault.moe/album/abc-123"))
+                .unwrap();
+        assert_eq!(shared.url.as_str(), "https://waifuvault.moe/album/abc-123");
+        assert_eq!(shared.public_token, "abc-123");
+
+        let err = WaifuShareResult::try_from(message(true, "album shared")).unwrap_err();
+        assert!(err.to_string().contains("not a URL"));
+
+        let err = WaifuShareResult::try_from(message(true, "https://waifuvault.moe/")).unwrap_err();
+        assert!(err.to_string().contains("no public token"));
+
+        let err = WaifuShareResult::try_from(message(false, "album not found")).unwrap_err();
+        assert!(err.to_string().contains("album not found"));
+    }
+
+    #[test]
+    fn bucket_stats_totals_files_and_albums() {
+        let file = |views: usize, protected: bool, one_time: bool| {
+            serde_json::json!({
+                "token": "file", "url": "https://waifuvault.moe/f/1/file.txt", "bucket": "bucket",
+                "album": null, "views": views, "retentionPeriod": 1000,
+                "options": { "hideFilename": false, "oneTimeDownload": one_time, "protected": protected }
+            })
+        };
+        let bucket: WaifuBucketEntry = serde_json::from_value(serde_json::json!({
+            "token": "bucket",
+            "files": [file(3, true, false), file(4, true, true), file(0, false, false)],
+            "albums": [
+                { "token": "album", "publicToken": null, "name": "one", "bucket": "bucket", "dateCreated": 0 }
+            ]
+        }))
+        .unwrap();
+
+        let stats = bucket.stats();
+        assert_eq!(stats.file_count, 3);
+        assert_eq!(stats.album_count, 1);
+        assert_eq!(stats.total_views, 7);
+        assert_eq!(stats.protected_count, 2);
+        assert_eq!(stats.one_time_count, 1);
+        assert_eq!(stats.total_bytes, None);
+
+        let empty: WaifuBucketEntry =
+            serde_json::from_value(serde_json::json!({ "token": "bucket", "files": [] })).unwrap();
+        assert_eq!(empty.stats(), BucketStats::default());
+    }
+
+    #[test]
+    fn expiry_from_durations() {
+        let expiry = |secs: u64| expiry_from_duration(Duration::from_secs(secs));
+        assert_eq!(expiry(90), "2m");
+        assert_eq!(expiry(120), "2m");
+        assert_eq!(expiry(90 * 60), "90m");
+        assert_eq!(expiry(36 * 60 * 60), "36h");
+        assert_eq!(expiry(30 * 24 * 60 * 60), "30d");
+        assert_eq!(expiry(0), "1m");
+        assert_eq!(
+            expiry_from_duration(Duration::from_millis(60_001)),
+            "2m",
+            "partial seconds round up too"
+        );
+
+        let request = WaifuUploadRequest::new().expires_in(Duration::from_secs(36 * 60 * 60));
+        assert_eq!(request.expires.as_deref(), Some("36h"));
+        let request =
+            WaifuModificationRequest::new("token").custom_expiry_in(Duration::from_secs(90));
+        assert_eq!(request.custom_expiry.as_deref(), Some("2m"));
+    }
+
+    #[cfg(feature = "humantime")]
+    #[test]
+    fn human_expiries_are_reencoded() {
+        let expires = |input: &str| {
+            WaifuUploadRequest::new()
+                .expires_human(input)
+                .map(|request| request.expires.unwrap())
+        };
+        assert_eq!(expires("90m").unwrap(), "90m");
+        assert_eq!(expires("2 days").unwrap(), "2d");
+        assert_eq!(expires("1h 30m").unwrap(), "90m");
+        assert_eq!(expires("36hours").unwrap(), "36h");
+        assert_eq!(expires("30s").unwrap(), "1m");
+
+        for bad in ["", "soon", "5 fortnights", "-5m"] {
+            let err = expires(bad).unwrap_err();
+            assert!(err.to_string().contains("invalid expiry"), "{bad:?}: {err}");
+        }
+
+        let request = WaifuModificationRequest::new("token")
+            .custom_expiry_human("1 week")
+            .unwrap();
+        assert_eq!(request.custom_expiry.as_deref(), Some("7d"));
+    }
+
+    #[test]
+    fn expiries_are_validated() {
+        for valid in ["1m", "90m", "36h", "365d", "0010d"] {
+            assert_eq!(validate_expiry(valid), Ok(()), "{valid}");
+        }
+
+        for invalid in [
+            "", "m", "1", "1hr", "1H", "1 h", "h1", "-1h", "1.5h", "1w", " 1h",
+        ] {
+            assert_eq!(
+                validate_expiry(invalid),
+                Err(Error::InvalidExpiry {
+                    expiry: invalid.to_string()
+                }),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tokens_are_encoded_as_one_path_segment() {
+        let encoded = |token: &str| FileToken::new(token).path_segment();
+        assert_eq!(encoded("abc-123_x.txt"), Ok("abc-123_x.txt".to_string()));
+        assert_eq!(encoded("a b"), Ok("a%20b".to_string()));
+        assert_eq!(encoded("../bucket/x"), Ok("..%2Fbucket%2Fx".to_string()));
+        assert_eq!(encoded("a?b#c%d"), Ok("a%3Fb%23c%25d".to_string()));
+        assert_eq!(
+            encoded("https://waifuvault.moe/f/1/a.txt"),
+            Ok("https:%2F%2Fwaifuvault.moe%2Ff%2F1%2Fa.txt".to_string())
+        );
+
+        for invalid in ["", ".", ".."] {
+            assert_eq!(
+                AlbumToken::new(invalid).path_segment(),
+                Err(Error::InvalidToken {
+                    token: invalid.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn content_disposition_filenames() {
+        let filename = content_disposition_filename;
+        assert_eq!(
+            filename("attachment; filename=\"some file.txt\""),
+            Some("some file.txt".to_string())
+        );
+        assert_eq!(
+            filename("inline;filename=plain.txt"),
+            Some("plain.txt".to_string())
+        );
+        assert_eq!(
+            filename(r#"attachment; filename="quoted \"name\"; with.txt"; size=12"#),
+            Some(r#"quoted "name"; with.txt"#.to_string())
+        );
+
+        // The extended form is preferred, in whichever order they come
+        assert_eq!(
+            filename(
+                "attachment; filename*=UTF-8''na%C3%AFve%20caf%C3%A9.txt; filename=\"naive.txt\""
+            ),
+            Some("na\u{ef}ve caf\u{e9}.txt".to_string())
+        );
+        assert_eq!(
+            filename("attachment; FILENAME=\"naive.txt\"; Filename*=utf-8'en'na%C3%AFve.txt"),
+            Some("na\u{ef}ve.txt".to_string())
+        );
+        assert_eq!(
+            filename("attachment; filename*=iso-8859-1''caf%E9.txt"),
+            Some("caf\u{e9}.txt".to_string())
+        );
+
+        // Unknown charsets and bad encodings fall back to the plain form
+        assert_eq!(
+            filename("attachment; filename*=koi8-r''%C1.txt; filename=fallback.txt"),
+            Some("fallback.txt".to_string())
+        );
+        assert_eq!(
+            filename("attachment; filename*=UTF-8''%FF.txt; filename=fallback.txt"),
+            Some("fallback.txt".to_string())
+        );
+
+        // Directories are dropped
+        assert_eq!(
+            filename("attachment; filename=\"../../etc/passwd\""),
+            Some("passwd".to_string())
+        );
+        assert_eq!(
+            filename("attachment; filename=\"C:\\\\temp\\\\x.txt\""),
+            Some("x.txt".to_string())
+        );
+
+        assert_eq!(filename("attachment"), None);
+        assert_eq!(filename("inline; size=10"), None);
+        assert_eq!(filename("attachment; filename=\"..\""), None);
+        assert_eq!(filename("attachment; filename=\"unterminated"), None);
+    }
+
+    #[test]
+    fn upload_urls_are_validated() {
+        assert_eq!(validate_upload_url("https://example.com/image.png"), Ok(()));
+        assert_eq!(validate_upload_url("http://127.0.0.1:8080/a"), Ok(()));
+
+        for invalid in [
+            "",
+            "/images/image.png",
+            "image.png",
+            "example.com/image.png",
+            "ftp://example.com/image.png",
+            "not a url",
+        ] {
+            assert_eq!(
+                validate_upload_url(invalid),
+                Err(Error::InvalidUrl {
+                    url: invalid.to_string()
+                }),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_filenames() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        let path = Path::new("/tmp").join(OsStr::from_bytes(b"bad\xffname.txt"));
+        let err = WaifuUploadRequest::new()
+            .upload_filename(&path)
+            .unwrap_err();
+        assert!(err.to_string().contains("not valid UTF-8"), "{err}");
+
+        let name = WaifuUploadRequest::new()
+            .lossy_filename(true)
+            .upload_filename(&path)
+            .unwrap();
+        assert_eq!(name, "bad\u{FFFD}name.txt");
+
+        let err = WaifuUploadRequest::new()
+            .upload_filename(Path::new("/"))
+            .unwrap_err();
+        assert!(err.to_string().contains("no filename"), "{err}");
+    }
+
+    #[test]
+    fn upload_sources_replace_each_other() {
+        let request = WaifuUploadRequest::new()
+            .file("/some/file")
+            .url("https://example.com/image.png");
+        assert!(matches!(
+            request.source,
+            Some(UploadSource::Url(ref url)) if url == "https://example.com/image.png"
+        ));
+
+        let request = request.bytes(b"content".to_vec(), "file.txt");
+        assert!(matches!(
+            request.source,
+            Some(UploadSource::Bytes { ref filename, .. }) if filename == "file.txt"
+        ));
+
+        let request = request.file("/some/file");
+        assert!(matches!(request.source, Some(UploadSource::File(_))));
+        assert!(WaifuUploadRequest::new().source.is_none());
+    }
+
+    #[test]
+    fn upload_bytes_are_shared_not_copied() {
+        let data = bytes::Bytes::from(vec![0u8; 100 * 1024 * 1024]);
+        let request = WaifuUploadRequest::new().bytes(data.clone(), "large.bin");
+        let cloned = request.clone();
+
+        for request in [&request, &cloned] {
+            match &request.source {
+                Some(UploadSource::Bytes { data: held, .. }) => {
+                    assert_eq!(held.as_ptr(), data.as_ptr());
+                    assert_eq!(held.len(), data.len());
+                }
+                other => panic!("unexpected source {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn passwords_are_redacted_in_debug_output() {
+        let upload = WaifuUploadRequest::new()
+            .url("https://example.com/image.png")
+            .password("hunter2")
+            .expires("1h");
+        let debug = format!("{upload:?}");
+        assert!(debug.contains(r#"password: Some("<redacted>")"#), "{debug}");
+        assert!(debug.contains(r#"expires: Some("1h")"#), "{debug}");
+        assert!(!debug.contains("hunter2"), "{debug}");
+
+        let modification = WaifuModificationRequest::new("file")
+            .password("hunter2")
+            .previous_password("swordfish")
+            .custom_expiry("1d");
+        let debug = format!("{modification:?}");
+        assert!(debug.contains(r#"password: Some("<redacted>")"#), "{debug}");
+        assert!(
+            debug.contains(r#"previous_password: Some("<redacted>")"#),
+            "{debug}"
+        );
+        assert!(
+            !debug.contains("hunter2") && !debug.contains("swordfish"),
+            "{debug}"
+        );
+        assert!(debug.contains("\"1d\""), "{debug}");
+
+        let debug = format!("{:?}", WaifuModificationRequest::new("file"));
+        assert!(debug.contains("password: None"), "{debug}");
+    }
+
+    #[test]
+    fn response_types_display_on_one_line() {
+        let mut file: WaifuFileEntry = serde_json::from_value(file_json()).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "some-file http://127.0.0.1:8081/f/some-file.txt (protected), expires in 1 hour"
+        );
+        file.retention_period = serde_json::json!(90_061_000u64);
+        file.options = Some(WaifuFileOptions {
+            hide_filename: false,
+            one_time_download: true,
+            protected: true,
+        });
+        assert_eq!(
+            file.to_string(),
+            "some-file http://127.0.0.1:8081/f/some-file.txt (protected, one time download), \
+             expires in 1d 1h 1m"
+        );
+        assert_eq!(
+            entry(serde_json::json!("45000")).to_string(),
+            "some-file http://127.0.0.1:8081/f/some-file.txt, expires in 45s"
+        );
+        assert_eq!(
+            entry(serde_json::Value::Null).to_string(),
+            "some-file http://127.0.0.1:8081/f/some-file.txt, retention unknown"
+        );
+
+        let bucket: WaifuBucketEntry = serde_json::from_value(serde_json::json!({
+            "token": "bucket", "files": [file_json()], "albums": []
+        }))
+        .unwrap();
+        assert_eq!(bucket.to_string(), "bucket bucket, 1 file");
+
+        let mut album: WaifuAlbumEntry = serde_json::from_value(serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": "public",
+            "name": "holiday", "files": [file_json(), file_json()]
+        }))
+        .unwrap();
+        assert_eq!(
+            album.to_string(),
+            r#"album "holiday" (album), 2 files, shared"#
+        );
+        album.public_token = None;
+        album.files.clear();
+        assert_eq!(
+            album.to_string(),
+            r#"album "holiday" (album), 0 files, not shared"#
+        );
+
+        let message = WaifuGenericMessage {
+            success: false,
+            description: "Album not found".to_string(),
+        };
+        assert_eq!(message.to_string(), "failed: Album not found");
+    }
+
+    #[test]
+    fn response_types_compare_by_value() {
+        let file: WaifuFileEntry = serde_json::from_value(file_json()).unwrap();
+        assert_eq!(file, serde_json::from_value(file_json()).unwrap());
+        assert_ne!(file, entry(serde_json::json!("1 hour")));
+        assert_ne!(
+            entry(serde_json::json!(3_600_000)),
+            entry(serde_json::json!("3600000"))
+        );
+
+        let album = serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [file_json()]
+        });
+        assert_eq!(
+            serde_json::from_value::<WaifuAlbumEntry>(album.clone()).unwrap(),
+            serde_json::from_value::<WaifuAlbumEntry>(album).unwrap()
+        );
+
+        let bucket: WaifuBucketEntry = serde_json::from_value(serde_json::json!({
+            "token": "bucket", "files": [file_json()], "albums": [metadata_json()]
+        }))
+        .unwrap();
+        let mut viewed = bucket.clone();
+        assert_eq!(viewed, bucket);
+        viewed.files[0].views += 1;
+        assert_ne!(viewed, bucket);
+
+        let message = |success| WaifuGenericMessage {
+            success,
+            description: "done".to_string(),
+        };
+        assert_eq!(message(true), message(true));
+        assert_ne!(message(true), message(false));
+    }
+
+    #[test]
+    fn upload_requests_describe_their_source() {
+        let request = WaifuUploadRequest::new();
+        assert_eq!(request.source_kind(), None);
+        assert_eq!(request.filename(), None);
+        assert!(!request.has_password());
+        assert!(request.bucket_token().is_none() && request.expiry().is_none());
+
+        let request = request.file("/some/dir/picture.png");
+        assert_eq!(request.source_kind(), Some(UploadSourceKind::File));
+        assert_eq!(request.filename().as_deref(), Some("picture.png"));
+
+        let request = request.url("https://example.com/image.png");
+        assert_eq!(request.source_kind(), Some(UploadSourceKind::Url));
+        assert_eq!(request.filename(), None);
+        assert_eq!(request.content_length(), None);
+
+        let request = request.bytes(b"content".to_vec(), "file.txt");
+        assert_eq!(request.content_length(), Some(7));
+
+        let stream = futures_util::stream::iter([Ok::<_, std::io::Error>(b"abc".to_vec())]);
+        let request = request.body_stream(stream, "stream.bin", Some(3));
+        assert_eq!(request.source_kind(), Some(UploadSourceKind::Stream));
+        assert_eq!(request.filename().as_deref(), Some("stream.bin"));
+        assert_eq!(request.content_length(), Some(3));
+    }
 }
@@ -0,0 +1,235 @@
+//! Unpacking album archives, available with the `zip` feature
+//!
+//! [`crate::ApiCaller::download_album`] returns a zip archive, these helpers open it so
+//! callers don't need a zip dependency of their own.
+use crate::Error;
+use anyhow::Context;
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+/// A file read from an album archive by [`crate::ApiCaller::download_album_entries`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AlbumZipEntry {
+    /// Name of the file within the archive
+    pub name: String,
+
+    /// Contents of the file
+    pub data: Vec<u8>,
+}
+
+/// How an album archive is written out by [`crate::ApiCaller::download_album_extract`]
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::archive::ExtractOptions;
+///
+/// let options = ExtractOptions {
+///     overwrite: true,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Replace files that already exist, instead of leaving them and skipping the entry
+    pub overwrite: bool,
+}
+
+/// Extracts every entry of an archive under `dest_dir`, returning the paths written
+///
+/// Entries whose names would escape `dest_dir`, such as `../file` or `/etc/file`, fail
+/// the whole extraction with [`Error::UnsafeArchivePath`] before anything is written.
+pub(crate) fn extract(
+    archive: &[u8],
+    dest_dir: &Path,
+    options: &ExtractOptions,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut archive = open(archive)?;
+
+    // Check every name first so a bad entry doesn't leave a partial extraction behind
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i).map_err(corrupt)?;
+        if entry.enclosed_name().is_none() {
+            return Err(Error::UnsafeArchivePath {
+                name: entry.name().to_string(),
+            }
+            .into());
+        }
+    }
+
+    let mut written = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(corrupt)?;
+        let path = match entry.enclosed_name() {
+            Some(name) => dest_dir.join(name),
+            None => continue,
+        };
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path)
+                .with_context(|| format!("creating directory {}", path.display()))?;
+            continue;
+        }
+
+        if path.exists() && !options.overwrite {
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("creating directory {}", parent.display()))?;
+        }
+
+        let content = read_entry(&mut entry)?;
+        fs::write(&path, content).with_context(|| format!("writing {}", path.display()))?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Reads every file in an archive into memory, skipping directory entries
+pub(crate) fn entries(archive: &[u8]) -> anyhow::Result<Vec<AlbumZipEntry>> {
+    let mut archive = open(archive)?;
+
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(corrupt)?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        entries.push(AlbumZipEntry {
+            name: entry.name().to_string(),
+            data: read_entry(&mut entry)?,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn open(archive: &[u8]) -> anyhow::Result<zip::ZipArchive<Cursor<&[u8]>>> {
+    zip::ZipArchive::new(Cursor::new(archive)).map_err(corrupt)
+}
+
+fn read_entry(entry: &mut zip::read::ZipFile<'_>) -> anyhow::Result<Vec<u8>> {
+    let mut content = Vec::with_capacity(entry.size() as usize);
+    entry
+        .read_to_end(&mut content)
+        .map_err(|e| corrupt(e.into()))
+        .with_context(|| format!("decompressing {}", entry.name()))?;
+
+    Ok(content)
+}
+
+fn corrupt(err: zip::result::ZipError) -> anyhow::Error {
+    Error::CorruptArchive {
+        reason: err.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn archive(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            archive
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        archive.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn extracts_into_nested_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = archive(&[("one.txt", "one"), ("nested/deeper/two.txt", "two")]);
+
+        let written = extract(&archive, dir.path(), &ExtractOptions::default()).unwrap();
+        assert_eq!(
+            written,
+            vec![
+                dir.path().join("one.txt"),
+                dir.path().join("nested/deeper/two.txt")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("nested/deeper/two.txt")).unwrap(),
+            "two"
+        );
+    }
+
+    #[test]
+    fn existing_files_are_skipped_unless_overwriting() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("one.txt"), "original").unwrap();
+        let archive = archive(&[("one.txt", "replaced"), ("two.txt", "two")]);
+
+        let written = extract(&archive, dir.path(), &ExtractOptions::default()).unwrap();
+        assert_eq!(written, vec![dir.path().join("two.txt")]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("one.txt")).unwrap(),
+            "original"
+        );
+
+        let options = ExtractOptions { overwrite: true };
+        let written = extract(&archive, dir.path(), &options).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("one.txt")).unwrap(),
+            "replaced"
+        );
+    }
+
+    #[test]
+    fn escaping_entries_are_rejected() {
+        for name in ["../evil.txt", "nested/../../evil.txt", "/tmp/evil.txt"] {
+            let dir = tempfile::tempdir().unwrap();
+            let dest = dir.path().join("dest");
+            let archive = archive(&[("fine.txt", "fine"), (name, "evil")]);
+
+            let err = extract(&archive, &dest, &ExtractOptions::default()).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<Error>(),
+                Some(&Error::UnsafeArchivePath {
+                    name: name.to_string()
+                })
+            );
+            assert!(!dest.exists(), "nothing is written for {name}");
+        }
+    }
+
+    #[test]
+    fn entries_are_read_in_memory() {
+        let archive = archive(&[("one.txt", "one"), ("nested/two.txt", "two")]);
+
+        let entries = entries(&archive).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, "nested/two.txt");
+        assert_eq!(entries[1].data, b"two");
+    }
+
+    #[test]
+    fn corrupt_archives_are_typed_errors() {
+        let mut truncated = archive(&[("one.txt", "one")]);
+        truncated.truncate(truncated.len() / 2);
+
+        for bad in [b"not a zip".to_vec(), truncated] {
+            let err = entries(&bad).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::CorruptArchive { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,701 @@
+//! Captures response fixtures from a running Waifu Vault instance
+//!
+//! Runs a scripted scenario against the instance (bucket lifecycle, uploads with every option
+//! combination, album lifecycle, and every error we know how to trigger) and writes the
+//! sanitized response bodies into the fixture directory with stable names.
+//!
+//! Tokens, file ids and origins are replaced with placeholders such as `<bucket-1>`, and the
+//! same raw value always maps to the same placeholder within a run, so diffs between two
+//! captures only show real changes in the API.
+//!
+//! ```text
+//! cargo run --features capture --bin capture-fixtures -- [base-url] [output-dir]
+//! ```
+//!
+//! The base URL defaults to `$WAIFUVAULT_URL` or `http://127.0.0.1:8081/rest` and the output
+//! directory defaults to `fixtures`. The decode tests include the committed fixtures, so run
+//! them after capturing again to see if the API has drifted.
+use anyhow::Context;
+use reqwest::{multipart, Client, Method, RequestBuilder, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Base URL used when none is supplied
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8081/rest";
+
+/// Content uploaded for every file in the scenario
+const FIXTURE_CONTENT: &[u8] = b"waifuvault fixture content\n";
+
+/// Password used for protected uploads
+const FIXTURE_PASSWORD: &str = "fixture-password";
+
+/// Token which is never valid on the instance
+const BOGUS_TOKEN: &str = "not-a-real-token";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let base_url = args
+        .next()
+        .or_else(|| std::env::var("WAIFUVAULT_URL").ok())
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+    let out_dir = PathBuf::from(args.next().unwrap_or_else(|| "fixtures".to_string()));
+
+    let mut recorder = Recorder::new(base_url.trim_end_matches('/'));
+    let outcome = scenario(&mut recorder).await;
+
+    // Whatever was captured before a failure is still worth writing out
+    let written = write_fixtures(&recorder.captures, &out_dir)?;
+    println!("wrote {written} fixtures to {}", out_dir.display());
+
+    outcome
+}
+
+/// A single captured response
+struct Capture {
+    /// Stable name of the fixture, used as the file name
+    name: String,
+
+    /// HTTP method of the request
+    method: Method,
+
+    /// Endpoint the request was sent to
+    endpoint: String,
+
+    /// Status returned by the instance
+    status: StatusCode,
+
+    /// Response body, parsed as JSON where possible
+    body: Body,
+}
+
+/// Response body of a captured call
+enum Body {
+    Json(Value),
+    Text(String),
+}
+
+/// Entry in the fixture index describing how a fixture was produced
+#[derive(Serialize)]
+struct IndexEntry {
+    name: String,
+    file: String,
+    method: String,
+    endpoint: String,
+    status: u16,
+}
+
+/// Sends requests to the instance and records every response
+struct Recorder {
+    client: Client,
+    base_url: String,
+    captures: Vec<Capture>,
+}
+
+impl Recorder {
+    fn new(base_url: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.to_string(),
+            captures: Vec::new(),
+        }
+    }
+
+    /// Calls an endpoint relative to the base URL and records the response
+    async fn call(
+        &mut self,
+        name: &str,
+        method: Method,
+        endpoint: &str,
+        build: impl FnOnce(RequestBuilder) -> RequestBuilder,
+    ) -> anyhow::Result<Value> {
+        let url = format!("{}{endpoint}", self.base_url);
+        self.call_url(name, method, &url, build).await
+    }
+
+    /// Calls an absolute URL and records the response
+    async fn call_url(
+        &mut self,
+        name: &str,
+        method: Method,
+        url: &str,
+        build: impl FnOnce(RequestBuilder) -> RequestBuilder,
+    ) -> anyhow::Result<Value> {
+        let response = build(self.client.request(method.clone(), url))
+            .send()
+            .await
+            .with_context(|| format!("sending request for fixture {name}"))?;
+
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .with_context(|| format!("reading body for fixture {name}"))?;
+
+        let body = match serde_json::from_str::<Value>(&text) {
+            Ok(value) => Body::Json(value),
+            Err(_) => Body::Text(text),
+        };
+
+        let value = match &body {
+            Body::Json(value) => value.clone(),
+            Body::Text(_) => Value::Null,
+        };
+
+        println!("{status} {method} {url} -> {name}");
+        self.captures.push(Capture {
+            name: name.to_string(),
+            method,
+            endpoint: url.to_string(),
+            status,
+            body,
+        });
+
+        Ok(value)
+    }
+}
+
+/// The scripted scenario run against the instance
+async fn scenario(rec: &mut Recorder) -> anyhow::Result<()> {
+    // Bucket lifecycle
+    let bucket = rec
+        .call("bucket_create", Method::GET, "/bucket/create", |r| r)
+        .await?;
+    let bucket_token = string_field(&bucket, "token")?;
+
+    // Uploads with every option combination
+    let mut plain_token = None;
+    let mut protected_token = None;
+    let mut file_tokens = Vec::new();
+    for hide_filename in [false, true] {
+        for one_time_download in [false, true] {
+            for protected in [false, true] {
+                let name = format!(
+                    "upload_hide-{hide_filename}_otd-{one_time_download}_protected-{protected}"
+                );
+                let part = multipart::Part::bytes(FIXTURE_CONTENT).file_name(format!("{name}.txt"));
+                let mut form = multipart::Form::new().part("file", part);
+                if protected {
+                    form = form.text("password", FIXTURE_PASSWORD);
+                }
+
+                let entry = rec
+                    .call(&name, Method::PUT, &format!("/{bucket_token}"), |r| {
+                        r.query(&[
+                            ("hide_filename", hide_filename),
+                            ("oneTimeDownload", one_time_download),
+                        ])
+                        .query(&[("expires", "1h")])
+                        .multipart(form)
+                    })
+                    .await?;
+
+                let token = string_field(&entry, "token")?;
+                if !one_time_download && !hide_filename {
+                    if protected {
+                        protected_token = Some((token.clone(), string_field(&entry, "url")?));
+                    } else {
+                        plain_token = Some((token.clone(), string_field(&entry, "url")?));
+                    }
+                }
+                file_tokens.push(token);
+            }
+        }
+    }
+
+    let url_upload = rec
+        .call("upload_url", Method::PUT, "/", |r| {
+            r.query(&[("expires", "1h")])
+                .form(&[("url", "https://waifuvault.moe/assets/custom/images/08.png")])
+        })
+        .await?;
+    file_tokens.push(string_field(&url_upload, "token")?);
+
+    let (plain_token, _) = plain_token.context("plain upload was not captured")?;
+    let (protected_token, protected_url) =
+        protected_token.context("protected upload was not captured")?;
+
+    // File information and modification
+    rec.call("file_info", Method::GET, &format!("/{plain_token}"), |r| {
+        r.query(&[("formatted", false)])
+    })
+    .await?;
+    rec.call(
+        "file_info_formatted",
+        Method::GET,
+        &format!("/{plain_token}"),
+        |r| r.query(&[("formatted", true)]),
+    )
+    .await?;
+    rec.call(
+        "file_update",
+        Method::PATCH,
+        &format!("/{plain_token}"),
+        |r| r.json(&serde_json::json!({ "customExpiry": "30m", "hideFilename": true })),
+    )
+    .await?;
+    rec.call("bucket_get", Method::POST, "/bucket/get", |r| {
+        r.json(&serde_json::json!({ "bucket_token": bucket_token }))
+    })
+    .await?;
+
+    // Album lifecycle
+    let album = rec
+        .call(
+            "album_create",
+            Method::POST,
+            &format!("/album/{bucket_token}"),
+            |r| r.json(&serde_json::json!({ "name": "fixture-album" })),
+        )
+        .await?;
+    let album_token = string_field(&album, "token")?;
+    let associated = &file_tokens[..2];
+
+    rec.call(
+        "album_associate",
+        Method::POST,
+        &format!("/album/{album_token}/associate"),
+        |r| r.json(&serde_json::json!({ "fileTokens": associated })),
+    )
+    .await?;
+    rec.call(
+        "album_get",
+        Method::GET,
+        &format!("/album/{album_token}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "album_share",
+        Method::GET,
+        &format!("/album/share/{album_token}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "album_get_shared",
+        Method::GET,
+        &format!("/album/{album_token}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "album_revoke",
+        Method::GET,
+        &format!("/album/revoke/{album_token}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "album_disassociate",
+        Method::POST,
+        &format!("/album/{album_token}/disassociate"),
+        |r| r.json(&serde_json::json!({ "fileTokens": &associated[..1] })),
+    )
+    .await?;
+
+    // Every error we know how to trigger
+    rec.call(
+        "error_file_info_bad_token",
+        Method::GET,
+        &format!("/{BOGUS_TOKEN}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "error_file_delete_bad_token",
+        Method::DELETE,
+        &format!("/{BOGUS_TOKEN}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "error_bucket_get_bad_token",
+        Method::POST,
+        "/bucket/get",
+        |r| r.json(&serde_json::json!({ "bucket_token": BOGUS_TOKEN })),
+    )
+    .await?;
+    rec.call(
+        "error_bucket_delete_bad_token",
+        Method::DELETE,
+        &format!("/bucket/{BOGUS_TOKEN}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "error_album_get_bad_token",
+        Method::GET,
+        &format!("/album/{BOGUS_TOKEN}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "error_album_create_bad_bucket",
+        Method::POST,
+        &format!("/album/{BOGUS_TOKEN}"),
+        |r| r.json(&serde_json::json!({ "name": "fixture-album" })),
+    )
+    .await?;
+    rec.call("error_upload_no_content", Method::PUT, "/", |r| {
+        r.form(&[("url", "")])
+    })
+    .await?;
+    rec.call(
+        "error_update_wrong_previous_password",
+        Method::PATCH,
+        &format!("/{protected_token}"),
+        |r| {
+            r.json(&serde_json::json!({
+                "password": "new-password",
+                "previousPassword": "wrong-password"
+            }))
+        },
+    )
+    .await?;
+    rec.call_url(
+        "error_download_missing_password",
+        Method::GET,
+        &protected_url,
+        |r| r,
+    )
+    .await?;
+    rec.call_url(
+        "error_download_wrong_password",
+        Method::GET,
+        &protected_url,
+        |r| r.header("x-password", "wrong-password"),
+    )
+    .await?;
+
+    // Cleanup
+    rec.call(
+        "album_delete",
+        Method::DELETE,
+        &format!("/album/{album_token}"),
+        |r| r.query(&[("deleteFiles", false)]),
+    )
+    .await?;
+    rec.call(
+        "file_delete",
+        Method::DELETE,
+        &format!("/{plain_token}"),
+        |r| r,
+    )
+    .await?;
+    rec.call(
+        "bucket_delete",
+        Method::DELETE,
+        &format!("/bucket/{bucket_token}"),
+        |r| r,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Writes every capture plus an `index.json` describing them, returning how many were written
+fn write_fixtures(captures: &[Capture], out_dir: &Path) -> anyhow::Result<usize> {
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("creating fixture directory {}", out_dir.display()))?;
+
+    // Collect every token before redacting anything so placeholders are numbered in
+    // capture order and a token is replaced even in responses captured before it was seen
+    let mut redactor = Redactor::default();
+    for capture in captures {
+        if let Body::Json(value) = &capture.body {
+            redactor.collect(value);
+        }
+    }
+
+    let mut index = Vec::with_capacity(captures.len());
+    for capture in captures {
+        let (file, contents) = match &capture.body {
+            Body::Json(value) => {
+                let redacted = redactor.redact(value);
+                let pretty = serde_json::to_string_pretty(&redacted)
+                    .context("serializing redacted fixture")?;
+                (format!("{}.json", capture.name), format!("{pretty}\n"))
+            }
+            Body::Text(text) => (format!("{}.txt", capture.name), redactor.redact_str(text)),
+        };
+
+        std::fs::write(out_dir.join(&file), contents)
+            .with_context(|| format!("writing fixture {file}"))?;
+
+        index.push(IndexEntry {
+            name: capture.name.clone(),
+            file,
+            method: capture.method.to_string(),
+            endpoint: redactor.redact_str(&capture.endpoint),
+            status: capture.status.as_u16(),
+        });
+    }
+
+    let index = serde_json::to_string_pretty(&index).context("serializing fixture index")?;
+    std::fs::write(out_dir.join("index.json"), format!("{index}\n"))
+        .context("writing fixture index")?;
+
+    Ok(captures.len())
+}
+
+/// Extracts a string field from a JSON object
+fn string_field(value: &Value, field: &str) -> anyhow::Result<String> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .with_context(|| format!("expected string field `{field}` in response: {value}"))
+}
+
+/// Replaces tokens and other run-specific values with stable placeholders
+///
+/// Within one run the same raw value always maps to the same placeholder.
+#[derive(Debug, Default)]
+struct Redactor {
+    /// Raw value to placeholder
+    placeholders: HashMap<String, String>,
+
+    /// Number of placeholders handed out per kind
+    counters: HashMap<&'static str, usize>,
+
+    /// Raw numeric ids to their sequential replacement
+    ids: HashMap<u64, u64>,
+}
+
+impl Redactor {
+    /// Records a raw value, handing it a placeholder if it hasn't been seen before
+    fn register(&mut self, kind: &'static str, raw: &str) {
+        if raw.is_empty() || self.placeholders.contains_key(raw) {
+            return;
+        }
+
+        let counter = self.counters.entry(kind).or_default();
+        *counter += 1;
+        self.placeholders
+            .insert(raw.to_string(), format!("<{kind}-{counter}>"));
+    }
+
+    /// Walks a response, registering every token, file id and origin found
+    fn collect(&mut self, value: &Value) {
+        match value {
+            Value::Object(map) => {
+                let kind = if map.contains_key("url") {
+                    "file"
+                } else if map.contains_key("bucketToken") || map.contains_key("bucket") {
+                    "album"
+                } else if map.contains_key("files") {
+                    "bucket"
+                } else {
+                    "token"
+                };
+
+                for (key, field) in map {
+                    match (key.as_str(), field) {
+                        ("token", Value::String(raw)) => self.register(kind, raw),
+                        ("bucketToken" | "bucket", Value::String(raw)) => {
+                            self.register("bucket", raw)
+                        }
+                        ("publicToken", Value::String(raw)) => self.register("public", raw),
+                        ("url", Value::String(raw)) => self.collect_url(raw),
+                        ("description", Value::String(raw)) => {
+                            if let Some((_, public)) = raw.rsplit_once("/album/") {
+                                self.register("public", public);
+                            }
+                            self.collect_origin(raw);
+                        }
+                        ("id", Value::Number(id)) => {
+                            if let Some(id) = id.as_u64() {
+                                let next = self.ids.len() as u64 + 1;
+                                self.ids.entry(id).or_insert(next);
+                            }
+                        }
+                        _ => self.collect(field),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| self.collect(item)),
+            _ => {}
+        }
+    }
+
+    /// Registers the origin and file id of a file URL
+    fn collect_url(&mut self, url: &str) {
+        self.collect_origin(url);
+        if let Some((_, rest)) = url.split_once("/f/") {
+            let id = rest.split('/').next().unwrap_or(rest);
+            self.register("file-id", id);
+        }
+    }
+
+    /// Registers the scheme and host portion of a URL
+    fn collect_origin(&mut self, url: &str) {
+        if let Some((scheme, rest)) = url.split_once("://") {
+            let host = rest.split('/').next().unwrap_or(rest);
+            self.register("origin", &format!("{scheme}://{host}"));
+        }
+    }
+
+    /// Replaces every registered value found within the string
+    fn redact_str(&self, raw: &str) -> String {
+        // Longest first so a token which contains another token is replaced whole
+        let mut known: Vec<_> = self.placeholders.iter().collect();
+        known.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+
+        known
+            .into_iter()
+            .fold(raw.to_string(), |acc, (raw, placeholder)| {
+                acc.replace(raw.as_str(), placeholder)
+            })
+    }
+
+    /// Produces a redacted copy of a response
+    ///
+    /// Values which change every run without carrying meaning (creation dates and
+    /// retention periods) are pinned to fixed values of the same type.
+    fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, field)| {
+                        let field = match (key.as_str(), field) {
+                            ("dateCreated", Value::Number(_)) => Value::from(1_700_000_000_000u64),
+                            ("retentionPeriod", Value::Number(_)) => Value::from(3_600_000u64),
+                            ("retentionPeriod", Value::String(_)) => {
+                                Value::from("59 minutes 59 seconds")
+                            }
+                            ("id", Value::Number(id)) => id
+                                .as_u64()
+                                .and_then(|id| self.ids.get(&id))
+                                .map(|id| Value::from(*id))
+                                .unwrap_or_else(|| field.clone()),
+                            _ => self.redact(field),
+                        };
+                        (key.clone(), field)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(|i| self.redact(i)).collect()),
+            Value::String(raw) => Value::String(self.redact_str(raw)),
+            other => other.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn captured() -> Vec<Value> {
+        vec![
+            json!({ "token": "bkt-aaa", "files": [], "albums": [] }),
+            json!({
+                "token": "file-aaa",
+                "url": "http://127.0.0.1:8081/f/1712345/a.txt",
+                "bucket": "bkt-aaa",
+                "views": 0,
+                "retentionPeriod": 3599123,
+                "options": { "hideFilename": false, "oneTimeDownload": false, "protected": false }
+            }),
+            json!({
+                "token": "alb-aaa",
+                "bucketToken": "bkt-aaa",
+                "publicToken": null,
+                "name": "fixture-album",
+                "files": [{
+                    "token": "file-aaa",
+                    "url": "http://127.0.0.1:8081/f/1712345/a.txt",
+                    "bucket": "bkt-aaa",
+                    "id": 42
+                }]
+            }),
+            json!({ "success": true, "description": "http://localhost:8081/album/pub-aaa" }),
+            json!({ "token": "alb-aaa", "bucketToken": "bkt-aaa", "publicToken": "pub-aaa", "name": "fixture-album", "files": [] }),
+        ]
+    }
+
+    fn redactor() -> Redactor {
+        let mut redactor = Redactor::default();
+        for value in captured() {
+            redactor.collect(&value);
+        }
+        redactor
+    }
+
+    #[test]
+    fn same_token_maps_to_same_placeholder() {
+        let redactor = redactor();
+        let redacted: Vec<_> = captured().iter().map(|v| redactor.redact(v)).collect();
+
+        // The bucket token appears under three different keys across three responses
+        assert_eq!(redacted[0]["token"], json!("<bucket-1>"));
+        assert_eq!(redacted[1]["bucket"], json!("<bucket-1>"));
+        assert_eq!(redacted[2]["bucketToken"], json!("<bucket-1>"));
+
+        // The file token is the same whether it is top level or nested in an album
+        assert_eq!(redacted[1]["token"], redacted[2]["files"][0]["token"]);
+        assert_eq!(redacted[1]["url"], redacted[2]["files"][0]["url"]);
+        assert_eq!(redacted[1]["url"], json!("<origin-1>/f/<file-id-1>/a.txt"));
+
+        // The public token embedded in the share description matches the album field
+        assert_eq!(redacted[4]["publicToken"], json!("<public-1>"));
+        assert_eq!(
+            redacted[3]["description"],
+            json!("<origin-2>/album/<public-1>")
+        );
+    }
+
+    #[test]
+    fn distinct_tokens_get_distinct_placeholders() {
+        let redactor = redactor();
+        let mut seen: Vec<_> = redactor.placeholders.values().collect();
+        let total = seen.len();
+        seen.sort();
+        seen.dedup();
+
+        assert_eq!(seen.len(), total);
+    }
+
+    #[test]
+    fn no_raw_values_survive_redaction() {
+        let redactor = redactor();
+        for value in captured() {
+            let redacted = redactor.redact(&value).to_string();
+            for raw in [
+                "bkt-aaa",
+                "file-aaa",
+                "alb-aaa",
+                "pub-aaa",
+                "1712345",
+                "127.0.0.1",
+            ] {
+                assert!(!redacted.contains(raw), "{raw} leaked into {redacted}");
+            }
+        }
+    }
+
+    #[test]
+    fn redaction_is_stable_across_runs() {
+        let first = redactor();
+        let second = redactor();
+
+        for value in captured() {
+            assert_eq!(first.redact(&value), second.redact(&value));
+        }
+    }
+
+    #[test]
+    fn volatile_fields_are_pinned() {
+        let redactor = redactor();
+        let redacted = redactor.redact(&captured()[1]);
+        assert_eq!(redacted["retentionPeriod"], json!(3_600_000));
+
+        let album = redactor.redact(&captured()[2]);
+        assert_eq!(album["files"][0]["id"], json!(1));
+    }
+}
@@ -0,0 +1,906 @@
+//! Blocking version of the Waifu Vault API caller
+//!
+//! Available with the `blocking` feature for programs without an async runtime. It takes
+//! and returns the same types from [`crate::api`] as the async [`crate::ApiCaller`].
+//!
+//! Like [`reqwest::blocking`], this must not be used from within an async runtime.
+//!
+//! ```rust,no_run
+//! use waifuvault::{blocking::ApiCaller, api::WaifuUploadRequest};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let caller = ApiCaller::new();
+//!
+//!     let request = WaifuUploadRequest::new().file("/some/file/path");
+//!     let response = caller.upload_file(request)?;
+//!
+//!     let content = caller.download_file(&response.url, None)?;
+//!
+//!     Ok(())
+//! }
+//! ```
+use std::{collections::HashMap, io::Read};
+
+use anyhow::Context;
+use reqwest::{
+    blocking::{multipart, Client, RequestBuilder, Response},
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    api::*, checksum::Hasher, decode, decode_error, download_forbidden, password_header,
+    transport_error, Checksum, Error, RedirectPolicy, Target,
+};
+
+/// Blocking api controller which calls the endpoint
+#[derive(Debug, Clone)]
+pub struct ApiCaller {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for ApiCaller {
+    fn default() -> Self {
+        Self {
+            client: client(),
+            base_url: Target::default().base_url().to_string(),
+        }
+    }
+}
+
+/// Client using the TLS backend selected with the crate's features
+///
+/// Redirects are followed by [`ApiCaller::send`] rather than the client, so passwords aren't
+/// sent on to other hosts.
+fn client() -> Client {
+    let builder = Client::builder().redirect(reqwest::redirect::Policy::none());
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+
+    builder
+        .build()
+        .expect("the default HTTP client can be built")
+}
+
+impl ApiCaller {
+    /// Create a new blocking Waifu Vault API Caller
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the instance this caller sends requests to
+    pub fn target(&self) -> Target {
+        Target::new(&self.base_url)
+    }
+
+    /// Returns a caller which sends its requests to another instance
+    ///
+    /// See [`crate::ApiCaller::on`]
+    pub fn on(&self, target: &Target) -> Self {
+        Self {
+            client: self.client.clone(),
+            base_url: target.base_url().to_string(),
+        }
+    }
+
+    /// Creates a bucket with the Waifu Vault API
+    ///
+    /// See [`crate::ApiCaller::create_bucket`]
+    pub fn create_bucket(&self) -> anyhow::Result<WaifuBucketEntry> {
+        let url = format!("{}/bucket/create", self.base_url);
+        let response = self
+            .send(self.client.get(&url))
+            .context("calling create bucket endpoint")?;
+
+        parse_response(response).context("converting create bucket api response")
+    }
+
+    /// Deletes a Bucket with the Waifu Vault API
+    ///
+    /// See [`crate::ApiCaller::delete_bucket`]
+    pub fn delete_bucket(&self, token: impl Into<BucketToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        let url = format!("{}/bucket/{}", self.base_url, token.path_segment()?);
+        let response = self
+            .send(self.client.delete(&url))
+            .context("sending delete bucket request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Gets information on files contained within a Bucket with the Waifu Vault API
+    ///
+    /// See [`crate::ApiCaller::get_bucket`]
+    pub fn get_bucket(
+        &self,
+        request: impl Into<WaifuBucketRequest>,
+    ) -> anyhow::Result<WaifuBucketEntry> {
+        let request = request.into();
+        let url = format!("{}/bucket/get", self.base_url);
+        let mut body = HashMap::new();
+        body.insert("bucket_token", &request.token);
+
+        let request = self
+            .client
+            .post(&url)
+            .query(&[("formatted", request.formatted)])
+            .json(&body);
+        let response = self.send(request).context("sending get bucket request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Retrieves the restrictions the service places on uploads
+    ///
+    /// See [`crate::ApiCaller::get_restrictions`]
+    pub fn get_restrictions(&self) -> anyhow::Result<WaifuRestrictions> {
+        let url = format!("{}/resources/restrictions", self.base_url);
+        let response = self
+            .send(self.client.get(&url))
+            .context("sending restrictions request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Upload a file to Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::upload_file`]
+    pub fn upload_file(&self, mut request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry> {
+        let source = request.source.take().ok_or(Error::NoUploadSource)?;
+        if let UploadSource::Url(url) = &source {
+            validate_upload_url(url)?;
+        }
+        if let Some(expiry) = &request.expires {
+            validate_expiry(expiry)?;
+        }
+
+        let url = match &request.bucket {
+            Some(bucket) => format!("{}/{}", self.base_url, bucket.path_segment()?),
+            None => self.base_url.clone(),
+        };
+
+        let mut intermediate = self.client.put(&url);
+
+        if let Some(hide) = request.hide_filename {
+            intermediate = intermediate.query(&[("hide_filename", hide)]);
+        }
+
+        if let Some(one_time) = request.one_time_download {
+            intermediate = intermediate.query(&[("oneTimeDownload", one_time)]);
+        }
+
+        if let Some(expiry) = &request.expires {
+            intermediate = intermediate.query(&[("expires", expiry)]);
+        }
+
+        if let Some(timeout) = request.timeout {
+            intermediate = intermediate.timeout(timeout);
+        }
+
+        let file_part = match source {
+            UploadSource::File(path) => {
+                let (file, length) = open_local_file(&path)?;
+                let filename = request.upload_filename(&path)?;
+                Some(multipart::Part::reader_with_length(file, length).file_name(filename))
+            }
+            UploadSource::Bytes { data, filename } => {
+                let length = data.len() as u64;
+                let part = multipart::Part::reader_with_length(std::io::Cursor::new(data), length);
+                Some(part.file_name(filename))
+            }
+            UploadSource::Stream { .. } => {
+                anyhow::bail!("streamed bodies can only be uploaded with the async caller")
+            }
+            // Form encoding escapes `&`, `=`, `+` and spaces, so the service reads the same
+            // password as from a multipart upload
+            UploadSource::Url(url) => {
+                intermediate = match request.password {
+                    Some(ref password) => {
+                        intermediate.form(&[("url", url.as_str()), ("password", password)])
+                    }
+                    None => intermediate.form(&[("url", url)]),
+                };
+                None
+            }
+        };
+
+        if let Some(file_part) = file_part {
+            let file_part = with_content_type(file_part, request.content_type.as_deref())?;
+            let mut form = multipart::Form::new().part("file", file_part);
+
+            if let Some(password) = request.password {
+                form = form.text("password", password);
+            }
+
+            intermediate = intermediate.multipart(form);
+        }
+
+        let response = self.send(intermediate).context("sending upload request")?;
+
+        parse_response(response).context("converting upload response")
+    }
+
+    /// Retrieves information about a file stored in Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::file_info`]
+    pub fn file_info(&self, request: WaifuGetRequest) -> anyhow::Result<WaifuFileEntry> {
+        let url = format!("{}/{}", self.base_url, request.token.path_segment()?);
+        let response = self
+            .send(
+                self.client
+                    .get(&url)
+                    .query(&[("formatted", request.formatted)]),
+            )
+            .context("sending file info request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Updates options on a stored file in Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::update_file`]
+    pub fn update_file(&self, request: WaifuModificationRequest) -> anyhow::Result<WaifuFileEntry> {
+        // An empty expiry clears the custom one
+        if let Some(expiry) = request.custom_expiry.as_deref().filter(|e| !e.is_empty()) {
+            validate_expiry(expiry)?;
+        }
+
+        let url = format!("{}/{}", self.base_url, request.token.path_segment()?);
+        let response = self
+            .send(self.client.patch(&url).json(&request))
+            .context("sending modification request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Deletes a file from Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::delete_file`]
+    pub fn delete_file(&self, token: impl Into<FileToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        let url = format!("{}/{}", self.base_url, token.path_segment()?);
+        let response = self
+            .send(self.client.delete(&url))
+            .context("sending delete request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Downloads a file from Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::download_file`]
+    pub fn download_file(&self, url: &str, password: Option<String>) -> anyhow::Result<Vec<u8>> {
+        let mut options = DownloadOptions::new();
+        options.password = password;
+        self.download_file_with(url, options)
+    }
+
+    /// Downloads a file from Waifu Vault with options such as a password or timeout
+    ///
+    /// See [`crate::ApiCaller::download_file_with`]
+    pub fn download_file_with(
+        &self,
+        url: &str,
+        options: DownloadOptions,
+    ) -> anyhow::Result<Vec<u8>> {
+        let password = &options.password;
+        let mut request = self.client.get(url);
+        if let Some(password) = &password {
+            request = request.header("x-password", password_header(password)?);
+        }
+
+        if options.identity_encoding {
+            request = request.header(reqwest::header::ACCEPT_ENCODING, "identity");
+        }
+
+        if let Some(timeout) = options.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = self.send(request).context("sending download request")?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::FORBIDDEN => return Err(download_forbidden(password.is_some())),
+            _ => return Err(error_response(response)),
+        }
+
+        read_body(response, options.max_bytes, options.checksum.as_ref())
+            .context("getting content bytes")
+    }
+
+    /// Creates an album on the WaifuVault service
+    ///
+    /// See [`crate::ApiCaller::create_album`]
+    pub fn create_album(
+        &self,
+        bucket_token: impl Into<BucketToken>,
+        album_name: &str,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        let bucket_token = bucket_token.into();
+        let url = format!("{}/album/{}", self.base_url, bucket_token.path_segment()?);
+        let mut body = HashMap::new();
+        body.insert("name", album_name);
+
+        let response = self
+            .send(self.client.post(&url).json(&body))
+            .context("sending create album request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Associates a collection of Files with an Album
+    ///
+    /// See [`crate::ApiCaller::associate_with_album`]
+    pub fn associate_with_album<I>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_tokens: I,
+    ) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let album_token = album_token.into();
+        let file_tokens = collect_file_tokens(file_tokens);
+        let url = format!(
+            "{}/album/{}/associate",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let mut body = HashMap::new();
+        body.insert("fileTokens", &file_tokens);
+
+        let response = self
+            .send(self.client.post(&url).json(&body))
+            .context("sending album association request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Disassociate a collection of Files with an Album
+    ///
+    /// See [`crate::ApiCaller::disassociate_from_album`]
+    pub fn disassociate_from_album<I>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_tokens: I,
+    ) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let album_token = album_token.into();
+        let file_tokens = collect_file_tokens(file_tokens);
+        let url = format!(
+            "{}/album/{}/disassociate",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let mut body = HashMap::new();
+        body.insert("fileTokens", &file_tokens);
+
+        let response = self
+            .send(self.client.post(&url).json(&body))
+            .context("sending album disassociation request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Delete an album from Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::delete_album`]
+    pub fn delete_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        delete_files: bool,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        let url = format!("{}/album/{}", self.base_url, album_token.path_segment()?);
+        let response = self
+            .send(
+                self.client
+                    .delete(&url)
+                    .query(&[("deleteFiles", delete_files)]),
+            )
+            .context("sending album delete request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Get information about album from Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::get_album`]
+    pub fn get_album(&self, album_token: impl Into<AlbumToken>) -> anyhow::Result<WaifuAlbumEntry> {
+        let album_token = album_token.into();
+        let url = format!("{}/album/{}", self.base_url, album_token.path_segment()?);
+        let response = self
+            .send(self.client.get(&url))
+            .context("sending get album request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Share an album from Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::share_album`]
+    pub fn share_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        let url = format!(
+            "{}/album/share/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let response = self
+            .send(self.client.get(&url))
+            .context("sending share album request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Revokes public access from an album on Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::revoke_album`]
+    pub fn revoke_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        let url = format!(
+            "{}/album/revoke/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let response = self
+            .send(self.client.get(&url))
+            .context("sending revoke album request")?;
+
+        parse_response(response).context("converting response")
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault
+    ///
+    /// See [`crate::ApiCaller::download_album`]
+    pub fn download_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let album_token = album_token.into();
+        let url = format!(
+            "{}/album/download/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let body = file_ids.unwrap_or(&[]);
+
+        let response = self
+            .send(self.client.post(&url).json(&body))
+            .context("sending download part album request")?;
+
+        if response.status() != StatusCode::OK {
+            return Err(error_response(response));
+        }
+
+        let content = response
+            .bytes()
+            .context("obtaining response bytes")?
+            .to_vec();
+
+        Ok(content)
+    }
+
+    /// Sends a request, following redirects the way [`crate::ApiCaller`] does with the
+    /// default [`RedirectPolicy`]
+    ///
+    /// Credentials such as the `x-password` header are dropped when a redirect leaves the
+    /// origin of the request.
+    fn send(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+        let mut request = request.build().map_err(transport_error)?;
+        let policy = RedirectPolicy::default();
+        let mut hops = 0;
+        loop {
+            let sent = request.try_clone();
+            let response = self.client.execute(request).map_err(transport_error)?;
+            // A body read from a file can't be sent again, so its redirects are returned
+            let next = match sent {
+                Some(sent) => policy.next(sent, response.status(), response.headers(), hops)?,
+                None => None,
+            };
+            let Some(next) = next else {
+                return Ok(response);
+            };
+
+            request = next;
+            hops += 1;
+        }
+    }
+}
+
+/// Blocking counterpart of [`crate::with_content_type`]
+fn with_content_type(
+    part: multipart::Part,
+    content_type: Option<&str>,
+) -> anyhow::Result<multipart::Part> {
+    match content_type {
+        Some(mime) => part
+            .mime_str(mime)
+            .with_context(|| format!("invalid content type {mime:?}")),
+        None => Ok(part),
+    }
+}
+
+/// Blocking counterpart of [`crate::parse_response`]
+/// Reads the whole body of a download, failing with [`Error::ResponseTooLarge`] once it goes
+/// over `limit` and with [`Error::ChecksumMismatch`] unless it has the checksum `expected`
+///
+/// See [`crate::ApiCaller::download_file_with`]
+fn read_body(
+    mut response: Response,
+    limit: Option<u64>,
+    expected: Option<&Checksum>,
+) -> anyhow::Result<Vec<u8>> {
+    let too_large = |received| {
+        limit
+            .filter(|&limit| received > limit)
+            .map(|limit| Error::ResponseTooLarge { limit, received })
+    };
+    let length = response.content_length();
+    if let Some(e) = length.and_then(too_large) {
+        return Err(e.into());
+    }
+
+    let mut hasher = expected.map(|expected| Hasher::new(expected.algorithm()));
+    let capacity = length.unwrap_or_default().min(limit.unwrap_or(u64::MAX));
+    let mut content = Vec::with_capacity(capacity as usize);
+    let mut buffer = vec![0; READ_CHUNK_SIZE];
+    loop {
+        let read = match response.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("reading response body"),
+        };
+        content.extend_from_slice(&buffer[..read]);
+        if let Some(e) = too_large(content.len() as u64) {
+            return Err(e.into());
+        }
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    if let (Some(hasher), Some(expected)) = (hasher, expected) {
+        hasher.verify(expected)?;
+    }
+
+    Ok(content)
+}
+
+/// Size of the reads made while reading a download
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+fn parse_response<T: DeserializeOwned>(response: Response) -> anyhow::Result<T> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response
+        .bytes()
+        .map_err(transport_error)
+        .context("reading response body")?;
+
+    decode(status, &headers, &body)
+}
+
+/// Blocking counterpart of [`crate::error_response`]
+fn error_response(response: Response) -> anyhow::Error {
+    let status = response.status();
+    let headers = response.headers().clone();
+    match response.bytes() {
+        Ok(body) => decode_error(status, &headers, &body),
+        Err(e) => anyhow::Error::new(e).context(format!("reading {status} response body")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{sent_password, TRICKY_PASSWORDS};
+    use wiremock::{
+        matchers::{body_json, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn file_json(token: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": 1,
+            "token": token,
+            "url": format!("http://127.0.0.1:8081/f/1712345/{token}.txt"),
+            "bucket": null,
+            "album": null,
+            "views": 0,
+            "retentionPeriod": 3600000,
+            "options": { "hideFilename": false, "oneTimeDownload": false, "protected": false }
+        })
+    }
+
+    /// Runs the blocking caller off the runtime driving the mock server
+    async fn blocking<T: Send + 'static>(
+        server: &MockServer,
+        call: impl FnOnce(ApiCaller) -> T + Send + 'static,
+    ) -> T {
+        // The blocking client owns a runtime of its own, so it can't be created or dropped here
+        let target = Target::new(format!("{}/rest", server.uri()));
+        tokio::task::spawn_blocking(move || call(ApiCaller::new().on(&target)))
+            .await
+            .expect("blocking call panicked")
+    }
+
+    #[tokio::test]
+    async fn upload_and_file_info() -> anyhow::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/some-bucket"))
+            .and(query_param("hide_filename", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/uploaded"))
+            .and(query_param("formatted", "false"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (uploaded, info) = blocking(&server, |caller| -> anyhow::Result<_> {
+            let request = WaifuUploadRequest::new()
+                .bytes(b"content".to_vec(), "file.txt")
+                .bucket("some-bucket")
+                .hide_filename(true);
+            let uploaded = caller.upload_file(request)?;
+            let info = caller.file_info(WaifuGetRequest::new(&uploaded.token))?;
+            Ok((uploaded, info))
+        })
+        .await?;
+
+        assert_eq!(uploaded.token, "uploaded");
+        assert_eq!(info.token, "uploaded");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tricky_passwords_survive_every_upload_source() -> anyhow::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .mount(&server)
+            .await;
+        let tmp = std::env::temp_dir().join("blocking_tricky_passwords.txt");
+        std::fs::write(&tmp, b"content")?;
+
+        blocking(&server, move |caller| -> anyhow::Result<_> {
+            for password in TRICKY_PASSWORDS {
+                for request in [
+                    WaifuUploadRequest::new().file(&tmp),
+                    WaifuUploadRequest::new().bytes(b"content".to_vec(), "file.txt"),
+                    WaifuUploadRequest::new().url("https://example.com/image.png"),
+                ] {
+                    caller.upload_file(request.password(password))?;
+                }
+            }
+            std::fs::remove_file(&tmp)?;
+            Ok(())
+        })
+        .await?;
+
+        let received = server.received_requests().await.unwrap();
+        assert_eq!(received.len(), TRICKY_PASSWORDS.len() * 3);
+        for (sent, password) in received.chunks(3).zip(TRICKY_PASSWORDS) {
+            for request in sent {
+                assert_eq!(sent_password(request).as_deref(), Some(password));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn passwords_are_not_sent_to_other_hosts() -> anyhow::Result<()> {
+        let vault = MockServer::start().await;
+        let elsewhere = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/moved.txt"))
+            .and(header("x-password", "secret"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("location", format!("{}/stored/file.txt", elsewhere.uri())),
+            )
+            .expect(1)
+            .mount(&vault)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/stored/file.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"content".to_vec()))
+            .expect(1)
+            .mount(&elsewhere)
+            .await;
+
+        let url = format!("{}/f/1/moved.txt", vault.uri());
+        let content = blocking(&vault, move |caller| {
+            caller.download_file(&url, Some("secret".to_string()))
+        })
+        .await?;
+        assert_eq!(content, b"content");
+
+        let received = elsewhere.received_requests().await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(!received[0].headers.contains_key("x-password"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_options_are_enforced() -> anyhow::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/file.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"content".to_vec()))
+            .mount(&server)
+            .await;
+        let url = format!("{}/f/1/file.txt", server.uri());
+        let download = |options: DownloadOptions| {
+            let url = url.clone();
+            blocking(&server, move |caller| {
+                caller.download_file_with(&url, options)
+            })
+        };
+
+        let options = DownloadOptions::new()
+            .checksum(Checksum::sha256(b"content"))
+            .identity_encoding();
+        assert_eq!(download(options).await?, b"content");
+        let received = server.received_requests().await.unwrap();
+        assert_eq!(received[0].headers["accept-encoding"], "identity");
+
+        let wrong = Checksum::sha1(b"something else");
+        let err = download(DownloadOptions::new().checksum(wrong))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::ChecksumMismatch {
+                expected: wrong,
+                actual: Checksum::sha1(b"content"),
+            })
+        );
+
+        let err = download(DownloadOptions::new().max_bytes(3))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::ResponseTooLarge {
+                limit: 3,
+                received: 7
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn custom_expiry_can_be_cleared() -> anyhow::Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/rest/file"))
+            .and(body_json(serde_json::json!({ "customExpiry": "" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = WaifuModificationRequest::new("file").clear_custom_expiry();
+        let entry = blocking(&server, move |caller| caller.update_file(request)).await?;
+        assert_eq!(entry.token, "file");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn album_operations() -> anyhow::Result<()> {
+        let server = MockServer::start().await;
+        let album = serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [file_json("one")]
+        });
+        Mock::given(method("POST"))
+            .and(path("/rest/album/album/associate"))
+            .and(body_json(serde_json::json!({ "fileTokens": ["one"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(album))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"PK\x03\x04".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (entry, archive) = blocking(&server, |caller| -> anyhow::Result<_> {
+            let entry = caller.associate_with_album("album", &["one"])?;
+            let archive = caller.download_album("album", None)?;
+            Ok((entry, archive))
+        })
+        .await?;
+
+        assert_eq!(entry.files.len(), 1);
+        assert!(archive.starts_with(b"PK"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn errors_are_decoded_like_the_async_caller() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/missing"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Unknown token", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3"))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bucket/some-bucket"))
+            .respond_with(ResponseTemplate::new(503).set_body_raw(
+                "<html><body>Down for maintenance</body></html>",
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+
+        let (missing, limited, unavailable) = blocking(&server, |caller| {
+            (
+                caller.file_info(WaifuGetRequest::new("missing")),
+                caller.create_bucket(),
+                caller.delete_bucket("some-bucket"),
+            )
+        })
+        .await;
+
+        let missing = missing.expect_err("bad token");
+        assert_eq!(
+            missing.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(400)
+        );
+
+        let limited = limited.expect_err("rate limited");
+        assert_eq!(
+            limited.downcast_ref::<Error>(),
+            Some(&Error::RateLimited {
+                retry_after: Some(std::time::Duration::from_secs(3))
+            })
+        );
+
+        let unavailable = unavailable.expect_err("unavailable");
+        assert_eq!(
+            unavailable.downcast_ref::<Error>(),
+            Some(&Error::ServiceUnavailable {
+                status: 503,
+                retry_after: None
+            })
+        );
+    }
+}
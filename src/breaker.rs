@@ -0,0 +1,246 @@
+//! Failing fast while the service is down
+//!
+//! Off unless a [`CircuitBreaker`] is set with [`crate::ApiCallerBuilder::circuit_breaker`].
+//! Once enough requests in a row have failed to get a response or got a server error (5xx),
+//! the circuit opens and requests fail with [`Error::CircuitOpen`] without being sent. After
+//! the cool-down a single probe request is let through: the circuit closes again if it
+//! succeeds, or stays open for another cool-down if it fails. Not available on wasm32.
+use crate::Error;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// When to stop sending requests to a failing service, and for how long
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use waifuvault::{ApiCaller, CircuitBreaker};
+///
+/// let caller = ApiCaller::builder()
+///     .circuit_breaker(CircuitBreaker {
+///         failure_threshold: 10,
+///         cool_down: Duration::from_secs(60),
+///     })
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Number of failures in a row that opens the circuit
+    pub failure_threshold: u32,
+
+    /// How long the circuit stays open before a probe request is let through
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    /// Requests are sent, counting the failures in a row
+    Closed { failures: u32 },
+    /// Requests fail without being sent until the cool-down ends
+    Open { until: Instant },
+    /// A probe request is in flight, others fail without being sent
+    HalfOpen,
+}
+
+/// Circuit state of a caller, shared by its clones
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    config: CircuitBreaker,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    pub(crate) fn new(config: CircuitBreaker) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Asks to send a request, failing with [`Error::CircuitOpen`] if it mustn't be
+    pub(crate) fn permit(&self) -> Result<Permit<'_>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let probe = match *state {
+            State::Closed { .. } => false,
+            State::Open { until } if Instant::now() >= until => {
+                crate::trace::debug!("circuit half-open, sending a probe request");
+                *state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => return Err(Error::CircuitOpen),
+        };
+
+        Ok(Permit {
+            breaker: self,
+            probe,
+            finished: false,
+        })
+    }
+
+    fn open(&self, state: &mut State) {
+        crate::trace::debug!(cool_down = ?self.config.cool_down, "circuit opened");
+        *state = State::Open {
+            until: Instant::now() + self.config.cool_down,
+        };
+    }
+}
+
+/// Circuits of a caller and the callers made from it with [`crate::ApiCaller::on`], one per host
+#[derive(Debug)]
+pub(crate) struct Breakers {
+    config: CircuitBreaker,
+    by_host: Mutex<HashMap<String, Arc<Breaker>>>,
+}
+
+impl Breakers {
+    pub(crate) fn new(config: CircuitBreaker) -> Self {
+        Self {
+            config,
+            by_host: Mutex::default(),
+        }
+    }
+
+    /// The circuit of the host `base_url` points at, closed the first time it's asked for
+    pub(crate) fn for_url(&self, base_url: &str) -> Arc<Breaker> {
+        let host = match reqwest::Url::parse(base_url) {
+            Ok(url) => format!(
+                "{}:{}",
+                url.host_str().unwrap_or_default(),
+                url.port_or_known_default().unwrap_or_default()
+            ),
+            Err(_) => base_url.to_string(),
+        };
+
+        self.by_host
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Breaker::new(self.config.clone())))
+            .clone()
+    }
+}
+
+/// Permission to send one request, told how it went with [`Permit::finish`]
+pub(crate) struct Permit<'a> {
+    breaker: &'a Breaker,
+    probe: bool,
+    finished: bool,
+}
+
+impl Permit<'_> {
+    /// Records whether the request failed to get a response or got a server error
+    pub(crate) fn finish(mut self, failed: bool) {
+        self.finished = true;
+        let mut state = self.breaker.state.lock().unwrap();
+        match (&mut *state, failed) {
+            (_, false) => *state = State::Closed { failures: 0 },
+            (State::HalfOpen, true) if self.probe => self.breaker.open(&mut state),
+            (State::Closed { failures }, true) => {
+                *failures += 1;
+                if *failures >= self.breaker.config.failure_threshold {
+                    self.breaker.open(&mut state);
+                }
+            }
+            // Requests sent before the circuit opened don't extend the cool-down
+            (_, true) => {}
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    /// A probe that never finished, such as when cancelled, lets the next request probe
+    fn drop(&mut self) {
+        if self.finished || !self.probe {
+            return;
+        }
+
+        let mut state = self.breaker.state.lock().unwrap();
+        if let State::HalfOpen = *state {
+            *state = State::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> Breaker {
+        Breaker::new(CircuitBreaker {
+            failure_threshold: 2,
+            cool_down: Duration::ZERO,
+        })
+    }
+
+    #[test]
+    fn successes_reset_the_failure_count() {
+        let breaker = breaker();
+        breaker.permit().unwrap().finish(true);
+        breaker.permit().unwrap().finish(false);
+        breaker.permit().unwrap().finish(true);
+
+        assert!(!breaker.permit().unwrap().probe);
+    }
+
+    #[test]
+    fn only_one_probe_at_a_time() {
+        let breaker = breaker();
+        breaker.permit().unwrap().finish(true);
+        breaker.permit().unwrap().finish(true);
+
+        let probe = breaker.permit().unwrap();
+        assert!(probe.probe);
+        assert_eq!(breaker.permit().err(), Some(Error::CircuitOpen));
+
+        probe.finish(true);
+        assert!(breaker.permit().unwrap().probe);
+    }
+
+    #[test]
+    fn abandoned_probe_lets_another_through() {
+        let breaker = breaker();
+        breaker.permit().unwrap().finish(true);
+        breaker.permit().unwrap().finish(true);
+
+        drop(breaker.permit().unwrap());
+        let probe = breaker.permit().unwrap();
+        assert!(probe.probe);
+
+        probe.finish(false);
+        assert!(!breaker.permit().unwrap().probe);
+    }
+
+    #[test]
+    fn circuits_are_kept_per_host() {
+        let breakers = Breakers::new(CircuitBreaker::default());
+        let public = breakers.for_url("https://waifuvault.moe/rest");
+
+        assert!(Arc::ptr_eq(
+            &public,
+            &breakers.for_url("https://waifuvault.moe:443/other")
+        ));
+        assert!(!Arc::ptr_eq(
+            &public,
+            &breakers.for_url("https://vault.example.com/rest")
+        ));
+        assert!(!Arc::ptr_eq(
+            &public,
+            &breakers.for_url("http://waifuvault.moe/rest")
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+//! Digests of file content, used to check downloads arrived intact and record what was
+//! uploaded
+//!
+//! A [`Checksum`] is given to [`crate::ApiCaller::download_file_verified`] or
+//! [`crate::api::DownloadOptions::checksum`]. The content is hashed as it arrives, and the
+//! download fails with [`Error::ChecksumMismatch`] if the digest isn't the one expected.
+//! Uploads hash their content as it is sent when asked to with
+//! [`crate::api::WaifuUploadRequest::compute_checksum`].
+use crate::Error;
+use anyhow::Context;
+use sha1::Digest;
+use std::sync::{Arc, Mutex};
+
+/// Algorithm a [`Checksum`] is computed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// SHA-1, producing a [`Checksum::Sha1`]
+    Sha1,
+
+    /// SHA-256, producing a [`Checksum::Sha256`]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Name of the algorithm, as used before the digest in the text form of a checksum
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Expected digest of a file
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::Checksum;
+///
+/// let checksum: Checksum = "sha1:2fd4e1c67a2d28fced849ee1bb76e7391b93eb12".parse()?;
+/// assert_eq!(checksum, Checksum::sha1(b"The quick brown fox jumps over the lazy dog"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// SHA-1 digest
+    Sha1([u8; 20]),
+
+    /// SHA-256 digest
+    Sha256([u8; 32]),
+}
+
+impl Checksum {
+    /// SHA-1 digest of `content`
+    pub fn sha1(content: impl AsRef<[u8]>) -> Self {
+        Self::Sha1(sha1::Sha1::digest(content).into())
+    }
+
+    /// SHA-256 digest of `content`
+    pub fn sha256(content: impl AsRef<[u8]>) -> Self {
+        Self::Sha256(sha2::Sha256::digest(content).into())
+    }
+
+    /// Bytes of the digest
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Sha1(digest) => digest,
+            Self::Sha256(digest) => digest,
+        }
+    }
+
+    /// Algorithm the digest was computed with
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Self::Sha1(_) => HashAlgorithm::Sha1,
+            Self::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    /// Formats the checksum as the algorithm and lowercase hex digest, such as `sha1:2fd4...`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.algorithm().name())?;
+        self.as_bytes()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = anyhow::Error;
+
+    /// Parses a checksum such as `sha256:<hex digest>`, as formatted by `Display`
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (algorithm, digest) = s
+            .split_once(':')
+            .with_context(|| format!("checksum {s:?} has no algorithm, expected sha1:<hex>"))?;
+        match algorithm.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(Self::Sha1(from_hex(digest)?)),
+            "sha256" => Ok(Self::Sha256(from_hex(digest)?)),
+            _ => anyhow::bail!("unsupported checksum algorithm {algorithm:?}"),
+        }
+    }
+}
+
+/// Decodes a hex digest of exactly `N` bytes
+fn from_hex<const N: usize>(hex: &str) -> anyhow::Result<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        anyhow::bail!("digest {hex:?} is not {} hex digits", N * 2);
+    }
+
+    let mut digest = [0; N];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).expect("checked to be ASCII");
+        *byte =
+            u8::from_str_radix(pair, 16).with_context(|| format!("digest {hex:?} is not hex"))?;
+    }
+
+    Ok(digest)
+}
+
+/// Hashes content as it arrives or is sent
+pub(crate) enum Hasher {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => hasher.update(chunk),
+            Self::Sha256(hasher) => hasher.update(chunk),
+        }
+    }
+
+    /// Digest of everything hashed
+    pub(crate) fn finish(self) -> Checksum {
+        match self {
+            Self::Sha1(hasher) => Checksum::Sha1(hasher.finalize().into()),
+            Self::Sha256(hasher) => Checksum::Sha256(hasher.finalize().into()),
+        }
+    }
+
+    /// Fails with [`Error::ChecksumMismatch`] unless the content hashed is `expected`
+    pub(crate) fn verify(self, expected: &Checksum) -> Result<(), Error> {
+        let actual = self.finish();
+        if actual != *expected {
+            return Err(Error::ChecksumMismatch {
+                expected: *expected,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Hasher fed by an upload body as it is sent, and read by the upload once it is done
+#[derive(Clone)]
+pub(crate) struct SharedHasher {
+    algorithm: HashAlgorithm,
+    hasher: Arc<Mutex<Hasher>>,
+}
+
+impl SharedHasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        Self {
+            algorithm,
+            hasher: Arc::new(Mutex::new(Hasher::new(algorithm))),
+        }
+    }
+
+    pub(crate) fn update(&self, chunk: &[u8]) {
+        self.lock().update(chunk);
+    }
+
+    /// Digest of everything hashed, once the body has been sent
+    ///
+    /// The client may still hold on to the body, so the hasher is swapped out rather than
+    /// unwrapped.
+    pub(crate) fn finish(self) -> Checksum {
+        std::mem::replace(&mut *self.lock(), Hasher::new(self.algorithm)).finish()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Hasher> {
+        self.hasher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Stream of upload content that hashes each chunk as it is read
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Hashed<St> {
+    inner: std::pin::Pin<Box<St>>,
+    hasher: SharedHasher,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<St> Hashed<St> {
+    pub(crate) fn new(inner: St, hasher: SharedHasher) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            hasher,
+        }
+    }
+}
+
+/// Body sending a stream of chunks that are already `Bytes`
+///
+/// Generic callers go through this so their own `Bytes: From<St::Ok>` bounds aren't taken
+/// for the bounds of the stream they wrap.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn stream_body<S, E>(stream: S) -> reqwest::Body
+where
+    S: futures_core::Stream<Item = Result<bytes::Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+{
+    reqwest::Body::wrap_stream(stream)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<St> futures_core::Stream for Hashed<St>
+where
+    St: futures_core::TryStream,
+    bytes::Bytes: From<St::Ok>,
+{
+    type Item = Result<bytes::Bytes, St::Error>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let item = match self.inner.as_mut().try_poll_next(cx) {
+            std::task::Poll::Ready(item) => item.map(|chunk| chunk.map(bytes::Bytes::from)),
+            std::task::Poll::Pending => return std::task::Poll::Pending,
+        };
+        if let Some(Ok(chunk)) = &item {
+            self.hasher.update(chunk);
+        }
+
+        std::task::Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_round_trip_as_text() {
+        let sha1 = Checksum::sha1(b"abc");
+        assert_eq!(
+            sha1.to_string(),
+            "sha1:a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        let sha256 = Checksum::sha256(b"abc");
+        assert_eq!(
+            sha256.to_string(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert_eq!(sha1.to_string().parse::<Checksum>().unwrap(), sha1);
+        assert_eq!(
+            sha256
+                .to_string()
+                .to_uppercase()
+                .parse::<Checksum>()
+                .unwrap(),
+            sha256
+        );
+        assert!("md5:900150983cd24fb0d6963f7d28e17f72"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("sha1:a9993e".parse::<Checksum>().is_err());
+        assert!("a9993e364706816aba3e25717850c26c9cd0d89d"
+            .parse::<Checksum>()
+            .is_err());
+    }
+
+    #[test]
+    fn hashing_in_chunks_matches_the_whole() {
+        let expected = Checksum::sha256(b"hello world");
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert_eq!(hasher.verify(&expected), Ok(()));
+
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+        hasher.update(b"hello");
+        assert!(matches!(
+            hasher.verify(&expected),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,369 @@
+//! Errors raised by the SDK itself rather than reported by the Waifu Vault API
+//!
+//! These are returned inside an [`anyhow::Error`] like [`crate::api::WaifuError`], and can be
+//! recovered with [`anyhow::Error::downcast_ref`] to decide how to react.
+use crate::{api::FileToken, Checksum};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::time::Duration;
+
+/// Typed failures that callers may want to handle programmatically
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The service is rate limiting requests (HTTP 429)
+    RateLimited {
+        /// How long the service asked to wait before retrying, if it said
+        retry_after: Option<Duration>,
+    },
+
+    /// The service is down, such as for maintenance (HTTP 502 or 503)
+    ///
+    /// The proxy in front of the service answers with an HTML page rather than an API error
+    /// while it is down, so the body isn't kept
+    ServiceUnavailable {
+        /// The HTTP status
+        status: u16,
+
+        /// How long the service asked to wait before retrying, if it said
+        retry_after: Option<Duration>,
+    },
+
+    /// A request kept failing with transient errors until the retry policy ran out
+    ///
+    /// This wraps the error from the final attempt, which is still reachable through
+    /// [`anyhow::Error::downcast_ref`] or [`anyhow::Error::chain`]
+    GaveUp {
+        /// How many attempts were made in total
+        attempts: u32,
+    },
+
+    /// Content is larger than the service accepts
+    TooLarge {
+        /// Size of the content in bytes
+        size: u64,
+
+        /// Largest size the service accepts in bytes
+        max: u64,
+    },
+
+    /// A download is larger than the most it was allowed to hold in memory
+    ResponseTooLarge {
+        /// Most bytes allowed
+        limit: u64,
+
+        /// Bytes the response said it had, or had sent when it went over the limit
+        received: u64,
+    },
+
+    /// A download didn't have the content it was expected to have
+    ChecksumMismatch {
+        /// Digest the content was expected to have
+        expected: Checksum,
+
+        /// Digest of the content received
+        actual: Checksum,
+    },
+
+    /// Content has a type the service refuses to store
+    BannedMimeType {
+        /// MIME type guessed from the filename
+        mime: String,
+    },
+
+    /// Files were asked for from an album they aren't part of
+    NotInAlbum {
+        /// Tokens of the files missing from the album
+        tokens: Vec<FileToken>,
+    },
+
+    /// A file is deleted once it is downloaded, so copying it would consume it
+    OneTimeDownload {
+        /// Token of the file
+        token: FileToken,
+    },
+
+    /// A protected file's password was changed without giving its current password
+    PreviousPasswordRequired {
+        /// Token of the file
+        token: FileToken,
+    },
+
+    /// A file was copied by a move, but the original couldn't be deleted, or the copy didn't
+    /// match it
+    ///
+    /// This wraps the error that stopped the move, which is still reachable through
+    /// [`anyhow::Error::downcast_ref`] or [`anyhow::Error::chain`]. Both files are left in
+    /// the vault for the caller to clean up.
+    MoveIncomplete {
+        /// Token of the original file
+        source: FileToken,
+
+        /// Token of the copy
+        copy: FileToken,
+    },
+
+    /// An archive entry would be written outside the directory it is extracted into
+    UnsafeArchivePath {
+        /// Name of the entry in the archive
+        name: String,
+    },
+
+    /// An archive couldn't be read as a zip file
+    CorruptArchive {
+        /// What the zip reader objected to
+        reason: String,
+    },
+
+    /// A URL isn't a Waifu Vault file URL of the form `https://waifuvault.moe/f/{id}/{file}`
+    InvalidFileUrl {
+        /// The URL that was given
+        url: String,
+    },
+
+    /// An upload request was sent without a file, url or bytes to upload
+    NoUploadSource,
+
+    /// The service answered with a body the SDK doesn't understand, such as after a change to
+    /// the API
+    ///
+    /// The whole body is kept, so it can be included in a bug report. A body that was meant
+    /// to be a successful response wraps the parse error, which is still reachable through
+    /// [`anyhow::Error::chain`].
+    UnexpectedResponse {
+        /// The HTTP status
+        status: u16,
+
+        /// The body as received, with invalid UTF-8 replaced
+        body: String,
+    },
+
+    /// A request took longer than the timeout set on it
+    TimedOut,
+
+    /// The caller was cancelled before the request finished
+    Cancelled,
+
+    /// The caller's circuit breaker is open after repeated failures, so the request wasn't
+    /// sent
+    CircuitOpen,
+
+    /// A streamed upload body would have to be sent more than once
+    ///
+    /// Streams can't be replayed, so they can't be uploaded by a caller that retries
+    /// uploads, or uploaded again from a clone of the request
+    NonReplayableBody,
+
+    /// A token can't be used in an endpoint path, such as an empty token
+    InvalidToken {
+        /// The token that was given
+        token: String,
+    },
+
+    /// A URL to upload from isn't an absolute http or https URL
+    InvalidUrl {
+        /// The URL that was given
+        url: String,
+    },
+
+    /// The service redirected a request somewhere the caller's redirect policy doesn't
+    /// allow, or too many times
+    RedirectNotFollowed {
+        /// Where the request was redirected to
+        location: String,
+    },
+
+    /// A proxy URL given to a caller builder can't be used
+    InvalidProxy {
+        /// The URL that was given
+        url: String,
+
+        /// What the HTTP client objected to
+        reason: String,
+    },
+
+    /// An expiry isn't in the `<number><m|h|d>` form the service accepts
+    InvalidExpiry {
+        /// The expiry that was given
+        expiry: String,
+    },
+
+    /// A download password has characters outside Latin-1, which can't be sent in the
+    /// `x-password` header
+    ///
+    /// The password itself isn't kept, so the error can be logged.
+    UnencodablePassword,
+
+    /// There is no bucket token saved at a path, such as before the first
+    /// [`crate::api::WaifuBucketEntry::save_token`]
+    NoSavedBucket {
+        /// Where the token was looked for
+        path: std::path::PathBuf,
+    },
+
+    /// A file that should hold a saved bucket token doesn't
+    CorruptSavedBucket {
+        /// The file that was read
+        path: std::path::PathBuf,
+
+        /// What was wrong with its contents
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::RateLimited {
+                retry_after: Some(wait),
+            } => write!(f, "rate limited, retry after {}s", wait.as_secs()),
+            Error::RateLimited { retry_after: None } => write!(f, "rate limited"),
+            Error::ServiceUnavailable {
+                status,
+                retry_after: Some(wait),
+            } => write!(
+                f,
+                "service unavailable ({status}), retry after {}s",
+                wait.as_secs()
+            ),
+            Error::ServiceUnavailable {
+                status,
+                retry_after: None,
+            } => write!(f, "service unavailable ({status})"),
+            Error::GaveUp { attempts } => write!(f, "gave up after {attempts} attempts"),
+            Error::TooLarge { size, max } => {
+                write!(f, "content is {size} bytes, the limit is {max} bytes")
+            }
+            Error::ResponseTooLarge { limit, received } => write!(
+                f,
+                "response is over the limit of {limit} bytes, got {received} bytes"
+            ),
+            Error::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch, expected {expected} but got {actual}")
+            }
+            Error::BannedMimeType { mime } => write!(f, "content type {mime} is not allowed"),
+            Error::NotInAlbum { tokens } => {
+                let tokens: Vec<&str> = tokens.iter().map(FileToken::as_str).collect();
+                write!(f, "files not in album: {}", tokens.join(", "))
+            }
+            Error::UnsafeArchivePath { name } => {
+                write!(
+                    f,
+                    "archive entry {name} would be written outside the destination"
+                )
+            }
+            Error::CorruptArchive { reason } => write!(f, "archive is corrupt: {reason}"),
+            Error::OneTimeDownload { token } => write!(
+                f,
+                "file {token} is deleted once downloaded, copying it would consume it"
+            ),
+            Error::PreviousPasswordRequired { token } => write!(
+                f,
+                "file {token} is protected, its previous password is needed to change it"
+            ),
+            Error::MoveIncomplete { source, copy } => {
+                write!(f, "file {source} was copied to {copy} but not moved")
+            }
+            Error::InvalidFileUrl { url } => write!(f, "{url} is not a Waifu Vault file URL"),
+            Error::NoUploadSource => {
+                write!(f, "upload request has no content, set a file, url or bytes")
+            }
+            Error::UnexpectedResponse { status, body } => write!(
+                f,
+                "unexpected {status} response: {}",
+                crate::body_snippet(body.as_bytes())
+            ),
+            Error::TimedOut => write!(f, "request timed out"),
+            Error::Cancelled => write!(f, "request was cancelled"),
+            Error::CircuitOpen => {
+                write!(f, "not sent, the service has been failing repeatedly")
+            }
+            Error::NonReplayableBody => {
+                write!(f, "a streamed upload body can't be sent more than once")
+            }
+            Error::InvalidToken { token } => write!(f, "invalid token {token:?}"),
+            Error::InvalidUrl { url } => {
+                write!(f, "invalid upload URL {url:?}, expected an http or https URL")
+            }
+            Error::RedirectNotFollowed { location } => {
+                write!(f, "redirect to {location} not allowed by the redirect policy")
+            }
+            Error::InvalidProxy { url, reason } => write!(f, "invalid proxy {url:?}: {reason}"),
+            Error::UnencodablePassword => write!(
+                f,
+                "password has characters outside Latin-1, which can't be sent in a header"
+            ),
+            Error::NoSavedBucket { path } => {
+                write!(f, "no bucket token saved at {}", path.display())
+            }
+            Error::CorruptSavedBucket { path, reason } => write!(
+                f,
+                "{} doesn't hold a bucket token: {reason}",
+                path.display()
+            ),
+            Error::InvalidExpiry { expiry } => write!(
+                f,
+                "invalid expiry {expiry:?}, expected a number followed by m (minutes), h (hours) or d (days)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reads the `Retry-After` header, which is either a number of seconds or an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    // There is no system clock to compare dates against on wasm32
+    #[cfg(target_arch = "wasm32")]
+    return None;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let at = httpdate::parse_http_date(value).ok()?;
+        Some(
+            at.duration_since(std::time::SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::time::SystemTime;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(retry_after(&headers("30")), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let at = SystemTime::now() + Duration::from_secs(120);
+        let wait = retry_after(&headers(&httpdate::fmt_http_date(at))).expect("valid date");
+        assert!(wait <= Duration::from_secs(120));
+        assert!(wait >= Duration::from_secs(110));
+
+        // Dates in the past mean there is no need to wait
+        let past = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH);
+        assert_eq!(retry_after(&headers(&past)), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_absent_or_garbage() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+}
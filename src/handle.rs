@@ -0,0 +1,214 @@
+//! Handles binding a caller to a single bucket or album
+//!
+//! These are a convenience layer over [`ApiCaller`], saving the token from being passed to
+//! every call. Everything they do can also be done with the methods on the caller.
+use crate::{api::*, ApiCaller};
+
+/// A bucket on Waifu Vault, bound to the caller used to manage it
+///
+/// Created with [`ApiCaller::create_bucket_handle`] or [`ApiCaller::bucket`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let caller = ApiCaller::new();
+///     let bucket = caller.create_bucket_handle().await?;
+///
+///     bucket.upload(WaifuUploadRequest::new().file("/some/file/path")).await?;
+///     for file in bucket.files().await? {
+///         println!("{}", file.url);
+///     }
+///
+///     bucket.delete().await?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    caller: ApiCaller,
+    token: BucketToken,
+}
+
+impl Bucket {
+    pub(crate) fn new(caller: ApiCaller, token: BucketToken) -> Self {
+        Self { caller, token }
+    }
+
+    /// Token of the bucket
+    pub fn token(&self) -> &BucketToken {
+        &self.token
+    }
+
+    /// Uploads a file into the bucket
+    ///
+    /// Any bucket already set on the request is replaced with this one.
+    pub async fn upload(&self, request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry> {
+        self.caller.upload_file(request.bucket(&self.token)).await
+    }
+
+    /// Uploads every file under a directory into the bucket
+    ///
+    /// See [`ApiCaller::upload_directory`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn upload_directory(
+        &self,
+        dir: impl AsRef<std::path::Path>,
+        template: WaifuUploadRequest<NoSource>,
+    ) -> anyhow::Result<DirectoryUpload> {
+        self.caller
+            .upload_directory(dir, &self.token, template)
+            .await
+    }
+
+    /// Information on the bucket and everything in it
+    pub async fn info(&self) -> anyhow::Result<WaifuBucketEntry> {
+        self.caller.get_bucket(&self.token).await
+    }
+
+    /// Totals describing how full and how busy the bucket is
+    pub async fn stats(&self) -> anyhow::Result<BucketStats> {
+        self.caller.bucket_stats(&self.token).await
+    }
+
+    /// Files contained within the bucket
+    pub async fn files(&self) -> anyhow::Result<Vec<WaifuFileEntry>> {
+        Ok(self.info().await?.files)
+    }
+
+    /// Creates an album in the bucket
+    pub async fn create_album(&self, name: &str) -> anyhow::Result<WaifuAlbumEntry> {
+        self.caller.create_album(&self.token, name).await
+    }
+
+    /// Deletes every file in the bucket, keeping the bucket
+    ///
+    /// See [`ApiCaller::empty_bucket`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn empty(&self) -> anyhow::Result<EmptiedBucket> {
+        self.caller.empty_bucket(&self.token).await
+    }
+
+    /// Deletes the bucket along with ALL files contained within it
+    pub async fn delete(self) -> anyhow::Result<bool> {
+        self.caller.delete_bucket(&self.token).await
+    }
+}
+
+/// An album on Waifu Vault, bound to the caller used to manage it
+///
+/// Created with [`ApiCaller::album`]. Sharing the album through the handle remembers its
+/// public link until access is revoked.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use waifuvault::ApiCaller;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let caller = ApiCaller::new();
+///     let mut album = caller.album("album-token");
+///
+///     album.associate(&["file-token-1", "file-token-2"]).await?;
+///     album.share().await?;
+///     println!("shared at {}", album.public_url().unwrap_or_default());
+///
+///     album.revoke().await?;
+///     album.delete(false).await?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Album {
+    caller: ApiCaller,
+    token: AlbumToken,
+    shared: Option<WaifuShareResult>,
+}
+
+impl Album {
+    pub(crate) fn new(caller: ApiCaller, token: AlbumToken) -> Self {
+        Self {
+            caller,
+            token,
+            shared: None,
+        }
+    }
+
+    /// Token of the album
+    pub fn token(&self) -> &AlbumToken {
+        &self.token
+    }
+
+    /// Public URL of the album, if it was shared through this handle
+    pub fn public_url(&self) -> Option<&str> {
+        self.shared.as_ref().map(|shared| shared.url.as_str())
+    }
+
+    /// Public token of the album, if it was shared through this handle
+    pub fn public_token(&self) -> Option<&str> {
+        self.shared
+            .as_ref()
+            .map(|shared| shared.public_token.as_str())
+    }
+
+    /// Information on the album and the files in it
+    pub async fn info(&self) -> anyhow::Result<WaifuAlbumEntry> {
+        self.caller.get_album(&self.token).await
+    }
+
+    /// Adds files to the album
+    pub async fn associate<I>(&self, file_tokens: I) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        self.caller
+            .associate_with_album(&self.token, file_tokens)
+            .await
+    }
+
+    /// Removes files from the album, leaving them in the bucket
+    pub async fn disassociate<I>(&self, file_tokens: I) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        self.caller
+            .disassociate_from_album(&self.token, file_tokens)
+            .await
+    }
+
+    /// Makes the album public, remembering its public link
+    pub async fn share(&mut self) -> anyhow::Result<WaifuShareResult> {
+        let shared = self.caller.share_album_link(&self.token).await?;
+        self.shared = Some(shared.clone());
+
+        Ok(shared)
+    }
+
+    /// Revokes public access to the album
+    pub async fn revoke(&mut self) -> anyhow::Result<WaifuGenericMessage> {
+        let message = self.caller.revoke_album(&self.token).await?;
+        if message.success {
+            self.shared = None;
+        }
+
+        Ok(message)
+    }
+
+    /// Downloads a zip archive of the album, or of only the files with the given ids
+    pub async fn download(&self, file_ids: Option<&[u64]>) -> anyhow::Result<Vec<u8>> {
+        self.caller.download_album(&self.token, file_ids).await
+    }
+
+    /// Deletes the album, and the files in it as well if `delete_files` is set
+    pub async fn delete(self, delete_files: bool) -> anyhow::Result<WaifuGenericMessage> {
+        self.caller.delete_album(&self.token, delete_files).await
+    }
+}
@@ -360,15 +360,104 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! # Talking to Another Instance
+//!
+//! ```rust,no_run
+//! use waifuvault::{ApiCaller, Target};
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let caller = ApiCaller::new();
+//!     let self_hosted = Target::new("https://vault.example.com/rest");
+//!
+//!     // Send a single call to a self-hosted instance, reusing the same client
+//!     let bucket = caller.on(&self_hosted).create_bucket().await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Retrying Transient Failures
+//!
+//! ```rust,no_run
+//! use waifuvault::{ApiCaller, Error, RetryPolicy, api::WaifuGetRequest};
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     // Reads are retried on 5xx responses and connection errors, uploads are not
+//!     let caller = ApiCaller::builder()
+//!         .retry(RetryPolicy::default())
+//!         .build();
+//!
+//!     if let Err(e) = caller.file_info(WaifuGetRequest::new("some-token")).await {
+//!         if let Some(Error::GaveUp { attempts }) = e.downcast_ref::<Error>() {
+//!             eprintln!("failed after {attempts} attempts");
+//!         }
+//!         return Err(e);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Stability
+//!
+//! Response and report types are `#[non_exhaustive]` so new fields returned by the service
+//! can be added without a breaking release. They can be read and destructured with `..`,
+//! but not built with struct literals outside this crate. Request types are built through
+//! their constructors and builder methods.
 
 pub mod api;
-
-use std::{collections::HashMap, path::PathBuf};
+#[cfg(feature = "zip")]
+pub mod archive;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod breaker;
+pub mod checksum;
+pub mod error;
+pub mod handle;
+pub mod observe;
+#[cfg(not(target_arch = "wasm32"))]
+mod pacing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod redirect;
+pub mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+mod store;
+#[cfg(all(any(test, feature = "test-utils"), not(target_arch = "wasm32")))]
+pub mod test_utils;
+mod trace;
+mod transport;
+pub mod vault;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use breaker::CircuitBreaker;
+pub use checksum::{Checksum, HashAlgorithm};
+pub use error::Error;
+pub use handle::{Album, Bucket};
+pub use observe::{ApiObserver, Operation};
+#[cfg(not(target_arch = "wasm32"))]
+pub use redirect::RedirectPolicy;
+pub use retry::RetryPolicy;
+pub use vault::WaifuVaultApi;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::{collections::HashMap, sync::Arc};
 
 use api::*;
 
 use anyhow::Context;
-use reqwest::Client;
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde::de::DeserializeOwned;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::OnceCell;
+use transport::HttpTransport;
 
 /// REST endpoint for the service
 #[cfg(not(test))]
@@ -377,578 +466,933 @@ const API: &str = "https://waifuvault.moe/rest";
 #[cfg(test)]
 const API: &str = "http://127.0.0.1:8081/rest";
 
+/// Longest a request may take from start to finish unless the caller is built with another
+///
+/// Not available on wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Longest connecting to the service may take unless the caller is built with another
+///
+/// Not available on wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Shortest interval [`ApiCaller::wait_until_consumed`] and [`ApiCaller::watch_retention`]
+/// poll at, so they don't flood the service
+///
+/// Not available on wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+pub const MIN_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A Waifu Vault instance that requests can be sent to
+///
+/// Defaults to the public service at `https://waifuvault.moe/rest`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Target {
+    /// REST endpoint of the instance, without a trailing slash
+    base_url: String,
+}
+
+impl Target {
+    /// Create a new target from the REST endpoint of an instance
+    ///
+    /// e.g. `https://waifuvault.moe/rest` or `http://localhost:8081/rest`
+    pub fn new(base_url: impl AsRef<str>) -> Self {
+        Self {
+            base_url: base_url.as_ref().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// REST endpoint of the instance
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::new(API)
+    }
+}
+
+/// Restrictions of each instance by base URL, fetched once for every caller sending to it
+type RestrictionsCache = std::sync::Mutex<HashMap<String, Arc<OnceCell<WaifuRestrictions>>>>;
+
+/// The restrictions of the instance at `base_url`, fetched on first use
+fn cached_restrictions(
+    cache: &RestrictionsCache,
+    base_url: &str,
+) -> Arc<OnceCell<WaifuRestrictions>> {
+    let mut cache = cache.lock().unwrap();
+    cache.entry(base_url.to_string()).or_default().clone()
+}
+
 /// Api controller which calls the endpoint
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct ApiCaller {
     client: Client,
+    transport: Arc<dyn HttpTransport>,
+    base_url: String,
+    retry: Option<RetryPolicy>,
+    restrictions_cache: Arc<RestrictionsCache>,
+    restrictions: Arc<OnceCell<WaifuRestrictions>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancel: Option<tokio_util::sync::CancellationToken>,
+    #[cfg(not(target_arch = "wasm32"))]
+    observer: Option<observe::Observer>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pacer: Option<Arc<pacing::Pacer>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    breakers: Option<Arc<breaker::Breakers>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    breaker: Option<Arc<breaker::Breaker>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    redirect: RedirectPolicy,
+    max_download_bytes: Option<u64>,
 }
 
-impl ApiCaller {
-    /// Create a new Waifu Vault API Caller
-    pub fn new() -> Self {
-        Self::default()
+impl Default for ApiCaller {
+    fn default() -> Self {
+        ApiCallerBuilder::default().build()
+    }
+}
+
+/// Builder for an [`ApiCaller`] with non-default settings
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::{ApiCaller, RetryPolicy, Target};
+///
+/// let caller = ApiCaller::builder()
+///     .target(&Target::new("https://vault.example.com/rest"))
+///     .retry(RetryPolicy::default())
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ApiCallerBuilder {
+    client: Option<Client>,
+    target: Target,
+    retry: Option<RetryPolicy>,
+    #[cfg(not(target_arch = "wasm32"))]
+    timeout: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    connect_timeout: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    observer: Option<observe::Observer>,
+    #[cfg(not(target_arch = "wasm32"))]
+    min_request_interval: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_max_idle_per_host: Option<usize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_idle_timeout: Option<Option<std::time::Duration>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    tcp_keepalive: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    proxy: Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    no_proxy: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    redirect: RedirectPolicy,
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "gzip", feature = "brotli", feature = "deflate")
+    ))]
+    decompress: Option<bool>,
+    max_download_bytes: Option<u64>,
+    #[cfg(feature = "middleware")]
+    middleware: Option<reqwest_middleware::ClientWithMiddleware>,
+}
+
+impl ApiCallerBuilder {
+    /// Use an existing HTTP client
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
     }
 
-    /// Creates a bucket with the Waifu Vault API
+    /// Send every request through a client with middleware, such as tracing or retry layers
     ///
-    /// This bucket can be used to upload files into
+    /// Requests are still built by the caller, then executed by `client`, so timeouts,
+    /// default headers and connections come from the client inside it rather than from
+    /// [`ApiCallerBuilder::client`]. Requires the `middleware` feature.
+    #[cfg(feature = "middleware")]
+    pub fn middleware_client(mut self, client: reqwest_middleware::ClientWithMiddleware) -> Self {
+        self.middleware = Some(client);
+        self
+    }
+
+    /// Send requests to another instance
+    pub fn target(mut self, target: &Target) -> Self {
+        self.target = target.clone();
+        self
+    }
+
+    /// Retry transient failures according to the policy
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Longest a request may take from start to finish, [`DEFAULT_TIMEOUT`] unless set
     ///
-    /// # Example
+    /// Requests with a timeout of their own, such as [`WaifuUploadRequest::timeout`], use
+    /// that instead. Ignored when a client is given with [`ApiCallerBuilder::client`], which
+    /// keeps its own timeouts. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Longest connecting to the service may take, [`DEFAULT_CONNECT_TIMEOUT`] unless set
     ///
-    /// ```rust,no_run
-    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    /// Ignored when a client is given with [`ApiCallerBuilder::client`], which keeps its own
+    /// timeouts. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Report every request the caller sends to `observer`, such as to collect metrics
     ///
-    /// #[tokio::main]
-    /// async fn main() -> anyhow::Result<()> {
-    ///     let caller = ApiCaller::new();
+    /// See [`ApiObserver`] for what is reported. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn observer(mut self, observer: Arc<dyn ApiObserver>) -> Self {
+        self.observer = Some(observe::Observer(observer));
+        self
+    }
+
+    /// Leave at least `interval` between the requests the caller sends
     ///
-    ///     // Create a new bucket to upload files to
-    ///     let bucket = caller.create_bucket().await?;
+    /// Every request waits its turn, including downloads and each retry of a request, and
+    /// requests made at the same time are sent one after the other. Clones of the caller and
+    /// callers made from it with [`ApiCaller::on`] share the spacing. Useful to go easy on a
+    /// shared instance during heavy use. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn min_request_interval(mut self, interval: std::time::Duration) -> Self {
+        self.min_request_interval = Some(interval);
+        self
+    }
+
+    /// Fail fast with [`Error::CircuitOpen`] while the service keeps failing
     ///
-    ///     // You can now use the bucket token to upload files to the bucket
+    /// See [`breaker`] for when the circuit opens and closes. Clones of the caller share the
+    /// circuit, while callers made from it with [`ApiCaller::on`] get their own since they
+    /// talk to another instance. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Most idle connections kept open to each host, unlimited unless set
     ///
-    ///     let request = WaifuUploadRequest::new()
-    ///         .file("/some/file/path")
-    ///         .bucket(&bucket.token)
-    ///         .password("set a password")
-    ///         .one_time_download(true);
-    ///     let response = caller.upload_file(request).await?;
+    /// Keeping connections open saves connecting again for every request, which adds up
+    /// when uploading many small files. Ignored when a client is given with
+    /// [`ApiCallerBuilder::client`]. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// How long an idle connection is kept open, 90 seconds unless set
     ///
-    ///     // Do something with the response
+    /// `None` keeps idle connections open until the service closes them. Ignored when a
+    /// client is given with [`ApiCallerBuilder::client`]. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_idle_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Send TCP keep-alive probes on connections after they've been idle for `interval`,
+    /// which are off unless set
     ///
-    ///     Ok(())
-    /// }
-    /// ```
-    pub async fn create_bucket(&self) -> anyhow::Result<WaifuBucketEntry> {
-        let url = format!("{API}/bucket/create");
+    /// Helps connections kept open between requests survive firewalls and load balancers
+    /// that drop quiet ones. Ignored when a client is given with
+    /// [`ApiCallerBuilder::client`]. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn tcp_keepalive(mut self, interval: std::time::Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
 
-        let response: WaifuApiResponse = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("calling create bucket endpoint")?
-            .json()
-            .await
-            .context("converting create bucket api response")?;
+    /// Which redirects to follow, up to 10 to any host unless set
+    ///
+    /// See [`redirect`] for how credentials are kept from other hosts. A client given with
+    /// [`ApiCallerBuilder::client`] should be built with `reqwest::redirect::Policy::none()`
+    /// for the policy to apply, since redirects it follows itself never reach the caller.
+    /// Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn redirect(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect = policy;
+        self
+    }
 
-        match response {
-            WaifuApiResponse::WaifuBucketResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response: {response:?}"),
-        }
+    /// Whether responses compressed by the server are decompressed, which they are unless
+    /// set
+    ///
+    /// With the `gzip`, `brotli` or `deflate` features on, the caller tells the server it
+    /// accepts those encodings and decompresses what comes back, saving bandwidth on large
+    /// bucket listings. Turning it off asks for and keeps responses as they are sent, such
+    /// as for downloads that must match the stored file byte for byte. Ignored when a client
+    /// is given with [`ApiCallerBuilder::client`]. Not available on wasm32.
+    #[cfg(all(
+        not(target_arch = "wasm32"),
+        any(feature = "gzip", feature = "brotli", feature = "deflate")
+    ))]
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.decompress = Some(enabled);
+        self
     }
 
-    /// Deletes a Bucket with the Waifu Vault API
+    /// Send every request through the proxy at `url`, such as `http://proxy:3128` or
+    /// `socks5://proxy:1080`
     ///
-    /// This will remove ALL files contained within the bucket
+    /// Applies to the API endpoints and file downloads alike, and takes the place of any
+    /// proxy set in the environment with `HTTPS_PROXY` and the like. An invalid URL fails
+    /// [`ApiCallerBuilder::try_build`] with [`Error::InvalidProxy`]. Ignored when a client is
+    /// given with [`ApiCallerBuilder::client`], which keeps its own proxies. Not available on
+    /// wasm32.
     ///
     /// # Example
     ///
-    /// ```rust,no_run
+    /// ```rust
     /// use waifuvault::ApiCaller;
     ///
-    /// #[tokio::main]
-    /// async fn main() -> anyhow::Result<()> {
-    ///     let caller = ApiCaller::new();
-    ///
-    ///     let token = "some-bucket-token";
-    ///
-    ///     // Delete the bucket and all files within
-    ///     caller.delete_bucket(token).await?;
-    ///
-    ///     Ok(())
-    /// }
+    /// let caller = ApiCaller::builder()
+    ///     .proxy("http://proxy.internal:3128")
+    ///     .try_build()?;
+    /// # Ok::<(), waifuvault::Error>(())
     /// ```
-    pub async fn delete_bucket(&self, token: &str) -> anyhow::Result<bool> {
-        let url = format!("{API}/bucket/{}", token);
-        let response: WaifuApiResponse = self
-            .client
-            .delete(&url)
-            .send()
-            .await
-            .context("sending delete bucket request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        match response {
-            WaifuApiResponse::Delete(success) => Ok(success),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("Received unexpected response from DELETE bucket endpoint"),
-        }
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
     }
 
-    /// Gets information on files contained within a Bucket with the Waifu Vault API
-    ///
-    /// This returns a [`api::WaifuBucketEntry`] which contains an array of all files
-    /// contained within the bucket as well as the bucket token.
+    /// Connect directly, ignoring proxies set in the environment with `HTTPS_PROXY` and the
+    /// like
     ///
-    /// # Example
-    ///
-    ///
-    pub async fn get_bucket(&self, token: &str) -> anyhow::Result<WaifuBucketEntry> {
-        let url = format!("{API}/bucket/get");
-        let mut body = HashMap::new();
-        body.insert("bucket_token", token);
-
-        let response: WaifuApiResponse = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .context("sending get bucket request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        match response {
-            WaifuApiResponse::WaifuBucketResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from get bucket endpoint: {response:?}"),
-        }
+    /// A proxy set with [`ApiCallerBuilder::proxy`] is still used. Ignored when a client is
+    /// given with [`ApiCallerBuilder::client`]. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
     }
 
-    /// Upload a file to Waifu Vault
-    ///
-    /// Takes an [`api::WaifuUploadRequest`] which details the content to upload and any
-    /// necessary options.
+    /// Fail downloads held in memory with [`Error::ResponseTooLarge`] once they go over
+    /// `max_bytes`, rather than reading the whole body
     ///
-    /// # Example
+    /// Applies to [`ApiCaller::download_file`], [`ApiCaller::download_album`] and their
+    /// variants returning the content, not to downloads written to disk or a writer. A
+    /// `Content-Length` over the limit fails before the body is read. Can be set per download
+    /// with [`DownloadOptions::max_bytes`].
+    pub fn max_download_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_download_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Create the caller
     ///
-    /// ```rust,no_run
-    /// use waifuvault::{
-    ///     ApiCaller,
-    ///     api::WaifuUploadRequest
-    /// };
-    /// #[tokio::main]
-    /// async fn main() -> anyhow::Result<()> {
-    ///     let caller = ApiCaller::new();
-    ///     let request = WaifuUploadRequest::new()
-    ///         .file("/some/file/to/upload")
-    ///         .password("supersecurepassword")
-    ///         .expires("1h");
+    /// # Panics
     ///
-    ///     let response = caller.upload_file(request).await?;
-    ///     // Do something with response
-    ///     Ok(())
-    /// }
-    /// ```
-    pub async fn upload_file(&self, request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry> {
-        let url = if let Some(bucket) = request.bucket {
-            &format!("{API}/{bucket}")
-        } else {
-            API
-        };
+    /// If the URL given to [`ApiCallerBuilder::proxy`] is invalid. Use
+    /// [`ApiCallerBuilder::try_build`] to handle that instead.
+    pub fn build(self) -> ApiCaller {
+        self.try_build().expect("the caller can be built")
+    }
 
-        let request = {
-            let mut intermediate = self.client.put(url).query(&[
-                ("hide_filename", request.hide_filename),
-                ("oneTimeDownload", request.one_time_download),
-            ]);
+    /// Create the caller, failing with [`Error::InvalidProxy`] if the URL given to
+    /// [`ApiCallerBuilder::proxy`] is invalid
+    pub fn try_build(self) -> Result<ApiCaller, Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let proxy = match &self.proxy {
+            Some(url) => Some(reqwest::Proxy::all(url).map_err(|e| Error::InvalidProxy {
+                url: url.clone(),
+                reason: e.to_string(),
+            })?),
+            None => None,
+        };
 
-            if let Some(expiry) = request.expires {
-                intermediate = intermediate.query(&[("expires", expiry)]);
+        #[cfg(not(target_arch = "wasm32"))]
+        let client = self.client.unwrap_or_else(|| {
+            let mut builder = Client::builder()
+                .timeout(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+                .connect_timeout(self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+                .redirect(reqwest::redirect::Policy::none());
+            if let Some(max) = self.pool_max_idle_per_host {
+                builder = builder.pool_max_idle_per_host(max);
             }
-
-            if let Some(file) = request.file {
-                let path = PathBuf::from(&file);
-                let f = std::fs::read(&path)
-                    .with_context(|| format!("reading file {}", path.display()))?;
-
-                let filename = path.file_name().expect("this should be a valid filename");
-                let filename = filename
-                    .to_str()
-                    .expect("this should be a valid convertion from os string");
-
-                let file_part = reqwest::multipart::Part::bytes(f).file_name(filename.to_owned());
-                let mut form = reqwest::multipart::Form::new().part("file", file_part);
-
-                if let Some(password) = request.password {
-                    form = form.text("password", password);
+            if let Some(timeout) = self.pool_idle_timeout {
+                builder = builder.pool_idle_timeout(timeout);
+            }
+            if let Some(interval) = self.tcp_keepalive {
+                builder = builder.tcp_keepalive(interval);
+            }
+            #[cfg(any(feature = "gzip", feature = "brotli", feature = "deflate"))]
+            if let Some(enabled) = self.decompress {
+                #[cfg(feature = "gzip")]
+                {
+                    builder = builder.gzip(enabled);
                 }
-
-                intermediate = intermediate.multipart(form);
-            } else if let Some(url) = request.url {
-                intermediate = match request.password {
-                    Some(password) => intermediate.form(&[("url", url), ("password", password)]),
-                    None => intermediate.form(&[("url", url)]),
-                };
-            } else if let (Some(raw), Some(filename)) = (request.bytes, request.filename) {
-                let file_part = reqwest::multipart::Part::bytes(raw).file_name(filename);
-                let mut form = reqwest::multipart::Form::new().part("file", file_part);
-
-                if let Some(password) = request.password {
-                    form = form.text("password", password);
+                #[cfg(feature = "brotli")]
+                {
+                    builder = builder.brotli(enabled);
+                }
+                #[cfg(feature = "deflate")]
+                {
+                    builder = builder.deflate(enabled);
                 }
-
-                intermediate = intermediate.multipart(form);
-            } else {
-                anyhow::bail!("need either a file, url, or stream");
             }
-
-            intermediate
+            if self.no_proxy {
+                builder = builder.no_proxy();
+            }
+            if let Some(proxy) = proxy {
+                builder = builder.proxy(proxy);
+            }
+            // Asking for rustls picks it over native-tls, which is on by default
+            #[cfg(feature = "rustls")]
+            let builder = builder.use_rustls_tls();
+
+            builder
+                .build()
+                .expect("the default HTTP client can be built")
+        });
+        #[cfg(target_arch = "wasm32")]
+        let client = self.client.unwrap_or_default();
+
+        #[cfg(feature = "middleware")]
+        let transport: Arc<dyn HttpTransport> = match self.middleware {
+            Some(middleware) => Arc::new(middleware),
+            None => Arc::new(client.clone()),
         };
+        #[cfg(not(feature = "middleware"))]
+        let transport: Arc<dyn HttpTransport> = Arc::new(client.clone());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let breakers = self
+            .circuit_breaker
+            .map(|config| Arc::new(breaker::Breakers::new(config)));
+        #[cfg(not(target_arch = "wasm32"))]
+        let breaker = breakers
+            .as_ref()
+            .map(|breakers| breakers.for_url(&self.target.base_url));
+
+        let restrictions_cache = Arc::<RestrictionsCache>::default();
+        let restrictions = cached_restrictions(&restrictions_cache, &self.target.base_url);
+
+        Ok(ApiCaller {
+            client,
+            transport,
+            base_url: self.target.base_url,
+            retry: self.retry,
+            restrictions_cache,
+            restrictions,
+            #[cfg(not(target_arch = "wasm32"))]
+            cancel: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            observer: self.observer,
+            #[cfg(not(target_arch = "wasm32"))]
+            pacer: self
+                .min_request_interval
+                .map(|interval| Arc::new(pacing::Pacer::new(interval))),
+            #[cfg(not(target_arch = "wasm32"))]
+            breakers,
+            #[cfg(not(target_arch = "wasm32"))]
+            breaker,
+            #[cfg(not(target_arch = "wasm32"))]
+            redirect: self.redirect,
+            max_download_bytes: self.max_download_bytes,
+        })
+    }
+}
 
-        let response = request
-            .send()
-            .await
-            .context("sending upload request")?
-            .json()
-            .await
-            .context("converting upload response")?;
-
-        let response = parse_response(response).context("parsing waifu api response")?;
+impl ApiCaller {
+    /// Create a new Waifu Vault API Caller
+    ///
+    /// Requests go to the public instance without retries. Outside wasm32, a request fails
+    /// with [`Error::TimedOut`] once it has taken [`DEFAULT_TIMEOUT`] (5 minutes) in total,
+    /// or [`DEFAULT_CONNECT_TIMEOUT`] (10 seconds) to connect. Use [`ApiCaller::builder`] to
+    /// change any of these.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        Ok(response)
+    /// Create a builder to configure a new caller
+    pub fn builder() -> ApiCallerBuilder {
+        ApiCallerBuilder::default()
     }
 
-    /// Retrieves information about a file stored in Waifu Vault
-    ///
-    /// # Example
+    /// Create a caller sending every request through a client with middleware
     ///
-    /// ```rust,no_run
-    /// use waifuvault::{
-    ///     ApiCaller,
-    ///     api::WaifuGetRequest
-    /// };
+    /// See [`ApiCallerBuilder::middleware_client`]. Requires the `middleware` feature.
     ///
-    /// #[tokio::main]
-    /// async fn main() -> anyhow::Result<()> {
-    ///     let token = "some-file-token-for-waifu-vault";
-    ///     let caller = ApiCaller::new();
-    ///     let request = WaifuGetRequest::new(token)
-    ///         .formatted(true);
+    /// # Example
     ///
-    ///     let response = caller.file_info(request).await?;
-    ///     // Do something with response
+    /// ```rust
+    /// use waifuvault::ApiCaller;
     ///
-    ///     Ok(())
-    /// }
+    /// let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build();
+    /// let caller = ApiCaller::with_middleware_client(client);
     /// ```
-    pub async fn file_info(&self, request: WaifuGetRequest) -> anyhow::Result<WaifuFileEntry> {
-        let url = format!("{API}/{}", request.token);
-        let request = self
-            .client
-            .get(url)
-            .query(&[("formatted", request.formatted)]);
-
-        let response: WaifuApiResponse = request
-            .send()
-            .await
-            .context("sending file info request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        let response = parse_response(response).context("parsing waifu api response")?;
+    #[cfg(feature = "middleware")]
+    pub fn with_middleware_client(client: reqwest_middleware::ClientWithMiddleware) -> Self {
+        Self::builder().middleware_client(client).build()
+    }
 
-        Ok(response)
+    /// Returns the instance this caller sends requests to
+    pub fn target(&self) -> Target {
+        Target::new(&self.base_url)
     }
 
-    /// Updates options on a stored file in Waifu Vault
+    /// Returns a caller which sends its requests to another instance
     ///
-    /// Allows the changing of the password, expiry time, and whether to hide the filename or not
+    /// The returned caller shares everything with this one except the base URL, so
+    /// a service talking to several instances can keep a single caller around and
+    /// pick the instance per call.
+    ///
+    /// The state shared between callers is the HTTP connection pool, which already keeps
+    /// connections per host, and the spacing set with
+    /// [`ApiCallerBuilder::min_request_interval`]. The restrictions fetched by
+    /// [`ApiCaller::upload_file_checked`] are cached per instance, and each host has its own
+    /// circuit breaker,
+    /// shared by every caller sending to it, so failures of one instance don't stop requests
+    /// to another.
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use waifuvault::{
-    ///     ApiCaller,
-    ///     api::WaifuModificationRequest
-    /// };
+    /// use waifuvault::{ApiCaller, Target};
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
-    ///     let token = "some-token";
     ///     let caller = ApiCaller::new();
-    ///     let request = WaifuModificationRequest::new(token)
-    ///         .password("banana")
-    ///         .previous_password("apple")
-    ///         .hide_filename(true);
-    ///     let response = caller.update_file(request).await?;
-    ///     // Do something with the response
+    ///     let self_hosted = Target::new("https://vault.example.com/rest");
+    ///
+    ///     // Goes to the public instance
+    ///     let public_bucket = caller.create_bucket().await?;
+    ///
+    ///     // Goes to the self-hosted instance using the same client
+    ///     let private_bucket = caller.on(&self_hosted).create_bucket().await?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn update_file(
-        &self,
-        request: WaifuModificationRequest,
-    ) -> anyhow::Result<WaifuFileEntry> {
-        let url = format!("{API}/{}", request.token);
-        let response: WaifuApiResponse = self
-            .client
-            .patch(url)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("sending modification request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        let response = parse_response(response).context("parsing waifu api response")?;
-        Ok(response)
+    pub fn on(&self, target: &Target) -> Self {
+        Self {
+            client: self.client.clone(),
+            transport: self.transport.clone(),
+            base_url: target.base_url.clone(),
+            retry: self.retry.clone(),
+            restrictions_cache: self.restrictions_cache.clone(),
+            restrictions: cached_restrictions(&self.restrictions_cache, &target.base_url),
+            #[cfg(not(target_arch = "wasm32"))]
+            cancel: self.cancel.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            observer: self.observer.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            pacer: self.pacer.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            breakers: self.breakers.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            breaker: self
+                .breakers
+                .as_ref()
+                .map(|breakers| breakers.for_url(&target.base_url)),
+            #[cfg(not(target_arch = "wasm32"))]
+            redirect: self.redirect,
+            max_download_bytes: self.max_download_bytes,
+        }
     }
 
-    /// Deletes a file from Waifu Vault
+    /// Returns a caller whose requests stop as soon as `token` is cancelled
+    ///
+    /// Anything the returned caller is doing when the token is cancelled, or is asked to do
+    /// afterwards, fails promptly with [`Error::Cancelled`]. That includes uploads and
+    /// downloads part way through their bodies, retries waiting to be sent, and every
+    /// request of batch helpers such as [`ApiCaller::download_bucket`]. Files are only
+    /// written once their content has been downloaded, so a cancelled download leaves
+    /// nothing behind. Not available on wasm32.
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use waifuvault::ApiCaller;
+    /// use tokio_util::sync::CancellationToken;
+    /// use waifuvault::{ApiCaller, Error, api::WaifuUploadRequest};
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
-    ///     let token = "token-to-delete";
-    ///     let caller = ApiCaller::new();
-    ///     let response = caller.delete_file(token).await?;
+    ///     let token = CancellationToken::new();
+    ///     let caller = ApiCaller::new().cancelled_by(token.clone());
+    ///
+    ///     let upload = tokio::spawn(async move {
+    ///         let request = WaifuUploadRequest::new().file("/some/large/file");
+    ///         caller.upload_file(request).await
+    ///     });
+    ///
+    ///     // The user gave up on the job
+    ///     token.cancel();
+    ///     if let Err(e) = upload.await? {
+    ///         assert_eq!(e.downcast_ref(), Some(&Error::Cancelled));
+    ///     }
+    ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn delete_file(&self, token: &str) -> anyhow::Result<bool> {
-        let url = format!("{API}/{}", token);
-        let response: WaifuApiResponse = self
-            .client
-            .delete(url)
-            .send()
-            .await
-            .context("sending delete request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        match response {
-            WaifuApiResponse::Delete(del) => Ok(del),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("received unexpected response from DELETE call"),
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cancelled_by(&self, token: tokio_util::sync::CancellationToken) -> Self {
+        Self {
+            cancel: Some(token),
+            ..self.clone()
         }
     }
 
-    /// Downloads a file from Waifu Vault
-    ///
-    /// Returns the contents of the file as an array of bytes
-    ///
-    /// # Example
-    ///
-    /// ```rust,no_run
-    /// use waifuvault::ApiCaller;
-    /// use std::io::Write;
-    ///
-    /// #[tokio::main]
-    /// async fn main() -> anyhow::Result<()> {
-    ///     let url = "https://waifuvault.moe/f/[some-id]/file.jpg";
-    ///     let caller = ApiCaller::new();
-    ///     let file_bytes = caller.download_file(url, Some("securepassword".to_string())).await?;
-    ///     let mut f = std::fs::File::create("downloaded.jpg")?;
-    ///     f.write_all(&file_bytes)?;
+    /// Runs `fut` unless the caller is cancelled first, see [`ApiCaller::cancelled_by`]
+    async fn cancellable<T>(
+        &self,
+        fut: impl std::future::Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(token) = &self.cancel {
+            return tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(Error::Cancelled.into()),
+                result = fut => result,
+            };
+        }
+
+        fut.await
+    }
+
+    /// Sends a request, retrying transient failures if a [`RetryPolicy`] is set and
+    /// `idempotent` is true
     ///
-    ///     Ok(())
-    /// }
-    /// ```
-    pub async fn download_file(
+    /// `build` is called for every attempt since request bodies can't be sent twice.
+    /// When the policy runs out the last failure is returned wrapped in [`Error::GaveUp`],
+    /// otherwise the response is returned whatever its status. Nothing is sent once the
+    /// caller is cancelled. The request is reported to the caller's [`ApiObserver`] as `op`.
+    async fn send<F>(
         &self,
-        url: &str,
-        password: Option<String>,
-    ) -> anyhow::Result<Vec<u8>> {
-        let request = {
-            let mut r = self.client.get(url);
-            if let Some(password) = &password {
-                r = r.header("x-password", password);
-            }
+        op: Operation,
+        idempotent: bool,
+        build: F,
+    ) -> anyhow::Result<reqwest::Response>
+    where
+        F: Fn() -> anyhow::Result<reqwest::RequestBuilder>,
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        let observation = self
+            .observer
+            .as_ref()
+            .map(|observer| observe::Observation::start(observer, op));
+        #[cfg(target_arch = "wasm32")]
+        let _ = op;
+
+        let response = self
+            .cancellable(self.send_attempts(idempotent, build))
+            .await?;
 
-            r
+        #[cfg(not(target_arch = "wasm32"))]
+        let response = match observation {
+            Some(observation) => observation.attach(response),
+            None => response,
         };
 
-        let response = request.send().await.context("sending download request")?;
-        let status = response.status();
+        Ok(response)
+    }
 
-        match status {
-            reqwest::StatusCode::OK => {}
-            reqwest::StatusCode::FORBIDDEN => {
-                if password.is_some() {
-                    anyhow::bail!("supplied password is incorrect");
-                } else {
-                    anyhow::bail!("this file requires a password to download");
-                }
+    async fn send_attempts<F>(
+        &self,
+        idempotent: bool,
+        build: F,
+    ) -> anyhow::Result<reqwest::Response>
+    where
+        F: Fn() -> anyhow::Result<reqwest::RequestBuilder>,
+    {
+        let policy = match &self.retry {
+            Some(policy) if idempotent => policy,
+            _ => return self.send_once(build()?).await,
+        };
+
+        let mut attempt = 1;
+        loop {
+            let result = self.send_once(build()?).await;
+            let transient = match &result {
+                Ok(response) => retry::is_transient_status(response.status()),
+                Err(e) => e
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(retry::is_transient_error),
+            };
+
+            if !transient {
+                return result;
             }
-            _ => {
-                let api_response: WaifuApiResponse =
-                    response.json().await.context("converting error")?;
-                match api_response {
-                    WaifuApiResponse::WaifuError(err) => return Err(err.into()),
-                    _ => anyhow::bail!("something went wrong"),
-                }
+
+            if attempt >= policy.max_attempts {
+                let err = match result {
+                    Ok(response) => error_response(response).await,
+                    Err(e) => e,
+                };
+                return Err(err.context(Error::GaveUp { attempts: attempt }));
             }
+
+            let delay = policy.delay(attempt);
+            trace::debug!(attempt, ?delay, "retrying transient failure");
+            retry::sleep(delay).await;
+            attempt += 1;
         }
+    }
 
-        let content = response
-            .bytes()
-            .await
-            .context("getting content bytes")?
-            .to_vec();
+    /// Sends a single attempt of a request through the caller's transport, once the
+    /// caller's [`ApiCallerBuilder::min_request_interval`] allows it
+    ///
+    /// Fails with [`Error::CircuitOpen`] without sending anything while the caller's circuit
+    /// breaker is open.
+    async fn send_once(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<reqwest::Response> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let permit = self.breaker.as_ref().map(|b| b.permit()).transpose()?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(pacer) = &self.pacer {
+            pacer.wait().await;
+        }
 
-        Ok(content)
+        trace::debug!("sending request");
+
+        let result = match request.build() {
+            #[cfg(not(target_arch = "wasm32"))]
+            Ok(request) => self.execute_following_redirects(request).await,
+            #[cfg(target_arch = "wasm32")]
+            Ok(request) => self.transport.execute(request).await,
+            Err(e) => Err(transport_error(e)),
+        };
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(response) => {
+                tracing::debug!(status = response.status().as_u16(), "received response")
+            }
+            Err(e) => tracing::debug!(error = %e, "request failed"),
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(permit) = permit {
+            // Like retries, only a failed connection or a server error says the service is in
+            // trouble. Refused redirects and requests that couldn't be built don't.
+            permit.finish(match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(retry::is_transient_error),
+            });
+        }
+
+        result
     }
 
-    /// Creates an album on the WaifuVault service
+    /// Sends a request through the caller's transport, following the redirects its
+    /// [`RedirectPolicy`] allows
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn execute_following_redirects(
+        &self,
+        mut request: reqwest::Request,
+    ) -> anyhow::Result<reqwest::Response> {
+        let mut hops = 0;
+        loop {
+            let sent = request.try_clone();
+            let response = self.transport.execute(request).await?;
+            // A streamed body can't be sent again, so its redirects are returned as they are
+            let next = match sent {
+                Some(sent) => {
+                    self.redirect
+                        .next(sent, response.status(), response.headers(), hops)?
+                }
+                None => None,
+            };
+            let Some(next) = next else {
+                return Ok(response);
+            };
+
+            trace::debug!(location = %next.url(), "following redirect");
+            request = next;
+            hops += 1;
+        }
+    }
+
+    /// If uploads should be retried
+    fn retries_uploads(&self) -> bool {
+        self.retry.as_ref().is_some_and(|p| p.retry_uploads)
+    }
+
+    /// Creates a bucket with the Waifu Vault API
     ///
-    /// This requires the token from a previously created bucket
-    /// Returns information relating to the newly created album
+    /// This bucket can be used to upload files into
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use waifuvault::ApiCaller;
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
+    ///     // Create a new bucket to upload files to
     ///     let bucket = caller.create_bucket().await?;
-    ///     let album_info = caller.create_album(&bucket.token, "new_album").await?;
+    ///
+    ///     // You can now use the bucket token to upload files to the bucket
+    ///
+    ///     let request = WaifuUploadRequest::new()
+    ///         .file("/some/file/path")
+    ///         .bucket(&bucket.token)
+    ///         .password("set a password")
+    ///         .one_time_download(true);
+    ///     let response = caller.upload_file(request).await?;
+    ///
+    ///     // Do something with the response
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn create_album(
-        &self,
-        bucket_token: &str,
-        album_name: &str,
-    ) -> anyhow::Result<WaifuAlbumEntry> {
-        let url = format!("{API}/album/{}", bucket_token);
-        let mut body = HashMap::new();
-        body.insert("name", album_name);
-        let response: WaifuApiResponse = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .context("sending create album request")?
-            .json()
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn create_bucket(&self) -> anyhow::Result<WaifuBucketEntry> {
+        let url = format!("{}/bucket/create", self.base_url);
+
+        let response = self
+            .send(Operation::CreateBucket, false, || Ok(self.client.get(&url)))
             .await
-            .context("converting response")?;
+            .context("calling create bucket endpoint")?;
 
-        match response {
-            WaifuApiResponse::WaifuAlbumResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from create album endpoint: {response:?}"),
-        }
+        parse_response(response)
+            .await
+            .context("converting create bucket api response")
     }
 
-    /// Associates a collection of Files with an Album
-    ///
-    /// This requires an array of File tokens already present on Waifu Vault
-    /// and a previously created album.
-    /// Returns information relating to the updated album
+    /// Creates a bucket and returns a [`Bucket`] handle bound to this caller
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use waifuvault::ApiCaller;
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let file_token1 = "file-token-1";
-    ///     let file_token2 = "file-token-2";
-    ///     let album_token = "album-token";
-    ///     let album_info = caller.associate_with_album(album_token, &[file_token1, file_token2]).await?;
+    ///     let bucket = caller.create_bucket_handle().await?;
+    ///     let response = bucket
+    ///         .upload(WaifuUploadRequest::new().file("/some/file/path"))
+    ///         .await?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn associate_with_album(
-        &self,
-        album_token: &str,
-        file_tokens: &[&str],
-    ) -> anyhow::Result<WaifuAlbumEntry> {
-        let url = format!("{API}/album/{}/associate", album_token);
-        let mut body = HashMap::new();
-        body.insert("fileTokens", file_tokens);
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn create_bucket_handle(&self) -> anyhow::Result<Bucket> {
+        let entry = self.create_bucket().await?;
+        Ok(self.bucket(entry.token))
+    }
 
-        let response: WaifuApiResponse = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .context("sending album association request")?
-            .json()
+    /// Gets the bucket whose token was saved with [`WaifuBucketEntry::save_token`]
+    ///
+    /// Fails with [`Error::NoSavedBucket`] if there is nothing at `path`, and
+    /// [`Error::CorruptSavedBucket`] if the file doesn't hold a token, so callers can create a
+    /// bucket instead. Errors getting the bucket, such as it having been deleted, are returned
+    /// as they are. See [`ApiCaller::get_or_create_bucket`] to create one when needed. Not
+    /// available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn load_bucket(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<WaifuBucketEntry> {
+        let path = path.as_ref();
+        let token = store::read_token(path)
+            .await?
+            .ok_or_else(|| Error::NoSavedBucket {
+                path: path.to_path_buf(),
+            })?;
+
+        self.get_bucket(&token)
             .await
-            .context("converting response")?;
-
-        match response {
-            WaifuApiResponse::WaifuAlbumResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from album association endpoint: {response:?}"),
-        }
+            .with_context(|| format!("getting saved bucket {token}"))
     }
 
-    /// Disassociate a collection of Files with an Album
+    /// Gets the bucket whose token is saved in `store`, or creates one and saves its token
     ///
-    /// This requires an array of File tokens already present on Waifu Vault
-    /// and a previously created album.
-    /// Returns information relating to the updated album
+    /// A saved bucket the service no longer knows, such as one that was deleted, is replaced
+    /// by a new one. A file that doesn't hold a saved token fails with
+    /// [`Error::CorruptSavedBucket`] rather than being overwritten, in case it was meant for
+    /// something else. Without a `store`, this is
+    /// [`ApiCaller::create_bucket`]. The file is only readable by its owner on Unix. Not
+    /// available on wasm32.
     ///
     /// # Example
     ///
     /// ```rust,no_run
+    /// use std::path::Path;
     /// use waifuvault::ApiCaller;
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let file_token1 = "file-token-1";
-    ///     let file_token2 = "file-token-2";
-    ///     let album_token = "album-token";
-    ///     let album_info = caller.disassociate_from_album(album_token, &[file_token1, file_token2]).await?;
+    ///     // The same bucket on every run, as long as it isn't deleted
+    ///     let bucket = caller
+    ///         .get_or_create_bucket(Some(Path::new("bucket.json")))
+    ///         .await?;
+    ///     println!("{} files", bucket.files.len());
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn disassociate_from_album(
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn get_or_create_bucket(
         &self,
-        album_token: &str,
-        file_tokens: &[&str],
-    ) -> anyhow::Result<WaifuAlbumEntry> {
-        let url = format!("{API}/album/{}/disassociate", album_token);
-        let mut body = HashMap::new();
-        body.insert("fileTokens", file_tokens);
-
-        let response: WaifuApiResponse = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .context("sending album association request")?
-            .json()
-            .await
-            .context("converting response")?;
+        store: Option<&std::path::Path>,
+    ) -> anyhow::Result<WaifuBucketEntry> {
+        let Some(store) = store else {
+            return self.create_bucket().await;
+        };
 
-        match response {
-            WaifuApiResponse::WaifuAlbumResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from album association endpoint: {response:?}"),
+        if let Some(token) = store::read_token(store).await? {
+            match self.get_bucket(&token).await {
+                Ok(bucket) => return Ok(bucket),
+                Err(err) if is_unknown_token(&err) => {
+                    trace::debug!("saved bucket no longer exists, creating another");
+                }
+                Err(err) => return Err(err.context(format!("getting saved bucket {token}"))),
+            }
         }
+
+        let bucket = self.create_bucket().await?;
+        store::write_token(store, &bucket.token).await?;
+
+        Ok(bucket)
     }
 
-    /// Delete an album from Waifu Vault
+    /// Returns a [`Bucket`] handle for an existing bucket
     ///
-    /// If `deleteFiles` is true, will delete the files from the bucket as well.
+    /// No request is made, so the token isn't checked until the handle is used.
+    pub fn bucket(&self, token: impl Into<BucketToken>) -> Bucket {
+        Bucket::new(self.clone(), token.into())
+    }
+
+    /// Deletes a Bucket with the Waifu Vault API
     ///
-    /// Returns the status of the operation indicating if it was successful or not.
+    /// This will remove ALL files contained within the bucket
     ///
     /// # Example
     ///
@@ -959,44 +1403,35 @@ impl ApiCaller {
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let album_token = "album-token";
-    ///
-    ///     // Keep files associated with the bucket
-    ///     // Pass `true` to delete files from the bucket as well
-    ///     let status = caller.delete_album(album_token, false).await?;
+    ///     let token = "some-bucket-token";
     ///
-    ///     assert!(status.success);
+    ///     // Delete the bucket and all files within
+    ///     caller.delete_bucket(token).await?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn delete_album(
-        &self,
-        album_token: &str,
-        delete_files: bool,
-    ) -> anyhow::Result<WaifuGenericMessage> {
-        let url = format!("{API}/album/{}", album_token);
-        let response: WaifuApiResponse = self
-            .client
-            .delete(&url)
-            .query(&[("deleteFiles", delete_files)])
-            .send()
-            .await
-            .context("sending album delete request")?
-            .json()
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn delete_bucket(&self, token: impl Into<BucketToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let url = format!("{}/bucket/{}", self.base_url, token.path_segment()?);
+        let response = self
+            .send(Operation::DeleteBucket, false, || {
+                Ok(self.client.delete(&url))
+            })
             .await
-            .context("converting response")?;
+            .context("sending delete bucket request")?;
 
-        match response {
-            WaifuApiResponse::WaifuGenericResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from album deletion endpoint: {response:?}"),
-        }
+        parse_response(response)
+            .await
+            .context("converting response")
     }
 
-    /// Get information about album from Waifu Vault
+    /// Totals describing how full and how busy a bucket is
     ///
-    /// Returns information relating to the album
+    /// Worked out from a single [`ApiCaller::get_bucket`] call, see
+    /// [`api::WaifuBucketEntry::stats`] to get them from a bucket already fetched.
     ///
     /// # Example
     ///
@@ -1007,35 +1442,23 @@ impl ApiCaller {
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let album_token = "album-token";
-    ///     let album_info = caller.get_album(album_token).await?;
+    ///     let stats = caller.bucket_stats("some-bucket-token").await?;
+    ///     println!("{} files viewed {} times", stats.file_count, stats.total_views);
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn get_album(&self, album_token: &str) -> anyhow::Result<WaifuAlbumEntry> {
-        let url = format!("{API}/album/{album_token}");
-        let response: WaifuApiResponse = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("sending get album request")?
-            .json()
-            .await
-            .context("converting response")?;
-
-        match response {
-            WaifuApiResponse::WaifuAlbumResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from get album endpoint: {response:?}"),
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn bucket_stats(&self, token: impl Into<BucketToken>) -> anyhow::Result<BucketStats> {
+        let bucket = self.get_bucket(WaifuBucketRequest::new(token)).await?;
+        Ok(bucket.stats())
     }
 
-    /// Share an album from Waifu Vault
+    /// Deletes every file in a bucket, leaving the bucket itself in place
     ///
-    /// Returns a staus object indicating the success of the operation.
-    /// It also contains the URL of the public album in the `description`.
+    /// A few files are deleted at a time. Files that can't be deleted are recorded in
+    /// [`EmptiedBucket::failed`] without stopping the others. Emptying a bucket with no
+    /// files in it succeeds without deleting anything. Not available on wasm32.
     ///
     /// # Example
     ///
@@ -1046,839 +1469,9149 @@ impl ApiCaller {
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let album_token = "album-token";
-    ///     let status = caller.share_album(album_token).await?;
-    ///
-    ///     assert!(status.success);
-    ///     let shareable_url = status.description;
+    ///     let emptied = caller.empty_bucket("some-bucket-token").await?;
+    ///     println!("deleted {} files", emptied.deleted);
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn share_album(&self, album_token: &str) -> anyhow::Result<WaifuGenericMessage> {
-        let url = format!("{API}/album/share/{album_token}");
-        let response: WaifuApiResponse = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("sending share album request")?
-            .json()
-            .await
-            .context("converting response")?;
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn empty_bucket(
+        &self,
+        token: impl Into<BucketToken>,
+    ) -> anyhow::Result<EmptiedBucket> {
+        let bucket = self.get_bucket(WaifuBucketRequest::new(token)).await?;
 
-        match response {
-            WaifuApiResponse::WaifuGenericResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from get album endpoint: {response:?}"),
+        let mut emptied = EmptiedBucket {
+            deleted: 0,
+            failed: Vec::new(),
+        };
+        let tokens = bucket.files.iter().map(|f| &f.token);
+        for (token, deleted) in self.delete_files(tokens).await {
+            match deleted {
+                Ok(true) => emptied.deleted += 1,
+                Ok(false) => emptied
+                    .failed
+                    .push((token, anyhow::anyhow!("the file was not deleted"))),
+                Err(err) => emptied.failed.push((token, err)),
+            }
         }
+
+        Ok(emptied)
     }
 
-    /// Revokes public access from an album on Waifu Vault
+    /// Gets information on files contained within a Bucket with the Waifu Vault API
     ///
-    /// Any public URLs to the album are invalidated.
-    /// Returns a staus object indicating the success of the operation.
+    /// This returns a [`api::WaifuBucketEntry`] which contains an array of all files
+    /// contained within the bucket as well as the bucket token.
+    ///
+    /// Takes either a bucket token, or an [`api::WaifuBucketRequest`] to have the expiry
+    /// of the files formatted like [`ApiCaller::file_info`] does.
     ///
     /// # Example
     ///
     /// ```rust,no_run
-    /// use waifuvault::ApiCaller;
+    /// use waifuvault::{ApiCaller, api::WaifuBucketRequest};
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let album_token = "album-token";
-    ///     let status = caller.revoke_album(album_token).await?;
+    ///     let bucket = caller.get_bucket("some-bucket-token").await?;
     ///
-    ///     assert!(status.success);
+    ///     let request = WaifuBucketRequest::new("some-bucket-token").formatted(true);
+    ///     let bucket = caller.get_bucket(request).await?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn revoke_album(&self, album_token: &str) -> anyhow::Result<WaifuGenericMessage> {
-        let url = format!("{API}/album/revoke/{album_token}");
-        let response: WaifuApiResponse = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .context("sending share album request")?
-            .json()
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn get_bucket(
+        &self,
+        request: impl Into<WaifuBucketRequest>,
+    ) -> anyhow::Result<WaifuBucketEntry> {
+        let request = request.into();
+        trace::record_token!(&request.token);
+        let url = format!("{}/bucket/get", self.base_url);
+        let mut body = HashMap::new();
+        body.insert("bucket_token", &request.token);
+
+        let response = self
+            .send(Operation::GetBucket, true, || {
+                Ok(self
+                    .client
+                    .post(&url)
+                    .query(&[("formatted", request.formatted)])
+                    .header("Content-Type", "application/json")
+                    .json(&body))
+            })
             .await
-            .context("converting response")?;
+            .context("sending get bucket request")?;
 
-        match response {
-            WaifuApiResponse::WaifuGenericResponse(resp) => Ok(resp),
-            WaifuApiResponse::WaifuError(err) => Err(err.into()),
-            _ => anyhow::bail!("unexpected response from get album endpoint: {response:?}"),
-        }
+        parse_response(response)
+            .await
+            .context("converting response")
     }
 
-    /// Downloads a zip archive of an album on Waifu Vault
+    /// Checks whether the service knows a bucket
     ///
-    /// If `file_ids` is passed, it returns only those files in the archive.
-    /// If `None`, the entire contents of the album are returned
+    /// Returns `false` when the service says the token is unknown, and any other failure,
+    /// such as a connection error or rate limit, as an error. See [`ApiCaller::file_exists`].
     ///
-    /// Returns a `Vec<u8>` containing the zipped data.
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     if !caller.bucket_exists("some-bucket-token").await? {
+    ///         caller.create_bucket().await?;
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn bucket_exists(&self, token: impl Into<BucketToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        trace::record_token!(&token);
+        exists(self.get_bucket(token).await)
+    }
+
+    /// Records every file in a bucket, such as to find out later which files have gone
+    ///
+    /// The manifest holds each file's token, URL, filename, options and when it expires, and
+    /// can be saved with serde and checked with [`ApiCaller::verify_manifest`]. Not available
+    /// on wasm32, where there is no clock to time the export with.
     ///
     /// # Example
     ///
     /// ```rust,no_run
     /// use waifuvault::ApiCaller;
-    /// use std::io::Write;
     ///
     /// #[tokio::main]
     /// async fn main() -> anyhow::Result<()> {
     ///     let caller = ApiCaller::new();
     ///
-    ///     let album_token = "album-token";
-    ///     let zipped_contents = caller.download_album(album_token, None).await?;
-    ///     let mut f = std::fs::File::create("archive.zip")?;
-    ///     f.write_all(&zipped_contents)?;
+    ///     let manifest = caller.export_bucket_manifest("some-bucket-token").await?;
+    ///     std::fs::write("manifest.json", serde_json::to_vec_pretty(&manifest)?)?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn download_album(
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn export_bucket_manifest(
         &self,
-        album_token: &str,
-        file_ids: Option<&[usize]>,
-    ) -> anyhow::Result<Vec<u8>> {
-        let url = format!("{API}/album/download/{album_token}");
-        let body = match file_ids {
-            Some(ids) => ids,
-            None => &vec![],
-        };
-        let response = self
-            .client
-            .post(&url)
-            .json(&body)
-            .header("Content-Type", "application/json")
-            .send()
-            .await
-            .context("sending download part album request")?;
+        token: impl Into<BucketToken>,
+    ) -> anyhow::Result<BucketManifest> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let bucket = self.get_bucket(&token).await?;
+        let now = std::time::SystemTime::now();
+        let exported_at = now
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+
+        Ok(BucketManifest {
+            bucket: bucket.token,
+            exported_at,
+            files: bucket
+                .files
+                .into_iter()
+                .map(|file| ManifestEntry::new(file, now))
+                .collect(),
+        })
+    }
 
-        let status = response.status();
-        match status {
-            reqwest::StatusCode::OK => {}
-            _ => {
-                let api_response: WaifuApiResponse =
-                    response.json().await.context("converting error")?;
-                match api_response {
-                    WaifuApiResponse::WaifuError(err) => return Err(err.into()),
-                    _ => anyhow::bail!(
-                        "unexpected error responser received from api: {api_response:?}"
-                    ),
-                }
+    /// Checks which files of a manifest the service still knows
+    ///
+    /// Each file is looked up on its own, a few at a time, so files that were moved to
+    /// another bucket still count as present. Files the service doesn't know are reported
+    /// as missing along with what the manifest recorded of them, and lookups that failed
+    /// for any other reason as failed. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::BucketManifest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let manifest: BucketManifest =
+    ///         serde_json::from_slice(&std::fs::read("manifest.json")?)?;
+    ///
+    ///     let check = caller.verify_manifest(&manifest).await;
+    ///     for file in &check.missing {
+    ///         println!("{} is gone", file.filename.as_deref().unwrap_or(file.url.as_str()));
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bucket = %manifest.bucket)))]
+    pub async fn verify_manifest(&self, manifest: &BucketManifest) -> ManifestCheck {
+        let tokens = manifest
+            .files
+            .iter()
+            .map(|file| file.token.clone())
+            .collect();
+        let outcomes = concurrently(tokens, LOOKUP_CONCURRENCY, |token| {
+            let caller = self.clone();
+            async move { caller.file_exists(token).await }
+        })
+        .await;
+
+        let mut check = ManifestCheck {
+            present: Vec::new(),
+            missing: Vec::new(),
+            failed: Vec::new(),
+        };
+        for (file, outcome) in manifest.files.iter().zip(outcomes) {
+            match outcome {
+                Ok(true) => check.present.push(file.token.clone()),
+                Ok(false) => check.missing.push(file.clone()),
+                Err(err) => check.failed.push((file.token.clone(), err)),
             }
         }
 
-        let content = response
-            .bytes()
-            .await
-            .context("obtaining response bytes")?
-            .to_vec();
-
-        Ok(content)
+        check
     }
-}
 
-/// Parses the response from the Waifu Vault API and converts it to
-/// a concrete type
-pub(crate) fn parse_response(response: WaifuApiResponse) -> anyhow::Result<WaifuFileEntry> {
-    match response {
-        WaifuApiResponse::WaifuFileResponse(resp) => Ok(resp),
-        WaifuApiResponse::WaifuError(err) => Err(anyhow::anyhow!(err)),
-        _ => unreachable!("unused"),
+    /// Upload a file to Waifu Vault
+    ///
+    /// Takes an [`api::WaifuUploadRequest`] which details the content to upload and any
+    /// necessary options.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{
+    ///     ApiCaller,
+    ///     api::WaifuUploadRequest
+    /// };
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuUploadRequest::new()
+    ///         .file("/some/file/to/upload")
+    ///         .password("supersecurepassword")
+    ///         .expires("1h");
+    ///
+    ///     let response = caller.upload_file(request).await?;
+    ///     // Do something with response
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bucket = request.bucket.as_ref().map(BucketToken::as_str))))]
+    pub async fn upload_file(&self, request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry> {
+        Ok(self.upload(request, None).await?.entry)
+    }
+
+    /// Upload a file to Waifu Vault, along with a digest of the content sent
+    ///
+    /// Like [`ApiCaller::upload_file`], but also returns the checksum asked for with
+    /// [`WaifuUploadRequest::compute_checksum`], such as to record exactly what was uploaded.
+    /// The content is hashed as it is read for sending, so a file or stream is only read
+    /// once. If the upload is retried, the checksum is of the attempt that succeeded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, HashAlgorithm, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuUploadRequest::new()
+    ///         .file("/some/file/to/upload")
+    ///         .compute_checksum(HashAlgorithm::Sha256);
+    ///
+    ///     let outcome = caller.upload_file_with_outcome(request).await?;
+    ///     if let Some(checksum) = outcome.checksum {
+    ///         println!("{} is {checksum}", outcome.entry.url);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bucket = request.bucket.as_ref().map(BucketToken::as_str))))]
+    pub async fn upload_file_with_outcome(
+        &self,
+        request: WaifuUploadRequest,
+    ) -> anyhow::Result<UploadOutcome> {
+        self.upload(request, None).await
+    }
+
+    /// Sends an upload, as a new file or, with `replacing`, as the new contents of that file
+    ///
+    /// A replacement the server has no route for fails with [`Error::UnexpectedResponse`]
+    /// holding its 404 or 405, so the caller can fall back to uploading a new file.
+    async fn upload(
+        &self,
+        request: WaifuUploadRequest,
+        replacing: Option<&FileToken>,
+    ) -> anyhow::Result<UploadOutcome> {
+        let source = request.source.as_ref().ok_or(Error::NoUploadSource)?;
+        if self.retries_uploads() && !source.is_replayable() {
+            return Err(Error::NonReplayableBody.into());
+        }
+        if let UploadSource::Url(url) = source {
+            validate_upload_url(url)?;
+        }
+        if let Some(expiry) = &request.expires {
+            validate_expiry(expiry)?;
+        }
+
+        let (url, op) = match (replacing, &request.bucket) {
+            (Some(token), _) => (
+                format!("{}/{}/replace", self.base_url, token.path_segment()?),
+                Operation::ReplaceFile,
+            ),
+            (None, Some(bucket)) => (
+                format!("{}/{}", self.base_url, bucket.path_segment()?),
+                Operation::Upload,
+            ),
+            (None, None) => (self.base_url.clone(), Operation::Upload),
+        };
+
+        // Each attempt hashes its own body, and the one that got a response is kept
+        let hasher = std::sync::Mutex::new(None);
+        let build = || {
+            let mut intermediate = self.client.put(&url);
+            let attempt_hasher = match source {
+                UploadSource::Url(_) => None,
+                _ => request.checksum.map(checksum::SharedHasher::new),
+            };
+            *hasher.lock().unwrap() = attempt_hasher.clone();
+
+            if let Some(hide) = request.hide_filename {
+                intermediate = intermediate.query(&[("hide_filename", hide)]);
+            }
+
+            if let Some(one_time) = request.one_time_download {
+                intermediate = intermediate.query(&[("oneTimeDownload", one_time)]);
+            }
+
+            if let Some(expiry) = &request.expires {
+                intermediate = intermediate.query(&[("expires", expiry)]);
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(timeout) = request.timeout {
+                intermediate = intermediate.timeout(timeout);
+            }
+
+            let file_part = match source {
+                UploadSource::File(path) => {
+                    let filename = request.upload_filename(path)?;
+                    file_part(path, attempt_hasher)?.file_name(filename)
+                }
+                UploadSource::Bytes { data, filename } => {
+                    trace::debug!(bytes = data.len(), "uploading bytes");
+                    if let Some(hasher) = attempt_hasher {
+                        hasher.update(data);
+                    }
+                    bytes_part(data.clone()).file_name(filename.clone())
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                UploadSource::Stream {
+                    body,
+                    filename,
+                    length,
+                } => {
+                    trace::debug!(bytes = length, "uploading stream");
+                    let part = match length {
+                        Some(length) => reqwest::multipart::Part::stream_with_length(
+                            body.take(attempt_hasher)?,
+                            *length,
+                        ),
+                        None => reqwest::multipart::Part::stream(body.take(attempt_hasher)?),
+                    };
+                    part.file_name(filename.clone())
+                }
+                // Form encoding escapes `&`, `=`, `+` and spaces, so the service reads the same
+                // password as from a multipart upload
+                UploadSource::Url(url) => {
+                    return Ok(match &request.password {
+                        Some(password) => {
+                            intermediate.form(&[("url", url), ("password", password)])
+                        }
+                        None => intermediate.form(&[("url", url)]),
+                    });
+                }
+            };
+
+            let file_part = with_content_type(file_part, request.content_type.as_deref())?;
+            let mut form = reqwest::multipart::Form::new().part("file", file_part);
+            if let Some(password) = &request.password {
+                form = form.text("password", password.clone());
+            }
+
+            Ok(intermediate.multipart(form))
+        };
+
+        let response = self
+            .send(op, self.retries_uploads(), build)
+            .await
+            .context("sending upload request")?;
+        let status = response.status();
+        if replacing.is_some()
+            && (status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED)
+        {
+            let body = observe::body(response)
+                .await
+                .map_err(transport_error)
+                .context("reading response body")?;
+            return Err(unexpected(status, &body).into());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let response = observe::uploaded(response, || source.content_length());
+
+        let entry = parse_response(response)
+            .await
+            .context("converting upload response")?;
+        let checksum = hasher
+            .into_inner()
+            .unwrap()
+            .map(checksum::SharedHasher::finish);
+
+        Ok(UploadOutcome { entry, checksum })
+    }
+
+    /// Copies a file in the vault into a new upload with its own options
+    ///
+    /// `source` is the token or public URL of the file, and `password` the one it is
+    /// protected with, if any. The file is downloaded and uploaded again with the options of
+    /// `request`, such as a different bucket, expiry or password. It keeps its filename, and
+    /// its content type unless the request sets one. The content is streamed straight from the
+    /// download into the upload, except with a caller that retries uploads, where it has to
+    /// be held in memory so it can be sent again.
+    ///
+    /// Downloading a one time download deletes it, so copying one fails with
+    /// [`Error::OneTimeDownload`] before anything is downloaded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuUploadRequest::new()
+    ///         .bucket("some-bucket-token")
+    ///         .expires("7d");
+    ///
+    ///     let copy = caller
+    ///         .copy_file("https://waifuvault.moe/f/1712345/picture.png", None, request)
+    ///         .await?;
+    ///     println!("copied to {}", copy.url);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(source = %source)))]
+    pub async fn copy_file(
+        &self,
+        source: &str,
+        password: Option<String>,
+        request: WaifuUploadRequest<NoSource>,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        Ok(self.copy(source, password, request).await?.entry)
+    }
+
+    /// Moves a file into another bucket
+    ///
+    /// The file is copied into `bucket_token` like [`ApiCaller::copy_file`] does, with the
+    /// options of [`MoveOptions::template`], and the original is only deleted once the copy
+    /// has been uploaded. With [`MoveOptions::verify`] set, the copy is downloaded again and
+    /// must match the content that was uploaded before the original is deleted.
+    ///
+    /// If the copy can't be verified, or the original can't be deleted, the move fails with
+    /// [`Error::MoveIncomplete`] wrapping what went wrong, leaving both files in the vault.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::MoveOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let options = MoveOptions {
+    ///         verify: true,
+    ///         ..Default::default()
+    ///     };
+    ///
+    ///     match caller.move_file("some-file-token", "other-bucket-token", options).await {
+    ///         Ok(moved) => println!("moved to {}", moved.entry.url),
+    ///         Err(e) => match e.downcast_ref() {
+    ///             Some(waifuvault::Error::MoveIncomplete { copy, .. }) => {
+    ///                 caller.delete_file(copy).await?;
+    ///             }
+    ///             _ => return Err(e),
+    ///         },
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn move_file(
+        &self,
+        file_token: impl Into<FileToken>,
+        bucket_token: impl Into<BucketToken>,
+        options: MoveOptions,
+    ) -> anyhow::Result<FileMove> {
+        let source = file_token.into();
+        trace::record_token!(&source);
+        if options.verify && options.template.one_time_download == Some(true) {
+            anyhow::bail!("a one time download can't be verified without consuming it");
+        }
+
+        let mut request = options.template.bucket(bucket_token);
+        if options.verify {
+            request = request.compute_checksum(HashAlgorithm::Sha256);
+        }
+        let copied = self
+            .copy(source.as_str(), options.password, request.clone())
+            .await?;
+        let incomplete = |err: anyhow::Error| {
+            err.context(Error::MoveIncomplete {
+                source: source.clone(),
+                copy: copied.entry.token.clone(),
+            })
+        };
+
+        if let Some(checksum) = copied.checksum {
+            let mut check = DownloadOptions::new().checksum(checksum);
+            check.password = request.password.clone();
+            self.download_body(&copied.entry.url, &check)
+                .await
+                .map_err(incomplete)?;
+        }
+        let source_deleted = self.delete_file(&source).await.map_err(incomplete)?;
+
+        Ok(FileMove {
+            entry: copied.entry,
+            source_deleted,
+        })
+    }
+
+    /// Keeps a file from expiring, uploading it again if its expiry can't be extended
+    ///
+    /// If the file has less than `threshold` left before it expires, its expiry is set to
+    /// [`KeepAliveOptions::expiry`] from now with [`ApiCaller::update_file`]. When the service
+    /// refuses the new expiry, or it still leaves less than `threshold`, the file is
+    /// downloaded and uploaded again into the same bucket with the same password and hidden
+    /// filename, and the original is deleted. The returned [`KeepAlive`] says which happened,
+    /// and the new token if there is one.
+    ///
+    /// Uploading again needs the password of a protected file. One time downloads can't be
+    /// uploaded again without being consumed, so they fail with [`Error::OneTimeDownload`]
+    /// rather than being touched. If the original can't be deleted after the new upload, this
+    /// fails with [`Error::MoveIncomplete`] naming both files. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use waifuvault::{ApiCaller, api::{KeepAlive, KeepAliveOptions}};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let options = KeepAliveOptions {
+    ///         expiry: Duration::from_secs(30 * 24 * 60 * 60),
+    ///         password: None,
+    ///     };
+    ///
+    ///     let day = Duration::from_secs(24 * 60 * 60);
+    ///     match caller.extend_or_reupload("some-file-token", day, options).await? {
+    ///         KeepAlive::Reuploaded { previous, entry } => {
+    ///             println!("{previous} is now {}", entry.token)
+    ///         }
+    ///         kept => println!("{} kept", kept.entry().token),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn extend_or_reupload(
+        &self,
+        token: impl Into<FileToken>,
+        threshold: std::time::Duration,
+        options: KeepAliveOptions,
+    ) -> anyhow::Result<KeepAlive> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let remaining = |entry: &WaifuFileEntry| {
+            entry
+                .expires_at()
+                .map(|at| {
+                    at.duration_since(std::time::SystemTime::now())
+                        .unwrap_or_default()
+                })
+                .with_context(|| format!("the retention period of {token} can't be understood"))
+        };
+
+        let entry = self.file_info(WaifuGetRequest::new(&token)).await?;
+        if remaining(&entry)? >= threshold {
+            return Ok(KeepAlive::Untouched(entry));
+        }
+
+        let extend = WaifuModificationRequest::new(&token).custom_expiry_in(options.expiry);
+        let entry = match self.update_file(extend).await {
+            Ok(extended) if remaining(&extended)? >= threshold => {
+                trace::debug!("extended the expiry of the file");
+                return Ok(KeepAlive::Extended(extended));
+            }
+            Ok(extended) => extended,
+            // The service refusing the expiry, rather than failing to answer
+            Err(err)
+                if !is_not_found(&err)
+                    && err
+                        .downcast_ref::<WaifuError>()
+                        .is_some_and(|e| (400..500).contains(&e.status)) =>
+            {
+                trace::debug!(error = %err, "the expiry of the file can't be extended");
+                entry
+            }
+            Err(err) => return Err(err),
+        };
+
+        let file_options = entry.options.as_ref();
+        if file_options.is_some_and(|o| o.one_time_download) {
+            return Err(Error::OneTimeDownload { token }.into());
+        }
+        let protected = file_options.is_some_and(|o| o.protected);
+        let mut request = WaifuUploadRequest::new()
+            .expires_in(options.expiry)
+            .hide_filename(file_options.is_some_and(|o| o.hide_filename));
+        if let Some(bucket) = &entry.bucket {
+            request = request.bucket(bucket);
+        }
+        if protected {
+            let password = options.password.as_deref().with_context(|| {
+                format!("{token} is protected, its password is needed to upload it again")
+            })?;
+            request = request.password(password);
+        }
+
+        let copied = self.copy_entry(entry, options.password, request).await?;
+        trace::debug!(token = %copied.entry.token, "uploaded the file again");
+        self.delete_file(&token).await.map_err(|err| {
+            err.context(Error::MoveIncomplete {
+                source: token.clone(),
+                copy: copied.entry.token.clone(),
+            })
+        })?;
+
+        Ok(KeepAlive::Reuploaded {
+            previous: token,
+            entry: copied.entry,
+        })
+    }
+
+    /// Copies a file into a new upload, for [`ApiCaller::copy_file`] and
+    /// [`ApiCaller::move_file`]
+    async fn copy(
+        &self,
+        source: &str,
+        password: Option<String>,
+        request: WaifuUploadRequest<NoSource>,
+    ) -> anyhow::Result<UploadOutcome> {
+        let info = if source.starts_with("http://") || source.starts_with("https://") {
+            WaifuGetRequest::from_url(source)?
+        } else {
+            WaifuGetRequest::new(source)
+        };
+        let entry = self
+            .file_info(info)
+            .await
+            .context("looking up the file to copy")?;
+
+        self.copy_entry(entry, password, request).await
+    }
+
+    /// Copies a file already looked up into a new upload
+    async fn copy_entry(
+        &self,
+        entry: WaifuFileEntry,
+        password: Option<String>,
+        request: WaifuUploadRequest<NoSource>,
+    ) -> anyhow::Result<UploadOutcome> {
+        if entry.options.as_ref().is_some_and(|o| o.one_time_download) {
+            return Err(Error::OneTimeDownload { token: entry.token }.into());
+        }
+
+        let mut options = DownloadOptions::new();
+        options.password = password;
+        let response = self
+            .download_response(&entry.url, &options, 0, None)
+            .await?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+        };
+        let filename = header(reqwest::header::CONTENT_DISPOSITION)
+            .and_then(content_disposition_filename)
+            .or_else(|| url_filename(&entry.url))
+            .unwrap_or_else(|| entry.token.to_string());
+        let content_type = header(reqwest::header::CONTENT_TYPE).map(str::to_string);
+
+        let mut request = request;
+        if request.content_type.is_none() {
+            request.content_type = content_type;
+        }
+        trace::debug!(filename = %filename, "copying file");
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if !self.retries_uploads() {
+            let length = response.content_length();
+            let request = request.body_stream(response.bytes_stream(), filename, length);
+            return self.upload(request, None).await;
+        }
+
+        let content = self
+            .read_body(response, &options)
+            .await
+            .context("getting content bytes")?;
+        self.upload(request.bytes(content, filename), None).await
+    }
+
+    /// Uploads every file under a directory into a bucket
+    ///
+    /// The directory is walked recursively and each regular file is uploaded with the
+    /// options set on `template`, such as its expiry, password or hidden filename. Any
+    /// content or bucket already on the template is replaced.
+    ///
+    /// Files that can't be uploaded, symbolic links and unreadable directories are recorded
+    /// in [`DirectoryUpload::failed`] without stopping the others. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let bucket = caller.create_bucket().await?;
+    ///
+    ///     let template = WaifuUploadRequest::new().expires("1d").hide_filename(true);
+    ///     let report = caller
+    ///         .upload_directory("/some/directory", &bucket.token, template)
+    ///         .await?;
+    ///     for (path, entry) in &report.uploaded {
+    ///         println!("{} -> {}", path.display(), entry.url);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn upload_directory(
+        &self,
+        dir: impl AsRef<std::path::Path>,
+        bucket_token: impl Into<BucketToken>,
+        template: WaifuUploadRequest<NoSource>,
+    ) -> anyhow::Result<DirectoryUpload> {
+        let dir = dir.as_ref();
+        let bucket_token = bucket_token.into();
+        trace::record_token!(&bucket_token);
+        let (files, failed) = walk_directory(dir).await?;
+
+        let mut report = DirectoryUpload {
+            uploaded: Vec::new(),
+            failed,
+        };
+        for (relative, path) in files {
+            let request = template.clone().bucket(&bucket_token).file(&path);
+            match self.upload_file(request).await {
+                Ok(entry) => report.uploaded.push((relative, entry)),
+                Err(err) => report.failed.push((relative, err)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Brings a bucket in line with a directory, uploading only what has changed
+    ///
+    /// Files are matched to the bucket by filename, since buckets have no folders. A file
+    /// already in the bucket is skipped unless its content has changed, in which case it is
+    /// uploaded again and the old copy deleted. Copies the same size as the local file are
+    /// downloaded to compare their SHA-256, using the template's password. With
+    /// [`SyncOptions::prune`] set, files in the bucket with no match in the directory are
+    /// deleted too. Files whose filename is hidden can't be matched and are left alone, and a
+    /// template that hides filenames is refused.
+    ///
+    /// As with [`ApiCaller::upload_directory`], anything that fails is recorded in the
+    /// report without stopping the sync. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::SyncOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let options = SyncOptions {
+    ///         prune: true,
+    ///         ..Default::default()
+    ///     };
+    ///     let report = caller
+    ///         .sync_directory_to_bucket("/some/directory", "bucket-token", options)
+    ///         .await?;
+    ///     println!("{} uploaded, {} unchanged", report.uploaded.len(), report.skipped.len());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn sync_directory_to_bucket(
+        &self,
+        dir: impl AsRef<std::path::Path>,
+        bucket_token: impl Into<BucketToken>,
+        options: SyncOptions,
+    ) -> anyhow::Result<SyncReport> {
+        let dir = dir.as_ref();
+        let bucket_token = bucket_token.into();
+        trace::record_token!(&bucket_token);
+        if options.template.hide_filename == Some(true) {
+            anyhow::bail!("files uploaded with hidden filenames can't be matched by later syncs");
+        }
+        let (files, failed) = walk_directory(dir).await?;
+        let bucket = self.get_bucket(&bucket_token).await?;
+
+        let mut report = SyncReport {
+            uploaded: Vec::new(),
+            skipped: Vec::new(),
+            deleted: Vec::new(),
+            failed,
+        };
+
+        let mut remote: HashMap<String, WaifuFileEntry> = bucket
+            .files
+            .into_iter()
+            .filter_map(|file| Some((visible_filename(&file)?, file)))
+            .collect();
+        let mut local_names = std::collections::HashSet::new();
+        let template = options.template;
+
+        for (relative, path) in files {
+            let Some(name) = relative
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            if !local_names.insert(name.clone()) {
+                let err = anyhow::anyhow!("another file named {name} is already being synced");
+                report.failed.push((relative, err));
+                continue;
+            }
+
+            let replacing = match remote.remove(&name) {
+                Some(existing) => {
+                    match self
+                        .sync_changed(&path, &existing, template.password.clone())
+                        .await
+                    {
+                        Ok(true) => Some(existing),
+                        Ok(false) => {
+                            report.skipped.push(relative);
+                            continue;
+                        }
+                        Err(err) => {
+                            report
+                                .failed
+                                .push((relative, err.context("comparing with the bucket")));
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let request = template.clone().bucket(&bucket_token).file(&path);
+            match self.upload_file(request).await {
+                Ok(entry) => report.uploaded.push((relative.clone(), entry)),
+                Err(err) => {
+                    report.failed.push((relative, err));
+                    continue;
+                }
+            }
+
+            if let Some(old) = replacing {
+                match self.delete_file(&old.token).await {
+                    Ok(_) => report.deleted.push(old.token),
+                    Err(err) => report
+                        .failed
+                        .push((relative, err.context("deleting old copy"))),
+                }
+            }
+        }
+
+        if options.prune {
+            for (name, file) in remote {
+                match self.delete_file(&file.token).await {
+                    Ok(_) => report.deleted.push(file.token),
+                    Err(err) => report.failed.push((PathBuf::from(name), err)),
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Whether the file at `path` differs from the copy of it already in a bucket
+    ///
+    /// Sizes are compared first. When they match, the copy is downloaded and its SHA-256
+    /// compared with the local file's, except for one time downloads, which would be
+    /// consumed and so are taken as unchanged.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn sync_changed(
+        &self,
+        path: &std::path::Path,
+        existing: &WaifuFileEntry,
+        password: Option<String>,
+    ) -> anyhow::Result<bool> {
+        let local_size = tokio::fs::metadata(path)
+            .await
+            .with_context(|| format!("reading {}", path.display()))?
+            .len();
+        let remote_size = self.file_size(&existing.url, password.clone()).await?;
+        if remote_size.is_some_and(|remote| remote != local_size) {
+            return Ok(true);
+        }
+        if existing
+            .options
+            .as_ref()
+            .is_some_and(|o| o.one_time_download)
+        {
+            return Ok(false);
+        }
+
+        let mut hasher = checksum::Hasher::new(checksum::HashAlgorithm::Sha256);
+        hash_file(path, local_size, &mut hasher).await?;
+        match self
+            .download_file_verified(&existing.url, password, hasher.finish())
+            .await
+        {
+            Ok(_) => Ok(false),
+            Err(err) if matches!(err.downcast_ref(), Some(Error::ChecksumMismatch { .. })) => {
+                Ok(true)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Upload a file to Waifu Vault after checking it against the service's restrictions
+    ///
+    /// The restrictions are fetched on the first checked upload and reused by this caller
+    /// afterwards, so oversized or banned content fails before anything is sent.
+    /// See [`api::WaifuUploadRequest::validate_against`] for what is checked.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, Error, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuUploadRequest::new().file("/some/large/video.mp4");
+    ///
+    ///     match caller.upload_file_checked(request).await {
+    ///         Ok(response) => println!("uploaded to {}", response.url),
+    ///         Err(e) => match e.downcast_ref::<Error>() {
+    ///             Some(Error::TooLarge { max, .. }) => println!("files must be under {max} bytes"),
+    ///             _ => return Err(e),
+    ///         },
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn upload_file_checked(
+        &self,
+        request: WaifuUploadRequest,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        let restrictions = self
+            .restrictions
+            .get_or_try_init(|| self.get_restrictions())
+            .await?;
+        request
+            .validate_against(restrictions)
+            .context("checking upload against restrictions")?;
+
+        self.upload_file(request).await
+    }
+
+    /// Retrieves the restrictions the service places on uploads
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let restrictions = caller.get_restrictions().await?;
+    ///
+    ///     // Check as many uploads as needed against the same restrictions
+    ///     for path in ["one.png", "two.png"] {
+    ///         let request = WaifuUploadRequest::new().file(path);
+    ///         request.validate_against(&restrictions)?;
+    ///         caller.upload_file(request).await?;
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn get_restrictions(&self) -> anyhow::Result<WaifuRestrictions> {
+        let url = format!("{}/resources/restrictions", self.base_url);
+        let response = self
+            .send(Operation::Restrictions, true, || Ok(self.client.get(&url)))
+            .await
+            .context("sending restrictions request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Sends a request to an endpoint of the service and returns the raw response
+    ///
+    /// An escape hatch for endpoints this crate doesn't wrap yet. `path` is joined onto the
+    /// base URL of the caller, such as `bucket/get` for `https://waifuvault.moe/rest/bucket/get`,
+    /// and `body`, if any, is sent as JSON. The request goes through the same client, retries,
+    /// observer and cancellation as the other methods, with only idempotent methods such as
+    /// `GET`, `PUT` and `DELETE` retried. The response is returned whatever its status:
+    /// it isn't checked or parsed, so error statuses are left to the caller.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use reqwest::Method;
+    /// use serde_json::json;
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let response = caller
+    ///         .execute(Method::POST, "bucket/get", Some(json!({ "bucket_token": "some-bucket" })))
+    ///         .await?;
+    ///     let bucket: serde_json::Value = response.error_for_status()?.json().await?;
+    ///     println!("{bucket}");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(%method, path)))]
+    pub async fn execute(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let url = format!("{}/{}", self.base_url, path.trim_start_matches('/'));
+        let idempotent = method.is_idempotent();
+        self.send(Operation::Raw, idempotent, || {
+            let r = self.client.request(method.clone(), &url);
+            Ok(match &body {
+                Some(body) => r.json(body),
+                None => r,
+            })
+        })
+        .await
+        .with_context(|| format!("sending {method} request to {path}"))
+    }
+
+    /// Retrieves information about a file stored in Waifu Vault
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{
+    ///     ApiCaller,
+    ///     api::WaifuGetRequest
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let token = "some-file-token-for-waifu-vault";
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuGetRequest::new(token)
+    ///         .formatted(true);
+    ///
+    ///     let response = caller.file_info(request).await?;
+    ///     // Do something with response
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = %request.token)))]
+    pub async fn file_info(&self, request: WaifuGetRequest) -> anyhow::Result<WaifuFileEntry> {
+        let url = format!("{}/{}", self.base_url, request.token.path_segment()?);
+        let response = self
+            .send(Operation::FileInfo, true, || {
+                Ok(self
+                    .client
+                    .get(&url)
+                    .query(&[("formatted", request.formatted)]))
+            })
+            .await
+            .context("sending file info request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Retrieves information about a file, returning `None` if the service doesn't know it
+    ///
+    /// Useful for polling whether a one time download has been consumed. Any error other
+    /// than the file not being found is still returned as an error.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuGetRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuGetRequest::new("some-file-token");
+    ///
+    ///     if caller.try_file_info(request).await?.is_none() {
+    ///         println!("file is gone");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = %request.token)))]
+    pub async fn try_file_info(
+        &self,
+        request: WaifuGetRequest,
+    ) -> anyhow::Result<Option<WaifuFileEntry>> {
+        match self.file_info(request).await {
+            Ok(entry) => Ok(Some(entry)),
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Checks whether the service knows a file
+    ///
+    /// Returns `false` when the service answers that the file wasn't found, or rejects the
+    /// token as unknown or invalid with a 400, as it does for tokens that never existed. A
+    /// token that can't be put in a request at all, such as an empty one, is also `false`.
+    /// Other failures, such as a connection error, a rate limit or a 400 about anything but
+    /// the token, are returned as errors.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     if caller.file_exists("some-file-token").await? {
+    ///         println!("still there");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn file_exists(&self, token: impl Into<FileToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        trace::record_token!(&token);
+        exists(self.file_info(WaifuGetRequest::new(token)).await)
+    }
+
+    /// Waits for a file to be consumed, such as a one time download being downloaded
+    ///
+    /// Looks the file up every `poll_interval`, but no more often than
+    /// [`MIN_POLL_INTERVAL`], until the service no longer knows it or `timeout` has passed.
+    /// Errors other than the file not being found end the wait. Cancelling the caller with
+    /// [`ApiCaller::cancelled_by`] ends it with [`Error::Cancelled`], whether a lookup is in
+    /// flight or not. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use waifuvault::{ApiCaller, api::Consumption};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let hour = Duration::from_secs(60 * 60);
+    ///     match caller.wait_until_consumed("some-file-token", Duration::from_secs(30), hour).await? {
+    ///         Consumption::Consumed => println!("the link was used"),
+    ///         Consumption::StillPresent(entry) => println!("{} has not been downloaded", entry.url),
+    ///         Consumption::TimedOut => println!("the service didn't answer in time"),
+    ///         _ => {}
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn wait_until_consumed(
+        &self,
+        token: impl Into<FileToken>,
+        poll_interval: std::time::Duration,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<Consumption> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let poll_interval = poll_interval.max(MIN_POLL_INTERVAL);
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        self.cancellable(async {
+            let mut last_seen = None;
+            loop {
+                let lookup = self.try_file_info(WaifuGetRequest::new(&token));
+                match tokio::time::timeout_at(deadline, lookup).await {
+                    Ok(Ok(None)) => return Ok(Consumption::Consumed),
+                    Ok(Ok(Some(entry))) => last_seen = Some(entry),
+                    Ok(Err(e)) => return Err(e),
+                    Err(_) => break,
+                }
+
+                let next = tokio::time::Instant::now() + poll_interval;
+                if next >= deadline {
+                    tokio::time::sleep_until(deadline).await;
+                    break;
+                }
+                trace::debug!(?poll_interval, "file still present, polling again");
+                tokio::time::sleep_until(next).await;
+            }
+
+            Ok(last_seen.map_or(Consumption::TimedOut, |entry| {
+                Consumption::StillPresent(Box::new(entry))
+            }))
+        })
+        .await
+    }
+
+    /// Follows a file's entry as its retention counts down, until the file is gone
+    ///
+    /// Returns a [`watch::RetentionWatch`] stream, which looks the file up straight away and
+    /// then every `interval`, but no more often than [`MIN_POLL_INTERVAL`]. Nothing is sent
+    /// until the stream is polled. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch_retention(
+        &self,
+        token: impl Into<FileToken>,
+        interval: std::time::Duration,
+    ) -> watch::RetentionWatch {
+        watch::RetentionWatch::new(self.clone(), token.into(), interval.max(MIN_POLL_INTERVAL))
+    }
+
+    /// Retrieves information about many files, with up to `concurrency` lookups at once
+    ///
+    /// Returns the outcome for each token in the order given. A token that can't be looked
+    /// up only fails its own lookup. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let tokens = ["token-1", "token-2", "token-3"];
+    ///     for (token, info) in tokens.iter().zip(caller.file_info_batch(&tokens, true, 8).await) {
+    ///         match info {
+    ///             Ok(info) => println!("{token} has {} views", info.views),
+    ///             Err(err) => eprintln!("{token}: {err:#}"),
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn file_info_batch<I>(
+        &self,
+        tokens: I,
+        formatted: bool,
+        concurrency: usize,
+    ) -> Vec<anyhow::Result<WaifuFileEntry>>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        concurrently(collect_file_tokens(tokens), concurrency, |token| {
+            let caller = self.clone();
+            let request = WaifuGetRequest::new(token).formatted(formatted);
+            async move { caller.file_info(request).await }
+        })
+        .await
+    }
+
+    /// Retrieves information about a file from its public URL
+    ///
+    /// Fails with [`Error::InvalidFileUrl`] if the URL isn't of the form
+    /// `https://waifuvault.moe/f/{id}/{file}`. See [`WaifuGetRequest::from_url`] to also set
+    /// other options on the request.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let info = caller
+    ///         .file_info_from_url("https://waifuvault.moe/f/1712345/picture.png")
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn file_info_from_url(&self, url: &str) -> anyhow::Result<WaifuFileEntry> {
+        self.file_info(WaifuGetRequest::from_url(url)?).await
+    }
+
+    /// Updates options on a stored file in Waifu Vault
+    ///
+    /// Allows the changing of the password, expiry time, and whether to hide the filename or not
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{
+    ///     ApiCaller,
+    ///     api::WaifuModificationRequest
+    /// };
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let token = "some-token";
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuModificationRequest::new(token)
+    ///         .password("banana")
+    ///         .previous_password("apple")
+    ///         .hide_filename(true);
+    ///     let response = caller.update_file(request).await?;
+    ///     // Do something with the response
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = %request.token)))]
+    pub async fn update_file(
+        &self,
+        request: WaifuModificationRequest,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        // An empty expiry clears the custom one
+        if let Some(expiry) = request.custom_expiry.as_deref().filter(|e| !e.is_empty()) {
+            validate_expiry(expiry)?;
+        }
+
+        let url = format!("{}/{}", self.base_url, request.token.path_segment()?);
+        let response = self
+            .send(Operation::UpdateFile, false, || {
+                Ok(self
+                    .client
+                    .patch(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&request))
+            })
+            .await
+            .context("sending modification request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Updates options on a stored file, reporting what changed
+    ///
+    /// Like [`ApiCaller::update_file`], but looks the file up first and compares it with
+    /// the entry the update returns. The [`OptionsDiff`] lists the options that changed,
+    /// which also shows when the service ignored part of the request. This costs an extra
+    /// request, which [`ApiCaller::update_file`] doesn't make. Not available on wasm32, where
+    /// there is no clock to compare the expiries with.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuModificationRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuModificationRequest::new("some-token").hide_filename(true);
+    ///
+    ///     let (entry, diff) = caller.update_file_with_diff(request).await?;
+    ///     println!("{}: {diff}", entry.token);
+    ///     if diff.hide_filename.is_none() {
+    ///         println!("the filename was already hidden");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = %request.token)))]
+    pub async fn update_file_with_diff(
+        &self,
+        request: WaifuModificationRequest,
+    ) -> anyhow::Result<(WaifuFileEntry, OptionsDiff)> {
+        let before = self
+            .file_info(WaifuGetRequest::new(&request.token))
+            .await
+            .context("looking up the file to update")?;
+        let before_at = std::time::SystemTime::now();
+
+        let after = self.update_file(request).await?;
+        let diff = OptionsDiff::between(&before, before_at, &after, std::time::SystemTime::now());
+
+        Ok((after, diff))
+    }
+
+    /// Updates options on a stored file, checking first that the change can be made
+    ///
+    /// Like [`ApiCaller::update_file`], but looks the file up first. Changing the password
+    /// of a protected file without giving its previous password fails with
+    /// [`Error::PreviousPasswordRequired`] before the change is sent, rather than the
+    /// service rejecting it with a vague error. This costs an extra request, which
+    /// [`ApiCaller::update_file`] doesn't make.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuModificationRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let request = WaifuModificationRequest::new("some-token").password("banana");
+    ///
+    ///     match caller.update_file_checked(request).await {
+    ///         Ok(entry) => println!("updated {}", entry.token),
+    ///         Err(e) if matches!(
+    ///             e.downcast_ref(),
+    ///             Some(waifuvault::Error::PreviousPasswordRequired { .. })
+    ///         ) => println!("the file already has a password"),
+    ///         Err(e) => return Err(e),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = %request.token)))]
+    pub async fn update_file_checked(
+        &self,
+        request: WaifuModificationRequest,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        if request.password.is_some() && request.previous_password.is_none() {
+            let entry = self
+                .file_info(WaifuGetRequest::new(&request.token))
+                .await
+                .context("looking up the file to update")?;
+            if entry.options.is_some_and(|o| o.protected) {
+                return Err(Error::PreviousPasswordRequired {
+                    token: request.token,
+                }
+                .into());
+            }
+        }
+
+        self.update_file(request).await
+    }
+
+    /// Deletes a file from Waifu Vault
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let token = "token-to-delete";
+    ///     let caller = ApiCaller::new();
+    ///     let response = caller.delete_file(token).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn delete_file(&self, token: impl Into<FileToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let url = format!("{}/{}", self.base_url, token.path_segment()?);
+        let response = self
+            .send(Operation::DeleteFile, false, || {
+                Ok(self.client.delete(&url))
+            })
+            .await
+            .context("sending delete request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Deletes many files, a few at a time
+    ///
+    /// Every token is tried, a failure to delete one doesn't stop the rest. Returns the
+    /// outcome for each token in the order given. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     for (token, deleted) in caller.delete_files(&["token-1", "token-2"]).await {
+    ///         if let Err(err) = deleted {
+    ///             eprintln!("{token}: {err:#}");
+    ///         }
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn delete_files<I>(&self, tokens: I) -> Vec<(FileToken, anyhow::Result<bool>)>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let tokens = collect_file_tokens(tokens);
+        let outcomes = concurrently(tokens.clone(), DELETE_CONCURRENCY, |token| {
+            let caller = self.clone();
+            async move { caller.delete_file(token).await }
+        })
+        .await;
+
+        tokens.into_iter().zip(outcomes).collect()
+    }
+
+    /// Replaces the contents of a file stored in Waifu Vault
+    ///
+    /// The contents are sent to the service's replace endpoint, which overwrites the file in
+    /// place. Servers without that endpoint answer 404 or 405, and the new contents are then
+    /// uploaded as a new file and the old file deleted once the upload has succeeded. The
+    /// returned [`api::Replaced`] reports `token_changed` in that case so callers know links
+    /// to the old file are broken and can update anything keyed on the token. A stream can't
+    /// be sent twice, so replacing with one always uploads a new file.
+    ///
+    /// The new file keeps what it can of the old one: its bucket, album membership,
+    /// hidden filename and one-time-download flags are carried over unless the request
+    /// sets them. A password can't be read back from the service, so replacing a
+    /// protected file requires the request to set one.
+    ///
+    /// If anything fails before the old file is deleted, the new upload is removed again
+    /// and the old file is left untouched. If deleting the old file fails, the new upload is
+    /// kept and the error names its token.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::WaifuUploadRequest};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let request = WaifuUploadRequest::new().file("/some/updated/file");
+    ///     let replaced = caller.replace_contents("old-token", request).await?;
+    ///
+    ///     if replaced.token_changed {
+    ///         // Update any stored links to point at `replaced.new_entry.url`
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn replace_contents(
+        &self,
+        token: impl Into<FileToken>,
+        mut request: WaifuUploadRequest,
+    ) -> anyhow::Result<Replaced> {
+        let token: FileToken = token.into();
+        trace::record_token!(&token);
+        let old = self
+            .file_info(WaifuGetRequest::new(&token))
+            .await
+            .context("getting file to replace")?;
+
+        if let Some(options) = &old.options {
+            if options.protected && request.password.is_none() {
+                anyhow::bail!(
+                    "file {token} is password protected, set a password on the replacement request to keep it protected"
+                );
+            }
+
+            if request.hide_filename.is_none() && options.hide_filename {
+                request.hide_filename = Some(true);
+            }
+            if request.one_time_download.is_none() && options.one_time_download {
+                request.one_time_download = Some(true);
+            }
+        }
+
+        let replayable = request.source.as_ref().is_some_and(|s| s.is_replayable());
+        if replayable {
+            match self.upload(request.clone(), Some(&token)).await {
+                Ok(outcome) => {
+                    return Ok(Replaced {
+                        token_changed: outcome.entry.token != old.token,
+                        new_entry: outcome.entry,
+                    })
+                }
+                Err(err) if no_replace_route(&err) => {
+                    trace::debug!("no replace endpoint, uploading a new file");
+                }
+                Err(err) => return Err(err.context("replacing contents")),
+            }
+        }
+
+        if request.bucket.is_none() {
+            request.bucket = old.bucket.clone();
+        }
+
+        let new_entry = self
+            .upload_file(request)
+            .await
+            .context("uploading replacement contents")?;
+
+        if let Some(album) = &old.album {
+            if let Err(e) = self
+                .associate_with_album(&album.token, &[&new_entry.token])
+                .await
+            {
+                let _ = self.delete_file(&new_entry.token).await;
+                return Err(e.context("adding replacement to the album of the old file"));
+            }
+        }
+
+        self.delete_file(&token).await.with_context(|| {
+            format!(
+                "replacement uploaded as {} but the old file could not be deleted",
+                new_entry.token
+            )
+        })?;
+
+        Ok(Replaced {
+            token_changed: new_entry.token != old.token,
+            new_entry,
+        })
+    }
+
+    /// Size of a file in bytes, without downloading it
+    ///
+    /// Sends a `HEAD` request for the file, with the password if one is given, and returns
+    /// its `Content-Length`, or `None` if the server doesn't say. A missing or wrong
+    /// password fails the same way as [`ApiCaller::download_file`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let url = "https://waifuvault.moe/f/[some-id]/file.jpg";
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     match caller.file_size(url, None).await? {
+    ///         Some(size) => println!("downloading {size} bytes"),
+    ///         None => println!("downloading a file of unknown size"),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn file_size(
+        &self,
+        url: &str,
+        password: Option<String>,
+    ) -> anyhow::Result<Option<u64>> {
+        let header = password.as_deref().map(password_header).transpose()?;
+        let response = self
+            .send(Operation::FileSize, true, || {
+                let mut r = self.client.head(url);
+                if let Some(header) = &header {
+                    r = r.header("x-password", header.clone());
+                }
+                Ok(r)
+            })
+            .await
+            .context("sending size request")?;
+
+        match response.status() {
+            // The body of a HEAD response is empty, so its length is read from the header
+            reqwest::StatusCode::OK => Ok(response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse().ok())),
+            reqwest::StatusCode::FORBIDDEN => Err(download_forbidden(password.is_some())),
+            _ => Err(error_response(response).await),
+        }
+    }
+
+    /// Downloads a file from Waifu Vault
+    ///
+    /// Returns the contents of the file as an array of bytes
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    /// use std::io::Write;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let url = "https://waifuvault.moe/f/[some-id]/file.jpg";
+    ///     let caller = ApiCaller::new();
+    ///     let file_bytes = caller.download_file(url, Some("securepassword".to_string())).await?;
+    ///     let mut f = std::fs::File::create("downloaded.jpg")?;
+    ///     f.write_all(&file_bytes)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file(
+        &self,
+        url: &str,
+        password: Option<String>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut options = DownloadOptions::new();
+        options.password = password;
+        self.download_file_with(url, options).await
+    }
+
+    /// Downloads a file from Waifu Vault with options such as a password or timeout
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use waifuvault::{ApiCaller, api::DownloadOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let options = DownloadOptions::new().timeout(Duration::from_secs(30));
+    ///
+    ///     match caller.download_file_with("https://waifuvault.moe/f/some-file.ext", options).await {
+    ///         Ok(content) => println!("downloaded {} bytes", content.len()),
+    ///         Err(e) if e.downcast_ref() == Some(&waifuvault::Error::TimedOut) => {
+    ///             println!("the download was too slow")
+    ///         }
+    ///         Err(e) => return Err(e),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_with(
+        &self,
+        url: &str,
+        options: DownloadOptions,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(self.download_body(url, &options).await?.to_vec())
+    }
+
+    /// Downloads a file from Waifu Vault without copying its content
+    ///
+    /// Like [`ApiCaller::download_file`], but returns the content as the HTTP client received
+    /// it rather than copying it into a `Vec<u8>`, so a large file is only held in memory
+    /// once. `Bytes` derefs to `&[u8]`, and can be turned into a vector with `.to_vec()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let content = caller
+    ///         .download_file_bytes("https://waifuvault.moe/f/some-file.ext", None)
+    ///         .await?;
+    ///     tokio::fs::write("downloaded.ext", &content).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_bytes(
+        &self,
+        url: &str,
+        password: Option<String>,
+    ) -> anyhow::Result<bytes::Bytes> {
+        let mut options = DownloadOptions::new();
+        options.password = password;
+        self.download_body(url, &options).await
+    }
+
+    /// Downloads a file from Waifu Vault, checking it has the expected content
+    ///
+    /// Like [`ApiCaller::download_file`], but the content is hashed as it arrives and the
+    /// download fails with [`Error::ChecksumMismatch`] unless its digest is `expected`. Use
+    /// [`DownloadOptions::checksum`] to check a download to disk the same way.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, Checksum};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let expected: Checksum =
+    ///         "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".parse()?;
+    ///
+    ///     match caller
+    ///         .download_file_verified("https://waifuvault.moe/f/some-file.ext", None, expected)
+    ///         .await
+    ///     {
+    ///         Ok(content) => println!("downloaded {} intact bytes", content.len()),
+    ///         Err(e) => match e.downcast_ref() {
+    ///             Some(waifuvault::Error::ChecksumMismatch { actual, .. }) => {
+    ///                 println!("the file was corrupted, got {actual}")
+    ///             }
+    ///             _ => return Err(e),
+    ///         },
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_verified(
+        &self,
+        url: &str,
+        password: Option<String>,
+        expected: Checksum,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut options = DownloadOptions::new().checksum(expected);
+        options.password = password;
+        self.download_file_with(url, options).await
+    }
+
+    /// Downloads the whole content of a file into memory
+    async fn download_body(
+        &self,
+        url: &str,
+        options: &DownloadOptions,
+    ) -> anyhow::Result<bytes::Bytes> {
+        let response = self.download_response(url, options, 0, None).await?;
+
+        let content = self
+            .read_body(response, options)
+            .await
+            .context("getting content bytes")?;
+        trace::debug!(bytes = content.len(), "downloaded file");
+
+        Ok(content)
+    }
+
+    /// Reads the whole body of a download, failing with [`Error::ResponseTooLarge`] once it
+    /// goes over the limit of the options, or the caller's limit if that isn't set, and with
+    /// [`Error::ChecksumMismatch`] if it doesn't have the checksum of the options
+    ///
+    /// On wasm32, where bodies can't be read in chunks, only the `Content-Length` is checked
+    /// before the body is read.
+    async fn read_body(
+        &self,
+        response: reqwest::Response,
+        options: &DownloadOptions,
+    ) -> anyhow::Result<bytes::Bytes> {
+        let limit = options.max_bytes.or(self.max_download_bytes);
+        let mut hasher = options
+            .checksum
+            .as_ref()
+            .map(|expected| checksum::Hasher::new(expected.algorithm()));
+        if limit.is_none() && hasher.is_none() {
+            return self
+                .cancellable(async { observe::body(response).await.map_err(transport_error) })
+                .await;
+        }
+
+        let too_large = |received| {
+            limit
+                .filter(|&limit| received > limit)
+                .map(|limit| Error::ResponseTooLarge { limit, received })
+        };
+        let length = response.content_length();
+        if let Some(e) = length.and_then(too_large) {
+            return Err(e.into());
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        let content = {
+            let content = self
+                .cancellable(async { observe::body(response).await.map_err(transport_error) })
+                .await?;
+            if let Some(e) = too_large(content.len() as u64) {
+                return Err(e.into());
+            }
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&content);
+            }
+            content
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let content = {
+            let mut response = response;
+            let capacity = length.unwrap_or_default().min(limit.unwrap_or(u64::MAX));
+            let mut content = bytes::BytesMut::with_capacity(capacity as usize);
+            while let Some(chunk) = self
+                .cancellable(async { observe::chunk(&mut response).await.map_err(transport_error) })
+                .await?
+            {
+                content.extend_from_slice(&chunk);
+                if let Some(e) = too_large(content.len() as u64) {
+                    return Err(e.into());
+                }
+                if let Some(hasher) = &mut hasher {
+                    hasher.update(&chunk);
+                }
+            }
+            content.freeze()
+        };
+
+        if let (Some(hasher), Some(expected)) = (hasher, &options.checksum) {
+            hasher.verify(expected)?;
+        }
+
+        Ok(content)
+    }
+
+    /// Downloads a file from Waifu Vault along with its type, name and size
+    ///
+    /// Like [`ApiCaller::download_file`], but keeps what the response headers said about the
+    /// file, such as to store it under the right name.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let file = caller
+    ///         .download_file_with_metadata("https://waifuvault.moe/f/some-file.ext", None)
+    ///         .await?;
+    ///
+    ///     let name = file.filename.as_deref().unwrap_or("download");
+    ///     std::fs::write(name, &file.bytes)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_with_metadata(
+        &self,
+        url: &str,
+        password: Option<String>,
+    ) -> anyhow::Result<DownloadedFile> {
+        let mut options = DownloadOptions::new();
+        options.password = password;
+        let response = self.download_response(url, &options, 0, None).await?;
+
+        let headers = response.headers();
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+        };
+        let content_type = header(reqwest::header::CONTENT_TYPE).map(str::to_string);
+        let filename =
+            header(reqwest::header::CONTENT_DISPOSITION).and_then(content_disposition_filename);
+        let length = header(reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse().ok());
+
+        let bytes = self
+            .read_body(response, &options)
+            .await
+            .context("getting content bytes")?
+            .to_vec();
+        trace::debug!(bytes = bytes.len(), "downloaded file");
+
+        Ok(DownloadedFile {
+            bytes,
+            content_type,
+            filename,
+            length,
+        })
+    }
+
+    /// Downloads a file from Waifu Vault into a file on disk, resuming a partial download
+    ///
+    /// The file is written as it arrives, so an interrupted download leaves what it got so
+    /// far at `path`. Downloading again with [`DownloadOptions::resume`] then only asks for
+    /// the rest of the file, and appends it. If the server doesn't support ranges, or the
+    /// partial file is longer than the file on the server, the whole file is downloaded again
+    /// and [`FileDownload::warning`] says why. Any other answer to the range, such as part of
+    /// the file from the wrong offset, fails with [`Error::UnexpectedResponse`] and leaves the
+    /// partial file alone. Without a resume option an existing file is overwritten. Not
+    /// available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::DownloadOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let url = "https://waifuvault.moe/f/some-file.iso";
+    ///
+    ///     let report = caller
+    ///         .download_file_to(url, "some-file.iso", DownloadOptions::new().resume())
+    ///         .await?;
+    ///     println!("{} bytes, {} already on disk", report.size, report.resumed_from);
+    ///     if let Some(warning) = report.warning {
+    ///         eprintln!("{warning}");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_to(
+        &self,
+        url: &str,
+        path: impl AsRef<std::path::Path>,
+        options: DownloadOptions,
+    ) -> anyhow::Result<FileDownload> {
+        let path = path.as_ref();
+        let offset = match options.resume {
+            None => 0,
+            Some(api::Resume::From(offset)) => offset,
+            Some(api::Resume::Existing) => match tokio::fs::metadata(path).await {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("reading metadata of {}", path.display()))
+                }
+            },
+        };
+
+        let mut response = self.download_response(url, &options, offset, None).await?;
+        let mut start = 0;
+        let mut warning = None;
+        if offset > 0 {
+            let (range_start, total) = content_range(response.headers());
+            match response.status() {
+                reqwest::StatusCode::PARTIAL_CONTENT if range_start == Some(offset) => {
+                    start = offset;
+                }
+                reqwest::StatusCode::RANGE_NOT_SATISFIABLE if total == Some(offset) => {
+                    // Everything was already downloaded
+                    let file = tokio::fs::OpenOptions::new()
+                        .write(true)
+                        .open(path)
+                        .await
+                        .with_context(|| format!("opening {}", path.display()))?;
+                    file.set_len(offset)
+                        .await
+                        .with_context(|| format!("writing {}", path.display()))?;
+                    if let Some(expected) = &options.checksum {
+                        let mut hasher = checksum::Hasher::new(expected.algorithm());
+                        hash_file(path, offset, &mut hasher).await?;
+                        hasher.verify(expected)?;
+                    }
+
+                    return Ok(FileDownload {
+                        size: offset,
+                        resumed_from: offset,
+                        warning: None,
+                    });
+                }
+                reqwest::StatusCode::OK => {
+                    warning = Some(
+                        "the server doesn't support resuming downloads, downloaded the whole file again"
+                            .to_string(),
+                    );
+                }
+                // The file on the server is shorter than what is already on disk
+                reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+                    if total.is_some_and(|total| total < offset) =>
+                {
+                    warning = Some(format!(
+                        "the {offset} bytes on disk don't fit the file on the server, downloaded the whole file again"
+                    ));
+                    response = self.download_response(url, &options, 0, None).await?;
+                }
+                status => {
+                    let body = observe::body(response)
+                        .await
+                        .map_err(transport_error)
+                        .context("reading response body")?;
+                    return Err(unexpected(status, &body).into());
+                }
+            }
+        }
+        trace::debug!(offset = start, "downloading file to disk");
+
+        let mut file = if start > 0 {
+            let file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .await
+                .with_context(|| format!("opening {}", path.display()))?;
+            file.set_len(start)
+                .await
+                .with_context(|| format!("writing {}", path.display()))?;
+            file
+        } else {
+            tokio::fs::File::create(path)
+                .await
+                .with_context(|| format!("creating {}", path.display()))?
+        };
+
+        let mut hasher = options
+            .checksum
+            .as_ref()
+            .map(|expected| checksum::Hasher::new(expected.algorithm()));
+        if let Some(hasher) = hasher.as_mut().filter(|_| start > 0) {
+            hash_file(path, start, hasher).await?;
+        }
+
+        let written = self
+            .write_body(&mut response, &mut file, path, hasher.as_mut())
+            .await?;
+        trace::debug!(bytes = written, "downloaded file");
+        if let (Some(hasher), Some(expected)) = (hasher, &options.checksum) {
+            hasher.verify(expected)?;
+        }
+
+        Ok(FileDownload {
+            size: start + written,
+            resumed_from: start,
+            warning,
+        })
+    }
+
+    /// Downloads a large file into a file on disk over several connections at once
+    ///
+    /// The first range of the file is requested to learn its size, then the file is
+    /// allocated and the rest is downloaded in ranges of [`DownloadTuning::chunk_size`],
+    /// up to [`DownloadTuning::connections`] at once, each written where it belongs. If the
+    /// server doesn't support ranges the file is downloaded over a single connection instead,
+    /// and [`FileDownload::warning`] says so. Either way the file ends up the same as with
+    /// [`ApiCaller::download_file`]. Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::DownloadTuning};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let url = "https://waifuvault.moe/f/some-file.iso";
+    ///
+    ///     let report = caller
+    ///         .download_file_parallel(url, None, "some-file.iso", DownloadTuning::default())
+    ///         .await?;
+    ///     println!("downloaded {} bytes", report.size);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(url = %url)))]
+    pub async fn download_file_parallel(
+        &self,
+        url: &str,
+        password: Option<String>,
+        path: impl AsRef<std::path::Path>,
+        tuning: DownloadTuning,
+    ) -> anyhow::Result<FileDownload> {
+        let path = path.as_ref();
+        let chunk_size = tuning.chunk_size.max(1);
+        let mut options = DownloadOptions::new();
+        options.password = password;
+
+        let mut response = self
+            .download_response(url, &options, 0, Some(chunk_size - 1))
+            .await?;
+        let total = match (response.status(), content_range(response.headers())) {
+            (reqwest::StatusCode::PARTIAL_CONTENT, (Some(0), Some(total))) => Some(total),
+            // An empty file has no range to give
+            (reqwest::StatusCode::RANGE_NOT_SATISFIABLE, (_, Some(0))) => Some(0),
+            _ => None,
+        };
+
+        let Some(total) = total else {
+            if response.status() != reqwest::StatusCode::OK {
+                response = self.download_response(url, &options, 0, None).await?;
+            }
+            let mut file = tokio::fs::File::create(path)
+                .await
+                .with_context(|| format!("creating {}", path.display()))?;
+            let size = self
+                .write_body(&mut response, &mut file, path, None)
+                .await?;
+
+            return Ok(FileDownload {
+                size,
+                resumed_from: 0,
+                warning: Some(
+                    "the server doesn't support ranges, downloaded over a single connection"
+                        .to_string(),
+                ),
+            });
+        };
+        trace::debug!(bytes = total, "downloading file in ranges");
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .with_context(|| format!("creating {}", path.display()))?;
+        file.set_len(total)
+            .await
+            .with_context(|| format!("allocating {}", path.display()))?;
+        if total > 0 {
+            let written = self
+                .write_body(&mut response, &mut file, path, None)
+                .await?;
+            if written != chunk_size.min(total) {
+                anyhow::bail!("the server sent {written} bytes for the first range of the file");
+            }
+        }
+        drop(file);
+
+        let mut ranges = Vec::new();
+        let mut start = chunk_size;
+        while start < total {
+            let end = start.saturating_add(chunk_size).min(total) - 1;
+            ranges.push((start, end));
+            start = end + 1;
+        }
+
+        let downloads = concurrently(ranges, tuning.connections, |(start, end)| {
+            let caller = self.clone();
+            let url = url.to_string();
+            let options = options.clone();
+            let path = path.to_path_buf();
+            async move {
+                caller
+                    .download_range(&url, &options, &path, start, end)
+                    .await
+                    .with_context(|| format!("downloading bytes {start}-{end}"))
+            }
+        })
+        .await;
+        downloads.into_iter().collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(FileDownload {
+            size: total,
+            resumed_from: 0,
+            warning: None,
+        })
+    }
+
+    /// Downloads bytes `start` to `end` inclusive of a file into the same place on disk
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn download_range(
+        &self,
+        url: &str,
+        options: &DownloadOptions,
+        path: &std::path::Path,
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<()> {
+        use tokio::io::AsyncSeekExt;
+
+        let mut response = self
+            .download_response(url, options, start, Some(end))
+            .await?;
+        let (range_start, _) = content_range(response.headers());
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT || range_start != Some(start) {
+            anyhow::bail!("the server didn't send the range asked for");
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .await
+            .with_context(|| format!("opening {}", path.display()))?;
+        file.seek(std::io::SeekFrom::Start(start))
+            .await
+            .with_context(|| format!("writing {}", path.display()))?;
+        let written = self
+            .write_body(&mut response, &mut file, path, None)
+            .await?;
+        if written != end - start + 1 {
+            anyhow::bail!("the server sent {written} bytes");
+        }
+
+        Ok(())
+    }
+
+    /// Writes the body of a download to `file` as it arrives, returning its length
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn write_body(
+        &self,
+        response: &mut reqwest::Response,
+        file: &mut tokio::fs::File,
+        path: &std::path::Path,
+        mut hasher: Option<&mut checksum::Hasher>,
+    ) -> anyhow::Result<u64> {
+        let mut written = 0;
+        while let Some(chunk) = self
+            .cancellable(async { observe::chunk(response).await.map_err(transport_error) })
+            .await
+            .context("reading file content")?
+        {
+            file.write_all(&chunk)
+                .await
+                .with_context(|| format!("writing {}", path.display()))?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+            written += chunk.len() as u64;
+        }
+        file.flush()
+            .await
+            .with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(written)
+    }
+
+    /// Requests a file, or the range of it from `start` to `end` if either is set
+    ///
+    /// Returns the response once it is known to be successful, or for a range request, that
+    /// the server couldn't satisfy the range.
+    async fn download_response(
+        &self,
+        url: &str,
+        options: &DownloadOptions,
+        start: u64,
+        end: Option<u64>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let ranged = start > 0 || end.is_some();
+        let password = options
+            .password
+            .as_deref()
+            .map(password_header)
+            .transpose()?;
+        let request = || {
+            let mut r = self.client.get(url);
+            if let Some(password) = &password {
+                r = r.header("x-password", password.clone());
+            }
+
+            if options.identity_encoding {
+                r = r.header(reqwest::header::ACCEPT_ENCODING, "identity");
+            }
+
+            if ranged {
+                let end = end.map(|end| end.to_string()).unwrap_or_default();
+                r = r.header(reqwest::header::RANGE, format!("bytes={start}-{end}"));
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(timeout) = options.timeout {
+                r = r.timeout(timeout);
+            }
+
+            Ok(r)
+        };
+
+        let response = self
+            .send(Operation::Download, true, request)
+            .await
+            .context("sending download request")?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => Ok(response),
+            reqwest::StatusCode::PARTIAL_CONTENT | reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+                if ranged =>
+            {
+                Ok(response)
+            }
+            reqwest::StatusCode::FORBIDDEN => Err(download_forbidden(options.password.is_some())),
+            _ => Err(error_response(response).await),
+        }
+    }
+
+    /// Downloads a file from Waifu Vault using its token instead of its URL
+    ///
+    /// The URL is looked up with [`ApiCaller::file_info`] first, so this works for files with
+    /// hidden filenames too. An unknown token fails the lookup with the [`WaifuError`] from
+    /// the service rather than attempting a download.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let file_bytes = caller.download_file_by_token("some-file-token", None).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn download_file_by_token(
+        &self,
+        token: impl Into<FileToken>,
+        password: Option<String>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let token = token.into();
+        trace::record_token!(&token);
+        let entry = self
+            .file_info(WaifuGetRequest::new(&token))
+            .await
+            .with_context(|| format!("looking up file {token}"))?;
+
+        self.download_file(&entry.url, password).await
+    }
+
+    /// Downloads every file in a bucket into a directory
+    ///
+    /// Files are saved under the names they were uploaded with, and up to `concurrency` are
+    /// downloaded at once. A file that can't be saved, including any that are password
+    /// protected, is recorded in [`BucketDownload::failed`] without stopping the others.
+    /// Not available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let report = caller.download_bucket("bucket-token", "backup", 4).await?;
+    ///     for (token, err) in &report.failed {
+    ///         eprintln!("{token}: {err:#}");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn download_bucket(
+        &self,
+        bucket_token: impl Into<BucketToken>,
+        dest_dir: impl Into<PathBuf>,
+        concurrency: usize,
+    ) -> anyhow::Result<BucketDownload> {
+        let bucket = self
+            .get_bucket(WaifuBucketRequest::new(bucket_token))
+            .await?;
+        let dest_dir = dest_dir.into();
+        tokio::fs::create_dir_all(&dest_dir)
+            .await
+            .with_context(|| format!("creating directory {}", dest_dir.display()))?;
+
+        let mut report = BucketDownload {
+            saved: Vec::new(),
+            failed: Vec::new(),
+        };
+        let mut names = std::collections::HashSet::new();
+        let mut pending = Vec::new();
+        for file in bucket.files {
+            if file.is_protected() {
+                let err = anyhow::anyhow!("this file requires a password to download");
+                report.failed.push((file.token, err));
+                continue;
+            }
+
+            let mut name = stored_filename(&file);
+            if !names.insert(name.clone()) {
+                name = format!("{}_{name}", file.token);
+            }
+            pending.push((file, dest_dir.join(name)));
+        }
+
+        let downloads = concurrently(pending, concurrency, |(file, path)| {
+            let caller = self.clone();
+            async move {
+                let saved = async {
+                    let content = caller.download_file(&file.url, None).await?;
+                    tokio::fs::write(&path, content)
+                        .await
+                        .with_context(|| format!("writing {}", path.display()))
+                }
+                .await;
+                (file.token, path, saved)
+            }
+        })
+        .await;
+
+        for download in downloads {
+            match download {
+                (token, path, Ok(())) => report.saved.push((token, path)),
+                (token, _, Err(err)) => report.failed.push((token, err)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Creates an album on the WaifuVault service
+    ///
+    /// This requires the token from a previously created bucket
+    /// Returns information relating to the newly created album
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let bucket = caller.create_bucket().await?;
+    ///     let album_info = caller.create_album(&bucket.token, "new_album").await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn create_album(
+        &self,
+        bucket_token: impl Into<BucketToken>,
+        album_name: &str,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        let bucket_token = bucket_token.into();
+        trace::record_token!(&bucket_token);
+        let url = format!("{}/album/{}", self.base_url, bucket_token.path_segment()?);
+        let mut body = HashMap::new();
+        body.insert("name", album_name);
+        let response = self
+            .send(Operation::CreateAlbum, false, || {
+                Ok(self
+                    .client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&body))
+            })
+            .await
+            .context("sending create album request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Associates a collection of Files with an Album
+    ///
+    /// This requires File tokens already present on Waifu Vault
+    /// and a previously created album.
+    /// The tokens can be any slice, collection or iterator of [`FileToken`]s or strings.
+    /// Returns information relating to the updated album
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let file_token1 = "file-token-1";
+    ///     let file_token2 = "file-token-2";
+    ///     let album_token = "album-token";
+    ///     let album_info = caller.associate_with_album(album_token, &[file_token1, file_token2]).await?;
+    ///
+    ///     // Tokens already held in another collection don't need collecting again
+    ///     let bucket = caller.get_bucket("bucket-token").await?;
+    ///     let album_info = caller
+    ///         .associate_with_album(album_token, bucket.files.iter().map(|f| &f.token))
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn associate_with_album<I>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_tokens: I,
+    ) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let file_tokens = collect_file_tokens(file_tokens);
+        let url = format!(
+            "{}/album/{}/associate",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let mut body = HashMap::new();
+        body.insert("fileTokens", &file_tokens);
+
+        let response = self
+            .send(Operation::AssociateFiles, false, || {
+                Ok(self
+                    .client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&body))
+            })
+            .await
+            .context("sending album association request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Disassociate a collection of Files with an Album
+    ///
+    /// This requires File tokens already present on Waifu Vault
+    /// and a previously created album.
+    /// The tokens can be any slice, collection or iterator of [`FileToken`]s or strings.
+    /// Returns information relating to the updated album
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let file_token1 = "file-token-1";
+    ///     let file_token2 = "file-token-2";
+    ///     let album_token = "album-token";
+    ///     let album_info = caller.disassociate_from_album(album_token, &[file_token1, file_token2]).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn disassociate_from_album<I>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_tokens: I,
+    ) -> anyhow::Result<WaifuAlbumEntry>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let file_tokens = collect_file_tokens(file_tokens);
+        let url = format!(
+            "{}/album/{}/disassociate",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let mut body = HashMap::new();
+        body.insert("fileTokens", &file_tokens);
+
+        let response = self
+            .send(Operation::DisassociateFiles, false, || {
+                Ok(self
+                    .client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .json(&body))
+            })
+            .await
+            .context("sending album association request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Delete an album from Waifu Vault
+    ///
+    /// If `deleteFiles` is true, will delete the files from the bucket as well.
+    ///
+    /// Returns the status of the operation indicating if it was successful or not.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let album_token = "album-token";
+    ///
+    ///     // Keep files associated with the bucket
+    ///     // Pass `true` to delete files from the bucket as well
+    ///     let status = caller.delete_album(album_token, false).await?;
+    ///
+    ///     assert!(status.success);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn delete_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        delete_files: bool,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let url = format!("{}/album/{}", self.base_url, album_token.path_segment()?);
+        let response = self
+            .send(Operation::DeleteAlbum, false, || {
+                Ok(self
+                    .client
+                    .delete(&url)
+                    .query(&[("deleteFiles", delete_files)]))
+            })
+            .await
+            .context("sending album delete request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Returns an [`Album`] handle for an existing album
+    ///
+    /// No request is made, so the token isn't checked until the handle is used.
+    pub fn album(&self, token: impl Into<AlbumToken>) -> Album {
+        Album::new(self.clone(), token.into())
+    }
+
+    /// Get information about album from Waifu Vault
+    ///
+    /// Returns information relating to the album
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let album_token = "album-token";
+    ///     let album_info = caller.get_album(album_token).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn get_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let url = format!("{}/album/{}", self.base_url, album_token.path_segment()?);
+        let response = self
+            .send(Operation::GetAlbum, true, || Ok(self.client.get(&url)))
+            .await
+            .context("sending get album request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Checks whether the service knows an album
+    ///
+    /// Returns `false` when the service says the token is unknown, and any other failure as
+    /// an error. See [`ApiCaller::file_exists`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     println!("{}", caller.album_exists("some-album-token").await?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn album_exists(&self, token: impl Into<AlbumToken>) -> anyhow::Result<bool> {
+        let token = token.into();
+        trace::record_token!(&token);
+        exists(self.get_album(token).await)
+    }
+
+    /// Share an album from Waifu Vault
+    ///
+    /// Returns a staus object indicating the success of the operation.
+    /// It also contains the URL of the public album in the `description`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let album_token = "album-token";
+    ///     let status = caller.share_album(album_token).await?;
+    ///
+    ///     assert!(status.success);
+    ///     let shareable_url = status.description;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn share_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let url = format!(
+            "{}/album/share/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let response = self
+            .send(Operation::ShareAlbum, false, || Ok(self.client.get(&url)))
+            .await
+            .context("sending share album request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Share an album from Waifu Vault, returning its public link
+    ///
+    /// Like [`ApiCaller::share_album`], but the URL in the response is parsed into a
+    /// [`WaifuShareResult`]. Fails if the service doesn't respond with a URL.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let shared = caller.share_album_link("album-token").await?;
+    ///     println!("{} ({})", shared.url, shared.public_token);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn share_album_link(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuShareResult> {
+        self.share_album(album_token).await?.try_into()
+    }
+
+    /// Revokes public access from an album on Waifu Vault
+    ///
+    /// Any public URLs to the album are invalidated.
+    /// Returns a staus object indicating the success of the operation.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let album_token = "album-token";
+    ///     let status = caller.revoke_album(album_token).await?;
+    ///
+    ///     assert!(status.success);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn revoke_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let url = format!(
+            "{}/album/revoke/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let response = self
+            .send(Operation::RevokeAlbum, false, || Ok(self.client.get(&url)))
+            .await
+            .context("sending share album request")?;
+
+        parse_response(response)
+            .await
+            .context("converting response")
+    }
+
+    /// Revokes public access to an album, reporting if it was shared in the first place
+    ///
+    /// The album is looked up first, and only revoked if it has a public token.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, api::RevokeOutcome};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     match caller.revoke_album_checked("album-token").await? {
+    ///         RevokeOutcome::Revoked => println!("link revoked"),
+    ///         _ => println!("album was not shared"),
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn revoke_album_checked(
+        &self,
+        album_token: impl Into<AlbumToken>,
+    ) -> anyhow::Result<RevokeOutcome> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let album = self.get_album(&album_token).await?;
+        if album.public_token.is_none() {
+            return Ok(RevokeOutcome::WasNotShared);
+        }
+
+        let message = self.revoke_album(&album_token).await?;
+        if !message.success {
+            anyhow::bail!("album was not revoked: {}", message.description);
+        }
+
+        Ok(RevokeOutcome::Revoked)
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault
+    ///
+    /// If `file_ids` is passed, it returns only those files in the archive.
+    /// If `None`, the entire contents of the album are returned
+    ///
+    /// The ids are the [`api::WaifuFileEntry::id`] of files returned by [`ApiCaller::get_album`].
+    ///
+    /// Returns a `Vec<u8>` containing the zipped data.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    /// use std::io::Write;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let album_token = "album-token";
+    ///     let zipped_contents = caller.download_album(album_token, None).await?;
+    ///     let mut f = std::fs::File::create("archive.zip")?;
+    ///     f.write_all(&zipped_contents)?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn download_album(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .download_album_bytes(album_token, file_ids)
+            .await?
+            .to_vec())
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault without copying it
+    ///
+    /// Like [`ApiCaller::download_album`], but returns the archive as the HTTP client
+    /// received it rather than copying it into a `Vec<u8>`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///     let archive = caller.download_album_bytes("album-token", None).await?;
+    ///     tokio::fs::write("album.zip", &archive).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn download_album_bytes(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+    ) -> anyhow::Result<bytes::Bytes> {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let response = self.album_download_response(album_token, file_ids).await?;
+
+        let content = self
+            .read_body(response, &DownloadOptions::new())
+            .await
+            .context("obtaining response bytes")?;
+        trace::debug!(bytes = content.len(), "downloaded album archive");
+
+        Ok(content)
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault into a writer
+    ///
+    /// Like [`ApiCaller::download_album`], but the archive is written to `writer` as it
+    /// arrives instead of being held in memory. Returns the number of bytes written.
+    /// Not available on wasm32, where responses can't be read in chunks.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let file = tokio::fs::File::create("archive.zip").await?;
+    ///     let written = caller.download_album_to("album-token", None, file).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn download_album_to<W>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+        mut writer: W,
+    ) -> anyhow::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let mut response = self.album_download_response(album_token, file_ids).await?;
+
+        let mut written = 0;
+        while let Some(chunk) = self
+            .cancellable(async { observe::chunk(&mut response).await.map_err(transport_error) })
+            .await
+            .context("reading album archive")?
+        {
+            writer
+                .write_all(&chunk)
+                .await
+                .context("writing album archive")?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await.context("writing album archive")?;
+        trace::debug!(bytes = written, "downloaded album archive");
+
+        Ok(written)
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault and extracts it into a directory
+    ///
+    /// Takes the same `file_ids` as [`ApiCaller::download_album`]. Directories are created as
+    /// needed, and existing files are skipped unless [`archive::ExtractOptions::overwrite`] is
+    /// set. Returns the paths of the files written.
+    ///
+    /// Entries that would land outside `dest_dir` fail the extraction with
+    /// [`Error::UnsafeArchivePath`] before anything is written.
+    /// Requires the `zip` feature, and isn't available on wasm32.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::{ApiCaller, archive::ExtractOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let written = caller
+    ///         .download_album_extract("album-token", None, "album", ExtractOptions::default())
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(all(feature = "zip", not(target_arch = "wasm32")))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn download_album_extract(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+        dest_dir: impl Into<PathBuf>,
+        options: archive::ExtractOptions,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let content = self.download_album_bytes(album_token, file_ids).await?;
+        let dest_dir = dest_dir.into();
+
+        tokio::task::spawn_blocking(move || archive::extract(&content, &dest_dir, &options))
+            .await
+            .context("extracting album archive")?
+    }
+
+    /// Downloads a zip archive of an album on Waifu Vault and reads its files into memory
+    ///
+    /// Takes the same `file_ids` as [`ApiCaller::download_album`]. An archive that can't be
+    /// read fails with [`Error::CorruptArchive`]. Requires the `zip` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     for entry in caller.download_album_entries("album-token", None).await? {
+    ///         println!("{} is {} bytes", entry.name, entry.data.len());
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "zip")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn download_album_entries(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_ids: Option<&[u64]>,
+    ) -> anyhow::Result<Vec<archive::AlbumZipEntry>> {
+        let content = self.download_album_bytes(album_token, file_ids).await?;
+        archive::entries(&content)
+    }
+
+    /// Requests an album archive, returning the response once it is known to be successful
+    async fn album_download_response(
+        &self,
+        album_token: AlbumToken,
+        file_ids: Option<&[u64]>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let url = format!(
+            "{}/album/download/{}",
+            self.base_url,
+            album_token.path_segment()?
+        );
+        let body = match file_ids {
+            Some(ids) => ids,
+            None => &[],
+        };
+        let response = self
+            .send(Operation::DownloadAlbum, true, || {
+                Ok(self
+                    .client
+                    .post(&url)
+                    .json(&body)
+                    .header("Content-Type", "application/json"))
+            })
+            .await
+            .context("sending download part album request")?;
+
+        if response.status() != reqwest::StatusCode::OK {
+            return Err(error_response(response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// Downloads a zip archive of some files in an album, chosen by their tokens
+    ///
+    /// The album is looked up first to find the ids [`ApiCaller::download_album`] needs.
+    /// Fails with [`Error::NotInAlbum`] listing any tokens that aren't in the album.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use waifuvault::ApiCaller;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let caller = ApiCaller::new();
+    ///
+    ///     let zipped_contents = caller
+    ///         .download_album_files("album-token", &["file-token-1", "file-token-2"])
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(token = tracing::field::Empty)))]
+    pub async fn download_album_files<I>(
+        &self,
+        album_token: impl Into<AlbumToken>,
+        file_tokens: I,
+    ) -> anyhow::Result<Vec<u8>>
+    where
+        I: IntoIterator,
+        I::Item: AsFileToken,
+    {
+        let album_token = album_token.into();
+        trace::record_token!(&album_token);
+        let file_tokens = collect_file_tokens(file_tokens);
+        if file_tokens.is_empty() {
+            anyhow::bail!("no files given to download from album {album_token}");
+        }
+
+        let album = self.get_album(&album_token).await?;
+        let ids: HashMap<&FileToken, u64> = album
+            .files
+            .iter()
+            .filter_map(|f| Some((&f.token, f.id?)))
+            .collect();
+
+        let mut file_ids = Vec::with_capacity(file_tokens.len());
+        let mut missing = Vec::new();
+        for token in file_tokens {
+            match ids.get(&token) {
+                Some(id) => file_ids.push(*id),
+                None => missing.push(token),
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(Error::NotInAlbum { tokens: missing }.into());
+        }
+
+        self.download_album(&album_token, Some(&file_ids)).await
+    }
+}
+
+/// Runs `task` on every item with at most `limit` running at once
+///
+/// Outputs are returned in the same order as the items, whatever order they finish in.
+/// A panicking task is resumed on the caller.
+#[cfg(not(target_arch = "wasm32"))]
+async fn concurrently<I, T, F, Fut>(items: Vec<I>, limit: usize, task: F) -> Vec<T>
+where
+    F: Fn(I) -> Fut,
+    Fut: std::future::Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let mut outputs: Vec<Option<T>> = items.iter().map(|_| None).collect();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut pending = items.into_iter().enumerate();
+
+    loop {
+        while tasks.len() < limit.max(1) {
+            let Some((i, item)) = pending.next() else {
+                break;
+            };
+
+            let future = task(item);
+            tasks.spawn(async move { (i, future.await) });
+        }
+
+        let Some(finished) = tasks.join_next().await else {
+            break;
+        };
+        match finished {
+            Ok((i, output)) => outputs[i] = Some(output),
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    outputs.into_iter().flatten().collect()
+}
+
+/// Hashes the first `len` bytes of the file at `path`
+#[cfg(not(target_arch = "wasm32"))]
+async fn hash_file(
+    path: &std::path::Path,
+    len: u64,
+    hasher: &mut checksum::Hasher,
+) -> anyhow::Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("opening {}", path.display()))?;
+    let mut file = file.take(len);
+    let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .with_context(|| format!("reading {}", path.display()))?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Most deletes sent at once when deleting many files
+#[cfg(not(target_arch = "wasm32"))]
+const DELETE_CONCURRENCY: usize = 4;
+
+/// Most lookups sent at once when checking many files
+#[cfg(not(target_arch = "wasm32"))]
+const LOOKUP_CONCURRENCY: usize = 4;
+
+/// Size of the reads made while streaming a file upload from disk
+#[cfg(not(target_arch = "wasm32"))]
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Longest part of an unexpected body included in error messages
+const BODY_SNIPPET_LEN: usize = 512;
+
+/// Parses the response from the Waifu Vault API and converts it to
+/// the concrete type expected from the endpoint
+///
+/// Success statuses are deserialized into `T`, anything else is treated as an error
+pub(crate) async fn parse_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> anyhow::Result<T> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = observe::body(response)
+        .await
+        .map_err(transport_error)
+        .context("reading response body")?;
+
+    decode(status, &headers, &body)
+}
+
+/// Converts an unsuccessful response from the Waifu Vault API into an error
+pub(crate) async fn error_response(response: reqwest::Response) -> anyhow::Error {
+    let status = response.status();
+    let headers = response.headers().clone();
+    match observe::body(response).await {
+        Ok(body) => decode_error(status, &headers, &body),
+        Err(e) => anyhow::Error::new(e).context(format!("reading {status} response body")),
+    }
+}
+
+/// If an error is the 404 or 405 of a server without the replace endpoint
+fn no_replace_route(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref(),
+        Some(Error::UnexpectedResponse {
+            status: 404 | 405,
+            ..
+        })
+    )
+}
+
+/// Error for a download refused with a 403, which the service answers when a file's password
+/// is missing or wrong
+pub(crate) fn download_forbidden(password_given: bool) -> anyhow::Error {
+    if password_given {
+        anyhow::anyhow!("supplied password is incorrect")
+    } else {
+        anyhow::anyhow!("this file requires a password to download")
+    }
+}
+
+/// Value of the `x-password` header for a download password
+///
+/// The service reads header values as Latin-1, as browsers send them, while it reads
+/// passwords given in upload forms as UTF-8. Encoding the header as Latin-1 rather than
+/// sending the UTF-8 bytes lets passwords such as `Passwört` open the files they were
+/// uploaded with. Passwords with other characters, such as emoji, have no Latin-1 form and
+/// fail with [`Error::UnencodablePassword`].
+pub(crate) fn password_header(password: &str) -> Result<reqwest::header::HeaderValue, Error> {
+    let latin1 = password
+        .chars()
+        .map(|c| u8::try_from(c).map_err(|_| Error::UnencodablePassword))
+        .collect::<Result<Vec<u8>, Error>>()?;
+    let mut value = reqwest::header::HeaderValue::from_bytes(&latin1)
+        .map_err(|_| Error::UnencodablePassword)?;
+    value.set_sensitive(true);
+
+    Ok(value)
+}
+
+/// Decodes a response body based on its HTTP status
+pub(crate) fn decode<T: DeserializeOwned>(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> anyhow::Result<T> {
+    if !status.is_success() {
+        return Err(decode_error(status, headers, body));
+    }
+
+    serde_json::from_slice(body)
+        .map_err(|e| anyhow::Error::new(e).context(unexpected(status, body)))
+}
+
+/// Error for a response the SDK doesn't understand, keeping its whole body
+fn unexpected(status: StatusCode, body: &[u8]) -> Error {
+    Error::UnexpectedResponse {
+        status: status.as_u16(),
+        body: String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+/// Decodes the body of an unsuccessful response into a [`api::WaifuError`] where possible
+///
+/// Statuses with a meaning of their own, such as rate limiting or the service being down,
+/// become an [`Error`]
+pub(crate) fn decode_error(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> anyhow::Error {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Error::RateLimited {
+            retry_after: error::retry_after(headers),
+        }
+        .into();
+    }
+
+    if status == StatusCode::BAD_GATEWAY || status == StatusCode::SERVICE_UNAVAILABLE {
+        return Error::ServiceUnavailable {
+            status: status.as_u16(),
+            retry_after: error::retry_after(headers),
+        }
+        .into();
+    }
+
+    match serde_json::from_slice::<WaifuError>(body) {
+        Ok(err) => err.into(),
+        Err(_) => unexpected(status, body).into(),
+    }
+}
+
+/// Multipart part reading a file from disk as it is sent, rather than up front
+///
+/// The length is known from the file's metadata, so the request still has a Content-Length
+fn file_part(
+    path: &std::path::Path,
+    hasher: Option<checksum::SharedHasher>,
+) -> anyhow::Result<reqwest::multipart::Part> {
+    let (file, length) = open_local_file(path)?;
+    trace::debug!(bytes = length, "uploading file");
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let stream = tokio_util::io::ReaderStream::with_capacity(
+            tokio::fs::File::from_std(file),
+            UPLOAD_CHUNK_SIZE,
+        );
+        let body = match hasher {
+            Some(hasher) => reqwest::Body::wrap_stream(checksum::Hashed::new(stream, hasher)),
+            None => reqwest::Body::wrap_stream(stream),
+        };
+        Ok(reqwest::multipart::Part::stream_with_length(body, length))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let mut file = file;
+        let mut content = Vec::with_capacity(length as usize);
+        std::io::Read::read_to_end(&mut file, &mut content)
+            .with_context(|| format!("reading file {}", path.display()))?;
+        if let Some(hasher) = hasher {
+            hasher.update(&content);
+        }
+        Ok(reqwest::multipart::Part::bytes(content))
+    }
+}
+
+/// Multipart part sharing the buffer of `data` rather than copying it
+fn bytes_part(data: bytes::Bytes) -> reqwest::multipart::Part {
+    #[cfg(not(target_arch = "wasm32"))]
+    return reqwest::multipart::Part::stream_with_length(data.clone(), data.len() as u64);
+
+    #[cfg(target_arch = "wasm32")]
+    return reqwest::multipart::Part::stream(data);
+}
+
+/// Sets the MIME type of an upload part, if one was asked for
+fn with_content_type(
+    part: reqwest::multipart::Part,
+    content_type: Option<&str>,
+) -> anyhow::Result<reqwest::multipart::Part> {
+    match content_type {
+        Some(mime) => part
+            .mime_str(mime)
+            .with_context(|| format!("invalid content type {mime:?}")),
+        None => Ok(part),
+    }
+}
+
+/// Converts an error from the HTTP client, marking timeouts with [`Error::TimedOut`]
+pub(crate) fn transport_error(err: reqwest::Error) -> anyhow::Error {
+    if err.is_timeout() {
+        anyhow::Error::new(err).context(Error::TimedOut)
+    } else {
+        err.into()
+    }
+}
+
+/// Start of the range and full size given by a `Content-Range` header, such as
+/// `bytes 100-199/200` or `bytes */200`, either of which may be missing
+#[cfg(not(target_arch = "wasm32"))]
+fn content_range(headers: &HeaderMap) -> (Option<u64>, Option<u64>) {
+    let value = headers
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes "))
+        .and_then(|value| value.split_once('/'));
+
+    match value {
+        Some((range, total)) => (
+            range
+                .split_once('-')
+                .and_then(|(start, _)| start.parse().ok()),
+            total.parse().ok(),
+        ),
+        None => (None, None),
+    }
+}
+
+/// If an error is the service reporting that something doesn't exist
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<WaifuError>().is_some_and(|e| {
+        e.status == StatusCode::NOT_FOUND.as_u16() || e.name.eq_ignore_ascii_case("NOT_FOUND")
+    })
+}
+
+/// What the service says in a 400 about a token it doesn't know
+const UNKNOWN_TOKEN_HINTS: [&str; 6] = [
+    "not found",
+    "unable to find",
+    "does not exist",
+    "doesn't exist",
+    "unknown",
+    "invalid token",
+];
+
+/// If `err` says the thing looked up doesn't exist, rather than that the lookup failed
+///
+/// The service answers 404 for some missing things and 400 for others, such as buckets,
+/// so a 400 only counts when its message is about the token.
+fn is_unknown_token(err: &anyhow::Error) -> bool {
+    if is_not_found(err) || matches!(err.downcast_ref(), Some(Error::InvalidToken { .. })) {
+        return true;
+    }
+
+    err.downcast_ref::<WaifuError>().is_some_and(|e| {
+        let message = e.message.to_lowercase();
+        e.status == StatusCode::BAD_REQUEST.as_u16()
+            && UNKNOWN_TOKEN_HINTS
+                .iter()
+                .any(|hint| message.contains(hint))
+    })
+}
+
+/// Whether a lookup found what it looked for
+fn exists<T>(lookup: anyhow::Result<T>) -> anyhow::Result<bool> {
+    match lookup {
+        Ok(_) => Ok(true),
+        Err(err) if is_unknown_token(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Relative and full paths of regular files found under a directory
+#[cfg(not(target_arch = "wasm32"))]
+type WalkedFiles = Vec<(PathBuf, PathBuf)>;
+
+/// Recursively lists the regular files under `root` in a stable order
+///
+/// Only failing to read `root` itself is an error, anything below it that can't be read or
+/// isn't a regular file is returned alongside the files, relative to `root`.
+#[cfg(not(target_arch = "wasm32"))]
+async fn walk_directory(
+    root: &std::path::Path,
+) -> anyhow::Result<(WalkedFiles, Vec<(PathBuf, anyhow::Error)>)> {
+    let mut files = Vec::new();
+    let mut failed = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let relative = || dir.strip_prefix(root).unwrap_or(&dir).to_path_buf();
+        let mut read = match tokio::fs::read_dir(&dir).await {
+            Ok(read) => read,
+            Err(err) if dir == root => {
+                return Err(err).with_context(|| format!("reading directory {}", dir.display()));
+            }
+            Err(err) => {
+                failed.push((
+                    relative(),
+                    anyhow::Error::new(err).context("reading directory"),
+                ));
+                continue;
+            }
+        };
+
+        let mut entries = Vec::new();
+        loop {
+            match read.next_entry().await {
+                Ok(Some(entry)) => entries.push(entry.path()),
+                Ok(None) => break,
+                Err(err) => {
+                    failed.push((
+                        relative(),
+                        anyhow::Error::new(err).context("reading directory"),
+                    ));
+                    break;
+                }
+            }
+        }
+        entries.sort();
+
+        for path in entries.into_iter().rev() {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            match tokio::fs::symlink_metadata(&path).await {
+                Ok(meta) if meta.is_symlink() => {
+                    failed.push((relative, anyhow::anyhow!("symbolic links are not followed")))
+                }
+                Ok(meta) if meta.is_dir() => dirs.push(path),
+                Ok(meta) if meta.is_file() => files.push((relative, path)),
+                Ok(_) => failed.push((relative, anyhow::anyhow!("not a regular file"))),
+                Err(err) => {
+                    failed.push((relative, anyhow::Error::new(err).context("reading file")))
+                }
+            }
+        }
+    }
+
+    files.sort();
+    Ok((files, failed))
+}
+
+/// Name a file was uploaded with, falling back to its token when the filename is hidden
+#[cfg(not(target_arch = "wasm32"))]
+fn stored_filename(file: &WaifuFileEntry) -> String {
+    visible_filename(file).unwrap_or_else(|| file.token.to_string())
+}
+
+/// Name a file was uploaded with, as far as it can be told from its URL
+#[cfg(not(target_arch = "wasm32"))]
+fn visible_filename(file: &WaifuFileEntry) -> Option<String> {
+    let hidden = file.options.as_ref().is_some_and(|o| o.hide_filename);
+    reqwest::Url::parse(&file.url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+        .map(|name| {
+            percent_encoding::percent_decode_str(&name)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .map(|name| name.replace(['/', '\\'], "_"))
+        .filter(|name| !hidden && !name.is_empty() && name != "." && name != "..")
+}
+
+/// Lossy, length-limited view of a body for use in error messages
+fn body_snippet(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body);
+    match text.char_indices().nth(BODY_SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // When running in test environment, it uses a local API version so the WaifuVault
+    // must be set up to run locally.
+    use super::*;
+    use anyhow::Result;
+    use sha1::{Digest, Sha1};
+    use std::path::PathBuf;
+    use test_utils::{Dropper, TempFile};
+    use tokio::fs;
+    use wiremock::{
+        matchers::{
+            body_json, body_string_contains, header, method, path, path_regex, query_param,
+            query_param_is_missing,
+        },
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn cleanup(caller: &ApiCaller, token: &FileToken) -> Result<()> {
+        caller.delete_file(token).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file() -> Result<()> {
+        let tmp = TempFile::new("upload_basic.bin").await?;
+        assert!(tmp.path().exists());
+        let caller = ApiCaller::new();
+        let upload_request = api::WaifuUploadRequest::new().file(tmp.path());
+
+        let response = caller
+            .upload_file(upload_request)
+            .await
+            .context("upload file - basic");
+
+        assert!(response.is_ok());
+
+        let response = response?;
+        let options = response
+            .options
+            .expect("expected options when there are none");
+
+        assert!(!options.hide_filename);
+        assert!(!options.protected);
+        assert!(!options.one_time_download);
+
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file_with_options() -> Result<()> {
+        let tmp = TempFile::new("upload_with_options.bin").await?;
+        let caller = ApiCaller::new();
+        let upload_request = api::WaifuUploadRequest::new()
+            .file(tmp.path())
+            .expires("1h")
+            .password("apple")
+            .one_time_download(true)
+            .hide_filename(true);
+
+        let response = caller
+            .upload_file(upload_request)
+            .await
+            .context("upload file with options")?;
+        let options = response
+            .options
+            .expect("expected options when there are none");
+
+        assert!(options.hide_filename);
+        assert!(options.protected);
+        assert!(options.one_time_download);
+
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file_from_url() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+        let caller = ApiCaller::new();
+        let request = WaifuUploadRequest::new().url(url).expires("1h");
+
+        let response = caller
+            .upload_file(request)
+            .await
+            .context("upload from url")?;
+        let options = response
+            .options
+            .expect("expected options when there are none");
+
+        assert!(!options.hide_filename);
+        assert!(!options.protected);
+        assert!(!options.one_time_download);
+
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file_bytes() -> Result<()> {
+        let tmp = TempFile::new("upload_from_raw_bytes.bin").await?;
+        let caller = ApiCaller::new();
+        let content = fs::read(tmp.path()).await?;
+        let request = WaifuUploadRequest::new()
+            .bytes(content, "test_raw_bytes.bin")
+            .expires("1h");
+
+        let response = caller.upload_file(request).await?;
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_file_info() -> Result<()> {
+        let tmp = TempFile::new("get_file_info_basic.bin").await?;
+        let caller = ApiCaller::new();
+        let upload = WaifuUploadRequest::new().file(tmp.path());
+        let response = caller.upload_file(upload).await?;
+
+        let token = response.token;
+        let options = response
+            .options
+            .expect("options expected but there are none");
+        let get_req = WaifuGetRequest::new(&token);
+        let response = caller.file_info(get_req).await?;
+
+        assert_eq!(&token, &response.token);
+        assert_eq!(
+            &options,
+            &response
+                .options
+                .expect("expected options but there are none")
+        );
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_token() -> Result<()> {
+        let caller = ApiCaller::new();
+        let request = WaifuGetRequest::new("hithere");
+        let response = caller.file_info(request).await;
+        assert!(response.is_err());
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn patch_entry() -> Result<()> {
+        let tmp = TempFile::new("some_entry_to_be_patched.bin").await?;
+        let caller = ApiCaller::new();
+
+        let init = WaifuUploadRequest::new().file(tmp.path()).expires("1h");
+        let response = caller.upload_file(init).await?;
+        let token = response.token;
+        let original_exp = response.retention_period;
+        let original_opts = response.options.unwrap();
+        be_nice().await;
+
+        // Add a password
+        let mod_request = WaifuModificationRequest::new(&token).password("banana");
+        let response = caller.update_file(mod_request).await?;
+        let options = response.options.unwrap();
+        assert!(options.protected);
+        assert_ne!(options.protected, original_opts.protected);
+        be_nice().await;
+
+        // Add an expiry
+        let mod_request = WaifuModificationRequest::new(&token).custom_expiry("5m");
+        let response = caller.update_file(mod_request).await?;
+        assert_ne!(response.retention_period, original_exp);
+        be_nice().await;
+
+        // Hide the filename
+        let mod_request = WaifuModificationRequest::new(&token).hide_filename(true);
+        let response = caller.update_file(mod_request).await?;
+        let options = response.options.unwrap();
+        assert!(options.hide_filename);
+        assert_ne!(options.hide_filename, original_opts.hide_filename);
+        be_nice().await;
+
+        // Update password
+        let mod_request = WaifuModificationRequest::new(&token)
+            .password("apple")
+            .previous_password("banana");
+        let response = caller.update_file(mod_request).await?;
+        let options = response.options.unwrap();
+        assert!(options.protected);
+        be_nice().await;
+
+        cleanup(&caller, &token).await?;
+        be_nice().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_file() -> Result<()> {
+        let tmp = TempFile::new("something_to_delete.bin").await?;
+        let caller = ApiCaller::new();
+        let request = WaifuUploadRequest::new().file(tmp.path());
+        let response = caller.upload_file(request).await?;
+        let token = response.token;
+        let success = caller.delete_file(&token).await?;
+
+        assert!(success);
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_file() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+        let original = reqwest::get(url).await?.bytes().await?.to_vec();
+        let og_hash = hash_item(&original);
+
+        let caller = ApiCaller::new();
+        let request = WaifuUploadRequest::new().url(url).expires("1h");
+        let response = caller.upload_file(request).await?;
+        let url = response.url;
+
+        let response = caller.download_file(&url, None).await?;
+        let result = hash_item(&response);
+
+        assert_eq!(og_hash, result);
+        be_nice().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_file_with_password() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+        let original = reqwest::get(url).await?.bytes().await?.to_vec();
+        let og_hash = hash_item(&original);
+
+        let caller = ApiCaller::new();
+        let request = WaifuUploadRequest::new()
+            .url(url)
+            .expires("1h")
+            .password("banana");
+        let response = caller
+            .upload_file(request)
+            .await
+            .context("uploading protected file to download")?;
+        let url = response.url;
+
+        let response = caller
+            .download_file(&url, Some("banana".to_string()))
+            .await?;
+        let result = hash_item(&response);
+
+        assert_eq!(og_hash, result);
+        be_nice().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_file_with_non_ascii_password() -> Result<()> {
+        let caller = ApiCaller::new();
+        let request = WaifuUploadRequest::new()
+            .bytes(CONTENT.to_vec(), "umlauts.txt")
+            .expires("1h")
+            .password("Passwört ÄÖÜ");
+        let response = caller
+            .upload_file(request)
+            .await
+            .context("uploading file protected with a non-ASCII password")?;
+
+        let content = caller
+            .download_file(&response.url, Some("Passwört ÄÖÜ".to_string()))
+            .await?;
+        assert_eq!(content, CONTENT);
+
+        cleanup(&caller, &response.token).await?;
+        be_nice().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_and_delete_bucket() -> Result<()> {
+        let caller = ApiCaller::new();
+        let response = caller.create_bucket().await?;
+        assert!(!response.token.as_str().is_empty());
+
+        let token = response.token;
+        assert!(response.files.is_empty());
+
+        let resp = caller.delete_bucket(&token).await?;
+        assert!(resp);
+
+        be_nice().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_and_get_bucket() -> Result<()> {
+        let caller = ApiCaller::new();
+        let bucket = caller.create_bucket().await?;
+        let token = bucket.token;
+
+        let info = caller.get_bucket(&token).await?;
+        assert!(info.token == token);
+        assert!(info.files.is_empty());
+
+        caller.delete_bucket(&token).await?;
+
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_bucket_and_upload_file() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+        let caller = ApiCaller::new();
+
+        let bucket = caller.create_bucket().await?;
+        let token = bucket.token;
+        let request = WaifuUploadRequest::new()
+            .bucket(&token)
+            .url(url)
+            .expires("1h");
+
+        caller.upload_file(request).await?;
+
+        let info = caller.get_bucket(&token).await?;
+        assert!(info.files.len() == 1);
+
+        caller.delete_bucket(&token).await?;
+
+        be_nice().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_bucket_incorrect_token() -> Result<()> {
+        let caller = ApiCaller::new();
+        let response = caller.delete_bucket("garbage").await;
+        assert!(response.is_err());
+
+        let inner = response.unwrap_err();
+        let waifu_err = inner.downcast::<WaifuError>()?;
+        assert_eq!(waifu_err.status, 400);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_album() -> Result<()> {
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket_response = dropper.create_bucket().await?;
+        assert!(!bucket_response.token.as_str().is_empty());
+
+        let bkt_tkn = bucket_response.token;
+        let album_response = dropper.create_album(&bkt_tkn, "bigknob").await?;
+        assert!(album_response.files.is_empty());
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn associate_file_with_album() -> Result<()> {
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+
+        let bucket = dropper.create_bucket().await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+
+        let file = dropper.upload_file(request).await?;
+        let album = dropper.create_album(&bucket.token, "kdkfjdk").await?;
+
+        let success = match caller
+            .associate_with_album(&album.token, &[&file.token])
+            .await
+        {
+            Ok(success) => success,
+            Err(e) => {
+                dropper.destroy().await?;
+                return Err(e);
+            }
+        };
+
+        assert_eq!(success.bucket_token, bucket.token);
+        assert_eq!(success.files.len(), 1);
+
+        let f = &success.files[0];
+        assert_eq!(f.token, file.token);
+        assert_eq!(f.bucket, Some(bucket.token));
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disassociate_from_album() -> Result<()> {
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+
+        let bucket = dropper.create_bucket().await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+
+        let file = dropper.upload_file(request).await?;
+        let album = dropper.create_album(&bucket.token, "kdkfjdk").await?;
+
+        let assoc = match caller
+            .associate_with_album(&album.token, &[&file.token])
+            .await
+        {
+            Ok(success) => success,
+            Err(e) => {
+                dropper.destroy().await?;
+                return Err(e);
+            }
+        };
+
+        assert_eq!(assoc.files.len(), 1);
+
+        let disassoc = match caller
+            .disassociate_from_album(&album.token, &[&file.token])
+            .await
+        {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert!(disassoc.files.is_empty());
+
+        let _ = dropper.destroy().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_album_keep_files() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+
+        dropper.upload_file(request).await?;
+        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+
+        let delete = match caller.delete_album(&album.token, false).await {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert!(delete.success);
+
+        let bucket_info = caller.get_bucket(&bucket.token).await?;
+        assert!(!bucket_info.files.is_empty());
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_album_remove_files() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+
+        dropper.upload_file(request).await?;
+        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+
+        let delete = match caller.delete_album(&album.token, true).await {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert!(delete.success);
+
+        let bucket_info = caller.get_bucket(&bucket.token).await?;
+        assert!(bucket_info.files.is_empty());
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_album() -> Result<()> {
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+        let retrieve = match caller.get_album(&album.token).await {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert_eq!(retrieve.token, album.token);
+        assert_eq!(retrieve.bucket_token, bucket.token);
+        assert!(retrieve.public_token.is_none());
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn share_album() -> Result<()> {
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+
+        let share = match caller.share_album(&album.token).await {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert!(share.success);
+        assert!(share
+            .description
+            .starts_with("http://localhost:8081/album/"));
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_album() -> Result<()> {
+        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+
+        let caller = ApiCaller::new();
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+
+        let file = dropper.upload_file(request).await?;
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .url(url)
+            .expires("1h");
+        let file2 = dropper.upload_file(request).await?;
+        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+        caller
+            .associate_with_album(&album.token, &[&file.token, &file2.token])
+            .await?;
+
+        let files = match caller.download_album(&album.token, None).await {
+            Ok(success) => success,
+            Err(e) => {
+                let _ = dropper.destroy().await;
+                return Err(e);
+            }
+        };
+
+        assert!(!files.is_empty());
+
+        let _ = dropper.destroy().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn per_call_target_is_partitioned_by_host() -> Result<()> {
+        let public = MockServer::start().await;
+        let private = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("public-bkt")))
+            .expect(2)
+            .mount(&public)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("private-bkt")))
+            .expect(1)
+            .mount(&private)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("private-bkt")))
+            .expect(1)
+            .mount(&private)
+            .await;
+        for server in [&public, &private] {
+            Mock::given(method("GET"))
+                .and(path("/rest/resources/restrictions"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(restrictions_json()))
+                .expect(1)
+                .mount(server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/rest/broken-file"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&private)
+            .await;
+
+        let public_target = Target::new(format!("{}/rest", public.uri()));
+        let private_target = Target::new(format!("{}/rest/", private.uri()));
+        let caller = ApiCaller::builder()
+            .circuit_breaker(CircuitBreaker {
+                failure_threshold: 1,
+                cool_down: std::time::Duration::from_secs(60),
+            })
+            .build()
+            .on(&public_target);
+
+        let bucket = caller.create_bucket().await?;
+        assert_eq!(bucket.token, "public-bkt");
+
+        let scoped = caller.on(&private_target);
+        let bucket = scoped.create_bucket().await?;
+        assert_eq!(bucket.token, "private-bkt");
+        let bucket = scoped.get_bucket(&bucket.token).await?;
+        assert_eq!(bucket.token, "private-bkt");
+
+        // Scoping a call never changes where the original caller points
+        assert_eq!(caller.target(), public_target);
+        assert_eq!(
+            scoped.target().base_url(),
+            format!("{}/rest", private.uri())
+        );
+
+        // Restrictions are fetched once per instance, however many callers point at it
+        let too_large = || WaifuUploadRequest::new().bytes(vec![0; 4096], "big.png");
+        for upload in [
+            caller.on(&private_target).upload_file_checked(too_large()),
+            caller.on(&private_target).upload_file_checked(too_large()),
+            caller.upload_file_checked(too_large()),
+            caller.on(&public_target).upload_file_checked(too_large()),
+        ] {
+            let err = upload.await.expect_err("too large");
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::TooLarge { .. })
+            ));
+        }
+
+        // A failing instance opens the circuit of every caller pointing at it, and only theirs
+        let err = scoped
+            .file_info(WaifuGetRequest::new("broken-file"))
+            .await
+            .unwrap_err();
+        assert_ne!(err.downcast_ref(), Some(&Error::CircuitOpen));
+        let err = caller
+            .on(&private_target)
+            .get_bucket("private-bkt")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::CircuitOpen));
+        caller.create_bucket().await?;
+
+        Ok(())
+    }
+
+    /// Response captured from a local instance with `capture-fixtures`
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_str!(concat!("../fixtures/", $name, ".json"))
+        };
+    }
+
+    #[test]
+    fn decode_rejects_mismatched_shape() {
+        let body = fixture!("bucket_create");
+
+        let err = decode::<WaifuFileEntry>(StatusCode::OK, &HeaderMap::new(), body.as_bytes())
+            .expect_err("a bucket is not a file entry");
+        assert!(err.to_string().contains("<bucket-1>"));
+
+        let err = decode::<WaifuFileEntry>(
+            StatusCode::OK,
+            &HeaderMap::new(),
+            fixture!("file_delete").as_bytes(),
+        )
+        .expect_err("not a file entry");
+        assert!(err.to_string().contains("true"));
+    }
+
+    #[test]
+    fn decode_by_status() -> Result<()> {
+        let body = fixture!("file_info");
+        let entry: WaifuFileEntry = decode(StatusCode::OK, &HeaderMap::new(), body.as_bytes())?;
+        assert_eq!(entry.token, "<file-1>");
+
+        let deleted: bool = decode(
+            StatusCode::OK,
+            &HeaderMap::new(),
+            fixture!("file_delete").as_bytes(),
+        )?;
+        assert!(deleted);
+
+        // An error shaped body is never mistaken for a success
+        let body = fixture!("error_file_info_bad_token");
+        let err =
+            decode::<WaifuFileEntry>(StatusCode::BAD_REQUEST, &HeaderMap::new(), body.as_bytes())
+                .expect_err("error status");
+        let waifu_err = err.downcast::<WaifuError>()?;
+        assert_eq!(waifu_err.status, 400);
+
+        // Bodies which aren't errors still produce a readable message
+        let html = "<html><body>Internal Server Error</body></html>";
+        let err = decode::<bool>(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &HeaderMap::new(),
+            html.as_bytes(),
+        )
+        .expect_err("html");
+        assert!(err.to_string().contains("500"));
+        assert!(err.to_string().contains("Internal Server Error"));
+
+        // Except from the proxy while the service is down, which is typed
+        let html = "<html><body>Bad Gateway</body></html>";
+        let err = decode::<bool>(StatusCode::BAD_GATEWAY, &HeaderMap::new(), html.as_bytes())
+            .expect_err("html");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::ServiceUnavailable {
+                status: 502,
+                retry_after: None
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn captured_fixtures_decode() -> Result<()> {
+        let headers = HeaderMap::new();
+
+        for body in [fixture!("bucket_create"), fixture!("bucket_get")] {
+            decode::<WaifuBucketEntry>(StatusCode::OK, &headers, body.as_bytes())?;
+        }
+
+        let uploads = [
+            fixture!("upload_hide-false_otd-false_protected-false"),
+            fixture!("upload_hide-false_otd-false_protected-true"),
+            fixture!("upload_hide-false_otd-true_protected-false"),
+            fixture!("upload_hide-false_otd-true_protected-true"),
+            fixture!("upload_hide-true_otd-false_protected-false"),
+            fixture!("upload_hide-true_otd-false_protected-true"),
+            fixture!("upload_hide-true_otd-true_protected-false"),
+            fixture!("upload_hide-true_otd-true_protected-true"),
+        ];
+        for (i, body) in uploads.into_iter().enumerate() {
+            let entry: WaifuFileEntry = decode(StatusCode::OK, &headers, body.as_bytes())?;
+            let options = entry.options.expect("uploads report their options");
+            assert_eq!(
+                (
+                    options.hide_filename,
+                    options.one_time_download,
+                    options.protected
+                ),
+                (i & 4 != 0, i & 2 != 0, i & 1 != 0),
+                "{body}"
+            );
+        }
+        for body in [
+            fixture!("upload_url"),
+            fixture!("file_info"),
+            fixture!("file_update"),
+        ] {
+            decode::<WaifuFileEntry>(StatusCode::OK, &headers, body.as_bytes())?;
+        }
+        let formatted: WaifuFileEntry = decode(
+            StatusCode::OK,
+            &headers,
+            fixture!("file_info_formatted").as_bytes(),
+        )?;
+        assert!(formatted.retention_period.is_string());
+
+        for body in [
+            fixture!("album_create"),
+            fixture!("album_associate"),
+            fixture!("album_get"),
+            fixture!("album_get_shared"),
+            fixture!("album_disassociate"),
+        ] {
+            decode::<WaifuAlbumEntry>(StatusCode::OK, &headers, body.as_bytes())?;
+        }
+        let shared: WaifuAlbumEntry = decode(
+            StatusCode::OK,
+            &headers,
+            fixture!("album_get_shared").as_bytes(),
+        )?;
+        assert_eq!(shared.public_token.as_deref(), Some("<public-1>"));
+
+        for body in [
+            fixture!("album_share"),
+            fixture!("album_revoke"),
+            fixture!("album_delete"),
+        ] {
+            let message: WaifuGenericMessage = decode(StatusCode::OK, &headers, body.as_bytes())?;
+            assert!(message.success);
+        }
+        for body in [fixture!("file_delete"), fixture!("bucket_delete")] {
+            assert!(decode::<bool>(StatusCode::OK, &headers, body.as_bytes())?);
+        }
+
+        for body in [
+            fixture!("error_file_info_bad_token"),
+            fixture!("error_file_delete_bad_token"),
+            fixture!("error_bucket_get_bad_token"),
+            fixture!("error_bucket_delete_bad_token"),
+            fixture!("error_album_get_bad_token"),
+            fixture!("error_album_create_bad_bucket"),
+            fixture!("error_upload_no_content"),
+            fixture!("error_update_wrong_previous_password"),
+            fixture!("error_download_missing_password"),
+            fixture!("error_download_wrong_password"),
+        ] {
+            let sent: serde_json::Value = serde_json::from_str(body)?;
+            let status = StatusCode::from_u16(sent["status"].as_u64().unwrap() as u16)?;
+            let err = decode::<WaifuFileEntry>(status, &headers, body.as_bytes())
+                .expect_err("error fixtures never decode");
+            assert_eq!(err.downcast::<WaifuError>()?.status, status.as_u16());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rate_limited_responses_are_typed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("Retry-After", "120")
+                    .set_body_string("Too Many Requests"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(ResponseTemplate::new(429))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let err = caller.create_bucket().await.expect_err("rate limited");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::RateLimited {
+                retry_after: Some(std::time::Duration::from_secs(120))
+            })
+        );
+
+        let err = caller
+            .file_info(WaifuGetRequest::new("some-file"))
+            .await
+            .expect_err("rate limited");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::RateLimited { retry_after: None })
+        );
+
+        let url = format!("{}/f/some-file", server.uri());
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "5"))
+            .mount(&server)
+            .await;
+        let err = caller
+            .download_file(&url, None)
+            .await
+            .expect_err("rate limited");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::RateLimited {
+                retry_after: Some(_)
+            })
+        ));
+
+        Ok(())
+    }
+
+    const MAINTENANCE_PAGE: &str = "<html>\r\n<head><title>503 Service Temporarily Unavailable</title></head>\r\n<body>\r\n<center><h1>503 Service Temporarily Unavailable</h1></center>\r\n<hr><center>nginx</center>\r\n</body>\r\n</html>\r\n";
+
+    fn maintenance(status: u16) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_raw(MAINTENANCE_PAGE, "text/html")
+    }
+
+    #[tokio::test]
+    async fn maintenance_pages_are_typed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(maintenance(503).insert_header("Retry-After", "60"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(maintenance(502))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .respond_with(maintenance(503))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/some-album"))
+            .respond_with(maintenance(503))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let unavailable = |status, retry_after| {
+            Some(Error::ServiceUnavailable {
+                status,
+                retry_after,
+            })
+        };
+
+        let err = caller.create_bucket().await.expect_err("unavailable");
+        assert_eq!(
+            err.downcast_ref::<Error>().cloned(),
+            unavailable(503, Some(std::time::Duration::from_secs(60)))
+        );
+
+        let err = caller
+            .file_info(WaifuGetRequest::new("some-file"))
+            .await
+            .expect_err("unavailable");
+        assert_eq!(err.downcast_ref::<Error>().cloned(), unavailable(502, None));
+
+        let url = format!("{}/f/some-file", server.uri());
+        let err = caller
+            .download_file(&url, None)
+            .await
+            .expect_err("unavailable");
+        assert_eq!(err.downcast_ref::<Error>().cloned(), unavailable(503, None));
+
+        let err = caller
+            .download_album("some-album", None)
+            .await
+            .expect_err("unavailable");
+        assert_eq!(err.downcast_ref::<Error>().cloned(), unavailable(503, None));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn maintenance_pages_are_retried() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(maintenance(502))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(maintenance(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("some-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let entry = retrying_caller(&server, false)
+            .file_info(WaifuGetRequest::new("some-file"))
+            .await?;
+        assert_eq!(entry.token, "some-file");
+
+        Ok(())
+    }
+
+    #[test]
+    fn tokens_are_transparent() -> Result<()> {
+        let entry: WaifuFileEntry = serde_json::from_value(file_json("some-file"))?;
+        assert_eq!(entry.token, FileToken::new("some-file"));
+        assert_eq!(entry.token, "some-file");
+        assert_eq!(entry.token.to_string(), "some-file");
+        assert_eq!(
+            serde_json::to_value(&entry.token)?,
+            serde_json::json!("some-file")
+        );
+
+        let bucket: WaifuBucketEntry = serde_json::from_value(bucket_json("some-bucket"))?;
+        assert_eq!(bucket.token.as_ref(), "some-bucket");
+        assert_eq!(String::from(bucket.token), "some-bucket");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn album_download_by_file_id() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut first = file_json("first");
+        first["id"] = 11.into();
+        let mut second = file_json("second");
+        second["id"] = 12.into();
+        let album = serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [first, second]
+        });
+        Mock::given(method("GET"))
+            .and(path("/rest/album/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(album))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .and(body_json(serde_json::json!([12])))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"PK\x03\x04".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let album = caller.get_album("album").await?;
+        let ids: Vec<u64> = album.files.iter().filter_map(|f| f.id).collect();
+        assert_eq!(ids, [11, 12]);
+
+        let archive = caller.download_album("album", Some(&ids[1..])).await?;
+        assert!(archive.starts_with(b"PK"));
+
+        // Older instances don't send an id
+        let mut no_id = file_json("old");
+        no_id.as_object_mut().unwrap().remove("id");
+        let entry: WaifuFileEntry = serde_json::from_value(no_id)?;
+        assert_eq!(entry.id, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bucket_handle_uploads_into_its_bucket() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut uploaded = file_json("uploaded");
+        uploaded["bucket"] = "some-bucket".into();
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("some-bucket")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/some-bucket"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&uploaded))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(
+                serde_json::json!({ "bucket_token": "some-bucket" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "some-bucket", "files": [uploaded], "albums": []
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bucket/some-bucket"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let bucket = caller.create_bucket_handle().await?;
+        assert_eq!(bucket.token(), &BucketToken::new("some-bucket"));
+
+        // The handle's bucket wins over one set on the request
+        let request = WaifuUploadRequest::new()
+            .bytes(b"content".to_vec(), "file.txt")
+            .bucket("another-bucket");
+        let entry = bucket.upload(request).await?;
+
+        let files = caller.get_bucket("some-bucket").await?.files;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].token, entry.token);
+        assert_eq!(bucket.files().await?.len(), 1);
+
+        assert!(bucket.delete().await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn album_handle_remembers_public_url() -> Result<()> {
+        let server = MockServer::start().await;
+        let album = serde_json::json!({
+            "token": "album", "bucketToken": "bucket", "publicToken": null,
+            "name": "holiday", "files": [file_json("one")]
+        });
+        Mock::given(method("POST"))
+            .and(path("/rest/album/album/associate"))
+            .and(body_json(serde_json::json!({ "fileTokens": ["one"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(album))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/share/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true, "description": "https://waifuvault.moe/album/public-token"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/revoke/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true, "description": "album unshared"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/album/album"))
+            .and(query_param("deleteFiles", "false"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true, "description": "album deleted"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut album = mock_caller(&server).album("album");
+        assert_eq!(album.public_url(), None);
+
+        let entry = album.associate(&[FileToken::new("one")]).await?;
+        assert_eq!(entry.files.len(), 1);
+
+        album.share().await?;
+        assert_eq!(
+            album.public_url(),
+            Some("https://waifuvault.moe/album/public-token")
+        );
+        assert_eq!(album.public_token(), Some("public-token"));
+
+        album.revoke().await?;
+        assert_eq!(album.public_token(), None);
+
+        assert!(album.delete(false).await?.success);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_entry_downloads_with_password() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/1712345/locked.txt"))
+            .and(header("x-password", "apple"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("secret"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let mut json = file_json("locked");
+        json["url"] = format!("{}/f/1712345/locked.txt", server.uri()).into();
+        json["options"]["protected"] = true.into();
+        let entry: WaifuFileEntry = serde_json::from_value(json)?;
+
+        let err = entry
+            .download(&caller, None)
+            .await
+            .expect_err("no password");
+        assert!(err.to_string().contains("requires a password"));
+
+        let content = entry.download(&caller, Some("apple".to_string())).await?;
+        assert_eq!(content, b"secret");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_entry_one_time_download_is_gone_after() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/1712345/once.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("only once"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/once"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/kept"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/kept"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("kept")))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let mut json = file_json("once");
+        json["url"] = format!("{}/f/1712345/once.txt", server.uri()).into();
+        json["options"]["oneTimeDownload"] = true.into();
+        let entry: WaifuFileEntry = serde_json::from_value(json)?;
+
+        assert_eq!(entry.download(&caller, None).await?, b"only once");
+        let err = entry.refresh(&caller).await.expect_err("consumed");
+        assert!(format!("{err:#}").contains("Unknown token"));
+
+        let kept: WaifuFileEntry = serde_json::from_value(file_json("kept"))?;
+        assert_eq!(kept.refresh(&caller).await?.token, "kept");
+        assert!(kept.delete(&caller).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn download_by_token_resolves_url() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut json = file_json("hidden");
+        json["url"] = format!("{}/f/1712345", server.uri()).into();
+        json["options"]["hideFilename"] = true.into();
+        Mock::given(method("GET"))
+            .and(path("/rest/hidden"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/1712345"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("hidden name"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/missing"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Unknown token", "status": 400
+            })))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let content = caller.download_file_by_token("hidden", None).await?;
+        assert_eq!(content, b"hidden name");
+
+        let err = caller
+            .download_file_by_token("missing", None)
+            .await
+            .expect_err("unknown token");
+        assert!(err.to_string().contains("looking up file missing"));
+        assert_eq!(
+            err.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(400)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_info_from_url_looks_up_id() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/1712345"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("some-token")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let info = caller
+            .file_info_from_url("https://waifuvault.moe/f/1712345/some-token.txt")
+            .await?;
+        assert_eq!(info.token, "some-token");
+
+        let err = caller
+            .file_info_from_url("https://waifuvault.moe/album/public")
+            .await
+            .expect_err("not a file url");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidFileUrl { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn try_file_info_maps_not_found_to_none() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/present"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("present")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/consumed"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/broken"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Invalid token", "status": 400
+            })))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let found = caller
+            .try_file_info(WaifuGetRequest::new("present"))
+            .await?;
+        assert_eq!(found.map(|f| f.token), Some(FileToken::new("present")));
+
+        let gone = caller
+            .try_file_info(WaifuGetRequest::new("consumed"))
+            .await?;
+        assert!(gone.is_none());
+
+        let err = caller
+            .try_file_info(WaifuGetRequest::new("broken"))
+            .await
+            .expect_err("bad request");
+        assert_eq!(
+            err.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(400)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retention_is_watched_until_the_file_is_gone() -> Result<()> {
+        use futures_util::StreamExt;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/watched"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(serde_json::json!({
+                "name": "INTERNAL_SERVER_ERROR", "message": "Oops", "status": 500
+            })))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/watched"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("watched")))
+            .up_to_n_times(1)
+            .with_priority(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/watched"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+            })))
+            .with_priority(3)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let started = std::time::Instant::now();
+        let items: Vec<_> = caller
+            .watch_retention("watched", std::time::Duration::ZERO)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        let err = items[0].as_ref().unwrap_err();
+        assert_eq!(err.downcast_ref::<WaifuError>().unwrap().status, 500);
+        assert_eq!(items[1].as_ref().unwrap().token, "watched");
+        assert!(started.elapsed() >= MIN_POLL_INTERVAL * 2);
+
+        let token = tokio_util::sync::CancellationToken::new();
+        token.cancel();
+        let mut watch = caller
+            .cancelled_by(token)
+            .watch_retention("watched", MIN_POLL_INTERVAL);
+        let err = watch.next().await.unwrap().unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::Cancelled));
+        assert!(watch.next().await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn waiting_ends_once_the_file_is_consumed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/one-time"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("one-time")))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/one-time"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/kept"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("kept")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/slow"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(file_json("slow"))
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let second = std::time::Duration::from_secs(1);
+
+        // Polls no faster than the minimum interval
+        let started = std::time::Instant::now();
+        let consumed = caller
+            .wait_until_consumed("one-time", std::time::Duration::ZERO, second * 5)
+            .await?;
+        assert!(matches!(consumed, Consumption::Consumed));
+        assert!(started.elapsed() >= MIN_POLL_INTERVAL);
+
+        let kept = caller
+            .wait_until_consumed("kept", second, second * 3 / 2)
+            .await?;
+        assert!(matches!(kept, Consumption::StillPresent(entry) if entry.token == "kept"));
+
+        let slow = caller
+            .wait_until_consumed("slow", second, second / 10)
+            .await?;
+        assert!(matches!(slow, Consumption::TimedOut));
+
+        let token = tokio_util::sync::CancellationToken::new();
+        let cancelled = caller.cancelled_by(token.clone());
+        let wait = tokio::spawn(async move {
+            cancelled
+                .wait_until_consumed("kept", second, second * 60)
+                .await
+        });
+        tokio::time::sleep(second / 10).await;
+        token.cancel();
+        let err = wait.await?.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::Cancelled));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_checked_reports_unshared_albums() -> Result<()> {
+        let server = MockServer::start().await;
+        for (token, public_token) in [("shared", Some("public")), ("private", None)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/rest/album/{token}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "token": token, "bucketToken": "bucket", "publicToken": public_token,
+                    "name": "album", "files": []
+                })))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/rest/album/revoke/shared"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true, "description": "album unshared"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/revoke/private"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        assert_eq!(
+            caller.revoke_album_checked("shared").await?,
+            RevokeOutcome::Revoked
+        );
+        assert_eq!(
+            caller.revoke_album_checked("private").await?,
+            RevokeOutcome::WasNotShared
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn album_download_by_file_token() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut second = file_json("two");
+        second["id"] = 2.into();
+        Mock::given(method("GET"))
+            .and(path("/rest/album/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "album", "bucketToken": "bucket", "publicToken": null,
+                "name": "album", "files": [file_json("one"), second]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .and(body_json(serde_json::json!([2])))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"PK\x03\x04".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let archive = caller.download_album_files("album", &["two"]).await?;
+        assert!(archive.starts_with(b"PK"));
+
+        let err = caller
+            .download_album_files("album", &["one", "three", "four"])
+            .await
+            .expect_err("missing files");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::NotInAlbum {
+                tokens: vec![FileToken::new("three"), FileToken::new("four")]
+            })
+        );
+        assert_eq!(err.to_string(), "files not in album: three, four");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn album_download_streams_to_writer() -> Result<()> {
+        let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in [("one.txt", "first file"), ("two.txt", "second file")] {
+            archive.start_file(name, zip::write::SimpleFileOptions::default())?;
+            std::io::Write::write_all(&mut archive, content.as_bytes())?;
+        }
+        let archive = archive.finish()?.into_inner();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/missing"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Album not found", "status": 400
+            })))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("album.zip");
+        let file = tokio::fs::File::create(&path).await?;
+        let written = caller.download_album_to("album", None, file).await?;
+        assert_eq!(written, archive.len() as u64);
+
+        let mut saved = zip::ZipArchive::new(std::fs::File::open(&path)?)?;
+        assert_eq!(saved.len(), 2);
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut saved.by_name("two.txt")?, &mut content)?;
+        assert_eq!(content, "second file");
+
+        let err = caller
+            .download_album_to("missing", None, Vec::new())
+            .await
+            .expect_err("unknown album");
+        assert_eq!(
+            err.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(400)
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[tokio::test]
+    async fn album_download_extracts_to_directory() -> Result<()> {
+        let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in [("one.txt", "first file"), ("two.txt", "second file")] {
+            archive.start_file(name, zip::write::SimpleFileOptions::default())?;
+            std::io::Write::write_all(&mut archive, content.as_bytes())?;
+        }
+        let archive = archive.finish()?.into_inner();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir()?;
+        let dest = dir.path().join("album");
+        let written = mock_caller(&server)
+            .download_album_extract("album", None, &dest, Default::default())
+            .await?;
+
+        assert_eq!(written, vec![dest.join("one.txt"), dest.join("two.txt")]);
+        assert_eq!(
+            std::fs::read_to_string(dest.join("two.txt"))?,
+            "second file"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[tokio::test]
+    async fn album_entries_are_read_in_memory() -> Result<()> {
+        let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        archive.start_file("one.txt", zip::write::SimpleFileOptions::default())?;
+        std::io::Write::write_all(&mut archive, b"first file")?;
+        let archive = archive.finish()?.into_inner();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/album"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/corrupt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>oops</html>"))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let entries = caller.download_album_entries("album", None).await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "one.txt");
+        assert_eq!(entries[0].data, b"first file");
+
+        let err = caller
+            .download_album_entries("corrupt", None)
+            .await
+            .expect_err("not a zip");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::CorruptArchive { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bucket_download_saves_files_and_reports_failures() -> Result<()> {
+        let server = MockServer::start().await;
+        let file = |token: &str, name: &str| {
+            let mut json = file_json(token);
+            json["url"] = format!("{}/f/{token}/{name}", server.uri()).into();
+            json
+        };
+        let mut locked = file("locked", "locked.txt");
+        locked["options"]["protected"] = true.into();
+        let mut hidden = file("hidden", "");
+        hidden["options"]["hideFilename"] = true.into();
+        let files = [
+            file("one", "notes%20one.txt"),
+            file("two", "notes%20one.txt"),
+            hidden,
+            file("gone", "gone.txt"),
+            locked,
+        ];
+
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "bucket", "files": files, "albums": []
+            })))
+            .mount(&server)
+            .await;
+        for token in ["one", "two", "hidden"] {
+            Mock::given(method("GET"))
+                .and(path_regex(format!("^/f/{token}/")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(token))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/f/gone/gone.txt"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "File not found", "status": 404
+            })))
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir()?;
+        let report = mock_caller(&server)
+            .download_bucket("bucket", dir.path(), 2)
+            .await?;
+
+        let mut saved: Vec<_> = report
+            .saved
+            .iter()
+            .map(|(token, path)| (token.as_str(), path.strip_prefix(dir.path()).unwrap()))
+            .collect();
+        saved.sort();
+        assert_eq!(
+            saved,
+            [
+                ("hidden", std::path::Path::new("hidden")),
+                ("one", std::path::Path::new("notes one.txt")),
+                ("two", std::path::Path::new("two_notes one.txt")),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes one.txt"))?,
+            "one"
+        );
+
+        let mut failed: Vec<_> = report.failed.iter().map(|(t, _)| t.as_str()).collect();
+        failed.sort();
+        assert_eq!(failed, ["gone", "locked"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn directory_upload_reports_each_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("nested/deeper"))?;
+        std::fs::write(dir.path().join("one.txt"), "one")?;
+        std::fs::write(dir.path().join("nested/deeper/two.txt"), "two")?;
+        std::fs::write(dir.path().join("nested/rejected.txt"), "rejected")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("one.txt"), dir.path().join("link.txt"))?;
+
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/bucket"))
+            .and(query_param("expires", "1d"))
+            .and(body_string_contains("rejected"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Rejected", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/bucket"))
+            .and(query_param("expires", "1d"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let template = WaifuUploadRequest::new().expires("1d");
+        let report = mock_caller(&server)
+            .upload_directory(dir.path(), "bucket", template)
+            .await?;
+
+        let uploaded: Vec<_> = report.uploaded.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(
+            uploaded,
+            [
+                PathBuf::from("nested/deeper/two.txt"),
+                PathBuf::from("one.txt")
+            ]
+        );
+
+        let mut failed: Vec<_> = report.failed.iter().map(|(p, _)| p.clone()).collect();
+        failed.sort();
+        let mut expected = vec![PathBuf::from("nested/rejected.txt")];
+        if cfg!(unix) {
+            expected.insert(0, PathBuf::from("link.txt"));
+        }
+        assert_eq!(failed, expected);
+
+        let missing = mock_caller(&server)
+            .upload_directory(
+                dir.path().join("missing"),
+                "bucket",
+                WaifuUploadRequest::new(),
+            )
+            .await;
+        assert!(missing.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn directory_sync_uploads_changes_and_prunes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(dir.path().join("nested"))?;
+        std::fs::write(dir.path().join("same.txt"), "same")?;
+        std::fs::write(dir.path().join("nested/changed.txt"), "changed")?;
+        std::fs::write(dir.path().join("new.txt"), "new")?;
+
+        let server = MockServer::start().await;
+        let stored = |token: &str, name: &str| {
+            let mut json = file_json(token);
+            json["url"] = format!("{}/f/{token}/{name}", server.uri()).into();
+            json
+        };
+        let mut hidden = stored("hidden", "");
+        hidden["options"]["hideFilename"] = true.into();
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "bucket",
+                "files": [
+                    stored("same", "same.txt"),
+                    stored("changed", "changed.txt"),
+                    stored("removed", "removed.txt"),
+                    hidden,
+                ],
+                "albums": []
+            })))
+            .mount(&server)
+            .await;
+        for (token, size) in [("same", 4), ("changed", 3)] {
+            Mock::given(method("HEAD"))
+                .and(path_regex(format!("^/f/{token}/")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0; size]))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/f/same/same.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"same".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/bucket"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(2)
+            .mount(&server)
+            .await;
+        for token in ["changed", "removed"] {
+            Mock::given(method("DELETE"))
+                .and(path(format!("/rest/{token}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(true))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let options = SyncOptions {
+            prune: true,
+            ..Default::default()
+        };
+        let report = mock_caller(&server)
+            .sync_directory_to_bucket(dir.path(), "bucket", options)
+            .await?;
+
+        let uploaded: Vec<_> = report.uploaded.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(
+            uploaded,
+            [
+                PathBuf::from("nested/changed.txt"),
+                PathBuf::from("new.txt")
+            ]
+        );
+        assert_eq!(report.skipped, [PathBuf::from("same.txt")]);
+        assert_eq!(report.deleted, ["changed", "removed"]);
+        assert!(report.failed.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn directory_sync_compares_content_with_the_template_password() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("same.txt"), "same")?;
+        std::fs::write(dir.path().join("edited.txt"), "after")?;
+        std::fs::write(dir.path().join("locked.txt"), "locked")?;
+
+        let server = MockServer::start().await;
+        let stored = |token: &str, name: &str| {
+            let mut json = file_json(token);
+            json["url"] = format!("{}/f/{token}/{name}", server.uri()).into();
+            json
+        };
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "bucket",
+                "files": [
+                    stored("same", "same.txt"),
+                    stored("edited", "edited.txt"),
+                    stored("locked", "locked.txt"),
+                ],
+                "albums": []
+            })))
+            .mount(&server)
+            .await;
+        for (token, content) in [("same", "same"), ("edited", "befor")] {
+            Mock::given(method("HEAD"))
+                .and(path_regex(format!("^/f/{token}/")))
+                .and(header("x-password", "secret"))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(content.as_bytes()))
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path_regex(format!("^/f/{token}/")))
+                .and(header("x-password", "secret"))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(content.as_bytes()))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("HEAD"))
+            .and(path("/f/locked/locked.txt"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/bucket"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/edited"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let options = SyncOptions {
+            template: WaifuUploadRequest::new().password("secret"),
+            ..Default::default()
+        };
+        let report = mock_caller(&server)
+            .sync_directory_to_bucket(dir.path(), "bucket", options)
+            .await?;
+
+        let uploaded: Vec<_> = report.uploaded.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(uploaded, [PathBuf::from("edited.txt")]);
+        assert_eq!(report.skipped, [PathBuf::from("same.txt")]);
+        assert_eq!(report.deleted, ["edited"]);
+        let failed: Vec<_> = report.failed.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(failed, [PathBuf::from("locked.txt")]);
+
+        let hidden = SyncOptions {
+            template: WaifuUploadRequest::new().hide_filename(true),
+            ..Default::default()
+        };
+        assert!(mock_caller(&server)
+            .sync_directory_to_bucket(dir.path(), "bucket", hidden)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_bucket_deletes_each_file() -> Result<()> {
+        let server = MockServer::start().await;
+        let files: Vec<_> = ["one", "two", "three", "stuck", "gone"]
+            .into_iter()
+            .map(file_json)
+            .collect();
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "full" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "full", "files": files, "albums": []
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "empty" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("empty")))
+            .mount(&server)
+            .await;
+        for token in ["one", "two", "three"] {
+            Mock::given(method("DELETE"))
+                .and(path(format!("/rest/{token}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(true))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("DELETE"))
+            .and(path("/rest/stuck"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(false))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/gone"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "File not found", "status": 404
+            })))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let emptied = caller.empty_bucket("full").await?;
+        assert_eq!(emptied.deleted, 3);
+        let failed: Vec<_> = emptied.failed.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(failed, ["stuck", "gone"]);
+
+        let emptied = caller.empty_bucket("empty").await?;
+        assert_eq!(emptied.deleted, 0);
+        assert!(emptied.failed.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_files_reports_each_token() -> Result<()> {
+        let server = MockServer::start().await;
+        for token in ["one", "two", "three", "four", "five"] {
+            Mock::given(method("DELETE"))
+                .and(path(format!("/rest/{token}")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(true))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bogus"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "File not found", "status": 404
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let tokens = ["one", "two", "bogus", "three", "four", "five"];
+        let outcomes = mock_caller(&server).delete_files(&tokens).await;
+
+        let order: Vec<_> = outcomes.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(order, tokens);
+        for (token, deleted) in &outcomes {
+            match token.as_str() {
+                "bogus" => assert_eq!(
+                    deleted
+                        .as_ref()
+                        .unwrap_err()
+                        .downcast_ref::<WaifuError>()
+                        .map(|e| e.status),
+                    Some(404)
+                ),
+                _ => assert!(deleted.as_ref().unwrap()),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_info_batch_keeps_order() -> Result<()> {
+        let server = MockServer::start().await;
+        for (i, token) in ["one", "two", "three"].into_iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path(format!("/rest/{token}")))
+                .and(query_param("formatted", "true"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(file_json(token))
+                        .set_delay(std::time::Duration::from_millis(30 * (3 - i as u64))),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/rest/bogus"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+            })))
+            .mount(&server)
+            .await;
+
+        let infos = mock_caller(&server)
+            .file_info_batch(["one", "bogus", "two", "three"], true, 2)
+            .await;
+
+        let tokens: Vec<_> = infos
+            .iter()
+            .map(|info| info.as_ref().map(|f| f.token.as_str()).ok())
+            .collect();
+        assert_eq!(tokens, [Some("one"), None, Some("two"), Some("three")]);
+        assert!(is_not_found(infos[1].as_ref().unwrap_err()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bucket_retention_can_be_formatted() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut formatted = file_json("file");
+        formatted["retentionPeriod"] = "59 minutes 59 seconds".into();
+        for (flag, file) in [("true", formatted), ("false", file_json("file"))] {
+            Mock::given(method("POST"))
+                .and(path("/rest/bucket/get"))
+                .and(query_param("formatted", flag))
+                .and(body_json(serde_json::json!({ "bucket_token": "bucket" })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "token": "bucket", "files": [file], "albums": []
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let caller = mock_caller(&server);
+        let bucket = caller
+            .get_bucket(WaifuBucketRequest::new("bucket").formatted(true))
+            .await?;
+        assert!(bucket.files[0].retention_period.is_string());
+
+        let bucket = caller.get_bucket("bucket").await?;
+        assert!(bucket.files[0].retention_period.is_number());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_uploads_are_rejected_before_sending() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let err = caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .bytes(vec![1], "file.txt")
+                    .expires("1hr"),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidExpiry {
+                expiry: "1hr".to_string()
+            })
+        );
+
+        let err = caller
+            .upload_file(WaifuUploadRequest::new().url("ftp://example.com/file.txt"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidUrl {
+                url: "ftp://example.com/file.txt".to_string()
+            })
+        );
+
+        let err = caller
+            .update_file(WaifuModificationRequest::new("file").custom_expiry("2 days"))
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("m (minutes), h (hours) or d (days)"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_content_type_is_sent() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .and(body_string_contains("Content-Type: image/png"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("image")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("image.dat");
+        std::fs::write(&file, b"not really a png")?;
+
+        let caller = mock_caller(&server);
+        caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .bytes(b"not really a png".to_vec(), "image.dat")
+                    .content_type("image/png"),
+            )
+            .await?;
+        caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .file(&file)
+                    .content_type("image/png"),
+            )
+            .await?;
+
+        let err = caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .bytes(vec![1], "image.dat")
+                    .content_type("not a mime"),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("invalid content type"),
+            "{err:#}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_file_paths() -> Result<()> {
+        let server = MockServer::start().await;
+        for name in ["Cargo.toml", "name with spaces.txt"] {
+            Mock::given(method("PUT"))
+                .and(path("/rest"))
+                .and(body_string_contains(format!("filename=\"{name}\"")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let dir = tempfile::tempdir()?;
+        let spaced = dir.path().join("name with spaces.txt");
+        std::fs::write(&spaced, "content")?;
+
+        let caller = mock_caller(&server);
+        // Tests run from the crate root, so this is resolved against it
+        caller
+            .upload_file(WaifuUploadRequest::new().file("Cargo.toml"))
+            .await?;
+        caller
+            .upload_file(WaifuUploadRequest::new().file(&spaced))
+            .await?;
+
+        let missing = dir.path().join("missing file.txt");
+        let err = caller
+            .upload_file(WaifuUploadRequest::new().file(&missing))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains(&format!("file {} does not exist", missing.display())),
+            "{err:#}"
+        );
+
+        let err = caller
+            .upload_file(WaifuUploadRequest::new().file(dir.path()))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("is not a regular file"),
+            "{err:#}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_without_source() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let err = caller
+            .upload_file(WaifuUploadRequest::new().expires("1h").unchecked())
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NoUploadSource));
+
+        Ok(())
+    }
+
+    fn chunk_stream() -> impl futures_util::Stream<Item = std::io::Result<Vec<u8>>> {
+        futures_util::stream::iter(vec![Ok(b"first-".to_vec()), Ok(b"second".to_vec())])
+    }
+
+    #[tokio::test]
+    async fn upload_body_stream() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .and(body_string_contains("filename=\"streamed.txt\""))
+            .and(body_string_contains("first-second"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("streamed")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        for length in [Some(12), None] {
+            let request =
+                WaifuUploadRequest::new().body_stream(chunk_stream(), "streamed.txt", length);
+            assert_eq!(caller.upload_file(request).await?.token, "streamed");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn body_streams_are_sent_once() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("streamed")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = WaifuUploadRequest::new().body_stream(chunk_stream(), "streamed.txt", None);
+        let err = retrying_caller(&server, true)
+            .upload_file(request.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NonReplayableBody));
+
+        // Refusing to retry it didn't use the stream up
+        let caller = retrying_caller(&server, false);
+        caller.upload_file(request.clone()).await?;
+
+        let err = caller.upload_file(request).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::NonReplayableBody));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn uploads_hash_what_they_send() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .mount(&server)
+            .await;
+        let caller = retrying_caller(&server, true);
+        let tmp = TempFile::new("upload_checksum.bin").await?;
+        let content = fs::read(tmp.path()).await?;
+
+        // The first attempt fails, and only the one that succeeded is hashed
+        let request = WaifuUploadRequest::new()
+            .file(tmp.path())
+            .compute_checksum(HashAlgorithm::Sha256);
+        let outcome = caller.upload_file_with_outcome(request).await?;
+        assert_eq!(outcome.entry.token, "uploaded");
+        assert_eq!(outcome.checksum, Some(Checksum::sha256(&content)));
+
+        let request = WaifuUploadRequest::new()
+            .bytes(content.clone(), "upload_checksum.bin")
+            .compute_checksum(HashAlgorithm::Sha1);
+        let outcome = caller.upload_file_with_outcome(request).await?;
+        assert_eq!(outcome.checksum, Some(Checksum::sha1(&content)));
+
+        let request = WaifuUploadRequest::new()
+            .body_stream(chunk_stream(), "streamed.txt", None)
+            .compute_checksum(HashAlgorithm::Sha1);
+        let outcome = mock_caller(&server)
+            .upload_file_with_outcome(request)
+            .await?;
+        assert_eq!(outcome.checksum, Some(Checksum::sha1(b"first-second")));
+
+        let request = WaifuUploadRequest::new()
+            .url("https://example.com/some-file.png")
+            .compute_checksum(HashAlgorithm::Sha1);
+        let outcome = caller.upload_file_with_outcome(request).await?;
+        assert_eq!(outcome.checksum, None);
+
+        let request = WaifuUploadRequest::new().bytes(content, "upload_checksum.bin");
+        let outcome = caller.upload_file_with_outcome(request).await?;
+        assert_eq!(outcome.checksum, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn files_are_copied_under_their_name() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut source = file_json("source");
+        source["url"] = format!("{}/f/1712345/picture.png", server.uri()).into();
+        Mock::given(method("GET"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(source))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/1712345/picture.png"))
+            .and(header("x-password", "secret"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "image/png")
+                    .set_body_bytes(CONTENT),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/new-bucket"))
+            .and(query_param("expires", "7d"))
+            .and(body_string_contains("filename=\"picture.png\""))
+            .and(body_string_contains("image/png"))
+            .and(body_string_contains(std::str::from_utf8(CONTENT)?))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("copy")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        // Streamed, then held in memory by a caller that may have to send it again
+        let request = WaifuUploadRequest::new().bucket("new-bucket").expires("7d");
+        for caller in [mock_caller(&server), retrying_caller(&server, true)] {
+            let copy = caller
+                .copy_file("source", Some("secret".to_string()), request.clone())
+                .await?;
+            assert_eq!(copy.token, "copy");
+        }
+
+        Ok(())
+    }
+
+    /// Server with a file `source` to move, which is copied into `target-bucket` as `copy`
+    async fn move_server(copy_content: &'static [u8]) -> MockServer {
+        let server = MockServer::start().await;
+        let mut source = file_json("source");
+        source["url"] = format!("{}/f/1/source.txt", server.uri()).into();
+        let mut copy = file_json("copy");
+        copy["url"] = format!("{}/f/2/source.txt", server.uri()).into();
+        Mock::given(method("GET"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(source))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/source.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/target-bucket"))
+            .and(body_string_contains("filename=\"source.txt\""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(copy))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/2/source.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(copy_content))
+            .mount(&server)
+            .await;
+
+        server
+    }
+
+    #[tokio::test]
+    async fn files_are_moved_once_copied() -> Result<()> {
+        let server = move_server(CONTENT).await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let options = MoveOptions {
+            verify: true,
+            ..Default::default()
+        };
+        let moved = mock_caller(&server)
+            .move_file("source", "target-bucket", options)
+            .await?;
+        assert_eq!(moved.entry.token, "copy");
+        assert!(moved.source_deleted);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn incomplete_moves_keep_both_files() -> Result<()> {
+        let incomplete = Error::MoveIncomplete {
+            source: "source".into(),
+            copy: "copy".into(),
+        };
+
+        // The copy doesn't match, so the original isn't deleted
+        let server = move_server(b"garbled").await;
+        let options = MoveOptions {
+            verify: true,
+            ..Default::default()
+        };
+        let err = mock_caller(&server)
+            .move_file("source", "target-bucket", options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&incomplete));
+        assert!(err
+            .chain()
+            .any(|e| matches!(e.downcast_ref(), Some(Error::ChecksumMismatch { .. }))));
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests
+            .iter()
+            .all(|r| r.method != wiremock::http::Method::DELETE));
+
+        // Without verifying, the copy is trusted, but the original can't be deleted
+        let server = move_server(b"garbled").await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Nope", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        let err = mock_caller(&server)
+            .move_file("source", "target-bucket", MoveOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&incomplete));
+        assert_eq!(err.downcast_ref::<WaifuError>().unwrap().status, 400);
+
+        Ok(())
+    }
+
+    /// Server with a file `source` in `some-bucket`, expiring in an hour
+    async fn keep_alive_server(options: serde_json::Value) -> MockServer {
+        let server = MockServer::start().await;
+        let mut source = file_json("source");
+        source["url"] = format!("{}/f/1/source.txt", server.uri()).into();
+        source["bucket"] = "some-bucket".into();
+        source["options"] = options;
+        Mock::given(method("GET"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(source))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/source.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .mount(&server)
+            .await;
+
+        server
+    }
+
+    fn keep_alive_options(password: Option<&str>) -> KeepAliveOptions {
+        KeepAliveOptions {
+            expiry: std::time::Duration::from_secs(30 * 24 * 60 * 60),
+            password: password.map(str::to_string),
+        }
+    }
+
+    const HOUR: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+    #[tokio::test]
+    async fn files_are_kept_alive_in_place_when_possible() -> Result<()> {
+        let server = keep_alive_server(serde_json::json!({
+            "hideFilename": false, "oneTimeDownload": false, "protected": false
+        }))
+        .await;
+        let mut extended = file_json("source");
+        extended["retentionPeriod"] = (30 * 24 * HOUR.as_millis() as u64).into();
+        Mock::given(method("PATCH"))
+            .and(path("/rest/source"))
+            .and(body_json(serde_json::json!({ "customExpiry": "30d" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(extended))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let kept = caller
+            .extend_or_reupload("source", HOUR / 2, keep_alive_options(None))
+            .await?;
+        assert!(matches!(kept, KeepAlive::Untouched(_)));
+
+        let kept = caller
+            .extend_or_reupload("source", 2 * HOUR, keep_alive_options(None))
+            .await?;
+        assert!(matches!(kept, KeepAlive::Extended(_)));
+        assert_eq!(kept.new_token(), None);
+        assert_eq!(kept.entry().token, "source");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn files_are_uploaded_again_when_they_cant_be_extended() -> Result<()> {
+        let server = keep_alive_server(serde_json::json!({
+            "hideFilename": true, "oneTimeDownload": false, "protected": true
+        }))
+        .await;
+        Mock::given(method("PATCH"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Expiry too long", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/some-bucket"))
+            .and(query_param("expires", "30d"))
+            .and(query_param("hide_filename", "true"))
+            .and(body_string_contains("name=\"password\""))
+            .and(body_string_contains("secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("renewed")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        // A protected file can't be uploaded again without its password
+        let err = caller
+            .extend_or_reupload("source", 2 * HOUR, keep_alive_options(None))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("protected"), "{err}");
+
+        let kept = caller
+            .extend_or_reupload("source", 2 * HOUR, keep_alive_options(Some("secret")))
+            .await?;
+        assert_eq!(kept.new_token().map(FileToken::as_str), Some("renewed"));
+        assert!(matches!(
+            kept,
+            KeepAlive::Reuploaded { ref previous, .. } if previous.as_str() == "source"
+        ));
+        let requests = server.received_requests().await.unwrap();
+        let download = requests
+            .iter()
+            .find(|r| r.url.path() == "/f/1/source.txt")
+            .unwrap();
+        assert_eq!(download.headers["x-password"], "secret");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn one_time_downloads_are_not_kept_alive() -> Result<()> {
+        let server = keep_alive_server(serde_json::json!({
+            "hideFilename": false, "oneTimeDownload": true, "protected": false
+        }))
+        .await;
+        Mock::given(method("PATCH"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Expiry too long", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let err = caller
+            .extend_or_reupload("source", 2 * HOUR, keep_alive_options(None))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::OneTimeDownload {
+                token: "source".into()
+            })
+        );
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| r.url.path() != "/f/1/source.txt"));
+
+        // A failing service isn't taken for one refusing the expiry
+        let server = keep_alive_server(serde_json::json!({
+            "hideFilename": false, "oneTimeDownload": false, "protected": false
+        }))
+        .await;
+        Mock::given(method("PATCH"))
+            .and(path("/rest/source"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(serde_json::json!({
+                "name": "INTERNAL_SERVER_ERROR", "message": "Oops", "status": 500
+            })))
+            .mount(&server)
+            .await;
+        let err = mock_caller(&server)
+            .extend_or_reupload("source", 2 * HOUR, keep_alive_options(None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<WaifuError>().unwrap().status, 500);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn one_time_downloads_are_not_copied() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut source = file_json("source");
+        source["options"]["oneTimeDownload"] = true.into();
+        Mock::given(method("GET"))
+            .and(path("/rest/1712345"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(source))
+            .mount(&server)
+            .await;
+
+        let err = mock_caller(&server)
+            .copy_file(
+                "https://waifuvault.moe/f/1712345/source.txt",
+                None,
+                WaifuUploadRequest::new(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::OneTimeDownload {
+                token: "source".into()
+            })
+        );
+        // Nothing was downloaded
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn large_files_are_streamed_with_a_length() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .and(wiremock::matchers::header_exists("content-length"))
+            .and(body_string_contains("filename=\"sparse.bin\""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("sparse")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Sparse, so it takes no space on disk and is only ever held a chunk at a time
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("sparse.bin");
+        let size = 64 * 1024 * 1024;
+        std::fs::File::create(&file)?.set_len(size)?;
+
+        let caller = mock_caller(&server);
+        let entry = caller
+            .upload_file(WaifuUploadRequest::new().file(&file))
+            .await?;
+        assert_eq!(entry.token, "sparse");
+
+        let received = &server.received_requests().await.unwrap()[0];
+        assert!(received.body.len() as u64 > size);
+        assert_eq!(
+            received.headers["content-length"].to_str()?,
+            received.body.len().to_string()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn slow_transfers_time_out() -> Result<()> {
+        let server = MockServer::start().await;
+        let slow = std::time::Duration::from_secs(5);
+        Mock::given(method("PUT"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(file_json("slow"))
+                    .set_delay(slow),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/slow.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("slow")
+                    .set_delay(slow),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/fast.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("fast"))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let timeout = std::time::Duration::from_millis(100);
+        let request = WaifuUploadRequest::new()
+            .bytes(b"content".to_vec(), "file.txt")
+            .timeout(timeout);
+        let err = caller.upload_file(request).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::TimedOut));
+
+        let options = DownloadOptions::new().timeout(timeout);
+        let err = caller
+            .download_file_with(&format!("{}/f/slow.txt", server.uri()), options.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::TimedOut));
+
+        let content = caller
+            .download_file_with(&format!("{}/f/fast.txt", server.uri()), options)
+            .await?;
+        assert_eq!(content, b"fast");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancelled_callers_send_nothing() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let token = tokio_util::sync::CancellationToken::new();
+        token.cancel();
+        let caller = mock_caller(&server).cancelled_by(token);
+
+        let err = caller
+            .upload_file(WaifuUploadRequest::new().bytes(vec![1], "file.txt"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Cancelled));
+
+        let results = caller.file_info_batch(["one", "two"], false, 2).await;
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_eq!(
+                result.unwrap_err().downcast_ref::<Error>(),
+                Some(&Error::Cancelled)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancelling_stops_transfers_in_flight() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/slow.txt"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("slow")
+                    .set_delay(std::time::Duration::from_secs(5)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let token = tokio_util::sync::CancellationToken::new();
+        let caller = mock_caller(&server).cancelled_by(token.clone());
+        let url = format!("{}/f/slow.txt", server.uri());
+        let download = tokio::spawn(async move { caller.download_file(&url, None).await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        token.cancel();
+        let err = tokio::time::timeout(std::time::Duration::from_secs(1), download)
+            .await
+            .expect("the download stops promptly")?
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>(), Some(&Error::Cancelled));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_flags_are_only_sent_when_set() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(query_param_is_missing("hide_filename"))
+            .and(query_param_is_missing("oneTimeDownload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("unset")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for value in ["true", "false"] {
+            Mock::given(method("PUT"))
+                .and(query_param("hide_filename", value))
+                .and(query_param("oneTimeDownload", value))
+                .respond_with(ResponseTemplate::new(200).set_body_json(file_json(value)))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let caller = mock_caller(&server);
+        let unset = caller
+            .upload_file(WaifuUploadRequest::new().bytes(vec![1], "a.txt"))
+            .await?;
+        assert_eq!(unset.token, "unset");
+
+        for set in [true, false] {
+            let entry = caller
+                .upload_file(
+                    WaifuUploadRequest::new()
+                        .bytes(vec![1], "a.txt")
+                        .hide_filename(set)
+                        .one_time_download(set),
+                )
+                .await?;
+            assert_eq!(entry.token, set.to_string().as_str());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tokens_stay_in_their_path_segment() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/a%20b%2Fc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("a b/c")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/https:%2F%2Fwaifuvault.moe%2Falbum%2Fx"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND",
+                "message": "album not found",
+                "status": 404
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let entry = caller.file_info(WaifuGetRequest::new("a b/c")).await?;
+        assert_eq!(entry.token, "a b/c");
+
+        let err = caller
+            .get_album("https://waifuvault.moe/album/x")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(404)
+        );
+
+        let err = caller.delete_file("..").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidToken {
+                token: "..".to_string()
+            })
+        );
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // When running in test environment, it uses a local API version so the WaifuVault
-    // must be set up to run locally.
-    use super::*;
-    use anyhow::Result;
-    use rand::RngCore;
-    use sha1::{Digest, Sha1};
-    use std::path::PathBuf;
-    use tokio::{fs, io::AsyncWriteExt};
+    #[tokio::test]
+    async fn connecting_times_out() -> Result<()> {
+        // A listener that never accepts, with its queue already full, leaves new connections
+        // waiting for a handshake that never comes
+        let socket = tokio::net::TcpSocket::new_v4()?;
+        socket.bind("127.0.0.1:0".parse()?)?;
+        let listener = socket.listen(1)?;
+        let address = listener.local_addr()?;
+        let mut queued = Vec::new();
+        for _ in 0..8 {
+            match tokio::time::timeout(
+                std::time::Duration::from_millis(100),
+                tokio::net::TcpStream::connect(address),
+            )
+            .await
+            {
+                Ok(stream) => queued.push(stream?),
+                Err(_) => break,
+            }
+        }
 
-    // I know I could use `tempfile` here but scope issues made it awkward
-    // at least with this i can control when to delete the temp file
-    struct TempFileCreator {
-        file: PathBuf,
+        let caller = ApiCaller::builder()
+            .target(&Target::new(format!("http://{address}/rest")))
+            .connect_timeout(std::time::Duration::from_millis(200))
+            .build();
+        let started = std::time::Instant::now();
+        let err = caller.get_restrictions().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::TimedOut),
+            "{err:#}"
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        Ok(())
     }
 
-    impl TempFileCreator {
-        pub async fn new(filename: &str) -> Result<Self> {
-            let tmp = std::env::temp_dir();
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn calls_are_traced() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("traced")))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/traced"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/looked-up"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("looked-up")))
+            .mount(&server)
+            .await;
+
+        let recorder = TraceRecorder::default();
+        let guard = tracing::subscriber::set_default(recorder.clone());
+        let caller = mock_caller(&server);
+        caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .bytes(b"content".to_vec(), "file.txt")
+                    .password("hunter2"),
+            )
+            .await?;
+        caller.delete_file("traced").await?;
+        assert!(caller.file_exists("looked-up").await?);
+        drop(guard);
+
+        let lines = recorder.lines();
+        let has = |parts: &[&str]| lines.iter().any(|l| parts.iter().all(|p| l.contains(p)));
+        assert!(has(&["span upload_file"]), "{lines:#?}");
+        assert!(has(&["event", "bytes=7", "uploading bytes"]), "{lines:#?}");
+        assert!(has(&["event", "sending request"]), "{lines:#?}");
+        assert!(
+            has(&["event", "status=200", "received response"]),
+            "{lines:#?}"
+        );
+        assert!(has(&["span delete_file"]), "{lines:#?}");
+        assert!(has(&["record", "token=traced"]), "{lines:#?}");
+        assert!(has(&["span file_exists"]), "{lines:#?}");
+        assert!(has(&["record", "token=looked-up"]), "{lines:#?}");
+        assert!(!has(&["hunter2"]), "{lines:#?}");
 
-            let mut data = Vec::with_capacity(16_384);
-            rand::thread_rng().fill_bytes(&mut data);
+        Ok(())
+    }
 
-            let test_file = tmp.join(filename);
-            let mut f = tokio::fs::File::create(&test_file).await?;
-            f.write_all(&mut data).await?;
+    /// Subscriber writing every span, recorded field and event it sees as a line of text
+    #[cfg(feature = "tracing")]
+    #[derive(Default, Clone)]
+    struct TraceRecorder(Arc<std::sync::Mutex<TraceState>>);
+
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct TraceState {
+        lines: Vec<String>,
+        spans: Vec<&'static tracing::Metadata<'static>>,
+        entered: Vec<tracing::span::Id>,
+    }
 
-            return Ok(Self { file: test_file });
+    #[cfg(feature = "tracing")]
+    impl TraceRecorder {
+        fn lines(&self) -> Vec<String> {
+            self.0.lock().unwrap().lines.clone()
         }
-    }
 
-    impl Drop for TempFileCreator {
-        fn drop(&mut self) {
-            let _ = std::fs::remove_file(&self.file);
+        fn push(&self, kind: &str, fields: impl FnOnce(&mut TraceFields)) {
+            let mut line = TraceFields(kind.to_string());
+            fields(&mut line);
+            self.0.lock().unwrap().lines.push(line.0);
         }
     }
 
-    struct Dropper<'a> {
-        pub album_tkn: Option<String>,
-        pub bucket_tkn: Option<String>,
-        pub file_tkn: Option<String>,
-        pub caller: &'a ApiCaller,
-    }
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for TraceRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
 
-    impl<'a> Dropper<'a> {
-        pub fn new(caller: &'a ApiCaller) -> Self {
-            Self {
-                caller,
-                album_tkn: None,
-                bucket_tkn: None,
-                file_tkn: None,
-            }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let kind = format!("span {}", span.metadata().name());
+            self.push(&kind, |line| span.record(line));
+            let mut state = self.0.lock().unwrap();
+            state.spans.push(span.metadata());
+            tracing::span::Id::from_u64(state.spans.len() as u64)
         }
 
-        pub async fn create_bucket(&mut self) -> Result<WaifuBucketEntry> {
-            match self.caller.create_bucket().await {
-                Ok(r) => {
-                    self.bucket_tkn = Some(r.token.clone());
-                    Ok(r)
-                }
-                Err(e) => {
-                    self.destroy().await?;
-                    return Err(e);
-                }
-            }
+        fn record(&self, _: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            self.push("record", |line| values.record(line));
         }
 
-        pub async fn upload_file(
-            &mut self,
-            upload_req: WaifuUploadRequest,
-        ) -> Result<WaifuFileEntry> {
-            match self.caller.upload_file(upload_req).await {
-                Ok(r) => {
-                    self.file_tkn = Some(r.token.clone());
-                    Ok(r)
-                }
-                Err(e) => {
-                    self.destroy().await?;
-                    return Err(e);
-                }
-            }
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            self.push("event", |line| event.record(line));
         }
 
-        pub async fn create_album(&mut self, bkt_tkn: &str, name: &str) -> Result<WaifuAlbumEntry> {
-            match self.caller.create_album(bkt_tkn, name).await {
-                Ok(r) => {
-                    self.album_tkn = Some(r.token.clone());
-                    Ok(r)
-                }
-                Err(e) => {
-                    self.destroy().await?;
-                    return Err(e);
+        fn enter(&self, span: &tracing::span::Id) {
+            self.0.lock().unwrap().entered.push(span.clone());
+        }
+
+        fn exit(&self, _: &tracing::span::Id) {
+            self.0.lock().unwrap().entered.pop();
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            let state = self.0.lock().unwrap();
+            match state.entered.last() {
+                Some(id) => {
+                    let metadata = state.spans[id.into_u64() as usize - 1];
+                    tracing_core::span::Current::new(id.clone(), metadata)
                 }
+                None => tracing_core::span::Current::none(),
             }
         }
+    }
 
-        pub async fn destroy(&self) -> anyhow::Result<()> {
-            if let Some(ref f_tkn) = self.file_tkn {
-                self.caller.delete_file(&f_tkn).await?;
-            }
+    #[cfg(feature = "tracing")]
+    struct TraceFields(String);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for TraceFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+
+    #[tokio::test]
+    async fn observers_see_every_request_once() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("observed")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/observed.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("hello"))
+            .mount(&server)
+            .await;
+        let not_found = serde_json::json!({
+            "name": "NOT_FOUND",
+            "message": "file not found",
+            "status": 404
+        });
+        Mock::given(method("GET"))
+            .and(path("/rest/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(&not_found))
+            .mount(&server)
+            .await;
+
+        let observer = Arc::new(RecordingObserver::default());
+        let caller = ApiCaller::builder()
+            .target(&Target::new(format!("{}/rest", server.uri())))
+            .observer(observer.clone())
+            .build();
+
+        let entry = caller
+            .upload_file(WaifuUploadRequest::new().bytes(b"content".to_vec(), "file.txt"))
+            .await?;
+        let content = caller
+            .download_file(&format!("{}/f/observed.txt", server.uri()), None)
+            .await?;
+        assert_eq!(content, b"hello");
+        assert!(caller
+            .file_info(WaifuGetRequest::new("missing"))
+            .await
+            .is_err());
+        assert!(caller
+            .on(&Target::new("http://127.0.0.1:1/rest"))
+            .delete_file("unreachable")
+            .await
+            .is_err());
+        // Invalid requests aren't sent, so they aren't reported
+        assert!(caller.delete_file("").await.is_err());
+
+        let requests = observer.requests.lock().unwrap().clone();
+        assert_eq!(
+            requests,
+            [
+                Operation::Upload,
+                Operation::Download,
+                Operation::FileInfo,
+                Operation::DeleteFile
+            ]
+        );
+
+        let upload_body = serde_json::to_vec(&file_json(entry.token.as_str()))?.len() as u64;
+        let not_found_body = serde_json::to_vec(&not_found)?.len() as u64;
+        let responses = observer.responses.lock().unwrap().clone();
+        assert_eq!(
+            responses,
+            [
+                (Operation::Upload, Some(StatusCode::OK), 7 + upload_body),
+                (Operation::Download, Some(StatusCode::OK), 5),
+                (
+                    Operation::FileInfo,
+                    Some(StatusCode::NOT_FOUND),
+                    not_found_body
+                ),
+                (Operation::DeleteFile, None, 0),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        requests: std::sync::Mutex<Vec<Operation>>,
+        responses: std::sync::Mutex<Vec<(Operation, Option<StatusCode>, u64)>>,
+    }
+
+    impl ApiObserver for RecordingObserver {
+        fn on_request(&self, op: Operation) {
+            self.requests.lock().unwrap().push(op);
+        }
+
+        fn on_response(
+            &self,
+            op: Operation,
+            status: Option<StatusCode>,
+            _: std::time::Duration,
+            bytes: u64,
+        ) {
+            self.responses.lock().unwrap().push((op, status, bytes));
+        }
+    }
 
-            if let Some(ref b_tkn) = self.bucket_tkn {
-                self.caller.delete_bucket(&b_tkn).await?;
+    #[cfg(feature = "middleware")]
+    #[tokio::test]
+    async fn requests_go_through_middleware() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Counting(Arc<AtomicUsize>);
+
+        #[async_trait::async_trait]
+        impl reqwest_middleware::Middleware for Counting {
+            async fn handle(
+                &self,
+                request: reqwest::Request,
+                extensions: &mut task_local_extensions::Extensions,
+                next: reqwest_middleware::Next<'_>,
+            ) -> reqwest_middleware::Result<reqwest::Response> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                next.run(request, extensions).await
             }
+        }
 
-            if let Some(ref a_tkn) = self.album_tkn {
-                self.caller.delete_album(&a_tkn, true).await?;
+        struct Refusing;
+
+        #[async_trait::async_trait]
+        impl reqwest_middleware::Middleware for Refusing {
+            async fn handle(
+                &self,
+                _: reqwest::Request,
+                _: &mut task_local_extensions::Extensions,
+                _: reqwest_middleware::Next<'_>,
+            ) -> reqwest_middleware::Result<reqwest::Response> {
+                Err(anyhow::anyhow!("refused by policy").into())
             }
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let target = Target::new(format!("{}/rest", server.uri()));
+        let seen = Arc::new(AtomicUsize::new(0));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(Counting(seen.clone()))
+            .build();
+        let caller = ApiCaller::with_middleware_client(client).on(&target);
+
+        caller.file_info(WaifuGetRequest::new("file")).await?;
+        caller
+            .upload_file(WaifuUploadRequest::new().bytes(b"content".to_vec(), "file.txt"))
+            .await?;
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+
+        let refusing = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(Refusing)
+            .build();
+        let err = ApiCaller::builder()
+            .target(&target)
+            .middleware_client(refusing)
+            .retry(RetryPolicy::default())
+            .build()
+            .file_info(WaifuGetRequest::new("file"))
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("refused by policy"), "{err:#}");
+        assert!(err.downcast_ref::<Error>().is_none(), "{err:#}");
+
+        Ok(())
+    }
+
+    /// Server answering every request for `from` with a 302 to `to`
+    async fn redirect(server: &MockServer, from: &str, to: &str) {
+        Mock::given(method("GET"))
+            .and(path(from))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", to))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn passwords_are_not_sent_to_other_hosts() -> Result<()> {
+        let vault = MockServer::start().await;
+        let elsewhere = MockServer::start().await;
+        let moved = format!("{}/stored/file.txt", elsewhere.uri());
+        redirect(&vault, "/f/1/moved.txt", &moved).await;
+        redirect(&vault, "/f/1/renamed.txt", "/f/1/file.txt").await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/file.txt"))
+            .and(header("x-password", "secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .expect(1)
+            .mount(&vault)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/stored/file.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .expect(1)
+            .mount(&elsewhere)
+            .await;
+        let caller = mock_caller(&vault);
+
+        let url = format!("{}/f/1/renamed.txt", vault.uri());
+        let content = caller
+            .download_file(&url, Some("secret".to_string()))
+            .await?;
+        assert_eq!(content, CONTENT);
+
+        let url = format!("{}/f/1/moved.txt", vault.uri());
+        let content = caller
+            .download_file(&url, Some("secret".to_string()))
+            .await?;
+        assert_eq!(content, CONTENT);
+        let received = elsewhere.received_requests().await.unwrap();
+        assert!(!received[0].headers.contains_key("x-password"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redirect_policies_refuse_what_they_dont_allow() -> Result<()> {
+        let vault = MockServer::start().await;
+        // Same port, another host
+        let elsewhere = vault.uri().replace("127.0.0.1", "localhost");
+        redirect(
+            &vault,
+            "/f/1/away.txt",
+            &format!("{elsewhere}/f/1/file.txt"),
+        )
+        .await;
+        redirect(&vault, "/f/1/twice.txt", "/f/1/renamed.txt").await;
+        redirect(&vault, "/f/1/renamed.txt", "/f/1/file.txt").await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/file.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .mount(&vault)
+            .await;
+        let caller = |policy| {
+            ApiCaller::builder()
+                .target(&Target::new(format!("{}/rest", vault.uri())))
+                .redirect(policy)
+                .build()
+        };
+        let refused = |result: Result<Vec<u8>>, to: &str| {
+            let err = result.unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<Error>(),
+                Some(&Error::RedirectNotFollowed {
+                    location: to.to_string()
+                }),
+                "{err:#}"
+            );
+        };
+        let url = |file: &str| format!("{}/f/1/{file}", vault.uri());
+
+        let same_host = caller(RedirectPolicy::SameHostOnly);
+        refused(
+            same_host.download_file(&url("away.txt"), None).await,
+            &format!("{elsewhere}/f/1/file.txt"),
+        );
+        same_host.download_file(&url("twice.txt"), None).await?;
+
+        let none = caller(RedirectPolicy::None);
+        refused(
+            none.download_file(&url("renamed.txt"), None).await,
+            &url("file.txt"),
+        );
+
+        let once = caller(RedirectPolicy::Follow(1));
+        once.download_file(&url("renamed.txt"), None).await?;
+        refused(
+            once.download_file(&url("twice.txt"), None).await,
+            &url("file.txt"),
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_sizes_come_from_head_requests() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/f/1/file.txt"))
+            .and(header("x-password", "secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/f/1/file.txt"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let url = format!("{}/f/1/file.txt", server.uri());
+
+        let size = caller.file_size(&url, Some("secret".to_string())).await?;
+        assert_eq!(size, Some(CONTENT.len() as u64));
+
+        let err = caller
+            .file_size(&url, Some("wrong".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "supplied password is incorrect");
+        let err = caller.file_size(&url, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "this file requires a password to download");
+
+        let transport = Arc::new(FakeTransport::answering(serde_json::Value::Null));
+        let size = fake_caller(&transport).file_size(&url, None).await?;
+        assert_eq!(size, None);
+        assert_eq!(transport.sent()[0].method(), reqwest::Method::HEAD);
+
+        Ok(())
+    }
 
-            Ok(())
+    #[tokio::test]
+    async fn identity_downloads_match_the_stored_file() -> Result<()> {
+        // CONTENT gzipped, as stored by someone uploading a compressed artifact
+        const STORED: &[u8] = &[
+            31, 139, 8, 0, 0, 0, 0, 0, 2, 255, 43, 201, 72, 85, 40, 207, 200, 207, 73, 85, 72, 203,
+            4, 18, 201, 249, 121, 37, 169, 121, 37, 0, 154, 8, 170, 102, 22, 0, 0, 0,
+        ];
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/artifact.gz"))
+            .and(header("accept-encoding", "identity"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(STORED))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        // A server sending the stored file as a gzip encoded response
+        Mock::given(method("GET"))
+            .and(path("/f/1/artifact.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_bytes(STORED),
+            )
+            .mount(&server)
+            .await;
+        let url = format!("{}/f/1/artifact.gz", server.uri());
+
+        let caller = mock_caller(&server);
+        let options = DownloadOptions::new().identity_encoding();
+        let content = caller.download_file_with(&url, options).await?;
+        assert_eq!(content, STORED);
+
+        #[cfg(feature = "gzip")]
+        {
+            let content = caller.download_file(&url, None).await?;
+            assert_eq!(content, CONTENT);
+
+            let raw = ApiCaller::builder()
+                .target(&caller.target())
+                .decompress(false)
+                .build();
+            let content = raw.download_file(&url, None).await?;
+            assert_eq!(content, STORED);
         }
+
+        Ok(())
     }
 
-    async fn cleanup(caller: &ApiCaller, token: &str) -> Result<()> {
-        caller.delete_file(token).await?;
+    #[tokio::test]
+    async fn pool_settings_reach_the_client() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let builder = ApiCaller::builder()
+            .target(&Target::new(format!("{}/rest", server.uri())))
+            .pool_max_idle_per_host(4)
+            .pool_idle_timeout(None)
+            .tcp_keepalive(std::time::Duration::from_secs(30));
+        assert_eq!(builder.pool_max_idle_per_host, Some(4));
+        assert_eq!(builder.pool_idle_timeout, Some(None));
+        assert_eq!(
+            builder.tcp_keepalive,
+            Some(std::time::Duration::from_secs(30))
+        );
+
+        let caller = builder.build();
+        caller.file_info(WaifuGetRequest::new("file")).await?;
+        caller.file_info(WaifuGetRequest::new("file")).await?;
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn upload_file() -> Result<()> {
-        let tmp = TempFileCreator::new("upload_basic.bin").await?;
-        assert!(tmp.file.exists());
-        let caller = ApiCaller::new();
-        let upload_request = api::WaifuUploadRequest::new().file(&tmp.file);
+    async fn proxies_carry_api_requests_and_downloads() -> Result<()> {
+        let proxy = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/file"))
+            .and(header("host", "vault.invalid"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/1/file.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let caller = ApiCaller::builder()
+            .target(&Target::new("http://vault.invalid/rest"))
+            .proxy(proxy.uri())
+            .no_proxy()
+            .try_build()?;
+        caller.file_info(WaifuGetRequest::new("file")).await?;
+        let content = caller
+            .download_file("http://vault.invalid/f/1/file.txt", None)
+            .await?;
+        assert_eq!(content, CONTENT);
 
-        let response = caller
-            .upload_file(upload_request)
+        let err = ApiCaller::builder()
+            .proxy("ftp://proxy.invalid")
+            .try_build()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidProxy { ref url, .. } if url == "ftp://proxy.invalid"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rustls")]
+    #[tokio::test]
+    async fn rustls_callers_send_requests() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let target = Target::new(format!("{}/rest", server.uri()));
+        let entry = ApiCaller::new()
+            .on(&target)
+            .file_info(WaifuGetRequest::new("file"))
+            .await?;
+        assert_eq!(entry.token, "file");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_bucket_posts_the_token() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(bucket_json("bucket-token")));
+        let caller = fake_caller(&transport);
+
+        let bucket = caller.get_bucket("bucket-token").await?;
+        assert_eq!(bucket.token, "bucket-token");
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].method(), reqwest::Method::POST);
+        assert_eq!(
+            sent[0].url().as_str(),
+            format!("{API}/bucket/get?formatted=false")
+        );
+        assert_eq!(
+            fake_json_body(&sent[0]),
+            serde_json::json!({ "bucket_token": "bucket-token" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_file_patches_the_modification() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = fake_caller(&transport);
+
+        let request = WaifuModificationRequest::new("file-token")
+            .password("new")
+            .previous_password("old")
+            .custom_expiry("1d")
+            .hide_filename(true);
+        caller.update_file(request.clone()).await?;
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].method(), reqwest::Method::PATCH);
+        assert_eq!(sent[0].url().as_str(), format!("{API}/file-token"));
+        assert_eq!(
+            sent[0].headers()[reqwest::header::CONTENT_TYPE],
+            "application/json"
+        );
+        assert_eq!(fake_json_body(&sent[0]), serde_json::to_value(&request)?);
+        assert_eq!(
+            fake_json_body(&sent[0]),
+            serde_json::json!({
+                "password": "new",
+                "previousPassword": "old",
+                "customExpiry": "1d",
+                "hideFilename": true
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removing_a_password_sends_an_empty_one() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = fake_caller(&transport);
+
+        let request = WaifuModificationRequest::new("file-token")
+            .password("ignored")
+            .remove_password("old");
+        caller.update_file(request).await?;
+
+        assert_eq!(
+            fake_json_body(&transport.sent()[0]),
+            serde_json::json!({ "password": "", "previousPassword": "old" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clearing_an_expiry_sends_an_empty_one() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = fake_caller(&transport);
+
+        let request = WaifuModificationRequest::new("file-token")
+            .custom_expiry("1d")
+            .clear_custom_expiry();
+        caller.update_file(request).await?;
+
+        assert_eq!(
+            fake_json_body(&transport.sent()[0]),
+            serde_json::json!({ "customExpiry": "" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn checked_updates_need_the_previous_password() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut protected = file_json("protected");
+        protected["options"]["protected"] = true.into();
+        Mock::given(method("GET"))
+            .and(path("/rest/protected"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(protected))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/open"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("open")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("patched")))
+            .expect(3)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let err = caller
+            .update_file_checked(WaifuModificationRequest::new("protected").password("new"))
             .await
-            .context("upload file - basic");
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::PreviousPasswordRequired {
+                token: "protected".into()
+            })
+        );
 
-        assert!(response.is_ok());
+        caller
+            .update_file_checked(
+                WaifuModificationRequest::new("protected")
+                    .password("new")
+                    .previous_password("old"),
+            )
+            .await?;
+        caller
+            .update_file_checked(WaifuModificationRequest::new("open").password("new"))
+            .await?;
+        // Nothing to check without a new password
+        caller
+            .update_file_checked(WaifuModificationRequest::new("protected").hide_filename(true))
+            .await?;
 
-        let response = response?;
-        let options = response
-            .options
-            .expect("expected options when there are none");
+        let lookups = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.method == wiremock::http::Method::GET)
+            .count();
+        assert_eq!(lookups, 2);
 
-        assert!(!options.hide_filename);
-        assert!(!options.protected);
-        assert!(!options.one_time_download);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn updates_report_what_changed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("some-token")))
+            .mount(&server)
+            .await;
+        let mut updated = file_json("some-token");
+        updated["options"]["protected"] = true.into();
+        updated["retentionPeriod"] = 7_200_000.into();
+        Mock::given(method("PATCH"))
+            .and(path("/rest/some-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(updated))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let request = WaifuModificationRequest::new("some-token")
+            .password("secret")
+            .hide_filename(false)
+            .custom_expiry("2h");
+        let (entry, diff) = caller.update_file_with_diff(request).await?;
+        assert!(entry.is_protected());
+        assert_eq!(
+            diff.protected,
+            Some(api::Change {
+                before: false,
+                after: true
+            })
+        );
+        assert_eq!(diff.hide_filename, None);
+        let retention = diff.retention.unwrap();
+        let longer = retention
+            .after
+            .unwrap()
+            .duration_since(retention.before.unwrap())?;
+        assert!(longer >= std::time::Duration::from_secs(3590), "{longer:?}");
+        assert!(diff
+            .to_string()
+            .starts_with("protected: false -> true, expires: "));
+
+        let same = OptionsDiff::between(
+            &entry,
+            std::time::SystemTime::now(),
+            &entry,
+            std::time::SystemTime::now() + std::time::Duration::from_secs(5),
+        );
+        assert!(same.is_empty());
+        assert_eq!(same.to_string(), "no changes");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn url_uploads_send_a_form() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = fake_caller(&transport);
+
+        caller
+            .upload_file(
+                WaifuUploadRequest::new()
+                    .url("https://example.com/image.png")
+                    .expires("1h")
+                    .one_time_download(true),
+            )
+            .await?;
+
+        let sent = transport.sent();
+        assert_eq!(sent[0].method(), reqwest::Method::PUT);
+        assert_eq!(sent[0].url().path(), "/rest");
+        assert_eq!(
+            sent[0].url().query(),
+            Some("oneTimeDownload=true&expires=1h")
+        );
+        assert_eq!(
+            sent[0].body().and_then(reqwest::Body::as_bytes),
+            Some(&b"url=https%3A%2F%2Fexample.com%2Fimage.png"[..])
+        );
+
+        Ok(())
+    }
+
+    /// Transport answering every request with the same JSON, keeping what was sent
+    #[derive(Debug)]
+    struct FakeTransport {
+        body: serde_json::Value,
+        status: std::sync::atomic::AtomicU16,
+        sent: std::sync::Mutex<Vec<reqwest::Request>>,
+    }
+
+    impl FakeTransport {
+        fn answering(body: serde_json::Value) -> Self {
+            Self {
+                body,
+                status: std::sync::atomic::AtomicU16::new(200),
+                sent: std::sync::Mutex::default(),
+            }
+        }
+
+        /// Answers the following requests with another status
+        fn set_status(&self, status: u16) {
+            self.status
+                .store(status, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        fn sent(&self) -> Vec<reqwest::Request> {
+            let sent = self.sent.lock().unwrap();
+            sent.iter()
+                .map(|r| r.try_clone().expect("fake requests have buffered bodies"))
+                .collect()
+        }
+    }
+
+    impl HttpTransport for FakeTransport {
+        fn execute(&self, request: reqwest::Request) -> transport::TransportFuture<'_> {
+            self.sent.lock().unwrap().push(request);
+            let response = http::Response::builder()
+                .status(self.status.load(std::sync::atomic::Ordering::Relaxed))
+                .header("Content-Type", "application/json")
+                .body(self.body.to_string())
+                .expect("valid response");
+
+            Box::pin(async move { Ok(reqwest::Response::from(response)) })
+        }
+    }
+
+    fn fake_caller(transport: &Arc<FakeTransport>) -> ApiCaller {
+        ApiCaller {
+            transport: transport.clone(),
+            ..ApiCaller::new()
+        }
+    }
+
+    fn fake_json_body(request: &reqwest::Request) -> serde_json::Value {
+        let body = request
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .unwrap_or_default();
+        serde_json::from_slice(body).expect("request body is JSON")
+    }
+
+    #[tokio::test]
+    async fn caller_works_as_a_trait_object() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(bucket_json("bucket-token")));
+        let vault: Arc<dyn WaifuVaultApi> = Arc::new(fake_caller(&transport));
+
+        let bucket = vault
+            .get_bucket(WaifuBucketRequest::from("bucket-token"))
+            .await?;
+        assert_eq!(bucket.token, "bucket-token");
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].url().as_str(),
+            format!("{API}/bucket/get?formatted=false")
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "mocks")]
+    #[tokio::test]
+    async fn mock_vault_answers_as_set_up() -> Result<()> {
+        let mut mock = vault::MockWaifuVaultApi::new();
+        mock.expect_delete_file()
+            .withf(|token| token.as_str() == "file-token")
+            .times(1)
+            .returning(|_| Ok(true));
+        let vault: Arc<dyn WaifuVaultApi> = Arc::new(mock);
 
-        cleanup(&caller, &response.token).await?;
-        be_nice().await;
+        assert!(vault.delete_file(FileToken::from("file-token")).await?);
 
         Ok(())
     }
 
+    #[cfg(feature = "mocks")]
     #[tokio::test]
-    async fn upload_file_with_options() -> Result<()> {
-        let tmp = TempFileCreator::new("upload_with_options.bin").await?;
-        let caller = ApiCaller::new();
-        let upload_request = api::WaifuUploadRequest::new()
-            .file(&tmp.file)
-            .expires("1h")
-            .password("apple")
-            .one_time_download(true)
-            .hide_filename(true);
+    async fn mock_vault_returns_built_responses() -> Result<()> {
+        let mut mock = vault::MockWaifuVaultApi::new();
+        mock.expect_file_info().returning(|_| {
+            Ok(
+                WaifuFileEntry::new("file-token", "https://waifuvault.moe/f/1/a.png")
+                    .retention_period(3_600_000)
+                    .options(WaifuFileOptions::new(false, false, true)),
+            )
+        });
+        mock.expect_get_restrictions().returning(|| {
+            Ok(WaifuRestrictions::new(vec![WaifuRestriction::new(
+                "MAX_FILE_SIZE",
+                1024,
+            )]))
+        });
+        let vault: Arc<dyn WaifuVaultApi> = Arc::new(mock);
+
+        let entry = vault.file_info(WaifuGetRequest::new("file-token")).await?;
+        assert_eq!(entry.token, "file-token");
+        assert!(entry.is_protected());
+        assert!(entry.expires_at().is_some());
+        assert_eq!(vault.get_restrictions().await?.max_file_size(), Some(1024));
 
-        let response = caller
-            .upload_file(upload_request)
-            .await
-            .context("upload file with options")?;
-        let options = response
-            .options
-            .expect("expected options when there are none");
+        Ok(())
+    }
 
-        assert!(options.hide_filename);
-        assert!(options.protected);
-        assert!(options.one_time_download);
+    #[tokio::test]
+    async fn requests_are_spaced_out() -> Result<()> {
+        let interval = std::time::Duration::from_millis(200);
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = ApiCaller {
+            transport: transport.clone(),
+            ..ApiCaller::builder().min_request_interval(interval).build()
+        };
 
-        cleanup(&caller, &response.token).await?;
-        be_nice().await;
+        let started = std::time::Instant::now();
+        for _ in 0..3 {
+            caller.file_info(WaifuGetRequest::new("file-token")).await?;
+        }
+        assert!(started.elapsed() >= interval * 2);
+
+        let started = std::time::Instant::now();
+        let clone = caller.clone();
+        let other = caller.on(&caller.target());
+        let (first, second, third) = tokio::join!(
+            caller.file_info(WaifuGetRequest::new("file-token")),
+            clone.file_info(WaifuGetRequest::new("file-token")),
+            other.file_info(WaifuGetRequest::new("file-token")),
+        );
+        first?;
+        second?;
+        third?;
+        assert!(started.elapsed() >= interval * 3);
+        assert_eq!(transport.sent().len(), 6);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn upload_file_from_url() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
-        let caller = ApiCaller::new();
-        let request = WaifuUploadRequest::new().url(url).expires("1h");
+    async fn circuit_opens_and_closes_again() -> Result<()> {
+        let cool_down = std::time::Duration::from_millis(100);
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = ApiCaller {
+            transport: transport.clone(),
+            ..ApiCaller::builder()
+                .circuit_breaker(CircuitBreaker {
+                    failure_threshold: 2,
+                    cool_down,
+                })
+                .build()
+        };
+        let clone = caller.clone();
+        let info = || WaifuGetRequest::new("file-token");
+        let is_open = |result: Result<WaifuFileEntry>| {
+            result.is_err_and(|e| e.downcast_ref() == Some(&Error::CircuitOpen))
+        };
 
-        let response = caller
-            .upload_file(request)
-            .await
-            .context("upload from url")?;
-        let options = response
-            .options
-            .expect("expected options when there are none");
+        // Closed, counting failures across clones until it opens
+        transport.set_status(503);
+        assert!(!is_open(caller.file_info(info()).await));
+        assert!(!is_open(clone.file_info(info()).await));
+        assert!(is_open(caller.file_info(info()).await));
+        assert_eq!(transport.sent().len(), 2);
+
+        // Half-open, the probe fails and opens it again
+        tokio::time::sleep(cool_down).await;
+        assert!(!is_open(caller.file_info(info()).await));
+        assert!(is_open(clone.file_info(info()).await));
+        assert_eq!(transport.sent().len(), 3);
+
+        // Half-open, the probe succeeds and closes it
+        tokio::time::sleep(cool_down).await;
+        transport.set_status(200);
+        clone.file_info(info()).await?;
+        caller.file_info(info()).await?;
+        assert_eq!(transport.sent().len(), 5);
 
-        assert!(!options.hide_filename);
-        assert!(!options.protected);
-        assert!(!options.one_time_download);
+        Ok(())
+    }
 
-        cleanup(&caller, &response.token).await?;
-        be_nice().await;
+    #[tokio::test]
+    async fn callers_on_the_same_host_share_a_circuit() -> Result<()> {
+        let transport = Arc::new(FakeTransport::answering(file_json("file-token")));
+        let caller = ApiCaller {
+            transport: transport.clone(),
+            ..ApiCaller::builder()
+                .circuit_breaker(CircuitBreaker {
+                    failure_threshold: 3,
+                    cool_down: std::time::Duration::from_secs(60),
+                })
+                .build()
+        };
+        let vault = Target::new("https://vault.example.com/rest");
+        let info = || WaifuGetRequest::new("file-token");
+
+        transport.set_status(503);
+        for _ in 0..3 {
+            let err = caller.on(&vault).file_info(info()).await.unwrap_err();
+            assert_ne!(err.downcast_ref(), Some(&Error::CircuitOpen));
+        }
+        let err = caller.on(&vault).file_info(info()).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::CircuitOpen));
+        assert_eq!(transport.sent().len(), 3);
+
+        // Other hosts, including the caller's own, keep their circuits closed
+        transport.set_status(200);
+        let other = Target::new("https://other.example.com/rest");
+        caller.on(&other).file_info(info()).await?;
+        caller.file_info(info()).await?;
+        assert_eq!(transport.sent().len(), 5);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn upload_file_bytes() -> Result<()> {
-        let tmp = TempFileCreator::new("upload_from_raw_bytes.bin").await?;
-        let caller = ApiCaller::new();
-        let content = fs::read(&tmp.file).await?;
-        let request = WaifuUploadRequest::new()
-            .bytes(content, "test_raw_bytes.bin")
-            .expires("1h");
+    async fn circuit_ignores_errors_that_are_not_the_service() -> Result<()> {
+        let vault = MockServer::start().await;
+        redirect(&vault, "/f/1/moved.txt", "/f/1/file.txt").await;
+        let caller = ApiCaller::builder()
+            .target(&Target::new(format!("{}/rest", vault.uri())))
+            .redirect(RedirectPolicy::None)
+            .circuit_breaker(CircuitBreaker {
+                failure_threshold: 1,
+                cool_down: std::time::Duration::from_secs(60),
+            })
+            .build();
+        let url = format!("{}/f/1/moved.txt", vault.uri());
+
+        for _ in 0..3 {
+            let err = caller.download_file(&url, None).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::RedirectNotFollowed { .. })
+            ));
+        }
+        let err = caller
+            .download_file(&url, Some("\u{1F511}".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::UnencodablePassword));
 
-        let response = caller.upload_file(request).await?;
-        cleanup(&caller, &response.token).await?;
-        be_nice().await;
+        // A server that can't be reached still opens it
+        let unreachable = "http://127.0.0.1:1/f/1/file.txt";
+        assert!(caller.download_file(unreachable, None).await.is_err());
+        let err = caller.download_file(&url, None).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&Error::CircuitOpen));
 
         Ok(())
     }
 
+    const CONTENT: &[u8] = b"the whole file content";
+
+    /// Answers requests for a file with the part of it asked for in a `Range` header
+    struct RangeResponder {
+        content: Vec<u8>,
+        ranges: bool,
+    }
+
+    impl wiremock::Respond for RangeResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let len = self.content.len();
+            let range = request
+                .headers
+                .get("range")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes="))
+                .and_then(|value| value.split_once('-'));
+            let Some((start, end)) = range.filter(|_| self.ranges) else {
+                return ResponseTemplate::new(200).set_body_bytes(self.content.clone());
+            };
+
+            let start: usize = start.parse().unwrap();
+            if start >= len {
+                return ResponseTemplate::new(416)
+                    .insert_header("Content-Range", format!("bytes */{len}").as_str());
+            }
+            let end = end.parse().map_or(len - 1, |end: usize| end.min(len - 1));
+            ResponseTemplate::new(206)
+                .insert_header(
+                    "Content-Range",
+                    format!("bytes {start}-{end}/{len}").as_str(),
+                )
+                .set_body_bytes(&self.content[start..=end])
+        }
+    }
+
+    /// Serves `content` at `/f/some-file`, answering range requests if `ranges` is set
+    async fn range_server(content: &[u8], ranges: bool) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .respond_with(RangeResponder {
+                content: content.to_vec(),
+                ranges,
+            })
+            .mount(&server)
+            .await;
+
+        server
+    }
+
     #[tokio::test]
-    async fn get_file_info() -> Result<()> {
-        let tmp = TempFileCreator::new("get_file_info_basic.bin").await?;
-        let caller = ApiCaller::new();
-        let upload = WaifuUploadRequest::new().file(&tmp.file);
-        let response = caller.upload_file(upload).await?;
+    async fn interrupted_downloads_are_resumed() -> Result<()> {
+        let server = range_server(CONTENT, true).await;
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("some-file");
+        let url = format!("{}/f/some-file", server.uri());
+        let caller = mock_caller(&server);
+
+        // What an earlier download got before the connection dropped
+        fs::write(&file, &CONTENT[..9]).await?;
+        let report = caller
+            .download_file_to(&url, &file, DownloadOptions::new().resume())
+            .await?;
+        assert_eq!(fs::read(&file).await?, CONTENT);
+        assert_eq!(report.size, CONTENT.len() as u64);
+        assert_eq!(report.resumed_from, 9);
+        assert_eq!(report.warning, None);
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers["range"], "bytes=9-");
+
+        // Nothing left to download
+        let report = caller
+            .download_file_to(&url, &file, DownloadOptions::new().resume())
+            .await?;
+        assert_eq!(report.resumed_from, CONTENT.len() as u64);
+        assert_eq!(fs::read(&file).await?, CONTENT);
 
-        let token = response.token;
-        let options = response
-            .options
-            .expect("options expected but there are none");
-        let get_req = WaifuGetRequest::new(&token);
-        let response = caller.file_info(get_req).await?;
+        // An explicit offset discards whatever is past it
+        fs::write(&file, b"the whole garbage").await?;
+        let report = caller
+            .download_file_to(&url, &file, DownloadOptions::new().resume_from(10))
+            .await?;
+        assert_eq!(report.resumed_from, 10);
+        assert_eq!(fs::read(&file).await?, CONTENT);
 
-        assert_eq!(&token, &response.token);
-        assert_eq!(
-            &options,
-            &response
-                .options
-                .expect("expected options but there are none")
-        );
-        cleanup(&caller, &response.token).await?;
-        be_nice().await;
+        // Without resuming, the file is downloaded again
+        let report = caller
+            .download_file_to(&url, &file, DownloadOptions::new())
+            .await?;
+        assert_eq!(report.resumed_from, 0);
+        assert_eq!(fs::read(&file).await?, CONTENT);
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests.last().unwrap().headers.contains_key("range"));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn invalid_token() -> Result<()> {
-        let caller = ApiCaller::new();
-        let request = WaifuGetRequest::new("hithere");
-        let response = caller.file_info(request).await;
-        assert!(response.is_err());
-        be_nice().await;
+    async fn downloads_restart_when_they_cant_resume() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("some-file");
+
+        // The server ignores the range and sends the whole file
+        let server = range_server(CONTENT, false).await;
+        let url = format!("{}/f/some-file", server.uri());
+        fs::write(&file, &CONTENT[..9]).await?;
+        let report = mock_caller(&server)
+            .download_file_to(&url, &file, DownloadOptions::new().resume())
+            .await?;
+        assert_eq!(fs::read(&file).await?, CONTENT);
+        assert_eq!(report.resumed_from, 0);
+        assert!(report.warning.is_some());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        // The partial file is larger than the file on the server
+        let server = range_server(CONTENT, true).await;
+        let url = format!("{}/f/some-file", server.uri());
+        fs::write(&file, [CONTENT, b" and more"].concat()).await?;
+        let report = mock_caller(&server)
+            .download_file_to(&url, &file, DownloadOptions::new().resume())
+            .await?;
+        assert_eq!(fs::read(&file).await?, CONTENT);
+        assert_eq!(report.resumed_from, 0);
+        assert!(report.warning.is_some());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn patch_entry() -> Result<()> {
-        let tmp = TempFileCreator::new("some_entry_to_be_patched.bin").await?;
-        let caller = ApiCaller::new();
-
-        let init = WaifuUploadRequest::new().file(&tmp.file).expires("1h");
-        let response = caller.upload_file(init).await?;
-        let token = response.token;
-        let original_exp = response.retention_period;
-        let original_opts = response.options.unwrap();
-        be_nice().await;
+    async fn resumed_downloads_reject_the_wrong_range() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header(
+                        "Content-Range",
+                        format!("bytes 4-{}/{}", CONTENT.len() - 1, CONTENT.len()).as_str(),
+                    )
+                    .set_body_bytes(&CONTENT[4..]),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("some-file");
+        let url = format!("{}/f/some-file", server.uri());
+
+        fs::write(&file, &CONTENT[..9]).await?;
+        let err = mock_caller(&server)
+            .download_file_to(&url, &file, DownloadOptions::new().resume())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::UnexpectedResponse { status: 206, .. })
+        ));
+        assert_eq!(fs::read(&file).await?, &CONTENT[..9]);
 
-        // Add a password
-        let mod_request = WaifuModificationRequest::new(&token).password("banana");
-        let response = caller.update_file(mod_request).await?;
-        let options = response.options.unwrap();
-        assert!(options.protected);
-        assert_ne!(options.protected, original_opts.protected);
-        be_nice().await;
+        Ok(())
+    }
 
-        // Add an expiry
-        let mod_request = WaifuModificationRequest::new(&token).custom_expiry("5m");
-        let response = caller.update_file(mod_request).await?;
-        assert_ne!(response.retention_period, original_exp);
-        be_nice().await;
+    #[tokio::test]
+    async fn downloads_are_checked_against_checksums() -> Result<()> {
+        let server = range_server(CONTENT, true).await;
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("some-file");
+        let url = format!("{}/f/some-file", server.uri());
+        let caller = mock_caller(&server);
+
+        let content = caller
+            .download_file_verified(&url, None, Checksum::sha256(CONTENT))
+            .await?;
+        assert_eq!(content, CONTENT);
 
-        // Hide the filename
-        let mod_request = WaifuModificationRequest::new(&token).hide_filename(true);
-        let response = caller.update_file(mod_request).await?;
-        let options = response.options.unwrap();
-        assert!(options.hide_filename);
-        assert_ne!(options.hide_filename, original_opts.hide_filename);
-        be_nice().await;
+        let wrong = Checksum::sha1(b"something else");
+        let err = caller
+            .download_file_verified(&url, None, wrong)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::ChecksumMismatch {
+                expected: wrong,
+                actual: Checksum::sha1(CONTENT),
+            })
+        );
 
-        // Update password
-        let mod_request = WaifuModificationRequest::new(&token)
-            .password("apple")
-            .previous_password("banana");
-        let response = caller.update_file(mod_request).await?;
-        let options = response.options.unwrap();
-        assert!(options.protected);
-        be_nice().await;
+        // The part already on disk counts towards the checksum of a resumed download
+        fs::write(&file, &CONTENT[..9]).await?;
+        let options = DownloadOptions::new()
+            .resume()
+            .checksum(Checksum::sha1(CONTENT));
+        caller
+            .download_file_to(&url, &file, options.clone())
+            .await?;
+        assert_eq!(fs::read(&file).await?, CONTENT);
+        caller.download_file_to(&url, &file, options).await?;
+
+        fs::write(&file, b"corrupted").await?;
+        let options = DownloadOptions::new()
+            .resume()
+            .checksum(Checksum::sha1(CONTENT));
+        let err = caller
+            .download_file_to(&url, &file, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ChecksumMismatch { .. })
+        ));
 
-        cleanup(&caller, &token).await?;
-        be_nice().await;
         Ok(())
     }
 
     #[tokio::test]
-    async fn delete_file() -> Result<()> {
-        let tmp = TempFileCreator::new("something_to_delete.bin").await?;
-        let caller = ApiCaller::new();
-        let request = WaifuUploadRequest::new().file(&tmp.file);
-        let response = caller.upload_file(request).await?;
-        let token = response.token;
-        let success = caller.delete_file(&token).await?;
+    async fn parallel_downloads_match_plain_ones() -> Result<()> {
+        let mut content = vec![0; 1_000_003];
+        for (i, byte) in content.iter_mut().enumerate() {
+            *byte = (i * 31 % 251) as u8;
+        }
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("some-file");
+        let tuning = DownloadTuning {
+            chunk_size: 64 * 1024,
+            connections: 4,
+        };
 
-        assert!(success);
-        be_nice().await;
+        for ranges in [true, false] {
+            let server = range_server(&content, ranges).await;
+            let url = format!("{}/f/some-file", server.uri());
+            let caller = mock_caller(&server);
+
+            let plain = caller.download_file(&url, None).await?;
+            let report = caller
+                .download_file_parallel(&url, None, &file, tuning.clone())
+                .await?;
+            assert_eq!(report.size, content.len() as u64);
+            assert_eq!(report.warning.is_some(), !ranges);
+            assert_eq!(hash_item(&fs::read(&file).await?), hash_item(&plain));
+
+            let requests = server.received_requests().await.unwrap().len();
+            assert_eq!(requests, if ranges { 1 + 16 } else { 1 + 1 });
+        }
+
+        // Files smaller than a chunk, and empty ones
+        for content in [&b"tiny"[..], b""] {
+            let server = range_server(content, true).await;
+            let url = format!("{}/f/some-file", server.uri());
+            let report = mock_caller(&server)
+                .download_file_parallel(&url, None, &file, tuning.clone())
+                .await?;
+            assert_eq!(report.size, content.len() as u64);
+            assert_eq!(fs::read(&file).await?, content);
+        }
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn download_file() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
-        let original = reqwest::get(url).await?.bytes().await?.to_vec();
-        let og_hash = hash_item(&original);
-
-        let caller = ApiCaller::new();
-        let request = WaifuUploadRequest::new().url(url).expires("1h");
-        let response = caller.upload_file(request).await?;
-        let url = response.url;
+    async fn downloads_keep_their_metadata() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .and(header("x-password", "secret"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(&b"%PDF-1.7"[..], "application/pdf")
+                    .insert_header(
+                        "Content-Disposition",
+                        "attachment; filename=\"report.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf",
+                    ),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/f/bare-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(&b"bare"[..]))
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let file = caller
+            .download_file_with_metadata(
+                &format!("{}/f/some-file", server.uri()),
+                Some("secret".to_string()),
+            )
+            .await?;
+        assert_eq!(file.bytes, b"%PDF-1.7");
+        assert_eq!(file.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(file.filename.as_deref(), Some("r\u{e9}sum\u{e9}.pdf"));
+        assert_eq!(file.length, Some(8));
 
-        let response = caller.download_file(&url, None).await?;
-        let result = hash_item(&response);
+        let file = caller
+            .download_file_with_metadata(&format!("{}/f/bare-file", server.uri()), None)
+            .await?;
+        assert_eq!(file.bytes, b"bare");
+        assert_eq!(file.content_type, None);
+        assert_eq!(file.filename, None);
 
-        assert_eq!(og_hash, result);
-        be_nice().await;
         Ok(())
     }
 
     #[tokio::test]
-    async fn download_file_with_password() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
-        let original = reqwest::get(url).await?.bytes().await?.to_vec();
-        let og_hash = hash_item(&original);
+    async fn downloads_over_the_limit_are_refused() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/some-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![1; 100]))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/download/some-album"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![1; 100]))
+            .mount(&server)
+            .await;
+
+        let caller = ApiCaller::builder()
+            .target(&Target::new(format!("{}/rest", server.uri())))
+            .max_download_bytes(50)
+            .build();
+        let url = format!("{}/f/some-file", server.uri());
+        let too_large = Some(Error::ResponseTooLarge {
+            limit: 50,
+            received: 100,
+        });
+
+        let err = caller.download_file(&url, None).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>().cloned(), too_large);
+        let err = caller.download_album("some-album", None).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<Error>().cloned(), too_large);
+
+        // The limit of a single download takes the place of the caller's
+        let options = DownloadOptions::new().max_bytes(100);
+        assert_eq!(caller.download_file_with(&url, options).await?.len(), 100);
+        let options = DownloadOptions::new().max_bytes(99);
+        let err = caller.download_file_with(&url, options).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ResponseTooLarge { limit: 99, .. })
+        ));
 
-        let caller = ApiCaller::new();
-        let request = WaifuUploadRequest::new()
-            .url(url)
-            .expires("1h")
-            .password("banana");
-        let response = caller
-            .upload_file(request)
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streamed_downloads_stop_at_the_limit() -> Result<()> {
+        // Sends a body of unknown length in chunks of 1000 bytes, until the client goes away
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = [0; 1024];
+            tokio::io::AsyncReadExt::read(&mut stream, &mut request).await?;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n")
+                .await?;
+            let chunk = format!("3e8\r\n{}\r\n", "x".repeat(1000));
+            while stream.write_all(chunk.as_bytes()).await.is_ok() {}
+            anyhow::Ok(())
+        });
+
+        let caller = ApiCaller::builder().max_download_bytes(2500).build();
+        let err = caller
+            .download_file(&format!("http://{addr}/f/some-file"), None)
             .await
-            .context("uploading protected file to download")?;
-        let url = response.url;
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::ResponseTooLarge {
+                limit: 2500,
+                received: 3000
+            })
+        );
 
-        let response = caller
-            .download_file(&url, Some("banana".to_string()))
-            .await?;
-        let result = hash_item(&response);
+        server.abort();
 
-        assert_eq!(og_hash, result);
-        be_nice().await;
         Ok(())
     }
 
-    #[tokio::test]
-    async fn create_and_delete_bucket() -> Result<()> {
-        let caller = ApiCaller::new();
-        let response = caller.create_bucket().await?;
-        assert!(!response.token.is_empty());
+    fn restrictions_json() -> serde_json::Value {
+        serde_json::json!([
+            { "type": "MAX_FILE_SIZE", "value": 1024 },
+            { "type": "BANNED_MIME_TYPE", "value": "application/x-msdownload,application/x-dosexec" }
+        ])
+    }
 
-        let token = response.token;
-        assert!(response.files.is_empty());
+    #[test]
+    fn restrictions_are_read_by_type() -> Result<()> {
+        let restrictions: WaifuRestrictions = serde_json::from_value(restrictions_json())?;
+        assert_eq!(restrictions.max_file_size(), Some(1024));
+        assert_eq!(
+            restrictions.banned_mime_types(),
+            ["application/x-msdownload", "application/x-dosexec"]
+        );
 
-        let resp = caller.delete_bucket(&token).await?;
-        assert!(resp);
+        let empty: WaifuRestrictions = serde_json::from_value(serde_json::json!([]))?;
+        assert_eq!(empty.max_file_size(), None);
+        assert!(empty.banned_mime_types().is_empty());
 
-        be_nice().await;
         Ok(())
     }
 
-    #[tokio::test]
-    async fn create_and_get_bucket() -> Result<()> {
-        let caller = ApiCaller::new();
-        let bucket = caller.create_bucket().await?;
-        let token = bucket.token;
+    #[test]
+    fn validate_against_restrictions() -> Result<()> {
+        let restrictions: WaifuRestrictions = serde_json::from_value(restrictions_json())?;
 
-        let info = caller.get_bucket(&token).await?;
-        assert!(info.token == token);
-        assert!(info.files.is_empty());
+        let small = WaifuUploadRequest::new().bytes(vec![0; 1024], "small.png");
+        small.validate_against(&restrictions)?;
 
-        caller.delete_bucket(&token).await?;
+        let dir = tempfile::tempdir()?;
+        let large = dir.path().join("large.png");
+        std::fs::write(&large, vec![0; 1025])?;
+        let err = WaifuUploadRequest::new()
+            .file(&large)
+            .validate_against(&restrictions)
+            .expect_err("too large");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::TooLarge {
+                size: 1025,
+                max: 1024
+            })
+        );
 
-        be_nice().await;
+        let err = WaifuUploadRequest::new()
+            .bytes(vec![0; 16], "setup.bat")
+            .validate_against(&restrictions)
+            .expect_err("banned");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::BannedMimeType {
+                mime: "application/x-msdownload".to_string()
+            })
+        );
+
+        // Only the service knows what is behind a URL
+        WaifuUploadRequest::new()
+            .url("https://example.com/setup.bat")
+            .validate_against(&restrictions)?;
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn create_bucket_and_upload_file() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
-        let caller = ApiCaller::new();
+    async fn checked_uploads_fail_fast_and_reuse_restrictions() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/resources/restrictions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(restrictions_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("small")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let err = caller
+            .upload_file_checked(WaifuUploadRequest::new().bytes(vec![0; 4096], "big.png"))
+            .await
+            .expect_err("too large");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::TooLarge { .. })
+        ));
+
+        let entry = caller
+            .upload_file_checked(WaifuUploadRequest::new().bytes(vec![0; 16], "small.png"))
+            .await?;
+        assert_eq!(entry.token, "small");
 
-        let bucket = caller.create_bucket().await?;
-        let token = bucket.token;
-        let request = WaifuUploadRequest::new()
-            .bucket(&token)
-            .url(url)
-            .expires("1h");
+        Ok(())
+    }
 
-        caller.upload_file(request).await?;
+    fn retrying_caller(server: &MockServer, retry_uploads: bool) -> ApiCaller {
+        ApiCaller::builder()
+            .target(&Target::new(format!("{}/rest", server.uri())))
+            .retry(RetryPolicy {
+                max_attempts: 3,
+                base_delay: std::time::Duration::from_millis(1),
+                jitter: false,
+                retry_uploads,
+                ..Default::default()
+            })
+            .build()
+    }
 
-        let info = caller.get_bucket(&token).await?;
-        assert!(info.files.len() == 1);
+    #[tokio::test]
+    async fn transient_failures_are_retried() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/some-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("some-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let entry = retrying_caller(&server, false)
+            .file_info(WaifuGetRequest::new("some-file"))
+            .await?;
+        assert_eq!(entry.token, "some-file");
 
-        caller.delete_bucket(&token).await?;
+        Ok(())
+    }
 
-        be_nice().await;
+    #[tokio::test]
+    async fn client_errors_are_not_retried() -> Result<()> {
+        let server = MockServer::start().await;
+        let not_found = serde_json::json!({
+            "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+        });
+        Mock::given(method("GET"))
+            .and(path("/rest/album/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(not_found))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = retrying_caller(&server, false)
+            .get_album("missing")
+            .await
+            .expect_err("not found");
+        assert_eq!(
+            err.downcast_ref::<WaifuError>().map(|e| e.status),
+            Some(404)
+        );
+        assert!(err.downcast_ref::<Error>().is_none());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn delete_bucket_incorrect_token() -> Result<()> {
-        let caller = ApiCaller::new();
-        let response = caller.delete_bucket("garbage").await;
-        assert!(response.is_err());
-
-        let inner = response.unwrap_err();
-        let waifu_err = inner.downcast::<WaifuError>()?;
-        assert_eq!(waifu_err.status, 400);
+    async fn exhausted_retries_report_attempts() -> Result<()> {
+        let server = MockServer::start().await;
+        let unavailable = serde_json::json!({
+            "name": "SERVICE_UNAVAILABLE", "message": "Down for maintenance", "status": 503
+        });
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(503).set_body_json(unavailable))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let err = retrying_caller(&server, false)
+            .get_bucket("some-bucket")
+            .await
+            .expect_err("unavailable");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::GaveUp { attempts: 3 })
+        );
+        assert!(err.chain().any(|e| {
+            e.downcast_ref::<Error>()
+                == Some(&Error::ServiceUnavailable {
+                    status: 503,
+                    retry_after: None,
+                })
+        }));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn create_album() -> Result<()> {
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket_response = dropper.create_bucket().await?;
-        assert!(!bucket_response.token.is_empty());
-
-        let bkt_tkn = bucket_response.token;
-        let album_response = dropper.create_album(&bkt_tkn, "bigknob").await?;
-        assert!(album_response.files.is_empty());
-        let _ = dropper.destroy().await;
+    async fn connection_failures_are_retried() -> Result<()> {
+        // Nothing listens on a port that was just bound and released
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?
+            .local_addr()?
+            .port();
+        let caller = ApiCaller::builder()
+            .retry(RetryPolicy {
+                base_delay: std::time::Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build();
+
+        let err = caller
+            .download_file(&format!("http://127.0.0.1:{port}/f/some-file"), None)
+            .await
+            .expect_err("refused");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::GaveUp { attempts: 3 })
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn associate_file_with_album() -> Result<()> {
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+    async fn uploads_are_only_retried_when_enabled() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1 + 3)
+            .mount(&server)
+            .await;
+
+        let request = || WaifuUploadRequest::new().bytes(b"data".to_vec(), "data.txt");
+        let err = retrying_caller(&server, false)
+            .upload_file(request())
+            .await
+            .expect_err("bad gateway");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ServiceUnavailable { status: 502, .. })
+        ));
+
+        let err = retrying_caller(&server, true)
+            .upload_file(request())
+            .await
+            .expect_err("bad gateway");
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::GaveUp { attempts: 3 })
+        );
 
-        let bucket = dropper.create_bucket().await?;
-        let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
+        Ok(())
+    }
 
-        let file = dropper.upload_file(request).await?;
-        let album = dropper.create_album(&bucket.token, "kdkfjdk").await?;
+    #[test]
+    fn body_snippet_is_truncated() {
+        let body = "a".repeat(BODY_SNIPPET_LEN * 2);
+        let snippet = body_snippet(body.as_bytes());
+        assert_eq!(snippet.len(), BODY_SNIPPET_LEN + 3);
+        assert!(snippet.ends_with("..."));
+    }
 
-        let success = match caller
-            .associate_with_album(&album.token, &[&file.token])
+    #[tokio::test]
+    async fn unexpected_bodies_are_kept_whole() -> Result<()> {
+        let server = MockServer::start().await;
+        let surprise = serde_json::json!({ "entry": { "token": "file" }, "version": 2 });
+        Mock::given(method("GET"))
+            .and(path("/rest/file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&surprise))
+            .mount(&server)
+            .await;
+        let page = format!("<html>{}</html>", "teapot ".repeat(BODY_SNIPPET_LEN));
+        Mock::given(method("DELETE"))
+            .and(path("/rest/file"))
+            .respond_with(ResponseTemplate::new(418).set_body_string(&page))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let err = caller
+            .file_info(WaifuGetRequest::new("file"))
             .await
-        {
-            Ok(success) => success,
-            Err(e) => {
-                dropper.destroy().await?;
-                return Err(e);
-            }
-        };
-
-        assert_eq!(success.bucket_token, bucket.token);
-        assert_eq!(success.files.len(), 1);
-
-        let f = &success.files[0];
-        assert_eq!(f.token, file.token);
-        assert_eq!(f.bucket, Some(bucket.token));
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::UnexpectedResponse {
+                status: 200,
+                body: surprise.to_string()
+            })
+        );
+        assert!(err.chain().any(|e| e.is::<serde_json::Error>()));
 
-        let _ = dropper.destroy().await;
+        let err = caller.delete_file("file").await.unwrap_err();
+        match err.downcast_ref::<Error>() {
+            Some(Error::UnexpectedResponse { status, body }) => {
+                assert_eq!(*status, 418);
+                assert_eq!(body, &page);
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(err.to_string().len() < page.len(), "{err}");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn disassociate_from_album() -> Result<()> {
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+    async fn passwords_are_sent_as_latin1() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/f/protected.txt"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(CONTENT))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/f/protected.txt"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let url = format!("{}/f/protected.txt", server.uri());
 
-        let bucket = dropper.create_bucket().await?;
-        let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
+        caller
+            .download_file(&url, Some("Passwört".to_string()))
+            .await?;
+        caller.file_size(&url, Some("Passwört".to_string())).await?;
+        let received = server.received_requests().await.unwrap();
+        assert_eq!(received.len(), 2);
+        for request in &received {
+            assert_eq!(request.headers["x-password"].as_bytes(), b"Passw\xf6rt");
+        }
 
-        let file = dropper.upload_file(request).await?;
-        let album = dropper.create_album(&bucket.token, "kdkfjdk").await?;
+        for password in ["🔑", "Пароль"] {
+            let err = caller
+                .download_file(&url, Some(password.to_string()))
+                .await
+                .unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&Error::UnencodablePassword));
+            assert!(!err.to_string().contains(password), "{err}");
+        }
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
 
-        let assoc = match caller
-            .associate_with_album(&album.token, &[&file.token])
-            .await
-        {
-            Ok(success) => success,
-            Err(e) => {
-                dropper.destroy().await?;
-                return Err(e);
-            }
-        };
+        Ok(())
+    }
 
-        assert_eq!(assoc.files.len(), 1);
+    /// Passwords that need escaping in a form or a multipart body
+    pub(crate) const TRICKY_PASSWORDS: [&str; 6] = [
+        "a&password=b",
+        "1+1 = 2",
+        "100% sure?",
+        "line\r\nbreak",
+        "ünïcødé 🔑",
+        "\"quoted\"; name=\"file\"",
+    ];
+
+    /// Password of an upload as the service would read it from the form or multipart body
+    pub(crate) fn sent_password(request: &wiremock::Request) -> Option<String> {
+        let content_type = request.headers["content-type"].to_str().unwrap();
+        if content_type == "application/x-www-form-urlencoded" {
+            return url::form_urlencoded::parse(&request.body)
+                .find(|(name, _)| name == "password")
+                .map(|(_, value)| value.into_owned());
+        }
 
-        let disassoc = match caller
-            .disassociate_from_album(&album.token, &[&file.token])
-            .await
-        {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8_lossy(&request.body);
+        body.split(&format!("--{boundary}"))
+            .filter_map(|part| part.split_once("\r\n\r\n"))
+            .find(|(headers, _)| headers.contains("name=\"password\""))
+            .map(|(_, value)| value.strip_suffix("\r\n").unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn tricky_passwords_survive_every_upload_source() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("uploaded")))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let tmp = TempFile::with_contents("tricky_passwords.txt", CONTENT).await?;
+
+        for password in TRICKY_PASSWORDS {
+            for request in [
+                WaifuUploadRequest::new().file(tmp.path()),
+                WaifuUploadRequest::new().bytes(CONTENT.to_vec(), "file.txt"),
+                WaifuUploadRequest::new().url("https://example.com/image.png"),
+            ] {
+                caller.upload_file(request.password(password)).await?;
             }
-        };
+        }
 
-        assert!(disassoc.files.is_empty());
+        let received = server.received_requests().await.unwrap();
+        assert_eq!(received.len(), TRICKY_PASSWORDS.len() * 3);
+        for (sent, password) in received.chunks(3).zip(TRICKY_PASSWORDS) {
+            for request in sent {
+                assert_eq!(sent_password(request).as_deref(), Some(password));
+            }
+        }
 
-        let _ = dropper.destroy().await;
         Ok(())
     }
 
     #[tokio::test]
-    async fn delete_album_keep_files() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
-
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket = dropper.create_bucket().await?;
-        let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
+    async fn existence_checks_tell_missing_from_failing() -> Result<()> {
+        let server = MockServer::start().await;
+        let answer = |status: u16, name: &str, message: &str| {
+            ResponseTemplate::new(status).set_body_json(serde_json::json!({
+                "name": name, "message": message, "status": status
+            }))
+        };
+        Mock::given(method("GET"))
+            .and(path("/rest/here"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("here")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/gone"))
+            .respond_with(answer(404, "NOT_FOUND", "File not found"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/never"))
+            .respond_with(answer(400, "BAD_REQUEST", "Unknown token"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/limited"))
+            .respond_with(answer(429, "TOO_MANY_REQUESTS", "Slow down"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/malformed"))
+            .respond_with(answer(400, "BAD_REQUEST", "formatted must be a boolean"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "bucket" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("bucket")))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "deleted" })))
+            .respond_with(answer(
+                400,
+                "BAD_REQUEST",
+                "Unable to find bucket with token",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/missing"))
+            .respond_with(answer(400, "BAD_REQUEST", "Album not found"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/album/broken"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("oops"))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        assert!(caller.file_exists("here").await?);
+        assert!(!caller.file_exists("gone").await?);
+        assert!(!caller.file_exists("never").await?);
+        assert!(!caller.file_exists("").await?);
+        assert!(caller.file_exists("limited").await.is_err());
+        assert!(caller.file_exists("malformed").await.is_err());
+
+        assert!(caller.bucket_exists("bucket").await?);
+        assert!(!caller.bucket_exists("deleted").await?);
+
+        assert!(!caller.album_exists("missing").await?);
+        assert!(caller.album_exists("broken").await.is_err());
 
-        dropper.upload_file(request).await?;
-        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+        Ok(())
+    }
 
-        let delete = match caller.delete_album(&album.token, false).await {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
-            }
-        };
+    #[tokio::test]
+    async fn saved_buckets_are_reused_until_they_are_gone() -> Result<()> {
+        let server = MockServer::start().await;
+        for token in ["first", "second"] {
+            Mock::given(method("GET"))
+                .and(path("/rest/bucket/create"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json(token)))
+                .up_to_n_times(1)
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "first" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("first")))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "first" })))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Unable to find bucket", "status": 400
+            })))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let store = TempFile::with_contents("saved_bucket.json", "").await?;
+        fs::remove_file(store.path()).await?;
+
+        let created = caller.get_or_create_bucket(Some(store.path())).await?;
+        assert_eq!(created.token, "first");
+        assert_eq!(
+            fs::read_to_string(store.path()).await?,
+            r#"{"token":"first"}"#
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(store.path()).await?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
 
-        assert!(delete.success);
+        let reused = caller.get_or_create_bucket(Some(store.path())).await?;
+        assert_eq!(reused.token, "first");
 
-        let bucket_info = caller.get_bucket(&bucket.token).await?;
-        assert!(!bucket_info.files.is_empty());
+        // The saved bucket was deleted, so another takes its place
+        let replaced = caller.get_or_create_bucket(Some(store.path())).await?;
+        assert_eq!(replaced.token, "second");
+        assert_eq!(
+            fs::read_to_string(store.path()).await?,
+            r#"{"token":"second"}"#
+        );
 
-        let _ = dropper.destroy().await;
+        fs::write(store.path(), "not a token").await?;
+        let err = caller
+            .get_or_create_bucket(Some(store.path()))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("doesn't hold a bucket token"),
+            "{err}"
+        );
+        assert_eq!(fs::read_to_string(store.path()).await?, "not a token");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn delete_album_remove_files() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+    async fn saved_tokens_load_their_bucket() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(body_json(serde_json::json!({ "bucket_token": "saved" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(bucket_json("saved")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+        let store = TempFile::with_contents("loaded_bucket.json", "").await?;
+        fs::remove_file(store.path()).await?;
+
+        let err = caller.load_bucket(store.path()).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::NoSavedBucket {
+                path: store.path().to_path_buf()
+            })
+        );
 
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket = dropper.create_bucket().await?;
-        let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
+        let bucket: WaifuBucketEntry = serde_json::from_value(bucket_json("saved"))?;
+        bucket.save_token(store.path()).await?;
+        assert_eq!(caller.load_bucket(store.path()).await?, bucket);
+
+        for corrupt in ["saved", r#"{"token":""}"#, r#"{"bucket":"saved"}"#] {
+            fs::write(store.path(), corrupt).await?;
+            let err = caller.load_bucket(store.path()).await.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(Error::CorruptSavedBucket { .. })),
+                "{corrupt}: {err}"
+            );
+        }
 
-        dropper.upload_file(request).await?;
-        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+        Ok(())
+    }
 
-        let delete = match caller.delete_album(&album.token, true).await {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
-            }
+    #[tokio::test]
+    async fn manifests_record_files_and_find_the_missing() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut hidden = file_json("hidden");
+        hidden["url"] = serde_json::json!("http://127.0.0.1:8081/f/1712346");
+        hidden["retentionPeriod"] = serde_json::Value::Null;
+        hidden["options"]["hideFilename"] = serde_json::json!(true);
+        hidden["options"]["protected"] = serde_json::json!(true);
+        let files = [file_json("kept"), hidden, file_json("deleted")];
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "bucket", "files": files, "albums": []
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/kept"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("kept")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/deleted"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "name": "NOT_FOUND", "message": "File not found", "status": 404
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/hidden"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("oops"))
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
+
+        let before = std::time::SystemTime::now();
+        let manifest = caller.export_bucket_manifest("bucket").await?;
+        assert_eq!(manifest.bucket, "bucket");
+        let tokens: Vec<_> = manifest.files.iter().map(|f| f.token.as_str()).collect();
+        assert_eq!(tokens, ["kept", "hidden", "deleted"]);
+
+        let kept = &manifest.files[0];
+        assert_eq!(kept.url, "http://127.0.0.1:8081/f/1712345/kept.txt");
+        assert_eq!(kept.filename.as_deref(), Some("kept.txt"));
+        let since_epoch = |at: std::time::SystemTime| {
+            at.duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
         };
+        let expires_at = kept.expires_at.unwrap();
+        assert!(expires_at >= since_epoch(before) + 3_600_000);
+        assert!(expires_at <= manifest.exported_at + 3_600_000);
 
-        assert!(delete.success);
+        let hidden = &manifest.files[1];
+        assert_eq!(hidden.filename, None);
+        assert_eq!(hidden.expires_at, None);
+        assert!(hidden.options.as_ref().is_some_and(|o| o.protected));
 
-        let bucket_info = caller.get_bucket(&bucket.token).await?;
-        assert!(bucket_info.files.is_empty());
+        let saved = serde_json::to_string(&manifest)?;
+        let manifest: BucketManifest = serde_json::from_str(&saved)?;
 
-        let _ = dropper.destroy().await;
+        let check = caller.verify_manifest(&manifest).await;
+        assert!(!check.is_intact());
+        assert_eq!(check.present, ["kept"]);
+        assert_eq!(check.missing, [manifest.files[2].clone()]);
+        assert_eq!(check.failed.len(), 1);
+        assert_eq!(check.failed[0].0, "hidden");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn get_album() -> Result<()> {
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket = dropper.create_bucket().await?;
-        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
-        let retrieve = match caller.get_album(&album.token).await {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
-            }
-        };
+    async fn raw_requests_reach_the_configured_base() -> Result<()> {
+        let server = MockServer::start().await;
+        let bucket = bucket_json("raw-bkt");
+        Mock::given(method("POST"))
+            .and(path("/rest/bucket/get"))
+            .and(header("content-type", "application/json"))
+            .and(body_json(serde_json::json!({ "bucket_token": "raw-bkt" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&bucket))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/rest/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("not here"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let caller = mock_caller(&server);
 
-        assert_eq!(retrieve.token, album.token);
-        assert_eq!(retrieve.bucket_token, bucket.token);
-        assert!(retrieve.public_token.is_none());
+        let response = caller
+            .execute(
+                reqwest::Method::POST,
+                "/bucket/get",
+                Some(serde_json::json!({ "bucket_token": "raw-bkt" })),
+            )
+            .await?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.json::<serde_json::Value>().await?, bucket);
 
-        let _ = dropper.destroy().await;
+        // Error statuses are left to the caller
+        let response = caller
+            .execute(reqwest::Method::GET, "missing", None)
+            .await?;
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.text().await?, "not here");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn share_album() -> Result<()> {
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket = dropper.create_bucket().await?;
-        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
+    async fn replace_contents_uploads_then_deletes() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut old = file_json("old-file");
+        old["bucket"] = "some-bkt".into();
+        old["options"]["hideFilename"] = true.into();
+        old["album"] = serde_json::json!({
+            "token": "some-album",
+            "publicToken": null,
+            "name": "album",
+            "bucket": "some-bkt",
+            "dateCreated": 0
+        });
+
+        Mock::given(method("GET"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&old))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/some-bkt"))
+            .and(query_param("hide_filename", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("new-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/some-album/associate"))
+            .and(body_json(serde_json::json!({ "fileTokens": ["new-file"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "some-album",
+                "bucketToken": "some-bkt",
+                "publicToken": null,
+                "name": "album",
+                "files": [file_json("new-file")]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The mock server has no replace endpoint, so answers it with a 404
+        let caller = mock_caller(&server);
+        let request = WaifuUploadRequest::new().bytes(b"new".to_vec(), "new.txt");
+        let replaced = caller.replace_contents("old-file", request).await?;
+
+        assert!(replaced.token_changed);
+        assert_eq!(replaced.new_entry.token, "new-file");
 
-        let share = match caller.share_album(&album.token).await {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
-            }
-        };
+        Ok(())
+    }
 
-        assert!(share.success);
-        assert!(share
-            .description
-            .starts_with("http://localhost:8081/album/"));
+    #[tokio::test]
+    async fn replace_contents_uses_the_replace_endpoint() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("old-file")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/old-file/replace"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("old-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let request = WaifuUploadRequest::new().bytes(b"new".to_vec(), "new.txt");
+        let replaced = mock_caller(&server)
+            .replace_contents("old-file", request)
+            .await?;
 
-        let _ = dropper.destroy().await;
+        assert!(!replaced.token_changed);
+        assert_eq!(replaced.new_entry.token, "old-file");
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn download_album() -> Result<()> {
-        let url = "https://waifuvault.moe/assets/custom/images/08.png";
+    async fn replace_contents_falls_back_without_a_replace_endpoint() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("old-file")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/old-file/replace"))
+            .respond_with(ResponseTemplate::new(405))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("new-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(true))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = WaifuUploadRequest::new().bytes(b"new".to_vec(), "new.txt");
+        let replaced = mock_caller(&server)
+            .replace_contents("old-file", request)
+            .await?;
 
-        let caller = ApiCaller::new();
-        let mut dropper = Dropper::new(&caller);
-        let bucket = dropper.create_bucket().await?;
-        let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
+        assert!(replaced.token_changed);
+        assert_eq!(replaced.new_entry.token, "new-file");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replace_contents_keeps_options_the_request_sets() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut old = file_json("old-file");
+        old["options"]["hideFilename"] = true.into();
+        old["options"]["oneTimeDownload"] = true.into();
+        Mock::given(method("GET"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&old))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/rest/old-file/replace"))
+            .and(query_param("hide_filename", "false"))
+            .and(query_param("oneTimeDownload", "false"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("old-file")))
+            .expect(1)
+            .mount(&server)
+            .await;
 
-        let file = dropper.upload_file(request).await?;
         let request = WaifuUploadRequest::new()
-            .bucket(&bucket.token)
-            .url(url)
-            .expires("1h");
-        let file2 = dropper.upload_file(request).await?;
-        let album = dropper.create_album(&bucket.token, "test_bucket").await?;
-        caller
-            .associate_with_album(&album.token, &[&file.token, &file2.token])
+            .bytes(b"new".to_vec(), "new.txt")
+            .hide_filename(false)
+            .one_time_download(false);
+        mock_caller(&server)
+            .replace_contents("old-file", request)
             .await?;
 
-        let files = match caller.download_album(&album.token, None).await {
-            Ok(success) => success,
-            Err(e) => {
-                let _ = dropper.destroy().await;
-                return Err(e);
-            }
-        };
-
-        assert!(!files.is_empty());
+        Ok(())
+    }
 
-        let _ = dropper.destroy().await;
+    #[tokio::test]
+    async fn replace_contents_requires_password_for_protected_files() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut old = file_json("old-file");
+        old["options"]["protected"] = true.into();
+
+        Mock::given(method("GET"))
+            .and(path("/rest/old-file"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&old))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_json("new-file")))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let caller = mock_caller(&server);
+        let request = WaifuUploadRequest::new().bytes(b"new".to_vec(), "new.txt");
+        let err = caller
+            .replace_contents("old-file", request)
+            .await
+            .expect_err("protected files need a password");
+        assert!(err.to_string().contains("password protected"));
 
         Ok(())
     }
 
+    fn mock_caller(server: &MockServer) -> ApiCaller {
+        ApiCaller::new().on(&Target::new(format!("{}/rest", server.uri())))
+    }
+
+    fn file_json(token: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": 1,
+            "token": token,
+            "url": format!("http://127.0.0.1:8081/f/1712345/{token}.txt"),
+            "bucket": null,
+            "album": null,
+            "views": 0,
+            "retentionPeriod": 3600000,
+            "options": { "hideFilename": false, "oneTimeDownload": false, "protected": false }
+        })
+    }
+
+    fn bucket_json(token: &str) -> serde_json::Value {
+        serde_json::json!({ "token": token, "files": [], "albums": [] })
+    }
+
     fn hash_item(content: &Vec<u8>) -> String {
         let mut hasher = Sha1::new();
         hasher.update(content);
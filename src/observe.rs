@@ -0,0 +1,227 @@
+//! Hooks for watching the requests a caller sends, such as to collect metrics
+//!
+//! An [`ApiObserver`] set with `ApiCallerBuilder::observer` is told when each request to the
+//! service starts and how it ended. Every request is reported exactly once, whether it
+//! succeeds, fails with an error status, or never gets a response at all. Observers can't
+//! be set on wasm32.
+use reqwest::StatusCode;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Endpoint of the service a request was sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Creating a bucket
+    CreateBucket,
+    /// Deleting a bucket
+    DeleteBucket,
+    /// Getting a bucket and its files
+    GetBucket,
+    /// Uploading a file
+    Upload,
+    /// Getting information on a file
+    FileInfo,
+    /// Checking the size of a file without downloading it
+    FileSize,
+    /// Changing the options of a file
+    UpdateFile,
+    /// Replacing the contents of a file
+    ReplaceFile,
+    /// Deleting a file
+    DeleteFile,
+    /// Downloading a file
+    Download,
+    /// Getting the upload restrictions of the service
+    Restrictions,
+    /// Creating an album
+    CreateAlbum,
+    /// Adding files to an album
+    AssociateFiles,
+    /// Removing files from an album
+    DisassociateFiles,
+    /// Deleting an album
+    DeleteAlbum,
+    /// Getting an album and its files
+    GetAlbum,
+    /// Sharing an album publicly
+    ShareAlbum,
+    /// Revoking public access to an album
+    RevokeAlbum,
+    /// Downloading an album as a zip archive
+    DownloadAlbum,
+    /// Any request sent with [`crate::ApiCaller::execute`]
+    Raw,
+}
+
+/// Receives an event when a request starts and another when it ends
+///
+/// Helpers that make several requests, such as [`crate::ApiCaller::download_bucket`],
+/// report each of them. Retries of a request are part of it, so a request retried three
+/// times is still reported once. Calls that fail before anything is sent, such as an upload
+/// without content, aren't reported.
+///
+/// Both methods are called on the task making the request, so they should return quickly.
+///
+/// The trait is meant to be implemented by applications, so it stays open. Every method has
+/// a default, and any added later will too, so implementations keep compiling.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{
+///     sync::{atomic::{AtomicU64, Ordering}, Arc},
+///     time::Duration,
+/// };
+/// use reqwest::StatusCode;
+/// use waifuvault::{ApiCaller, ApiObserver, Operation};
+///
+/// #[derive(Default)]
+/// struct Metrics {
+///     failures: AtomicU64,
+///     bytes: AtomicU64,
+/// }
+///
+/// impl ApiObserver for Metrics {
+///     fn on_response(
+///         &self,
+///         _: Operation,
+///         status: Option<StatusCode>,
+///         _: Duration,
+///         bytes: u64,
+///     ) {
+///         if !status.is_some_and(|s| s.is_success()) {
+///             self.failures.fetch_add(1, Ordering::Relaxed);
+///         }
+///         self.bytes.fetch_add(bytes, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let metrics = Arc::new(Metrics::default());
+/// let caller = ApiCaller::builder().observer(metrics.clone()).build();
+/// ```
+pub trait ApiObserver: Send + Sync {
+    /// A request is about to be sent
+    fn on_request(&self, op: Operation) {
+        let _ = op;
+    }
+
+    /// A request has ended
+    ///
+    /// `status` is the status of the final response, or `None` if there wasn't one because
+    /// the request failed to send, timed out or was cancelled. `elapsed` runs from the start
+    /// of the request until its response body was read or dropped. `bytes` counts the content
+    /// uploaded, if any, and the response body read.
+    fn on_response(
+        &self,
+        op: Operation,
+        status: Option<StatusCode>,
+        elapsed: Duration,
+        bytes: u64,
+    ) {
+        let _ = (op, status, elapsed, bytes);
+    }
+}
+
+/// Observer set on a caller, wrapped so the caller can still be printed
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub(crate) struct Observer(pub(crate) Arc<dyn ApiObserver>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// A request being watched, reported to the observer when dropped
+///
+/// Attached to the response of the request so reading the body counts towards it.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Observation {
+    observer: Arc<dyn ApiObserver>,
+    op: Operation,
+    started: std::time::Instant,
+    status: Option<StatusCode>,
+    bytes: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Observation {
+    /// Reports the start of a request
+    pub(crate) fn start(observer: &Observer, op: Operation) -> Self {
+        observer.0.on_request(op);
+        Self {
+            observer: observer.0.clone(),
+            op,
+            started: std::time::Instant::now(),
+            status: None,
+            bytes: 0,
+        }
+    }
+
+    /// Attaches the observation to the response it got
+    pub(crate) fn attach(mut self, mut response: reqwest::Response) -> reqwest::Response {
+        self.status = Some(response.status());
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Observation {
+    fn drop(&mut self) {
+        self.observer
+            .on_response(self.op, self.status, self.started.elapsed(), self.bytes);
+    }
+}
+
+/// Counts content uploaded with a request towards its observation
+///
+/// `length` is only asked for when the request is being observed.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn uploaded(
+    mut response: reqwest::Response,
+    length: impl FnOnce() -> Option<u64>,
+) -> reqwest::Response {
+    if let Some(observation) = response.extensions_mut().get_mut::<Observation>() {
+        observation.bytes += length().unwrap_or_default();
+    }
+
+    response
+}
+
+/// Reads the whole body of a response, counting it towards its observation
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn body(mut response: reqwest::Response) -> reqwest::Result<bytes::Bytes> {
+    let observation = response.extensions_mut().remove::<Observation>();
+    let body = response.bytes().await;
+    if let (Some(mut observation), Ok(body)) = (observation, &body) {
+        observation.bytes += body.len() as u64;
+    }
+
+    body
+}
+
+/// Reads the whole body of a response
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn body(response: reqwest::Response) -> reqwest::Result<bytes::Bytes> {
+    response.bytes().await
+}
+
+/// Reads the next chunk of a response body, counting it towards its observation
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn chunk(
+    response: &mut reqwest::Response,
+) -> reqwest::Result<Option<bytes::Bytes>> {
+    let chunk = response.chunk().await?;
+    if let (Some(observation), Some(chunk)) =
+        (response.extensions_mut().get_mut::<Observation>(), &chunk)
+    {
+        observation.bytes += chunk.len() as u64;
+    }
+
+    Ok(chunk)
+}
@@ -0,0 +1,32 @@
+//! Spacing out the requests of a caller, set with `ApiCallerBuilder::min_request_interval`
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Holds each request back until enough time has passed since the previous one
+///
+/// Shared by clones of a caller. Requests waiting at the same time are let through one by
+/// one, in the order they started waiting.
+#[derive(Debug)]
+pub(crate) struct Pacer {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Pacer {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the next request may be sent, and counts it as sent
+    pub(crate) async fn wait(&self) {
+        let mut last = self.last.lock().await;
+        if let Some(wait) = last.and_then(|at| self.interval.checked_sub(at.elapsed())) {
+            crate::trace::debug!(?wait, "waiting before the next request");
+            tokio::time::sleep(wait).await;
+        }
+        *last = Some(Instant::now());
+    }
+}
@@ -0,0 +1,198 @@
+//! Following redirects without leaking passwords
+//!
+//! Callers follow redirects themselves rather than leaving it to reqwest, which would send
+//! the `x-password` header of a download on to whichever host the vault redirects to. When a
+//! redirect leaves the origin (scheme, host and port) of the request, the password and other
+//! credentials are dropped, whatever the [`RedirectPolicy`]. Set with
+//! [`crate::ApiCallerBuilder::redirect`]. Not available on wasm32, where the browser follows
+//! redirects.
+use crate::Error;
+use reqwest::{
+    header::{self, HeaderMap},
+    Method, StatusCode, Url,
+};
+use std::time::Duration;
+
+/// Which redirects a caller follows
+///
+/// A redirect the policy doesn't allow fails the request with
+/// [`Error::RedirectNotFollowed`].
+///
+/// # Example
+///
+/// ```rust
+/// use waifuvault::{ApiCaller, RedirectPolicy};
+///
+/// let caller = ApiCaller::builder()
+///     .redirect(RedirectPolicy::SameHostOnly)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Follow up to this many redirects in a row, to any host
+    Follow(usize),
+
+    /// Don't follow redirects
+    None,
+
+    /// Follow up to 10 redirects in a row, as long as they stay on the host of the request
+    SameHostOnly,
+}
+
+impl Default for RedirectPolicy {
+    /// Follows up to 10 redirects, as reqwest does
+    fn default() -> Self {
+        Self::Follow(10)
+    }
+}
+
+/// Headers that carry credentials, which aren't sent on to another origin
+const CREDENTIALS: [&str; 5] = [
+    "x-password",
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "www-authenticate",
+];
+
+impl RedirectPolicy {
+    fn limit(&self) -> usize {
+        match self {
+            Self::Follow(limit) => *limit,
+            Self::None => 0,
+            Self::SameHostOnly => 10,
+        }
+    }
+
+    /// Request to send next if a response with `status` and `headers` to `request` is a
+    /// redirect the policy follows
+    ///
+    /// `request` is a copy of the one sent, and `hops` the number of redirects followed so
+    /// far. Returns `None` if the response isn't a redirect.
+    pub(crate) fn next<R: Redirectable>(
+        &self,
+        request: R,
+        status: StatusCode,
+        headers: &HeaderMap,
+        hops: usize,
+    ) -> Result<Option<R>, Error> {
+        if !matches!(
+            status,
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        ) {
+            return Ok(None);
+        }
+        let Some(location) = headers
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| request.url().join(value).ok())
+        else {
+            return Ok(None);
+        };
+
+        let refused = || Error::RedirectNotFollowed {
+            location: location.to_string(),
+        };
+        if hops >= self.limit() {
+            return Err(refused());
+        }
+        if *self == Self::SameHostOnly && location.host_str() != request.url().host_str() {
+            return Err(refused());
+        }
+
+        // A 303 asks for a GET, and like browsers a 301 or 302 turns a POST into one, both
+        // without the body
+        let method = match status {
+            StatusCode::SEE_OTHER if request.method() != Method::HEAD => Method::GET,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND
+                if request.method() == Method::POST =>
+            {
+                Method::GET
+            }
+            _ => request.method().clone(),
+        };
+        let origin = request.url().origin();
+        let mut next = if method == request.method() {
+            request
+        } else {
+            let mut next = R::new(method, location.clone());
+            *next.headers_mut() = request.headers().clone();
+            *next.timeout_mut() = request.timeout().copied();
+            for name in [
+                header::CONTENT_TYPE,
+                header::CONTENT_LENGTH,
+                header::TRANSFER_ENCODING,
+            ] {
+                next.headers_mut().remove(name);
+            }
+            next
+        };
+
+        if location.origin() != origin {
+            for name in CREDENTIALS {
+                next.headers_mut().remove(name);
+            }
+        }
+        *next.url_mut() = location;
+
+        Ok(Some(next))
+    }
+}
+
+/// Requests of the async and blocking clients, which are redirected the same way
+pub(crate) trait Redirectable: Sized {
+    fn new(method: Method, url: Url) -> Self;
+    fn method(&self) -> &Method;
+    fn url(&self) -> &Url;
+    fn url_mut(&mut self) -> &mut Url;
+    fn headers(&self) -> &HeaderMap;
+    fn headers_mut(&mut self) -> &mut HeaderMap;
+    fn timeout(&self) -> Option<&Duration>;
+    fn timeout_mut(&mut self) -> &mut Option<Duration>;
+}
+
+macro_rules! redirectable {
+    ($request:ty) => {
+        impl Redirectable for $request {
+            fn new(method: Method, url: Url) -> Self {
+                <$request>::new(method, url)
+            }
+
+            fn method(&self) -> &Method {
+                <$request>::method(self)
+            }
+
+            fn url(&self) -> &Url {
+                <$request>::url(self)
+            }
+
+            fn url_mut(&mut self) -> &mut Url {
+                <$request>::url_mut(self)
+            }
+
+            fn headers(&self) -> &HeaderMap {
+                <$request>::headers(self)
+            }
+
+            fn headers_mut(&mut self) -> &mut HeaderMap {
+                <$request>::headers_mut(self)
+            }
+
+            fn timeout(&self) -> Option<&Duration> {
+                <$request>::timeout(self)
+            }
+
+            fn timeout_mut(&mut self) -> &mut Option<Duration> {
+                <$request>::timeout_mut(self)
+            }
+        }
+    };
+}
+
+redirectable!(reqwest::Request);
+#[cfg(feature = "blocking")]
+redirectable!(reqwest::blocking::Request);
@@ -0,0 +1,149 @@
+//! Automatic retries for transient failures
+//!
+//! Retries are off unless a [`RetryPolicy`] is set with [`crate::ApiCallerBuilder::retry`].
+//! Only server errors (5xx) and connection failures are retried, anything else such as a
+//! bad token or a wrong password is returned straight away.
+use reqwest::StatusCode;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// How transient failures should be retried
+///
+/// Reads (file info, bucket and album lookups, downloads) are retried. Uploads are only
+/// retried when `retry_uploads` is set, since a request that failed mid-way may still
+/// have been stored by the service.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use waifuvault::{ApiCaller, RetryPolicy};
+///
+/// let caller = ApiCaller::builder()
+///     .retry(RetryPolicy {
+///         max_attempts: 5,
+///         base_delay: Duration::from_millis(500),
+///         ..Default::default()
+///     })
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled for every retry after
+    pub base_delay: Duration,
+
+    /// Upper bound on the delay between attempts
+    pub max_delay: Duration,
+
+    /// Randomise each delay to between half and all of its value
+    pub jitter: bool,
+
+    /// Also retry uploads
+    pub retry_uploads: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retry_uploads: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay to wait after the given failed attempt, starting from 1
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        if !self.jitter {
+            return delay;
+        }
+
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+        delay.mul_f64(fraction)
+    }
+}
+
+/// If a response with this status is worth trying again
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error()
+}
+
+/// If a failure to get any response is worth trying again
+pub(crate) fn is_transient_error(err: &reqwest::Error) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    if err.is_connect() {
+        return true;
+    }
+
+    err.is_timeout() || err.is_request()
+}
+
+/// Waits between attempts using whichever timer the platform has
+pub(crate) async fn sleep(delay: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(delay).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter,
+            retry_uploads: false,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let policy = policy(false);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(64), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_of_the_delay() {
+        let policy = policy(true);
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn only_server_errors_are_transient() {
+        assert!(is_transient_status(StatusCode::BAD_GATEWAY));
+        assert!(is_transient_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_transient_status(StatusCode::BAD_REQUEST));
+        assert!(!is_transient_status(StatusCode::FORBIDDEN));
+        assert!(!is_transient_status(StatusCode::NOT_FOUND));
+    }
+}
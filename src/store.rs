@@ -0,0 +1,80 @@
+//! Saving a bucket token to a file
+//!
+//! Used by [`crate::api::WaifuBucketEntry::save_token`], [`crate::ApiCaller::load_bucket`]
+//! and [`crate::ApiCaller::get_or_create_bucket`]. The file holds a small JSON object with
+//! the token, such as `{"token":"..."}`. Anyone with the token can read and delete everything
+//! in the bucket, so on Unix the file is only readable by its owner.
+use crate::{api::BucketToken, Error};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize)]
+struct SavedBucket {
+    token: BucketToken,
+}
+
+/// Reads the token saved at `path`, or `None` if there is no file
+///
+/// A file that doesn't hold a token fails with [`Error::CorruptSavedBucket`].
+pub(crate) async fn read_token(path: &Path) -> anyhow::Result<Option<BucketToken>> {
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("reading bucket token from {}", path.display()))
+        }
+    };
+
+    let saved: SavedBucket =
+        serde_json::from_slice(&contents).map_err(|e| Error::CorruptSavedBucket {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+    if saved.token.as_str().is_empty() {
+        return Err(Error::CorruptSavedBucket {
+            path: path.to_path_buf(),
+            reason: "the token is empty".to_string(),
+        }
+        .into());
+    }
+
+    Ok(Some(saved.token))
+}
+
+/// Saves `token` to `path`, replacing any token saved there before
+///
+/// The token is written to a file next to `path` first and moved over it, so a failed write
+/// never leaves a partial token behind.
+pub(crate) async fn write_token(path: &Path, token: &BucketToken) -> anyhow::Result<()> {
+    let contents = serde_json::to_vec(&SavedBucket {
+        token: token.clone(),
+    })?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = std::path::PathBuf::from(partial);
+
+    let write = async {
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&partial).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, &contents).await?;
+        file.sync_all().await?;
+        // A partial file left over from before keeps its own permissions
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&partial, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        tokio::fs::rename(&partial, path).await
+    };
+
+    if let Err(e) = write.await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e).with_context(|| format!("saving bucket token to {}", path.display()));
+    }
+
+    Ok(())
+}
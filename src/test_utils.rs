@@ -0,0 +1,352 @@
+//! Helpers for tests that run against a real Waifu Vault
+//!
+//! A [`Dropper`] creates buckets, albums and files through a caller and deletes them all again
+//! when the test is done, whether its assertions passed or not. A [`TempFile`] is a file of
+//! random content that is removed when dropped. Enabled with the `test-utils` feature, and not
+//! available on wasm32.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use waifuvault::{
+//!     api::WaifuUploadRequest,
+//!     test_utils::{Dropper, TempFile},
+//!     ApiCaller, Target,
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let caller = ApiCaller::new().on(&Target::new("http://127.0.0.1:8081/rest"));
+//!     let mut dropper = Dropper::new(&caller);
+//!
+//!     let tmp = TempFile::new("my_test.bin").await?;
+//!     let bucket = dropper.create_bucket().await?;
+//!     let upload = WaifuUploadRequest::new()
+//!         .file(tmp.path())
+//!         .bucket(&bucket.token);
+//!     let result = dropper.upload_file(upload).await;
+//!
+//!     dropper.destroy().await?;
+//!     result?;
+//!
+//!     Ok(())
+//! }
+//! ```
+use crate::{api::*, is_not_found, ApiCaller};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+};
+
+/// A temporary file of random content, deleted when dropped
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Size of the content written by [`TempFile::new`]
+    pub const SIZE: usize = 16_384;
+
+    /// Creates `filename` in the temp directory, filled with [`TempFile::SIZE`] random bytes
+    ///
+    /// An existing file of the same name is overwritten, so tests running at the same time
+    /// should use different names.
+    pub async fn new(filename: &str) -> anyhow::Result<Self> {
+        Self::with_contents(filename, random_bytes(Self::SIZE)).await
+    }
+
+    /// Creates `filename` in the temp directory with the given content
+    pub async fn with_contents(filename: &str, contents: impl AsRef<[u8]>) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(filename);
+        tokio::fs::write(&path, contents).await?;
+
+        Ok(Self { path })
+    }
+
+    /// Where the file is
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Bytes that differ on every call, so uploads aren't deduplicated by the service
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut state = RandomState::new().build_hasher();
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        state.write_usize(data.len());
+        data.extend_from_slice(&state.finish().to_le_bytes());
+    }
+    data.truncate(len);
+
+    data
+}
+
+/// Keeps track of what a test created so it can all be deleted afterwards
+///
+/// Everything created through the dropper is tracked, and things created some other way can
+/// be added with the `track_*` methods. If creating something fails, everything tracked so
+/// far is deleted before the error is returned.
+#[derive(Debug)]
+pub struct Dropper<'a> {
+    caller: &'a ApiCaller,
+    files: Vec<FileToken>,
+    buckets: Vec<BucketToken>,
+    albums: Vec<AlbumToken>,
+}
+
+impl<'a> Dropper<'a> {
+    /// Creates a dropper that tracks nothing yet, making its requests with `caller`
+    pub fn new(caller: &'a ApiCaller) -> Self {
+        Self {
+            caller,
+            files: Vec::new(),
+            buckets: Vec::new(),
+            albums: Vec::new(),
+        }
+    }
+
+    /// Creates a bucket and tracks it
+    pub async fn create_bucket(&mut self) -> anyhow::Result<WaifuBucketEntry> {
+        let result = self.caller.create_bucket().await;
+        match result {
+            Ok(bucket) => {
+                self.track_bucket(bucket.token.clone());
+                Ok(bucket)
+            }
+            Err(e) => self.failed(e).await,
+        }
+    }
+
+    /// Uploads a file and tracks it
+    pub async fn upload_file(
+        &mut self,
+        request: WaifuUploadRequest,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        let result = self.caller.upload_file(request).await;
+        match result {
+            Ok(file) => {
+                self.track_file(file.token.clone());
+                Ok(file)
+            }
+            Err(e) => self.failed(e).await,
+        }
+    }
+
+    /// Creates an album in a bucket and tracks it
+    pub async fn create_album(
+        &mut self,
+        bucket_token: impl Into<BucketToken>,
+        name: &str,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        let result = self.caller.create_album(bucket_token, name).await;
+        match result {
+            Ok(album) => {
+                self.track_album(album.token.clone());
+                Ok(album)
+            }
+            Err(e) => self.failed(e).await,
+        }
+    }
+
+    /// Tracks a file that wasn't uploaded through the dropper
+    pub fn track_file(&mut self, token: impl Into<FileToken>) {
+        self.files.push(token.into());
+    }
+
+    /// Tracks a bucket that wasn't created through the dropper
+    pub fn track_bucket(&mut self, token: impl Into<BucketToken>) {
+        self.buckets.push(token.into());
+    }
+
+    /// Tracks an album that wasn't created through the dropper
+    pub fn track_album(&mut self, token: impl Into<AlbumToken>) {
+        self.albums.push(token.into());
+    }
+
+    /// Deletes everything tracked
+    ///
+    /// Albums are deleted along with their files first, then files, then buckets. Every
+    /// deletion is attempted even if an earlier one failed, and things that are already gone
+    /// are skipped, such as files deleted with their album. The first other failure is
+    /// returned once all of them have been tried.
+    pub async fn destroy(&self) -> anyhow::Result<()> {
+        let mut results = Vec::new();
+        for token in &self.albums {
+            results.push(self.caller.delete_album(token, true).await.map(drop));
+        }
+        for token in &self.files {
+            results.push(self.caller.delete_file(token).await.map(drop));
+        }
+        for token in &self.buckets {
+            results.push(self.caller.delete_bucket(token).await.map(drop));
+        }
+
+        results
+            .into_iter()
+            .filter(|result| !result.as_ref().is_err_and(is_not_found))
+            .collect()
+    }
+
+    /// Deletes everything tracked so far and returns the error that made a creation fail
+    async fn failed<T>(&self, err: anyhow::Error) -> anyhow::Result<T> {
+        let _ = self.destroy().await;
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Target;
+    use anyhow::Result;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn caller(server: &MockServer) -> ApiCaller {
+        ApiCaller::new().on(&Target::new(format!("{}/rest", server.uri())))
+    }
+
+    fn not_found() -> ResponseTemplate {
+        ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "name": "NOT_FOUND", "message": "Unknown token", "status": 404
+        }))
+    }
+
+    #[tokio::test]
+    async fn temp_files_are_removed_when_dropped() -> Result<()> {
+        let first = TempFile::new("test_utils_random_1.bin").await?;
+        let second = TempFile::new("test_utils_random_2.bin").await?;
+        let path = first.path().to_path_buf();
+
+        let content = tokio::fs::read(first.path()).await?;
+        assert_eq!(content.len(), TempFile::SIZE);
+        assert_ne!(content, tokio::fs::read(second.path()).await?);
+
+        drop(first);
+        assert!(!path.exists());
+
+        let fixed = TempFile::with_contents("test_utils_fixed.txt", "hello").await?;
+        assert_eq!(tokio::fs::read(fixed.path()).await?, b"hello");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn destroy_deletes_everything_tracked() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/album/album-token"))
+            .and(query_param("deleteFiles", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true, "description": "album deleted"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/file-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bucket/bucket-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = caller(&server);
+        let mut dropper = Dropper::new(&caller);
+        dropper.track_album("album-token");
+        dropper.track_file("file-token");
+        dropper.track_bucket("bucket-token");
+        dropper.destroy().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn destroy_keeps_going_after_a_failure() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/gone-token"))
+            .respond_with(not_found())
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/broken-token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "name": "BAD_REQUEST", "message": "Nope", "status": 400
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bucket/bucket-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = caller(&server);
+        let mut dropper = Dropper::new(&caller);
+        dropper.track_file("gone-token");
+        dropper.track_file("broken-token");
+        dropper.track_bucket("bucket-token");
+
+        let err = dropper.destroy().await.unwrap_err();
+        let err = err.downcast_ref::<WaifuError>().unwrap();
+        assert_eq!(err.status, 400);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_creation_cleans_up() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/rest/bucket/create"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token": "bucket-token", "files": [], "albums": []
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/rest/album/bucket-token"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(serde_json::json!({
+                "name": "INTERNAL_SERVER_ERROR", "message": "Oops", "status": 500
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/rest/bucket/bucket-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("true"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let caller = caller(&server);
+        let mut dropper = Dropper::new(&caller);
+        let bucket = dropper.create_bucket().await?;
+        let err = dropper
+            .create_album(&bucket.token, "album")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<WaifuError>().unwrap().status, 500);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+//! Debug events for the optional `tracing` feature
+//!
+//! The macros here expand to nothing without the feature, so the crate only depends on
+//! `tracing` when it is asked for. Spans are opened with `#[tracing::instrument]` behind
+//! `cfg_attr` on the methods of [`crate::ApiCaller`].
+
+/// Emits a debug event when the `tracing` feature is enabled
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)+);
+    };
+}
+
+/// Records the token a call is about on its span, declared with `fields(token)`
+macro_rules! record_token {
+    ($token:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("token", tracing::field::display($token));
+    };
+}
+
+pub(crate) use {debug, record_token};
@@ -0,0 +1,51 @@
+//! How requests built by a caller are executed
+//!
+//! [`crate::ApiCaller`] builds every request with its `reqwest::Client`, then hands it to an
+//! [`HttpTransport`] to send. That is the client itself unless a middleware client was set,
+//! and lets tests check the requests endpoints build without a server to send them to.
+use crate::transport_error;
+use std::{future::Future, pin::Pin};
+
+/// Future of a response from an [`HttpTransport`]
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<reqwest::Response>> + Send + 'a>>;
+
+/// Future of a response from an [`HttpTransport`]
+#[cfg(target_arch = "wasm32")]
+pub(crate) type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<reqwest::Response>> + 'a>>;
+
+/// Sends a request and returns its response, whatever its status
+///
+/// Failures to send are returned through [`transport_error`], so transport errors can still
+/// be told apart and retried.
+pub(crate) trait HttpTransport: std::fmt::Debug + Send + Sync {
+    /// Sends `request`
+    fn execute(&self, request: reqwest::Request) -> TransportFuture<'_>;
+}
+
+impl HttpTransport for reqwest::Client {
+    fn execute(&self, request: reqwest::Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            reqwest::Client::execute(self, request)
+                .await
+                .map_err(transport_error)
+        })
+    }
+}
+
+/// Middleware errors are returned as they are, so they aren't retried
+#[cfg(feature = "middleware")]
+impl HttpTransport for reqwest_middleware::ClientWithMiddleware {
+    fn execute(&self, request: reqwest::Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            reqwest_middleware::ClientWithMiddleware::execute(self, request)
+                .await
+                .map_err(|e| match e {
+                    reqwest_middleware::Error::Reqwest(e) => transport_error(e),
+                    reqwest_middleware::Error::Middleware(e) => e,
+                })
+        })
+    }
+}
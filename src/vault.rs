@@ -0,0 +1,220 @@
+//! A trait over the core operations of a caller, for code that wants to swap it out
+//!
+//! Application code can depend on `Arc<dyn WaifuVaultApi>` instead of [`ApiCaller`], and be
+//! handed a stub in its own tests. With the `mocks` feature, a `MockWaifuVaultApi` generated
+//! by [`mockall`](https://docs.rs/mockall) is provided as well.
+use crate::{api::*, ApiCaller};
+
+/// The operations of the Waifu Vault API, implemented by [`ApiCaller`]
+///
+/// Each method does the same as the method of the same name on [`ApiCaller`], but takes
+/// concrete types so the trait can be used as `dyn WaifuVaultApi`. Helpers built on top of
+/// these, such as directory syncing, are only on the caller.
+///
+/// The trait is deliberately open so applications can implement their own stubs and
+/// wrappers. Methods may be added with defaults in minor releases, but existing ones won't
+/// change outside a major release.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use waifuvault::{api::WaifuUploadRequest, ApiCaller, WaifuVaultApi};
+///
+/// struct Gallery {
+///     vault: Arc<dyn WaifuVaultApi>,
+/// }
+///
+/// impl Gallery {
+///     async fn publish(&self, path: &str) -> anyhow::Result<String> {
+///         let entry = self
+///             .vault
+///             .upload_file(WaifuUploadRequest::new().file(path))
+///             .await?;
+///         Ok(entry.url)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let gallery = Gallery {
+///         vault: Arc::new(ApiCaller::new()),
+///     };
+///     println!("{}", gallery.publish("/some/file/path").await?);
+///
+///     Ok(())
+/// }
+/// ```
+#[cfg_attr(feature = "mocks", mockall::automock)]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+pub trait WaifuVaultApi {
+    /// See [`ApiCaller::upload_file`]
+    async fn upload_file(&self, request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry>;
+
+    /// See [`ApiCaller::file_info`]
+    async fn file_info(&self, request: WaifuGetRequest) -> anyhow::Result<WaifuFileEntry>;
+
+    /// See [`ApiCaller::update_file`]
+    async fn update_file(
+        &self,
+        request: WaifuModificationRequest,
+    ) -> anyhow::Result<WaifuFileEntry>;
+
+    /// See [`ApiCaller::delete_file`]
+    async fn delete_file(&self, token: FileToken) -> anyhow::Result<bool>;
+
+    /// See [`ApiCaller::download_file`]
+    async fn download_file(&self, url: &str, password: Option<String>) -> anyhow::Result<Vec<u8>>;
+
+    /// See [`ApiCaller::get_restrictions`]
+    async fn get_restrictions(&self) -> anyhow::Result<WaifuRestrictions>;
+
+    /// See [`ApiCaller::create_bucket`]
+    async fn create_bucket(&self) -> anyhow::Result<WaifuBucketEntry>;
+
+    /// See [`ApiCaller::get_bucket`]
+    async fn get_bucket(&self, request: WaifuBucketRequest) -> anyhow::Result<WaifuBucketEntry>;
+
+    /// See [`ApiCaller::delete_bucket`]
+    async fn delete_bucket(&self, token: BucketToken) -> anyhow::Result<bool>;
+
+    /// See [`ApiCaller::create_album`]
+    async fn create_album(
+        &self,
+        bucket_token: BucketToken,
+        album_name: &str,
+    ) -> anyhow::Result<WaifuAlbumEntry>;
+
+    /// See [`ApiCaller::get_album`]
+    async fn get_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuAlbumEntry>;
+
+    /// See [`ApiCaller::associate_with_album`]
+    async fn associate_with_album(
+        &self,
+        album_token: AlbumToken,
+        file_tokens: Vec<FileToken>,
+    ) -> anyhow::Result<WaifuAlbumEntry>;
+
+    /// See [`ApiCaller::disassociate_from_album`]
+    async fn disassociate_from_album(
+        &self,
+        album_token: AlbumToken,
+        file_tokens: Vec<FileToken>,
+    ) -> anyhow::Result<WaifuAlbumEntry>;
+
+    /// See [`ApiCaller::share_album`]
+    async fn share_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuGenericMessage>;
+
+    /// See [`ApiCaller::revoke_album`]
+    async fn revoke_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuGenericMessage>;
+
+    /// See [`ApiCaller::download_album`]
+    async fn download_album(
+        &self,
+        album_token: AlbumToken,
+        file_ids: Option<Vec<u64>>,
+    ) -> anyhow::Result<Vec<u8>>;
+
+    /// See [`ApiCaller::delete_album`]
+    async fn delete_album(
+        &self,
+        album_token: AlbumToken,
+        delete_files: bool,
+    ) -> anyhow::Result<WaifuGenericMessage>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+impl WaifuVaultApi for ApiCaller {
+    async fn upload_file(&self, request: WaifuUploadRequest) -> anyhow::Result<WaifuFileEntry> {
+        ApiCaller::upload_file(self, request).await
+    }
+
+    async fn file_info(&self, request: WaifuGetRequest) -> anyhow::Result<WaifuFileEntry> {
+        ApiCaller::file_info(self, request).await
+    }
+
+    async fn update_file(
+        &self,
+        request: WaifuModificationRequest,
+    ) -> anyhow::Result<WaifuFileEntry> {
+        ApiCaller::update_file(self, request).await
+    }
+
+    async fn delete_file(&self, token: FileToken) -> anyhow::Result<bool> {
+        ApiCaller::delete_file(self, token).await
+    }
+
+    async fn download_file(&self, url: &str, password: Option<String>) -> anyhow::Result<Vec<u8>> {
+        ApiCaller::download_file(self, url, password).await
+    }
+
+    async fn get_restrictions(&self) -> anyhow::Result<WaifuRestrictions> {
+        ApiCaller::get_restrictions(self).await
+    }
+
+    async fn create_bucket(&self) -> anyhow::Result<WaifuBucketEntry> {
+        ApiCaller::create_bucket(self).await
+    }
+
+    async fn get_bucket(&self, request: WaifuBucketRequest) -> anyhow::Result<WaifuBucketEntry> {
+        ApiCaller::get_bucket(self, request).await
+    }
+
+    async fn delete_bucket(&self, token: BucketToken) -> anyhow::Result<bool> {
+        ApiCaller::delete_bucket(self, token).await
+    }
+
+    async fn create_album(
+        &self,
+        bucket_token: BucketToken,
+        album_name: &str,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        ApiCaller::create_album(self, bucket_token, album_name).await
+    }
+
+    async fn get_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuAlbumEntry> {
+        ApiCaller::get_album(self, album_token).await
+    }
+
+    async fn associate_with_album(
+        &self,
+        album_token: AlbumToken,
+        file_tokens: Vec<FileToken>,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        ApiCaller::associate_with_album(self, album_token, file_tokens).await
+    }
+
+    async fn disassociate_from_album(
+        &self,
+        album_token: AlbumToken,
+        file_tokens: Vec<FileToken>,
+    ) -> anyhow::Result<WaifuAlbumEntry> {
+        ApiCaller::disassociate_from_album(self, album_token, file_tokens).await
+    }
+
+    async fn share_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuGenericMessage> {
+        ApiCaller::share_album(self, album_token).await
+    }
+
+    async fn revoke_album(&self, album_token: AlbumToken) -> anyhow::Result<WaifuGenericMessage> {
+        ApiCaller::revoke_album(self, album_token).await
+    }
+
+    async fn download_album(
+        &self,
+        album_token: AlbumToken,
+        file_ids: Option<Vec<u64>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        ApiCaller::download_album(self, album_token, file_ids.as_deref()).await
+    }
+
+    async fn delete_album(
+        &self,
+        album_token: AlbumToken,
+        delete_files: bool,
+    ) -> anyhow::Result<WaifuGenericMessage> {
+        ApiCaller::delete_album(self, album_token, delete_files).await
+    }
+}
@@ -0,0 +1,126 @@
+//! Following a file's retention as it counts down
+//!
+//! [`crate::ApiCaller::watch_retention`] returns a [`RetentionWatch`], a stream of the
+//! file's entry looked up again at an interval. Not available on wasm32.
+use crate::{
+    api::{FileToken, WaifuFileEntry, WaifuGetRequest},
+    ApiCaller, Error,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+type Lookup = Pin<Box<dyn Future<Output = anyhow::Result<Option<WaifuFileEntry>>> + Send>>;
+
+/// Stream of a file's entry, looked up at an interval until the file is gone
+///
+/// Each item is the entry as the service returned it, so its
+/// [`WaifuFileEntry::expires_at`] can be shown as a countdown. The stream ends once the
+/// service no longer knows the file, or the caller is cancelled. Other errors are yielded
+/// as items and the lookups carry on, so the consumer decides whether to stop. Dropping
+/// the stream abandons any lookup in flight.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use futures_util::StreamExt;
+/// use std::time::Duration;
+/// use waifuvault::ApiCaller;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let caller = ApiCaller::new();
+///
+///     let mut watch = caller.watch_retention("some-file-token", Duration::from_secs(60));
+///     while let Some(entry) = watch.next().await {
+///         match entry {
+///             Ok(entry) => println!("{} expires at {:?}", entry.token, entry.expires_at()),
+///             Err(e) => eprintln!("lookup failed: {e:#}"),
+///         }
+///     }
+///     println!("the file is gone");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RetentionWatch {
+    caller: ApiCaller,
+    token: FileToken,
+    interval: Duration,
+    next_at: Instant,
+    lookup: Option<Lookup>,
+    done: bool,
+}
+
+impl RetentionWatch {
+    pub(crate) fn new(caller: ApiCaller, token: FileToken, interval: Duration) -> Self {
+        Self {
+            caller,
+            token,
+            interval,
+            next_at: Instant::now(),
+            lookup: None,
+            done: false,
+        }
+    }
+
+    /// Looks the file up once `next_at` has come
+    fn lookup(&self) -> Lookup {
+        let caller = self.caller.clone();
+        let request = WaifuGetRequest::new(&self.token);
+        let at = self.next_at;
+        Box::pin(async move {
+            tokio::time::sleep_until(at).await;
+            caller.try_file_info(request).await
+        })
+    }
+}
+
+impl std::fmt::Debug for RetentionWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetentionWatch")
+            .field("token", &self.token)
+            .field("interval", &self.interval)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl futures_core::Stream for RetentionWatch {
+    type Item = anyhow::Result<WaifuFileEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let mut lookup = match self.lookup.take() {
+            Some(lookup) => lookup,
+            None => self.lookup(),
+        };
+        let result = match lookup.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                self.lookup = Some(lookup);
+                return Poll::Pending;
+            }
+        };
+
+        self.next_at = Instant::now() + self.interval;
+        match result {
+            Ok(Some(entry)) => Poll::Ready(Some(Ok(entry))),
+            Ok(None) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Err(e) => {
+                self.done = e.downcast_ref() == Some(&Error::Cancelled);
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Memory used by downloads held in memory
+//!
+//! Counts the bytes allocated on the test thread while downloading, with the mock server
+//! running on threads of its own so its allocations aren't counted.
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+use waifuvault::{ApiCaller, Target};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size.saturating_sub(layout.size()));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count(size: usize) {
+    let _ = ALLOCATED.try_with(|allocated| {
+        if let Some(total) = allocated.get() {
+            allocated.set(Some(total + size));
+        }
+    });
+}
+
+/// Bytes allocated on this thread while running `fut`
+async fn allocated_by<T>(fut: impl std::future::Future<Output = T>) -> (T, usize) {
+    ALLOCATED.with(|allocated| allocated.set(Some(0)));
+    let output = fut.await;
+    let total = ALLOCATED
+        .with(|allocated| allocated.take())
+        .unwrap_or_default();
+
+    (output, total)
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn byte_downloads_are_not_copied() -> anyhow::Result<()> {
+    const SIZE: usize = 8 * 1024 * 1024;
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/f/large-file"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![7; SIZE]))
+        .mount(&server)
+        .await;
+
+    let caller = ApiCaller::new().on(&Target::new(format!("{}/rest", server.uri())));
+    let url = format!("{}/f/large-file", server.uri());
+
+    // The first request also sets up the connection
+    caller.download_file_bytes(&url, None).await?;
+
+    let (content, shared) = allocated_by(caller.download_file_bytes(&url, None)).await;
+    assert_eq!(content?.len(), SIZE);
+    let (content, copied) = allocated_by(caller.download_file(&url, None)).await;
+    assert_eq!(content?.len(), SIZE);
+
+    // Receiving the body takes about its size, copying it into a vector as much again
+    assert!(copied >= shared + SIZE * 9 / 10, "{copied} vs {shared}");
+    assert!(shared < SIZE * 5 / 2, "{shared}");
+
+    Ok(())
+}
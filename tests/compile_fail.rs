@@ -0,0 +1,27 @@
+//! Compile tests enforcing the public API stability contract
+//!
+//! Types the service is expected to grow are `#[non_exhaustive]`, so downstream code
+//! can't construct them with struct literals or match them exhaustively, and builds the
+//! responses its stubs return with their constructors instead. Tokens are
+//! distinct types so one kind can't be passed where another is expected, and an upload
+//! request can't be sent before it has content. Traits only the crate implements are sealed.
+//!
+//! The cases under `tests/ui/pass` are call patterns that must keep compiling.
+
+#[test]
+fn stability_contract() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+    // The blocking caller is also named `ApiCaller`, so with it enabled the compiler prints
+    // the full path of the async one in notes. These cases are recorded for both.
+    #[cfg(not(feature = "blocking"))]
+    t.compile_fail("tests/ui/default_features/*.rs");
+    #[cfg(feature = "blocking")]
+    t.compile_fail("tests/ui/blocking/*.rs");
+}
+
+#[test]
+fn call_patterns() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass/*.rs");
+}
@@ -0,0 +1,54 @@
+//! Builds the crate with each TLS backend on its own, as CI would
+//!
+//! The default features are off for these builds so a backend that still pulls in another,
+//! such as native-tls through reqwest's defaults, fails to build where that one isn't
+//! available. Checked into their own target directory so they don't wait on the build
+//! running this test.
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::{path::Path, process::Command};
+
+const MATRIX: &[&str] = &[
+    "native-tls",
+    "rustls",
+    "rustls,blocking",
+    "rustls,middleware",
+];
+
+#[test]
+fn each_tls_backend_builds_alone() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("target").join("feature-matrix");
+
+    for features in MATRIX {
+        let status = Command::new(env!("CARGO"))
+            .current_dir(manifest_dir)
+            .args(["check", "--lib", "--no-default-features", "--features"])
+            .arg(features)
+            .env("CARGO_TARGET_DIR", &target_dir)
+            .status()
+            .expect("cargo can be run");
+        assert!(status.success(), "failed to build with features {features}");
+    }
+}
+
+#[test]
+fn rustls_builds_without_openssl() {
+    let output = Command::new(env!("CARGO"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["tree", "--no-default-features", "--features", "rustls"])
+        .args(["--edges", "normal", "--prefix", "none"])
+        .output()
+        .expect("cargo can be run");
+    assert!(output.status.success());
+
+    let tree = String::from_utf8_lossy(&output.stdout);
+    for crate_name in ["openssl", "native-tls"] {
+        assert!(
+            !tree
+                .lines()
+                .any(|line| line.starts_with(&format!("{crate_name} "))),
+            "{crate_name} is built with only rustls:\n{tree}"
+        );
+    }
+}
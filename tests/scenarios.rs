@@ -0,0 +1,544 @@
+//! End-to-end conformance scenarios runnable against any Waifu Vault instance
+//!
+//! ```text
+//! WAIFUVAULT_URL=http://127.0.0.1:8081/rest \
+//!     cargo test --features live-scenarios --test scenarios -- --ignored --nocapture
+//! ```
+//!
+//! Every scenario runs to completion even when an earlier one fails, and the outcome of
+//! each is collected into a [`SuiteReport`] which is printed as JSON at the end. Set
+//! `WAIFUVAULT_SCENARIO_REPORT` to a path to also write the report to disk.
+//!
+//! Anything a scenario creates is tracked and deleted afterwards, whether it passed or not.
+use anyhow::{ensure, Context, Result};
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use waifuvault::{
+    api::{
+        AlbumToken, BucketToken, FileToken, WaifuError, WaifuGetRequest, WaifuModificationRequest,
+        WaifuUploadRequest,
+    },
+    ApiCaller, Target,
+};
+
+/// Instance used when `WAIFUVAULT_URL` isn't set
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8081/rest";
+
+/// Remote content used for URL uploads
+const REMOTE_IMAGE: &str = "https://waifuvault.moe/assets/custom/images/08.png";
+
+/// Outcome of a single scenario
+#[derive(Debug, Serialize)]
+struct ScenarioReport {
+    name: String,
+    passed: bool,
+    error: Option<String>,
+    duration_ms: u128,
+}
+
+/// Outcome of the whole run
+#[derive(Debug, Serialize)]
+struct SuiteReport {
+    base_url: String,
+    passed: usize,
+    failed: usize,
+    scenarios: Vec<ScenarioReport>,
+}
+
+/// Resources created during a scenario which need deleting afterwards
+#[derive(Default)]
+struct Tracker {
+    files: Mutex<Vec<FileToken>>,
+    buckets: Mutex<Vec<BucketToken>>,
+    albums: Mutex<Vec<AlbumToken>>,
+}
+
+impl Tracker {
+    fn file(&self, token: &FileToken) {
+        self.files.lock().unwrap().push(token.clone());
+    }
+
+    fn bucket(&self, token: &BucketToken) {
+        self.buckets.lock().unwrap().push(token.clone());
+    }
+
+    fn album(&self, token: &AlbumToken) {
+        self.albums.lock().unwrap().push(token.clone());
+    }
+
+    /// Best-effort deletion of everything tracked, ignoring failures
+    async fn destroy(&self, caller: &ApiCaller) {
+        let albums = std::mem::take(&mut *self.albums.lock().unwrap());
+        for token in albums {
+            let _ = caller.delete_album(&token, true).await;
+        }
+
+        let files = std::mem::take(&mut *self.files.lock().unwrap());
+        for token in files {
+            let _ = caller.delete_file(&token).await;
+        }
+
+        let buckets = std::mem::take(&mut *self.buckets.lock().unwrap());
+        for token in buckets {
+            let _ = caller.delete_bucket(&token).await;
+        }
+    }
+}
+
+/// Everything a scenario needs
+#[derive(Clone)]
+struct Ctx {
+    caller: ApiCaller,
+    track: Arc<Tracker>,
+}
+
+/// Runs scenarios one after another, recording each outcome
+struct Suite {
+    base_url: String,
+    caller: ApiCaller,
+    reports: Vec<ScenarioReport>,
+}
+
+impl Suite {
+    fn new() -> Self {
+        let base_url =
+            std::env::var("WAIFUVAULT_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let caller = ApiCaller::new().on(&Target::new(&base_url));
+
+        Self {
+            base_url,
+            caller,
+            reports: Vec::new(),
+        }
+    }
+
+    /// Runs a scenario, catching errors and panics, then cleans up after it
+    async fn run<F, Fut>(&mut self, name: impl Into<String>, scenario: F)
+    where
+        F: FnOnce(Ctx) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let name = name.into();
+        let ctx = Ctx {
+            caller: self.caller.clone(),
+            track: Arc::new(Tracker::default()),
+        };
+
+        let started = Instant::now();
+        let outcome = tokio::spawn(scenario(ctx.clone())).await;
+        let duration_ms = started.elapsed().as_millis();
+        ctx.track.destroy(&ctx.caller).await;
+
+        let error = match outcome {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{e:#}")),
+            Err(e) => Some(format!("scenario panicked: {e}")),
+        };
+
+        println!(
+            "{} {name} ({duration_ms}ms)",
+            if error.is_none() { "PASS" } else { "FAIL" }
+        );
+        self.reports.push(ScenarioReport {
+            name,
+            passed: error.is_none(),
+            error,
+            duration_ms,
+        });
+    }
+
+    fn finish(self) -> SuiteReport {
+        let passed = self.reports.iter().filter(|r| r.passed).count();
+        SuiteReport {
+            base_url: self.base_url,
+            passed,
+            failed: self.reports.len() - passed,
+            scenarios: self.reports,
+        }
+    }
+}
+
+/// The different places content can be uploaded from
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    File,
+    Bytes,
+    Url,
+}
+
+/// One combination of upload options
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    hide_filename: bool,
+    one_time_download: bool,
+    password: bool,
+}
+
+fn option_matrix() -> Vec<Options> {
+    let mut matrix = Vec::new();
+    for hide_filename in [false, true] {
+        for one_time_download in [false, true] {
+            for password in [false, true] {
+                matrix.push(Options {
+                    hide_filename,
+                    one_time_download,
+                    password,
+                });
+            }
+        }
+    }
+    matrix
+}
+
+fn content() -> Vec<u8> {
+    b"waifuvault conformance scenario content\n".repeat(64)
+}
+
+async fn upload(ctx: &Ctx, request: WaifuUploadRequest) -> Result<waifuvault::api::WaifuFileEntry> {
+    let entry = ctx.caller.upload_file(request).await?;
+    ctx.track.file(&entry.token);
+    Ok(entry)
+}
+
+async fn upload_scenario(ctx: Ctx, source: Source, options: Options) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut request = match source {
+        Source::File => {
+            let path = dir.path().join("scenario upload.bin");
+            std::fs::write(&path, content())?;
+            WaifuUploadRequest::new().file(&path)
+        }
+        Source::Bytes => WaifuUploadRequest::new().bytes(content(), "scenario.bin"),
+        Source::Url => WaifuUploadRequest::new().url(REMOTE_IMAGE),
+    };
+
+    request = request
+        .expires("10m")
+        .hide_filename(options.hide_filename)
+        .one_time_download(options.one_time_download);
+    if options.password {
+        request = request.password("scenario-password");
+    }
+
+    let entry = upload(&ctx, request).await?;
+    let returned = entry.options.context("upload response has no options")?;
+    ensure!(
+        returned.hide_filename == options.hide_filename,
+        "hide_filename"
+    );
+    ensure!(
+        returned.one_time_download == options.one_time_download,
+        "one_time_download"
+    );
+    ensure!(returned.protected == options.password, "protected");
+
+    if !options.one_time_download {
+        let info = ctx
+            .caller
+            .file_info(WaifuGetRequest::new(&entry.token))
+            .await?;
+        ensure!(info.token == entry.token, "file info returned another file");
+    }
+
+    let password = options.password.then(|| "scenario-password".to_string());
+    let downloaded = ctx.caller.download_file(&entry.url, password).await?;
+    if !matches!(source, Source::Url) {
+        ensure!(downloaded == content(), "downloaded content differs");
+    }
+
+    Ok(())
+}
+
+async fn bucket_lifecycle(ctx: Ctx) -> Result<()> {
+    let bucket = ctx.caller.create_bucket().await?;
+    ctx.track.bucket(&bucket.token);
+    ensure!(bucket.files.is_empty(), "new bucket has files");
+
+    let request = WaifuUploadRequest::new()
+        .bucket(&bucket.token)
+        .bytes(content(), "bucket.bin")
+        .expires("10m");
+    let file = upload(&ctx, request).await?;
+
+    let info = ctx.caller.get_bucket(&bucket.token).await?;
+    ensure!(info.files.len() == 1, "expected one file in the bucket");
+    ensure!(info.files[0].token == file.token, "wrong file in bucket");
+
+    ensure!(
+        ctx.caller.delete_bucket(&bucket.token).await?,
+        "bucket delete failed"
+    );
+    ensure!(
+        ctx.caller.get_bucket(&bucket.token).await.is_err(),
+        "deleted bucket still exists"
+    );
+
+    Ok(())
+}
+
+async fn album_lifecycle(ctx: Ctx) -> Result<()> {
+    let bucket = ctx.caller.create_bucket().await?;
+    ctx.track.bucket(&bucket.token);
+
+    let mut files = Vec::new();
+    for name in ["one.bin", "two.bin"] {
+        let request = WaifuUploadRequest::new()
+            .bucket(&bucket.token)
+            .bytes(content(), name)
+            .expires("10m");
+        files.push(upload(&ctx, request).await?.token);
+    }
+
+    let album = ctx.caller.create_album(&bucket.token, "scenario").await?;
+    ctx.track.album(&album.token);
+
+    let associated = ctx
+        .caller
+        .associate_with_album(&album.token, &files)
+        .await?;
+    ensure!(associated.files.len() == 2, "association failed");
+
+    let shared = ctx.caller.share_album(&album.token).await?;
+    ensure!(shared.success, "share failed");
+    let info = ctx.caller.get_album(&album.token).await?;
+    ensure!(
+        info.public_token.is_some(),
+        "shared album has no public token"
+    );
+
+    let public = reqwest::get(&shared.description).await?;
+    ensure!(
+        public.status().is_success(),
+        "public album returned {}",
+        public.status()
+    );
+
+    let archive = ctx.caller.download_album(&album.token, None).await?;
+    ensure!(archive.starts_with(b"PK"), "album download isn't a zip");
+
+    let revoked = ctx.caller.revoke_album(&album.token).await?;
+    ensure!(revoked.success, "revoke failed");
+    let info = ctx.caller.get_album(&album.token).await?;
+    ensure!(info.public_token.is_none(), "revoked album is still public");
+
+    let disassociated = ctx
+        .caller
+        .disassociate_from_album(&album.token, &files[..1])
+        .await?;
+    ensure!(disassociated.files.len() == 1, "disassociation failed");
+
+    let deleted = ctx.caller.delete_album(&album.token, false).await?;
+    ensure!(deleted.success, "album delete failed");
+    let remaining = ctx.caller.get_bucket(&bucket.token).await?;
+    ensure!(remaining.files.len() == 2, "album delete removed files");
+
+    Ok(())
+}
+
+async fn modification_matrix(ctx: Ctx) -> Result<()> {
+    let request = WaifuUploadRequest::new()
+        .bytes(content(), "modify.bin")
+        .expires("10m");
+    let file = upload(&ctx, request).await?;
+
+    let protected = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).password("first"))
+        .await?;
+    ensure!(
+        protected.options.context("no options")?.protected,
+        "password not applied"
+    );
+
+    let changed = ctx
+        .caller
+        .update_file(
+            WaifuModificationRequest::new(&file.token)
+                .password("second")
+                .previous_password("first"),
+        )
+        .await?;
+    ensure!(
+        changed.options.context("no options")?.protected,
+        "password change dropped protection"
+    );
+    ctx.caller
+        .download_file(&changed.url, Some("second".to_string()))
+        .await
+        .context("downloading with the changed password")?;
+
+    let expiry = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).custom_expiry("5m"))
+        .await?;
+    ensure!(
+        expiry.retention_period != file.retention_period,
+        "expiry not applied"
+    );
+
+    let hidden = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).hide_filename(true))
+        .await?;
+    ensure!(
+        hidden.options.context("no options")?.hide_filename,
+        "hide_filename not applied"
+    );
+
+    Ok(())
+}
+
+async fn wrong_password(ctx: Ctx) -> Result<()> {
+    let request = WaifuUploadRequest::new()
+        .bytes(content(), "protected.bin")
+        .password("right")
+        .expires("10m");
+    let file = upload(&ctx, request).await?;
+
+    ensure!(
+        ctx.caller.download_file(&file.url, None).await.is_err(),
+        "downloaded without a password"
+    );
+    ensure!(
+        ctx.caller
+            .download_file(&file.url, Some("wrong".to_string()))
+            .await
+            .is_err(),
+        "downloaded with the wrong password"
+    );
+
+    Ok(())
+}
+
+async fn password_removal(ctx: Ctx) -> Result<()> {
+    let request = WaifuUploadRequest::new()
+        .bytes(content(), "unprotect.bin")
+        .password("secret")
+        .expires("10m");
+    let file = upload(&ctx, request).await?;
+    ensure!(
+        file.options.context("no options")?.protected,
+        "password not applied"
+    );
+
+    let unprotected = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).remove_password("secret"))
+        .await?;
+    ensure!(
+        !unprotected.options.context("no options")?.protected,
+        "password not removed"
+    );
+    ctx.caller
+        .download_file(&unprotected.url, None)
+        .await
+        .context("downloading without a password")?;
+
+    Ok(())
+}
+
+async fn expiry_reset(ctx: Ctx) -> Result<()> {
+    let request = WaifuUploadRequest::new().bytes(content(), "expiry.bin");
+    let file = upload(&ctx, request).await?;
+    let default = file.expires_at().context("no default expiry")?;
+
+    let shortened = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).custom_expiry("5m"))
+        .await?;
+    let custom = shortened.expires_at().context("no custom expiry")?;
+    ensure!(custom < default, "custom expiry not applied");
+
+    let reset = ctx
+        .caller
+        .update_file(WaifuModificationRequest::new(&file.token).clear_custom_expiry())
+        .await?;
+    let restored = reset.expires_at().context("no expiry after the reset")?;
+    let drift = restored
+        .duration_since(default)
+        .unwrap_or_else(|e| e.duration());
+    ensure!(
+        drift < Duration::from_secs(60 * 60),
+        "expiry not back to the default, {drift:?} off"
+    );
+
+    Ok(())
+}
+
+async fn bad_tokens(ctx: Ctx) -> Result<()> {
+    let err = ctx
+        .caller
+        .file_info(WaifuGetRequest::new("not-a-real-token"))
+        .await
+        .err()
+        .context("file info succeeded for a bad token")?;
+    ensure!(
+        err.downcast_ref::<WaifuError>().is_some(),
+        "bad file token didn't produce an API error: {err:#}"
+    );
+
+    let err = ctx
+        .caller
+        .delete_bucket("not-a-real-token")
+        .await
+        .err()
+        .context("bucket delete succeeded for a bad token")?;
+    ensure!(
+        err.downcast_ref::<WaifuError>().is_some(),
+        "bad bucket token didn't produce an API error: {err:#}"
+    );
+
+    ensure!(
+        ctx.caller.get_album("not-a-real-token").await.is_err(),
+        "album lookup succeeded for a bad token"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs a running Waifu Vault instance"]
+async fn conformance() -> Result<()> {
+    let mut suite = Suite::new();
+
+    for source in [Source::File, Source::Bytes, Source::Url] {
+        for options in option_matrix() {
+            let name = format!(
+                "upload {source:?} hide={} otd={} password={}",
+                options.hide_filename, options.one_time_download, options.password
+            );
+            suite
+                .run(name, move |ctx| upload_scenario(ctx, source, options))
+                .await;
+        }
+    }
+
+    suite.run("bucket lifecycle", bucket_lifecycle).await;
+    suite.run("album lifecycle", album_lifecycle).await;
+    suite.run("modification matrix", modification_matrix).await;
+    suite.run("wrong password", wrong_password).await;
+    suite.run("password removal", password_removal).await;
+    suite.run("expiry reset", expiry_reset).await;
+    suite.run("bad tokens", bad_tokens).await;
+
+    let report = suite.finish();
+    let json = serde_json::to_string_pretty(&report)?;
+    println!("{json}");
+
+    if let Ok(path) = std::env::var("WAIFUVAULT_SCENARIO_REPORT") {
+        std::fs::write(&path, &json).with_context(|| format!("writing report to {path}"))?;
+    }
+
+    ensure!(
+        report.failed == 0,
+        "{} of {} scenarios failed",
+        report.failed,
+        report.passed + report.failed
+    );
+
+    Ok(())
+}
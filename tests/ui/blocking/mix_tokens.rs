@@ -0,0 +1,8 @@
+use waifuvault::{api::WaifuBucketEntry, ApiCaller};
+
+async fn delete_wrong_thing(caller: &ApiCaller, bucket: &WaifuBucketEntry) {
+    // A bucket token can't be passed where a file token is expected
+    let _ = caller.delete_file(&bucket.token).await;
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use waifuvault::api::{Replaced, WaifuFileEntry};
+
+fn replaced(new_entry: WaifuFileEntry) -> Replaced {
+    Replaced {
+        new_entry,
+        token_changed: true,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use waifuvault::api::WaifuFileOptions;
+
+fn main() {
+    let _options = WaifuFileOptions {
+        hide_filename: false,
+        one_time_download: false,
+        protected: false,
+    };
+}
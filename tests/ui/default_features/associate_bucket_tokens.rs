@@ -0,0 +1,8 @@
+use waifuvault::{api::WaifuBucketEntry, ApiCaller};
+
+async fn associate_wrong_thing(caller: &ApiCaller, bucket: &WaifuBucketEntry) {
+    // Bucket tokens aren't file tokens, even when given as a list
+    let _ = caller.associate_with_album("album", [&bucket.token]).await;
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use waifuvault::api::WaifuGenericMessage;
+
+fn describe(message: WaifuGenericMessage) -> String {
+    let WaifuGenericMessage {
+        success,
+        description,
+    } = message;
+
+    format!("{success}: {description}")
+}
+
+fn main() {}
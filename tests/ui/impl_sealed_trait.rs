@@ -0,0 +1,12 @@
+use waifuvault::api::{AsFileToken, BucketToken};
+
+// Only the crate's own types are accepted as file tokens
+struct Wrapper(BucketToken);
+
+impl AsFileToken for Wrapper {
+    fn as_file_token(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+fn main() {}
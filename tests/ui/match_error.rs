@@ -0,0 +1,9 @@
+use waifuvault::Error;
+
+fn describe(error: &Error) -> &'static str {
+    match error {
+        Error::RateLimited { .. } => "rate limited",
+    }
+}
+
+fn main() {}
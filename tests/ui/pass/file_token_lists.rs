@@ -0,0 +1,28 @@
+use waifuvault::{
+    api::{FileToken, WaifuBucketEntry},
+    ApiCaller,
+};
+
+// Ways of holding a list of file tokens that can be passed straight to album association
+async fn associate_patterns(caller: &ApiCaller, bucket: &WaifuBucketEntry) {
+    let strs: [&str; 2] = ["one", "two"];
+    let strings: Vec<String> = vec!["one".to_string(), "two".to_string()];
+    let tokens: Vec<FileToken> = bucket.files.iter().map(|f| f.token.clone()).collect();
+
+    let _ = caller.associate_with_album("album", &strs).await;
+    let _ = caller.associate_with_album("album", &strings).await;
+    let _ = caller.associate_with_album("album", &strings[..1]).await;
+    let _ = caller.associate_with_album("album", strings.clone()).await;
+    let _ = caller.associate_with_album("album", &tokens).await;
+    let _ = caller.associate_with_album("album", [&tokens[0]]).await;
+    let _ = caller
+        .associate_with_album("album", bucket.files.iter().map(|f| &f.token))
+        .await;
+    let _ = caller
+        .disassociate_from_album("album", strings.iter().filter(|t| t.len() > 3))
+        .await;
+}
+
+fn main() {
+    let _ = associate_patterns;
+}
@@ -0,0 +1,44 @@
+use waifuvault::api::{
+    WaifuAlbumEntry, WaifuAlbumMetadata, WaifuBucketEntry, WaifuFileEntry, WaifuFileOptions,
+    WaifuGenericMessage, WaifuRestriction, WaifuRestrictions,
+};
+
+// Responses a stub of `WaifuVaultApi` can return, built outside the crate
+fn file() -> anyhow::Result<WaifuFileEntry> {
+    Ok(WaifuFileEntry::new("file-token", "https://waifuvault.moe/f/1/a.png")
+        .id(1)
+        .bucket("bucket-token")
+        .album(WaifuAlbumMetadata::new("album-token", "bucket-token", "holiday", 1_712_345_678_000))
+        .views(3)
+        .retention_period(3_600_000)
+        .options(WaifuFileOptions::new(false, false, true)))
+}
+
+fn bucket() -> anyhow::Result<WaifuBucketEntry> {
+    Ok(WaifuBucketEntry::new("bucket-token")
+        .files(vec![file()?])
+        .albums(vec![WaifuAlbumMetadata::new("album-token", "bucket-token", "holiday", 0)
+            .public_token("public-token")]))
+}
+
+fn album() -> anyhow::Result<WaifuAlbumEntry> {
+    Ok(WaifuAlbumEntry::new("album-token", "bucket-token", "holiday")
+        .public_token("public-token")
+        .files(vec![file()?])
+        .date_created(1_712_345_678_000))
+}
+
+fn message() -> anyhow::Result<WaifuGenericMessage> {
+    Ok(WaifuGenericMessage::new(true, "album deleted"))
+}
+
+fn restrictions() -> anyhow::Result<WaifuRestrictions> {
+    Ok(WaifuRestrictions::new(vec![
+        WaifuRestriction::new("MAX_FILE_SIZE", 1024),
+        WaifuRestriction::new("BANNED_MIME_TYPE", "application/x-dosexec"),
+    ]))
+}
+
+fn main() {
+    let _ = (file, bucket, album, message, restrictions);
+}
@@ -0,0 +1,36 @@
+use waifuvault::{
+    api::{NoSource, WaifuUploadRequest},
+    ApiCaller,
+};
+
+// Ways of building an upload request that can be passed straight to an upload
+async fn upload_patterns(caller: &ApiCaller, from_config: Option<&str>) {
+    let _ = caller
+        .upload_file(WaifuUploadRequest::new().url("https://example.com/a.png"))
+        .await;
+    let _ = caller
+        .upload_file(WaifuUploadRequest::new().expires("1h").file("/some/file"))
+        .await;
+    let _ = caller
+        .upload_file(WaifuUploadRequest::new().bytes(vec![1], "a.bin").password("pw"))
+        .await;
+
+    // Options can be shared before the content is known
+    let template: WaifuUploadRequest<NoSource> = WaifuUploadRequest::new().hide_filename(true);
+    let request: WaifuUploadRequest = match from_config {
+        Some(url) => template.clone().url(url),
+        None => template.clone().file("/some/fallback"),
+    };
+    let _ = caller.upload_file(request).await;
+
+    // Content decided at runtime is checked when uploading instead
+    let mut request = WaifuUploadRequest::new().unchecked();
+    if let Some(url) = from_config {
+        request = request.url(url);
+    }
+    let _ = caller.upload_file(request).await;
+}
+
+fn main() {
+    let _ = upload_patterns;
+}
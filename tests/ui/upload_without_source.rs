@@ -0,0 +1,10 @@
+use waifuvault::{api::WaifuUploadRequest, ApiCaller};
+
+async fn upload_nothing(caller: &ApiCaller) {
+    // A request can't be uploaded until it has a file, url or bytes
+    let _ = caller
+        .upload_file(WaifuUploadRequest::new().expires("1h"))
+        .await;
+}
+
+fn main() {}
@@ -0,0 +1,42 @@
+//! Uploads from a browser through reqwest's wasm backend
+//!
+//! There is no way to start a mock server from inside the browser, so these talk to
+//! whatever instance `WAIFUVAULT_MOCK_URL` points at when they are built. Anything that
+//! answers like the API will do, e.g. a wiremock server replaying the captured fixtures.
+//!
+//! ```text
+//! WAIFUVAULT_MOCK_URL=http://127.0.0.1:8081/rest wasm-pack test --headless --firefox -- --test wasm
+//! ```
+#![cfg(target_arch = "wasm32")]
+
+use waifuvault::{api::WaifuUploadRequest, ApiCaller, Target};
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn caller() -> ApiCaller {
+    let base_url = option_env!("WAIFUVAULT_MOCK_URL").unwrap_or("http://127.0.0.1:8081/rest");
+    ApiCaller::new().on(&Target::new(base_url))
+}
+
+#[wasm_bindgen_test]
+async fn upload_file_bytes() {
+    let request = WaifuUploadRequest::new()
+        .bytes(b"uploaded from wasm".to_vec(), "wasm.txt")
+        .expires("10m");
+
+    let entry = caller().upload_file(request).await.expect("upload");
+    assert!(!entry.token.as_str().is_empty());
+    assert!(entry.url.ends_with("wasm.txt"));
+}
+
+#[wasm_bindgen_test]
+async fn upload_file_url() {
+    let request = WaifuUploadRequest::new()
+        .url("https://waifuvault.moe/assets/custom/images/08.png")
+        .hide_filename(true);
+
+    let entry = caller().upload_file(request).await.expect("upload");
+    let options = entry.options.expect("options");
+    assert!(options.hide_filename);
+}